@@ -0,0 +1,32 @@
+# part of libsig.a, for testing library signatures, built with
+# llvm-mc -triple=riscv64 -mattr=+m,-c,-relax -filetype=obj puts.s -o puts.o && llvm-ar rcsD ../libsig.a strlen.o puts.o
+	.text
+	.globl	puts
+	.type	puts, @function
+puts:
+	addi	sp, sp, -16
+	sd	ra, 8(sp)
+	sd	s0, 0(sp)
+	mv	s0, a0
+	call	strlen
+	mv	a2, a0
+	mv	a1, s0
+	li	a0, 1
+	li	a7, 64
+	ecall
+	lla	a1, newline
+	li	a2, 1
+	li	a0, 1
+	li	a7, 64
+	ecall
+	ld	ra, 8(sp)
+	ld	s0, 0(sp)
+	addi	sp, sp, 16
+	ret
+	.size	puts, .-puts
+	.globl	_IO_puts
+	.set	_IO_puts, puts
+
+	.section .rodata
+newline:
+	.byte	10
@@ -0,0 +1,14 @@
+# part of libsig.a, for testing library signatures, built with
+# llvm-mc -triple=riscv64 -mattr=+m,-c,-relax -filetype=obj strlen.s -o strlen.o && llvm-ar rcsD ../libsig.a strlen.o puts.o
+	.text
+	.globl	strlen
+	.type	strlen, @function
+strlen:
+	mv	a1, a0
+1:	lbu	a2, 0(a1)
+	addi	a1, a1, 1
+	bnez	a2, 1b
+	sub	a0, a1, a0
+	addi	a0, a0, -1
+	ret
+	.size	strlen, .-strlen
@@ -0,0 +1,40 @@
+/*
+ * asha.h - what decompiled code uses that c doesn't have by itself
+ *
+ * the output of asha includes this, so it compiles as it is
+ * anything the environment does, like system calls and the control and status registers,
+ * is only declared here, and has to be provided by whatever the code is built against
+ */
+
+#ifndef ASHA_H
+#define ASHA_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+/* the upper 64 bits of a 128-bit product, for each signedness of the operands */
+static inline int64_t mulh(int64_t a, int64_t b) {
+    return (int64_t)(((__int128)a * (__int128)b) >> 64);
+}
+
+static inline int64_t mulhsu(int64_t a, uint64_t b) {
+    return (int64_t)(((__int128)a * (__int128)b) >> 64);
+}
+
+static inline int64_t mulhu(uint64_t a, uint64_t b) {
+    return (int64_t)(((unsigned __int128)a * (unsigned __int128)b) >> 64);
+}
+
+/* handing control to the environment */
+void ecall(void);
+void ebreak(void);
+
+/* control and status registers, each giving back the value from before it was written */
+int64_t csrrw(int64_t csr, int64_t value);
+int64_t csrrs(int64_t csr, int64_t mask);
+int64_t csrrc(int64_t csr, int64_t mask);
+int64_t csrrwi(int64_t csr, int64_t value);
+int64_t csrrsi(int64_t csr, int64_t mask);
+int64_t csrrci(int64_t csr, int64_t mask);
+
+#endif
@@ -0,0 +1,76 @@
+/*
+ * libc.h - the prototypes of the common parts of the c library
+ *
+ * asha reads these to know what calls to them pass and get back, and what types those are,
+ * before any other headers are loaded
+ * only the functions are here, as everything else in a header is skipped when it's read
+ */
+
+#include <stddef.h>
+#include <sys/types.h>
+
+typedef struct _IO_FILE FILE;
+
+/* stdio.h */
+int printf(const char *format, ...);
+int fprintf(FILE *stream, const char *format, ...);
+int sprintf(char *str, const char *format, ...);
+int snprintf(char *str, size_t size, const char *format, ...);
+int scanf(const char *format, ...);
+int sscanf(const char *str, const char *format, ...);
+int puts(const char *s);
+int fputs(const char *s, FILE *stream);
+int putchar(int c);
+int fputc(int c, FILE *stream);
+int getchar(void);
+int fgetc(FILE *stream);
+char *fgets(char *s, int size, FILE *stream);
+FILE *fopen(const char *pathname, const char *mode);
+int fclose(FILE *stream);
+size_t fread(void *ptr, size_t size, size_t nmemb, FILE *stream);
+size_t fwrite(const void *ptr, size_t size, size_t nmemb, FILE *stream);
+int fflush(FILE *stream);
+void perror(const char *s);
+
+/* stdlib.h */
+void *malloc(size_t size);
+void *calloc(size_t nmemb, size_t size);
+void *realloc(void *ptr, size_t size);
+void free(void *ptr);
+void exit(int status);
+void abort(void);
+int atoi(const char *nptr);
+long atol(const char *nptr);
+long strtol(const char *nptr, char **endptr, int base);
+unsigned long strtoul(const char *nptr, char **endptr, int base);
+char *getenv(const char *name);
+int rand(void);
+void srand(unsigned int seed);
+int abs(int j);
+void qsort(void *base, size_t nmemb, size_t size, int (*compar)(const void *, const void *));
+
+/* string.h */
+size_t strlen(const char *s);
+int strcmp(const char *s1, const char *s2);
+int strncmp(const char *s1, const char *s2, size_t n);
+char *strcpy(char *dest, const char *src);
+char *strncpy(char *dest, const char *src, size_t n);
+char *strcat(char *dest, const char *src);
+char *strchr(const char *s, int c);
+char *strrchr(const char *s, int c);
+char *strstr(const char *haystack, const char *needle);
+char *strdup(const char *s);
+void *memcpy(void *dest, const void *src, size_t n);
+void *memmove(void *dest, const void *src, size_t n);
+void *memset(void *s, int c, size_t n);
+int memcmp(const void *s1, const void *s2, size_t n);
+
+/* unistd.h and fcntl.h */
+ssize_t read(int fd, void *buf, size_t count);
+ssize_t write(int fd, const void *buf, size_t count);
+int open(const char *pathname, int flags, ...);
+int close(int fd);
+off_t lseek(int fd, off_t offset, int whence);
+pid_t getpid(void);
+long syscall(long number, ...);
+unsigned int sleep(unsigned int seconds);
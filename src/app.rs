@@ -1,18 +1,18 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
-use crate::{decompilation::{generate_sections, output_decompiled_code, InstructionSection, SectionMap}, disassemble_file, instructions::InstructionType, output_assembly, read_compiled};
+use crate::{ast::{Braces, Indent, Item, Language, Style}, callgraph::CallGraph, dataflow::{DefUse, Definition}, decompilation::{decompile, InstructionSection, SectionMap}, disassemble_file, flirt::Library, generate_call_graph, headers::{libc, PrototypeMap}, image::Image, instructions::InstructionType, load_headers, load_image, load_signatures, loops::LoopForest, output_assembly, read_compiled, recompile::{differential, Recompilable}, signatures::{infer_signatures, SignatureMap}, strings::{find_references, find_strings, StringMap}, types::FieldNames, xrefs::Xrefs};
 
 // ----------------------------------------
 
-type ViewFunction = fn(&egui::Context, &State);
+type ViewFunction = fn(&egui::Context, &mut State);
 
-fn no_view_selected(ctx: &egui::Context, _state: &State) {
+fn no_view_selected(ctx: &egui::Context, _state: &mut State) {
     egui::CentralPanel::default().show(ctx, |ui| {
         ui.label("please choose a file to analyse");
     });
 }
 
-fn disassembly_view(ctx: &egui::Context, state: &State) {
+fn disassembly_view(ctx: &egui::Context, state: &mut State) {
     egui::CentralPanel::default().show(ctx, |ui| {
         if let Some(file_chosen) = state.get_source_file() {
             let path = std::path::Path::new(file_chosen);
@@ -23,7 +23,7 @@ fn disassembly_view(ctx: &egui::Context, state: &State) {
             ui.monospace(filename);
 
             egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
-                ui.monospace(output_assembly(state.bytes.clone().unwrap()).expect("error reading object file"));
+                ui.monospace(output_assembly(state.bytes.clone().unwrap(), &state.get_string_comments()).expect("error reading object file"));
             });
         }     
     });
@@ -33,17 +33,35 @@ fn disassembly_view(ctx: &egui::Context, state: &State) {
 
 type ISWrapper = (InstructionSection, egui::Pos2);
 
-fn cfg_view(ctx: &egui::Context, state: &State) {
+/// background for a block inside a loop, darker the deeper it's nested
+/// loops with more than one way in are tinted red instead, as they can't be structured cleanly
+fn loop_colour(forest: &LoopForest, block: usize) -> Option<egui::Color32> {
+    let index = forest.innermost_loop(block)?;
+    let alpha = (40 * forest.depth(block)).min(160) as u8;
+
+    if forest.get_loop(index)?.is_reducible() {
+        Some(egui::Color32::from_rgba_unmultiplied(90, 140, 255, alpha))
+    } else {
+        Some(egui::Color32::from_rgba_unmultiplied(255, 110, 110, alpha))
+    }
+}
+
+fn cfg_view(ctx: &egui::Context, state: &mut State) {
     egui::CentralPanel::default().show(ctx, |ui| {
         if let Some(file_chosen) = state.get_source_file() {
             let path = std::path::Path::new(file_chosen);
             let filename: String = path.file_name().unwrap().to_str().unwrap().to_string();
 
             ui.label("control flow graph view for ");
-            ui.monospace(filename);
+            ui.monospace(format!("{} ({})", filename, state.get_function_name()));
 
             let _disassembly = state.disassembly.clone().unwrap();
-            let block_map = state.cfg.clone().unwrap();
+            let Some(block_map) = state.cfg.clone() else { return; };
+            let forest = LoopForest::from_sections(&block_map);
+
+            if !forest.get_loops().is_empty() {
+                ui.label(format!("{} loop(s) found, loop bodies are highlighted", forest.get_loops().len()));
+            }
 
             egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
                 
@@ -60,14 +78,53 @@ fn cfg_view(ctx: &egui::Context, state: &State) {
 
                 // now render each block and add labels for branches
                 for (block, position) in &wrapped_blocks {
-                    ui.group(|ui| {
+                    let mut frame = egui::Frame::group(ui.style());
+                    if let Some(colour) = loop_colour(&forest, block.get_id()) {
+                        frame = frame.fill(colour);
+                    }
+
+                    frame.show(ui, |ui| {
+                        if let Some(found) = forest.loop_with_header(block.get_id()) {
+                            ui.label(format!("loop header ({:?}, depth {})", found.kind(), found.get_depth()));
+                        }
+
                         // draw block (using its position and a rectangle to represent it)
                         ui.painter().rect_filled(
                             egui::Rect::from_min_size(*position, egui::vec2(0.0, 10.0)),
                             0.0,
                             egui::Color32::LIGHT_GRAY,
                         );
-                        ui.monospace(format!("{}", &block)); // show block data
+
+                        // show block data, one line per instruction so a definition can be clicked to show its uses
+                        ui.spacing_mut().item_spacing.y = 0.0;
+                        let text = format!("{}", &block);
+                        let mut lines = text.lines();
+                        let addresses = block.get_instructions().into_keys();
+
+                        if let Some(header) = lines.next() {
+                            ui.monospace(header);
+                        }
+                        for (address, line) in addresses.zip(lines.by_ref()) {
+                            let defined = state.selected_definition.as_ref().is_some_and(|d| d.get_address() == address);
+                            let mut text = egui::RichText::new(line).monospace();
+                            if state.get_selected_uses().contains(&address) {
+                                text = text.background_color(ui.visuals().warn_fg_color.gamma_multiply(0.3));
+                            }
+
+                            let line = ui.selectable_label(defined, text);
+                            if line.clicked() {
+                                state.select_definition(address);
+                            }
+                            line.context_menu(|ui| {
+                                if ui.button("show references").clicked() {
+                                    state.show_references(address);
+                                    ui.close_menu();
+                                }
+                            });
+                        }
+                        for line in lines {
+                            ui.monospace(line);
+                        }
                     });
                 }
             });
@@ -77,7 +134,7 @@ fn cfg_view(ctx: &egui::Context, state: &State) {
 
 // ----------------------------------------
 
-fn decompiled_view(ctx: &egui::Context, state: &State) {
+fn decompiled_view(ctx: &egui::Context, state: &mut State) {
     egui::CentralPanel::default().show(ctx, |ui| {
         if let Some(file_chosen) = state.get_source_file() {
             let path = std::path::Path::new(file_chosen);
@@ -85,11 +142,59 @@ fn decompiled_view(ctx: &egui::Context, state: &State) {
             // wow that's ugly
 
             ui.label("decompilation of ");
-            ui.monospace(filename);
+            ui.monospace(format!("{} ({})", filename, state.get_function_name()));
+
+            // fields are named by their offset until they're renamed here
+            ui.horizontal(|ui| {
+                let (structure, offset, name) = &mut state.renaming;
+                ui.label("rename field of");
+                ui.add(egui::TextEdit::singleline(structure).hint_text("struct_0").desired_width(80.0));
+                ui.label("at offset");
+                ui.add(egui::DragValue::new(offset));
+                ui.label("to");
+                ui.add(egui::TextEdit::singleline(name).desired_width(80.0));
+
+                if ui.button("rename").clicked() && !name.is_empty() {
+                    state.rename_field();
+                }
+            });
+
+            // how the output is laid out, which only needs it printing again
+            ui.horizontal(|ui| {
+                let style = &mut state.style;
+                ui.label("print as");
+                ui.radio_value(&mut style.language, Language::C, "c");
+                ui.radio_value(&mut style.language, Language::Rust, "rust");
+
+                ui.separator();
+                ui.label("indent with");
+                ui.radio_value(&mut style.indent, Indent::Tabs, "tabs");
+                if ui.radio(matches!(style.indent, Indent::Spaces(_)), "spaces").clicked() && style.indent == Indent::Tabs {
+                    style.indent = Indent::Spaces(4);
+                }
+                if let Indent::Spaces(width) = &mut style.indent {
+                    ui.add(egui::DragValue::new(width).range(1..=8));
+                }
+
+                let mut next_line = style.braces == Braces::NextLine;
+                if ui.checkbox(&mut next_line, "braces on their own line").changed() {
+                    style.braces = if next_line { Braces::NextLine } else { Braces::SameLine };
+                }
+            });
+
+            // leaf functions can be checked by compiling what comes out and running it against the original
+            ui.horizontal(|ui| {
+                if ui.button("recompile and compare").clicked() {
+                    state.compare_recompiled();
+                }
+                if let Some(result) = &state.recompiled {
+                    ui.label(result);
+                }
+            });
 
             if let Some(decomp) = &state.decompilation {
                 egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
-                    for line in decomp {
+                    for line in state.style.print(decomp) {
                         ui.monospace(line);
                     }
                 });
@@ -100,13 +205,255 @@ fn decompiled_view(ctx: &egui::Context, state: &State) {
 
 // ----------------------------------------
 
+/// place each function in a row based on how many calls it takes to reach it from a root
+/// anything unreachable goes on a final row of its own
+fn call_graph_layers(graph: &CallGraph) -> Vec<Vec<u64>> {
+    let mut depth: BTreeMap<u64, usize> = BTreeMap::new();
+    let mut queue: VecDeque<(u64, usize)> = graph.get_roots().iter().map(|r| (*r, 0)).collect();
+
+    while let Some((function, d)) = queue.pop_front() {
+        if depth.contains_key(&function) {
+            continue;
+        }
+        depth.insert(function, d);
+
+        for callee in graph.get_callees(function) {
+            queue.push_back((callee, d + 1));
+        }
+    }
+
+    let mut layers: Vec<Vec<u64>> = Vec::new();
+    for (function, d) in depth.iter() {
+        if layers.len() <= *d {
+            layers.resize(d + 1, Vec::new());
+        }
+        layers[*d].push(*function);
+    }
+
+    let unreachable: Vec<u64> = graph.unreachable().into_iter().collect();
+    if !unreachable.is_empty() {
+        layers.push(unreachable);
+    }
+
+    layers
+}
+
+fn call_graph_view(ctx: &egui::Context, state: &mut State) {
+    const NODE_SIZE: egui::Vec2 = egui::vec2(140.0, 24.0);
+    const SPACING: egui::Vec2 = egui::vec2(40.0, 60.0);
+
+    egui::CentralPanel::default().show(ctx, |ui| {
+        if let Some(file_chosen) = state.get_source_file() {
+            let path = std::path::Path::new(file_chosen);
+            let filename: String = path.file_name().unwrap().to_str().unwrap().to_string();
+
+            ui.label("call graph of ");
+            ui.monospace(filename);
+            ui.label("click a function to open its control flow graph, or right-click for more options");
+
+            let Some(graph) = state.call_graph.clone() else { return; };
+
+            let layers = call_graph_layers(&graph);
+            let recursive: BTreeSet<u64> = graph.recursive_components().into_iter().flatten().collect();
+            let unreachable = graph.unreachable();
+
+            egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+                let widest = layers.iter().map(|l| l.len()).max().unwrap_or(0) as f32;
+                let size = egui::vec2(
+                    widest * (NODE_SIZE.x + SPACING.x),
+                    layers.len() as f32 * (NODE_SIZE.y + SPACING.y)
+                );
+                let (canvas, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+
+                // work out where each node goes
+                let mut positions: BTreeMap<u64, egui::Rect> = BTreeMap::new();
+                for (row, layer) in layers.iter().enumerate() {
+                    for (column, function) in layer.iter().enumerate() {
+                        let min = canvas.min + egui::vec2(
+                            column as f32 * (NODE_SIZE.x + SPACING.x),
+                            row as f32 * (NODE_SIZE.y + SPACING.y)
+                        );
+                        positions.insert(*function, egui::Rect::from_min_size(min, NODE_SIZE));
+                    }
+                }
+
+                // draw the calls first so the nodes sit on top of them
+                let stroke = egui::Stroke::new(1.0, ui.visuals().weak_text_color());
+                for (caller, rect) in positions.iter() {
+                    for callee in graph.get_callees(*caller) {
+                        if let Some(target) = positions.get(&callee) {
+                            if callee == *caller {
+                                continue;
+                            }
+                            let origin = rect.center_bottom();
+                            ui.painter().arrow(origin, target.center_top() - origin, stroke);
+                        }
+                    }
+                }
+
+                for (function, rect) in positions.iter() {
+                    let name = graph.get_function(*function).map(|f| f.get_name().to_string()).unwrap_or_default();
+
+                    // recursive functions are marked in red, unreachable ones are greyed out
+                    let mut text = egui::RichText::new(name).monospace();
+                    if recursive.contains(function) {
+                        text = text.color(egui::Color32::LIGHT_RED);
+                    }
+                    if unreachable.contains(function) {
+                        text = text.weak();
+                    }
+                    if graph.get_roots().contains(function) {
+                        text = text.strong();
+                    }
+
+                    let node = ui.put(*rect, egui::Button::new(text).selected(state.selected_function == Some(*function)))
+                        .on_hover_text(format!("{:#x}", function));
+
+                    if node.clicked() {
+                        state.select_function(*function);
+                        state.current_tab = Tab::ContextFlowGraph;
+                    }
+
+                    node.context_menu(|ui| {
+                        if ui.button("open control flow graph").clicked() {
+                            state.select_function(*function);
+                            state.current_tab = Tab::ContextFlowGraph;
+                            ui.close_menu();
+                        }
+                        if ui.button("open decompilation").clicked() {
+                            state.select_function(*function);
+                            state.current_tab = Tab::Decompilation;
+                            ui.close_menu();
+                        }
+                        if ui.button("show references").clicked() {
+                            state.show_references(*function);
+                            ui.close_menu();
+                        }
+                    });
+                }
+            });
+        }
+    });
+}
+
+fn strings_view(ctx: &egui::Context, state: &mut State) {
+    egui::CentralPanel::default().show(ctx, |ui| {
+        if let Some(file_chosen) = state.get_source_file() {
+            let path = std::path::Path::new(file_chosen);
+            let filename: String = path.file_name().unwrap().to_str().unwrap().to_string();
+
+            ui.label("text found in the data of ");
+            ui.monospace(filename);
+            ui.label("click where a string is used to open the decompilation of that function, or right-click its address for everything that refers to it");
+
+            let Some(graph) = state.call_graph.clone() else { return; };
+            let strings = state.strings.clone();
+
+            egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+                egui::Grid::new("strings").striped(true).show(ui, |ui| {
+                    for (address, text) in strings.iter() {
+                        ui.add(egui::Label::new(egui::RichText::new(format!("{:#x}", address)).monospace()).sense(egui::Sense::click()))
+                            .context_menu(|ui| {
+                                if ui.button("show references").clicked() {
+                                    state.show_references(*address);
+                                    ui.close_menu();
+                                }
+                            });
+                        ui.label(if text.is_wide() { "utf-16" } else { "utf-8" });
+                        ui.monospace(text.to_expression().to_string());
+
+                        ui.horizontal(|ui| {
+                            for xref in state.string_references.get(address).cloned().unwrap_or_default() {
+                                let Some(function) = graph.function_containing(xref) else { continue; };
+
+                                if ui.button(format!("{} {:#x}", function.get_name(), xref)).clicked() {
+                                    state.select_function(function.get_start());
+                                    state.current_tab = Tab::Decompilation;
+                                }
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+            });
+        }
+    });
+}
+
+/// everything referring to the chosen address, beside whichever view is open
+fn references_panel(ctx: &egui::Context, state: &mut State) {
+    egui::SidePanel::right("references").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.heading("references");
+            if ui.button("close").clicked() {
+                state.references_open = false;
+            }
+        });
+
+        // any address or symbol can be looked up by typing it in
+        ui.horizontal(|ui| {
+            let query = ui.add(egui::TextEdit::singleline(&mut state.reference_query).hint_text("address or symbol").desired_width(140.0));
+            let entered = query.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+
+            if ui.button("find").clicked() || entered {
+                state.references_to = state.find_address(&state.reference_query);
+            }
+        });
+
+        let Some(target) = state.references_to else {
+            ui.label("type in an address, or the name of a function or symbol");
+            return;
+        };
+        let Some(graph) = state.call_graph.clone() else { return; };
+        let image = state.image.clone().unwrap_or_default();
+
+        let name = image.symbol_containing(target).map(|symbol| symbol.get_name().to_string())
+            .or(graph.get_function(target).map(|function| function.get_name().to_string()));
+        match name {
+            Some(name) => ui.monospace(format!("{:#x} ({})", target, name)),
+            None => ui.monospace(format!("{:#x}", target))
+        };
+
+        let references = state.xrefs.get_references(target);
+        if references.is_empty() {
+            ui.label("nothing refers to it");
+        }
+
+        egui::ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+            egui::Grid::new("reference list").striped(true).show(ui, |ui| {
+                for reference in references {
+                    ui.label(reference.get_kind().to_string());
+
+                    // instructions open the function they're in, data just says where it is
+                    let from = reference.get_from();
+                    match graph.function_containing(from).filter(|_| reference.is_code()) {
+                        Some(function) => if ui.button(format!("{} {:#x}", function.get_name(), from)).clicked() {
+                            state.select_function(function.get_start());
+                            state.current_tab = Tab::Decompilation;
+                        },
+                        None => {
+                            let name = image.symbol_containing(from).map(|symbol| symbol.get_name().to_string()).unwrap_or_default();
+                            ui.monospace(format!("{} {:#x}", name, from));
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+    });
+}
+
+// ----------------------------------------
+
 // Use these to select which view is active
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Default)]
 pub enum Tab {
     #[default]
     Disassembly,
     ContextFlowGraph,
-    Decompilation
+    Decompilation,
+    CallGraph,
+    Strings
 }
 
 impl core::fmt::Display for Tab {
@@ -134,16 +481,177 @@ pub struct State {
     // disassembled input file
     disassembly: Option<BTreeMap<u64, InstructionType>>,
 
-    // control flow graph
+    // loaded sections and symbols of the input file
+    image: Option<Image>,
+
+    // functions found in the file and the calls between them
+    call_graph: Option<CallGraph>,
+
+    // patterns of library functions, for naming the ones a stripped file links in
+    library: Library,
+
+    // prototypes read from headers, for the functions they declare
+    prototypes: PrototypeMap,
+
+    // what each function takes and gives back, worked out over the whole call graph
+    signatures: SignatureMap,
+
+    // text found in the data, by its address
+    strings: StringMap,
+
+    // the instructions that use each string, by the string's address
+    string_references: BTreeMap<u64, BTreeSet<u64>>,
+
+    // what refers to every address, and from where
+    xrefs: Xrefs,
+
+    // whether the references panel is showing, what it's showing the references to, and what's been typed in to look up
+    references_open: bool,
+    references_to: Option<u64>,
+    reference_query: String,
+
+    // names given to the fields of each function's structs, by the function's start address
+    field_names: BTreeMap<u64, FieldNames>,
+
+    // the struct, offset and new name being typed in to rename a field
+    renaming: (String, i64, String),
+
+    // start address of the function currently being viewed
+    selected_function: Option<u64>,
+
+    // control flow graph of the selected function
     cfg: Option<SectionMap>,
 
-    // decompilation
-    decompilation: Option<Vec<String>>
+    // def-use chains of the selected function's cfg
+    def_use: Option<DefUse>,
+
+    // definition clicked on in the cfg view, whose uses are highlighted
+    selected_definition: Option<Definition>,
+
+    // decompilation of the selected function
+    decompilation: Option<Vec<Item>>,
+
+    // how the decompilation is printed
+    style: Style,
+
+    // how the recompiled selected function compared with the original
+    recompiled: Option<String>
 }
 
 impl State {
     fn get_source_file(&self) -> Option<&String> {
-        return self.source_file.as_ref()
+        self.source_file.as_ref()
+    }
+
+    fn get_function_name(&self) -> String {
+        self.selected_function
+            .and_then(|f| self.call_graph.as_ref()?.get_function(f))
+            .map(|f| f.get_name().to_string())
+            .unwrap_or_default()
+    }
+
+    /// open the references panel on an address
+    fn show_references(&mut self, address: u64) {
+        self.references_open = true;
+        self.references_to = Some(address);
+        self.reference_query = format!("{:#x}", address);
+    }
+
+    /// # the address something typed in refers to
+    /// either the name of a function or symbol, or the address itself in hex,
+    /// with names tried first as some of them (like `add`) are hex too
+    fn find_address(&self, query: &str) -> Option<u64> {
+        let query = query.trim();
+
+        let function = self.call_graph.as_ref()
+            .and_then(|graph| graph.get_functions().values().find(|function| function.get_name() == query))
+            .map(|function| function.get_start());
+        let symbol = self.image.as_ref()
+            .and_then(|image| image.symbol_named(query))
+            .map(|symbol| symbol.get_address());
+
+        function.or(symbol).or(u64::from_str_radix(query.trim_start_matches("0x"), 16).ok())
+    }
+
+    /// the text each instruction uses, written out to go beside it in the disassembly
+    fn get_string_comments(&self) -> BTreeMap<u64, String> {
+        let mut comments = BTreeMap::new();
+        for (address, xrefs) in self.string_references.iter() {
+            let Some(text) = self.strings.get(address) else { continue; };
+            for xref in xrefs {
+                comments.insert(*xref, text.to_expression().to_string());
+            }
+        }
+        comments
+    }
+
+    /// name whatever the loaded libraries recognise, then work out the signatures again with the new names
+    fn identify_library_functions(&mut self) {
+        let (Some(call_graph), Some(image)) = (self.call_graph.as_mut(), self.image.as_ref()) else { return; };
+
+        call_graph.identify(image, &self.library);
+        self.update_signatures();
+    }
+
+    /// work out the signatures again, after functions have been renamed or given prototypes
+    fn update_signatures(&mut self) {
+        if let Some(call_graph) = &self.call_graph {
+            self.signatures = infer_signatures(call_graph, &self.prototypes);
+        }
+    }
+
+    /// switch to a different function, regenerating its cfg and decompilation
+    fn select_function(&mut self, start: u64) {
+        let Some(function) = self.call_graph.as_ref().and_then(|g| g.get_function(start)) else { return; };
+
+        let cfg = function.cfg();
+        let image = self.image.clone().unwrap_or_default();
+        let fields = self.field_names.get(&start).cloned().unwrap_or_default();
+        self.decompilation = Some(decompile(cfg.clone(), &image, &self.signatures, &fields));
+        self.recompiled = None;
+        self.def_use = Some(DefUse::new(&cfg));
+        self.selected_definition = None;
+        self.cfg = Some(cfg);
+        self.selected_function = Some(start);
+    }
+
+    /// recompile the selected function and run it against the original on random inputs
+    fn compare_recompiled(&mut self) {
+        let Some(cfg) = &self.cfg else { return; };
+        let image = self.image.clone().unwrap_or_default();
+
+        let result = Recompilable::new(cfg, &image, &self.signatures)
+            .map_err(|reason| format!("can't be recompiled by itself, as it {}", reason))
+            .and_then(|recompilable| differential(cfg, &recompilable, 100, 0x5eed));
+
+        self.recompiled = Some(match result {
+            Ok(agreement) => agreement.to_string(),
+            Err(reason) => reason
+        });
+    }
+
+    /// rename a field of one of the selected function's structs, then decompile it again to show the new name
+    fn rename_field(&mut self) {
+        let Some(start) = self.selected_function else { return; };
+        let (structure, offset, name) = std::mem::take(&mut self.renaming);
+
+        self.field_names.entry(start).or_default().insert((structure.trim().to_string(), offset), name.trim().to_string());
+        self.select_function(start);
+    }
+
+    /// pick the definition made at an address, or clear it if it's already picked or the instruction defines nothing
+    fn select_definition(&mut self, address: u64) {
+        let definition = self.def_use.as_ref().and_then(|chains| chains.definition_at(address)).cloned();
+
+        self.selected_definition = if definition == self.selected_definition { None } else { definition };
+    }
+
+    /// the instructions that might read the selected definition
+    fn get_selected_uses(&self) -> BTreeSet<u64> {
+        match (&self.def_use, &self.selected_definition) {
+            (Some(chains), Some(definition)) => chains.get_uses(definition),
+            _ => BTreeSet::new()
+        }
     }
 }
 
@@ -166,6 +674,14 @@ impl AshaApp {
             (
                 "Decompilation",
                 Tab::Decompilation
+            ),
+            (
+                "Call Graph",
+                Tab::CallGraph
+            ),
+            (
+                "Strings",
+                Tab::Strings
             )
         ];
 
@@ -174,19 +690,22 @@ impl AshaApp {
 
     /// called once before the first frame.
     pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Default::default()
+        let mut app = AshaApp::default();
+        app.state.prototypes = libc();
+        app
     }
 
-    fn show_selected_view(&self, ctx: &egui::Context, state: &State) {
+    fn show_selected_view(&mut self, ctx: &egui::Context) {
         let selected_tab = self.state.current_tab;
         let view_function: ViewFunction = match selected_tab {
             Tab::Disassembly => disassembly_view,
             Tab::ContextFlowGraph => cfg_view,
             Tab::Decompilation => decompiled_view,
-            _ => no_view_selected
+            Tab::CallGraph => call_graph_view,
+            Tab::Strings => strings_view
         };
 
-        view_function(ctx, state);
+        view_function(ctx, &mut self.state);
     }
 }
 
@@ -212,12 +731,70 @@ impl eframe::App for AshaApp {
                             // disassemble and cache
                             self.state.bytes = Some(read_compiled(&file_chosen));
                             self.state.disassembly = Some(disassemble_file(self.state.bytes.clone().unwrap()).expect("error disassembling"));
+                            self.state.image = load_image(self.state.bytes.clone().unwrap()).ok();
+
+                            // split into functions and cache the call graph
+                            let mut call_graph = generate_call_graph(self.state.bytes.clone().unwrap()).expect("error finding functions");
+                            if let Some(image) = &self.state.image {
+                                call_graph.identify(image, &self.state.library);
+                            }
+
+                            // find what refers to what, and the text in the data along with where it's used
+                            let image = self.state.image.clone().unwrap_or_default();
+                            self.state.xrefs = Xrefs::new(&call_graph, &image);
+                            self.state.strings = find_strings(&image);
+                            self.state.string_references = find_references(&self.state.xrefs, &self.state.strings);
+                            self.state.references_to = None;
+
+                            // start off looking at the entry point, or the first function if there isn't one
+                            let first = call_graph.get_roots().iter().next()
+                                .or(call_graph.get_functions().keys().next())
+                                .copied();
+                            self.state.signatures = infer_signatures(&call_graph, &self.state.prototypes);
+                            self.state.field_names.clear();
+                            self.state.call_graph = Some(call_graph);
+
+                            // create and cache cfg and decompilation
+                            self.state.cfg = None;
+                            self.state.def_use = None;
+                            self.state.selected_definition = None;
+                            self.state.decompilation = None;
+                            if let Some(start) = first {
+                                self.state.select_function(start);
+                            }
+                        }
+                    }
 
-                            // create and cache cfg
-                            self.state.cfg = Some(generate_sections(self.state.disassembly.clone().unwrap()));
+                    if ui.button("Load signatures…").clicked() {
+                        if let Some(paths) = rfd::FileDialog::new().add_filter("signatures", &["sig"]).pick_files() {
+                            for path in paths {
+                                match load_signatures(&path.display().to_string()) {
+                                    Ok(library) => self.state.library.extend(library),
+                                    Err(e) => log::warn!("couldn't load signatures from {}: {}", path.display(), e)
+                                }
+                            }
+
+                            // anything already open gets named too
+                            self.state.identify_library_functions();
+                            if let Some(start) = self.state.selected_function {
+                                self.state.select_function(start);
+                            }
+                        }
+                    }
 
-                            // decompile and cache
-                            self.state.decompilation = Some(output_decompiled_code(self.state.cfg.clone().unwrap()));
+                    if ui.button("Load headers…").clicked() {
+                        if let Some(paths) = rfd::FileDialog::new().add_filter("headers", &["h"]).pick_files() {
+                            for path in paths {
+                                match load_headers(&path.display().to_string()) {
+                                    Ok(prototypes) => self.state.prototypes.extend(prototypes),
+                                    Err(e) => log::warn!("couldn't load headers from {}: {}", path.display(), e)
+                                }
+                            }
+
+                            self.state.update_signatures();
+                            if let Some(start) = self.state.selected_function {
+                                self.state.select_function(start);
+                            }
                         }
                     }
 
@@ -235,12 +812,23 @@ impl eframe::App for AshaApp {
                             self.state.current_tab = tab;
                         }
                     }
+
+                    ui.separator();
+                    if ui.selectable_label(self.state.references_open, "References").clicked() {
+                        self.state.references_open = !self.state.references_open;
+                    }
                 })
             });
         });
 
         if self.state.source_file.is_some() {
-            self.show_selected_view(ctx, &self.state);
+            // side panels have to be laid out before the view in the middle
+            if self.state.references_open {
+                references_panel(ctx, &mut self.state);
+            }
+            self.show_selected_view(ctx);
+        } else {
+            no_view_selected(ctx, &mut self.state);
         }
     }
 }
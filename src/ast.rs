@@ -0,0 +1,843 @@
+//! # c syntax
+//! the decompiled output is put together as c first and only printed at the end,
+//! so it always comes out as c a compiler accepts, along with `asha.h` for anything c doesn't have
+//!
+//! expressions only get brackets where precedence needs them, apart from a few places it's easy to misread,
+//! and the layout is left to a [`Style`], which can indent with tabs or spaces and put braces on their own lines,
+//! or print the same tree as rust instead, which [`crate::rust`] does
+
+use std::fmt;
+
+use crate::conditions::{Comparison, Condition};
+use crate::ir::{quote, BinaryOp, Expression, Statement, Type};
+use crate::rust::Externs;
+
+/// the header the output includes, with the intrinsics and fixed-width types it uses
+pub const HEADER: &str = include_str!("../include/asha.h");
+
+// ----------------------------------------
+// expressions
+// ----------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+    Deref,
+    AddressOf
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    ShiftLeft,
+    ShiftRight,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or
+}
+
+impl Operator {
+    /// how tightly it binds, higher first, as in the c standard
+    fn precedence(self) -> u8 {
+        match self {
+            Operator::Mul | Operator::Div | Operator::Rem => 13,
+            Operator::Add | Operator::Sub => 12,
+            Operator::ShiftLeft | Operator::ShiftRight => 11,
+            Operator::Less | Operator::Greater | Operator::LessEqual | Operator::GreaterEqual => 10,
+            Operator::Equal | Operator::NotEqual => 9,
+            Operator::BitAnd => 8,
+            Operator::BitXor => 7,
+            Operator::BitOr => 6,
+            Operator::And => 5,
+            Operator::Or => 4
+        }
+    }
+
+    /// the bitwise operators and shifts are easy to get the precedence of wrong, as are `&&` and `||`,
+    /// so one of these inside another kind always gets brackets
+    pub(crate) fn is_misread(self) -> bool {
+        matches!(self,
+            Operator::ShiftLeft | Operator::ShiftRight | Operator::BitAnd | Operator::BitXor | Operator::BitOr | Operator::And | Operator::Or
+        )
+    }
+
+    pub(crate) fn symbol(self) -> &'static str {
+        match self {
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Rem => "%",
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::ShiftLeft => "<<",
+            Operator::ShiftRight => ">>",
+            Operator::Less => "<",
+            Operator::Greater => ">",
+            Operator::LessEqual => "<=",
+            Operator::GreaterEqual => ">=",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::BitAnd => "&",
+            Operator::BitXor => "^",
+            Operator::BitOr => "|",
+            Operator::And => "&&",
+            Operator::Or => "||"
+        }
+    }
+}
+
+/// # an expression in c
+/// which is also what the other languages are printed from, so it only has what they all have some way of saying
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Ident(String),
+    Int(i64),
+    Str(String, bool),                  // whether it's utf-16
+    Unary(UnaryOp, Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    Assign(Box<Expr>, Box<Expr>),
+    Cast(Type, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Arrow(Box<Expr>, String)
+}
+
+/// precedence of anything that's never split up
+pub(crate) const PRIMARY: u8 = 16;
+pub(crate) const POSTFIX: u8 = 15;
+pub(crate) const PREFIX: u8 = 14;
+pub(crate) const ASSIGNMENT: u8 = 2;
+
+impl Expr {
+    pub fn ident(name: &str) -> Self {
+        Expr::Ident(name.to_string())
+    }
+
+    pub fn binary(op: Operator, lhs: Expr, rhs: Expr) -> Self {
+        Expr::Binary(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn cast(ty: Type, value: Expr) -> Self {
+        Expr::Cast(ty, Box::new(value))
+    }
+
+    pub fn assign(dst: Expr, value: Expr) -> Self {
+        Expr::Assign(Box::new(dst), Box::new(value))
+    }
+
+    pub fn call(function: Expr, args: Vec<Expr>) -> Self {
+        Expr::Call(Box::new(function), args)
+    }
+
+    /// a value made unsigned, which a constant that isn't negative already is once the other side is
+    fn unsigned(value: Expr) -> Self {
+        match value {
+            Expr::Int(c) if c >= 0 => value,
+            value => Expr::cast(Type::Int { size: 8, signed: false }, value)
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            // a negative number is really a minus in front of one
+            Expr::Int(c) if *c < 0 => PREFIX,
+            Expr::Ident(_) | Expr::Int(_) | Expr::Str(..) => PRIMARY,
+            Expr::Call(..) | Expr::Index(..) | Expr::Arrow(..) => POSTFIX,
+            Expr::Unary(..) | Expr::Cast(..) => PREFIX,
+            Expr::Binary(op, ..) => op.precedence(),
+            Expr::Assign(..) => ASSIGNMENT
+        }
+    }
+
+    /// this, in brackets if it binds less tightly than where it's going needs
+    fn at(&self, precedence: u8) -> String {
+        if self.precedence() < precedence {
+            format!("({})", self)
+        } else {
+            self.to_string()
+        }
+    }
+
+    /// an operand of a binary operator, bracketed where precedence or clarity needs it
+    fn operand(&self, op: Operator, precedence: u8) -> String {
+        match self {
+            Expr::Binary(inner, ..) if *inner != op && (inner.is_misread() || op.is_misread()) && inner.precedence() < PREFIX => format!("({})", self),
+            _ => self.at(precedence)
+        }
+    }
+
+    // ----------------------------------------
+
+    /// # a lifted expression in c
+    /// anything c has no operator for is an intrinsic from `asha.h`
+    pub fn from_expression(expr: &Expression) -> Self {
+        let from = |expr: &Expression| Expr::from_expression(expr);
+        let unsigned = |expr: &Expression| Expr::unsigned(from(expr));
+
+        match expr {
+            Expression::Variable(variable) => Expr::Ident(variable.to_string()),
+            Expression::Constant(c) => Expr::Int(*c),
+            // subtracting reads better than adding a negative
+            Expression::Binary(BinaryOp::Add, lhs, rhs) if matches!(**rhs, Expression::Constant(c) if c < 0 && c != i64::MIN) => {
+                let Expression::Constant(c) = **rhs else { unreachable!() };
+                Expr::binary(Operator::Sub, from(lhs), Expr::Int(-c))
+            },
+            Expression::Binary(op, lhs, rhs) => {
+                // the high multiplies have no operator, and the unsigned ones need their operands to be unsigned
+                let operator = match op {
+                    BinaryOp::MulHigh => return Expr::call(Expr::ident("mulh"), vec![from(lhs), from(rhs)]),
+                    BinaryOp::MulHighSignedUnsigned => return Expr::call(Expr::ident("mulhsu"), vec![from(lhs), from(rhs)]),
+                    BinaryOp::MulHighUnsigned => return Expr::call(Expr::ident("mulhu"), vec![from(lhs), from(rhs)]),
+                    BinaryOp::DivUnsigned => return Expr::binary(Operator::Div, unsigned(lhs), unsigned(rhs)),
+                    BinaryOp::RemUnsigned => return Expr::binary(Operator::Rem, unsigned(lhs), unsigned(rhs)),
+                    BinaryOp::LessUnsigned => return Expr::binary(Operator::Less, unsigned(lhs), unsigned(rhs)),
+                    BinaryOp::GreaterEqualUnsigned => return Expr::binary(Operator::GreaterEqual, unsigned(lhs), unsigned(rhs)),
+                    BinaryOp::ShiftRightLogical => return Expr::binary(Operator::ShiftRight, unsigned(lhs), from(rhs)),
+                    BinaryOp::Add => Operator::Add,
+                    BinaryOp::Sub => Operator::Sub,
+                    BinaryOp::Mul => Operator::Mul,
+                    BinaryOp::Div => Operator::Div,
+                    BinaryOp::Rem => Operator::Rem,
+                    BinaryOp::And => Operator::BitAnd,
+                    BinaryOp::Or => Operator::BitOr,
+                    BinaryOp::Xor => Operator::BitXor,
+                    BinaryOp::ShiftLeft => Operator::ShiftLeft,
+                    BinaryOp::ShiftRightArithmetic => Operator::ShiftRight,
+                    BinaryOp::Equal => Operator::Equal,
+                    BinaryOp::NotEqual => Operator::NotEqual,
+                    BinaryOp::Less => Operator::Less,
+                    BinaryOp::GreaterEqual => Operator::GreaterEqual
+                };
+                Expr::binary(operator, from(lhs), from(rhs))
+            },
+            Expression::Load { addr, size, signed } => {
+                let pointer = Type::Pointer(Box::new(Type::Int { size: *size, signed: *signed }));
+                Expr::Unary(UnaryOp::Deref, Box::new(Expr::cast(pointer, from(addr))))
+            },
+            Expression::Extend { value, bits, signed } => Expr::cast(Type::Int { size: bits / 8, signed: *signed }, from(value)),
+            // symbols are declared as arrays of bytes, so they can be stepped through a byte at a time
+            Expression::Symbol { name, offset: 0 } => Expr::ident(name),
+            Expression::Symbol { name, offset } if *offset < 0 => Expr::binary(Operator::Sub, Expr::ident(name), Expr::Int(offset.wrapping_neg())),
+            Expression::Symbol { name, offset } => Expr::binary(Operator::Add, Expr::ident(name), Expr::Int(*offset)),
+            Expression::String { text, wide } => Expr::Str(text.clone(), *wide),
+            // an element along reads best as an index
+            Expression::Deref(addr) => match &**addr {
+                Expression::Binary(BinaryOp::Add, base, index) => Expr::Index(Box::new(from(base)), Box::new(from(index))),
+                Expression::Binary(BinaryOp::Sub, base, index) if matches!(**index, Expression::Constant(_)) => {
+                    let Expression::Constant(c) = **index else { unreachable!() };
+                    Expr::Index(Box::new(from(base)), Box::new(Expr::Int(c.wrapping_neg())))
+                },
+                addr => Expr::Unary(UnaryOp::Deref, Box::new(from(addr)))
+            },
+            Expression::Cast { value, ty } => Expr::cast(ty.clone(), from(value)),
+            Expression::Field { base, name } => Expr::Arrow(Box::new(from(base)), name.clone())
+        }
+    }
+
+    /// # a condition in c
+    /// unsigned comparisons cast both sides, as c goes by the types of the operands
+    pub fn from_condition(condition: &Condition) -> Self {
+        match condition {
+            Condition::Constant(value) => Expr::ident(if *value { "true" } else { "false" }),
+            Condition::Compare { op, lhs, rhs, unsigned } => {
+                let op = match op {
+                    Comparison::Equal => Operator::Equal,
+                    Comparison::NotEqual => Operator::NotEqual,
+                    Comparison::Less => Operator::Less,
+                    Comparison::GreaterEqual => Operator::GreaterEqual,
+                    Comparison::Greater => Operator::Greater,
+                    Comparison::LessEqual => Operator::LessEqual
+                };
+                let (lhs, rhs) = (Expr::from_expression(lhs), Expr::from_expression(rhs));
+
+                if *unsigned {
+                    Expr::binary(op, Expr::unsigned(lhs), Expr::unsigned(rhs))
+                } else {
+                    Expr::binary(op, lhs, rhs)
+                }
+            },
+            Condition::Variable(name) => Expr::ident(name),
+            Condition::Not(inner) => Expr::Unary(UnaryOp::Not, Box::new(Expr::from_condition(inner))),
+            Condition::And(a, b) => Expr::binary(Operator::And, Expr::from_condition(a), Expr::from_condition(b)),
+            Condition::Or(a, b) => Expr::binary(Operator::Or, Expr::from_condition(a), Expr::from_condition(b))
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Ident(name) => write!(f, "{}", name),
+            // small numbers read better in decimal, addresses and masks in hex
+            Expr::Int(c) if c.unsigned_abs() < 0x1000 => write!(f, "{}", c),
+            Expr::Int(c) if *c < 0 => write!(f, "-{:#x}", c.unsigned_abs()),
+            Expr::Int(c) => write!(f, "{:#x}", c),
+            Expr::Str(text, wide) => write!(f, "{}{}", if *wide { "u" } else { "" }, quote(text)),
+            Expr::Unary(op, value) => {
+                let symbol = match op {
+                    UnaryOp::Negate => "-",
+                    UnaryOp::Not => "!",
+                    UnaryOp::Deref => "*",
+                    UnaryOp::AddressOf => "&"
+                };
+
+                // `- -x` shouldn't turn into `--x`
+                match **value {
+                    Expr::Int(c) if *op == UnaryOp::Negate && c < 0 => write!(f, "{}({})", symbol, value),
+                    Expr::Unary(UnaryOp::Negate, _) if *op == UnaryOp::Negate => write!(f, "{}({})", symbol, value),
+                    _ => write!(f, "{}{}", symbol, value.at(PREFIX))
+                }
+            },
+            Expr::Binary(op, lhs, rhs) => {
+                // everything's left associative, so only the right needs brackets at the same precedence
+                let precedence = op.precedence();
+                write!(f, "{} {} {}", lhs.operand(*op, precedence), op.symbol(), rhs.operand(*op, precedence + 1))
+            },
+            Expr::Assign(dst, value) => write!(f, "{} = {}", dst.at(PREFIX), value.at(ASSIGNMENT)),
+            Expr::Cast(ty, value) => write!(f, "({}){}", ty, value.at(PREFIX)),
+            Expr::Call(function, args) => {
+                let args = args.iter().map(|arg| arg.at(ASSIGNMENT + 1)).collect::<Vec<_>>().join(", ");
+                write!(f, "{}({})", function.at(POSTFIX), args)
+            },
+            Expr::Index(base, index) => write!(f, "{}[{}]", base.at(POSTFIX), index),
+            Expr::Arrow(base, name) => write!(f, "{}->{}", base.at(POSTFIX), name)
+        }
+    }
+}
+
+// ----------------------------------------
+// statements
+// ----------------------------------------
+
+/// # something being declared
+/// arrays are declared along with how long they are, if that's known
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Declaration {
+    ty: Type,
+    name: String,
+    length: Option<Option<u64>>         // none when it isn't an array, and some none when it's one of unknown length
+}
+
+impl Declaration {
+    pub fn new(ty: Type, name: impl ToString) -> Self {
+        Declaration { ty, name: name.to_string(), length: None }
+    }
+
+    pub fn array(ty: Type, name: impl ToString, length: u64) -> Self {
+        Declaration { ty, name: name.to_string(), length: Some(Some(length)) }
+    }
+
+    /// an array defined somewhere else, which is as long as it is
+    pub fn unsized_array(ty: Type, name: impl ToString) -> Self {
+        Declaration { ty, name: name.to_string(), length: Some(None) }
+    }
+
+    pub fn get_type(&self) -> &Type {
+        &self.ty
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_length(&self) -> Option<Option<u64>> {
+        self.length
+    }
+}
+
+impl fmt::Display for Declaration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let declarator = match self.length {
+            None => self.name.clone(),
+            Some(None) => format!("{}[]", self.name),
+            Some(Some(length)) => format!("{}[{}]", self.name, length)
+        };
+        write!(f, "{}", self.ty.declare(&declarator))
+    }
+}
+
+/// # the prototype of a function
+/// with no parameters at all when they aren't known, which c takes as whatever it's called with,
+/// and a variadic one takes whatever it's called with after the ones it declares
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Prototype {
+    pub name: String,
+    pub parameters: Option<Vec<Declaration>>,
+    pub returns: Option<Type>,
+    pub variadic: bool
+}
+
+impl fmt::Display for Prototype {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parameters = match &self.parameters {
+            None => Vec::new(),
+            Some(parameters) if parameters.is_empty() && !self.variadic => vec!["void".to_string()],
+            Some(parameters) => parameters.iter().map(|p| p.to_string()).collect()
+        };
+        if self.variadic {
+            parameters.push("...".to_string());
+        }
+        let parameters = parameters.join(", ");
+        let function = format!("{}({})", self.name, parameters);
+
+        match &self.returns {
+            Some(returns) => write!(f, "{}", returns.declare(&function)),
+            None => write!(f, "void {}", function)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Stmt {
+    Declare(Declaration),
+    Expr(Expr),
+    If { condition: Expr, then: Vec<Stmt>, otherwise: Vec<Stmt> },
+    While { condition: Expr, body: Vec<Stmt> },
+    DoWhile { body: Vec<Stmt>, condition: Expr },
+    For { condition: Expr, step: Vec<Expr>, body: Vec<Stmt> },
+    /// every case with the values that go to it, falling through to the next unless it ends in a break
+    Switch { on: Expr, cases: Vec<(Vec<u64>, Vec<Stmt>)> },
+    Break,
+    Continue,
+    Return(Option<Expr>),
+    Goto(String),
+    Label(String)
+}
+
+impl Stmt {
+    /// # a lifted statement in c
+    /// branches are left out, as they're what the structure of the output replaces
+    pub fn from_statement(statement: &Statement) -> Option<Self> {
+        let from = Expr::from_expression;
+
+        let expr = match statement {
+            Statement::Assign { dst, value } => Expr::assign(Expr::Ident(dst.to_string()), from(value)),
+            Statement::Store { addr, value, size } => {
+                let pointer = Type::Pointer(Box::new(Type::Int { size: *size, signed: true }));
+                Expr::assign(Expr::Unary(UnaryOp::Deref, Box::new(Expr::cast(pointer, from(addr)))), from(value))
+            },
+            Statement::Write { dst, value } => Expr::assign(from(dst), from(value)),
+            Statement::Call { target, args, dst } => {
+                let function = match target {
+                    Expression::Constant(address) => Expr::Ident(format!("sub_{:x}", address)),
+                    Expression::Symbol { name, offset: 0 } => Expr::ident(name),
+                    target => Expr::cast(Type::Pointer(Box::new(Type::Function(Box::new(Type::Int { size: 8, signed: true })))), from(target))
+                };
+                let call = Expr::call(function, args.iter().flatten().map(from).collect());
+
+                match dst {
+                    Some(dst) => Expr::assign(Expr::Ident(dst.to_string()), call),
+                    None => call
+                }
+            },
+            Statement::Branch { .. } => return None,
+            Statement::Return { values } => {
+                let value = match values.as_deref() {
+                    Some([value]) => Some(from(value)),
+                    // a pair comes back as the two halves of something twice the size
+                    Some([low, high]) => Some(Expr::binary(
+                        Operator::BitOr,
+                        Expr::binary(Operator::ShiftLeft, Expr::cast(Type::Int { size: 16, signed: true }, from(high)), Expr::Int(64)),
+                        Expr::cast(Type::Int { size: 8, signed: false }, from(low))
+                    )),
+                    _ => None
+                };
+                return Some(Stmt::Return(value));
+            },
+            Statement::Intrinsic { name, dst, args } => {
+                let call = Expr::call(Expr::ident(name), args.iter().map(from).collect());
+
+                match dst {
+                    Some(dst) => Expr::assign(Expr::Ident(dst.to_string()), call),
+                    None => call
+                }
+            }
+        };
+
+        Some(Stmt::Expr(expr))
+    }
+
+    /// # go through every statement
+    /// this one, then everything nested inside it in order
+    pub fn walk(&self, f: &mut impl FnMut(&Stmt)) {
+        f(self);
+
+        let blocks: Vec<&Vec<Stmt>> = match self {
+            Stmt::If { then, otherwise, .. } => vec![then, otherwise],
+            Stmt::While { body, .. } | Stmt::DoWhile { body, .. } | Stmt::For { body, .. } => vec![body],
+            Stmt::Switch { cases, .. } => cases.iter().map(|(_, body)| body).collect(),
+            _ => Vec::new()
+        };
+
+        for stmt in blocks.into_iter().flatten() {
+            stmt.walk(f);
+        }
+    }
+}
+
+/// # something at the top level of a file
+/// functions are declared by their prototypes, and anything else declared is external
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Item {
+    Include(String),
+    Prototype(Prototype),
+    Extern(Declaration),
+    Struct { name: String, members: Vec<Declaration> },
+    Function { prototype: Prototype, body: Vec<Stmt> }
+}
+
+// ----------------------------------------
+// printing
+// ----------------------------------------
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Indent {
+    #[default]
+    Tabs,
+    Spaces(usize)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Braces {
+    /// opening braces at the end of the line, as in k&r
+    #[default]
+    SameLine,
+    /// opening braces on a line of their own, as in allman
+    NextLine
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    C,
+    /// rust-like, for reading rather than compiling, as there's nothing for a goto to become
+    Rust
+}
+
+/// # how the output is laid out
+/// c with tabs and k&r braces unless it's told otherwise
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    pub language: Language,
+    pub indent: Indent,
+    pub braces: Braces
+}
+
+impl Style {
+    /// # print a file
+    /// with a blank line between everything apart from declarations, which are kept together
+    pub fn print(&self, items: &[Item]) -> Vec<String> {
+        // rust has nothing to include
+        let items: Vec<&Item> = items.iter()
+            .filter(|item| !(self.language == Language::Rust && matches!(item, Item::Include(_))))
+            .collect();
+
+        let mut printer = Printer { style: *self, externs: Externs::of(&items), lines: Vec::new(), depth: 0 };
+
+        let declaration = |item: &&Item| matches!(item, Item::Prototype(_) | Item::Extern(_));
+        for (index, group) in items.chunk_by(|a, b| declaration(a) && declaration(b)).enumerate() {
+            if index > 0 {
+                printer.lines.push(String::new());
+            }
+
+            match self.language {
+                Language::C => group.iter().for_each(|item| printer.item(item)),
+                Language::Rust => printer.rust_items(group)
+            }
+        }
+
+        printer.lines
+    }
+
+    fn indentation(&self, depth: usize) -> String {
+        match self.indent {
+            Indent::Tabs => "\t".repeat(depth),
+            Indent::Spaces(width) => " ".repeat(width * depth)
+        }
+    }
+}
+
+pub(crate) struct Printer {
+    pub(crate) style: Style,
+    /// what's declared as coming from c, which rust can only touch in `unsafe`
+    pub(crate) externs: Externs,
+    lines: Vec<String>,
+    pub(crate) depth: usize
+}
+
+impl Printer {
+    pub(crate) fn line(&mut self, text: impl AsRef<str>) {
+        let line = format!("{}{}", self.style.indentation(self.depth), text.as_ref());
+        self.lines.push(line);
+    }
+
+    /// start a block after something like `if (x)`
+    pub(crate) fn open(&mut self, header: impl AsRef<str>) {
+        match self.style.braces {
+            Braces::SameLine => self.line(format!("{} {{", header.as_ref())),
+            Braces::NextLine => {
+                self.line(header);
+                self.line("{");
+            }
+        }
+        self.depth += 1;
+    }
+
+    /// end a block, with whatever follows the brace on the same line as it, or the line after
+    pub(crate) fn close(&mut self, trailer: &str) {
+        self.depth -= 1;
+        match self.style.braces {
+            Braces::SameLine if !trailer.is_empty() => self.line(format!("}} {}", trailer)),
+            Braces::NextLine if !trailer.is_empty() => {
+                self.line("}");
+                self.line(trailer);
+            },
+            _ => self.line("}")
+        }
+    }
+
+    /// the else of an if, once its first block has been printed, ending it
+    pub(crate) fn otherwise(&mut self, block: &[Stmt]) {
+        if !block.is_empty() {
+            self.close("else");
+            if self.style.braces == Braces::SameLine {
+                // the brace goes after the else, which is already on the line
+                let last = self.lines.last_mut().unwrap();
+                last.push_str(" {");
+            } else {
+                self.line("{");
+            }
+            self.depth += 1;
+            self.block(block);
+        }
+
+        self.close("");
+    }
+
+    pub(crate) fn block(&mut self, block: &[Stmt]) {
+        for (index, stmt) in block.iter().enumerate() {
+            match (self.style.language, stmt) {
+                // a label has to be followed by something
+                (Language::C, Stmt::Label(name)) if index == block.len() - 1 => self.line(format!("{}:;", name)),
+                (Language::C, stmt) => self.statement(stmt),
+                (Language::Rust, stmt) => self.rust_statement(stmt)
+            }
+        }
+    }
+
+    fn item(&mut self, item: &Item) {
+        match item {
+            Item::Include(header) => self.line(format!("#include \"{}\"", header)),
+            Item::Prototype(prototype) => self.line(format!("{};", prototype)),
+            Item::Extern(declaration) => self.line(format!("extern {};", declaration)),
+            Item::Struct { name, members } => {
+                self.open(format!("struct {}", name));
+                for member in members {
+                    self.line(format!("{};", member));
+                }
+                self.depth -= 1;
+                self.line("};");
+            },
+            Item::Function { prototype, body } => {
+                self.open(prototype.to_string());
+                self.block(body);
+                self.close("");
+            }
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Declare(declaration) => self.line(format!("{};", declaration)),
+            Stmt::Expr(expr) => self.line(format!("{};", expr)),
+            // leaving a loop early fits on one line
+            Stmt::If { condition, then, otherwise } if otherwise.is_empty() && matches!(then[..], [Stmt::Break] | [Stmt::Continue]) => {
+                let keyword = if then[0] == Stmt::Break { "break" } else { "continue" };
+                self.line(format!("if ({}) {};", condition, keyword));
+            },
+            Stmt::If { condition, then, otherwise } => {
+                self.open(format!("if ({})", condition));
+                self.block(then);
+                self.otherwise(otherwise);
+            },
+            Stmt::While { condition, body } => {
+                self.open(format!("while ({})", condition));
+                self.block(body);
+                self.close("");
+            },
+            Stmt::DoWhile { body, condition } => {
+                self.open("do");
+                self.block(body);
+                self.close(&format!("while ({});", condition));
+            },
+            Stmt::For { condition, step, body } => {
+                let step = step.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(", ");
+                self.open(format!("for (; {}; {})", condition, step));
+                self.block(body);
+                self.close("");
+            },
+            Stmt::Switch { on, cases } => {
+                self.open(format!("switch ({})", on));
+                for (values, body) in cases {
+                    for value in values {
+                        self.line(format!("case {}:", value));
+                    }
+                    self.depth += 1;
+                    self.block(body);
+                    self.depth -= 1;
+                }
+                self.close("");
+            },
+            Stmt::Break => self.line("break;"),
+            Stmt::Continue => self.line("continue;"),
+            Stmt::Return(Some(value)) => self.line(format!("return {};", value)),
+            Stmt::Return(None) => self.line("return;"),
+            Stmt::Goto(label) => self.line(format!("goto {};", label)),
+            Stmt::Label(name) => self.line(format!("{}:", name))
+        }
+    }
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::instructions::ABIRegister;
+    use crate::ir::Variable;
+
+    fn var(name: &str) -> Expr {
+        Expr::ident(name)
+    }
+
+    fn register(register: ABIRegister) -> Expression {
+        Expression::Variable(Variable::Register(register))
+    }
+
+    #[test]
+    fn test_precedence() {
+        let sum = Expr::binary(Operator::Add, var("a"), var("b"));
+
+        assert_eq!(Expr::binary(Operator::Mul, sum.clone(), var("c")).to_string(), "(a + b) * c");
+        assert_eq!(Expr::binary(Operator::Add, var("c"), Expr::binary(Operator::Mul, var("a"), var("b"))).to_string(), "c + a * b");
+        assert_eq!(Expr::binary(Operator::Sub, var("c"), sum.clone()).to_string(), "c - (a + b)");
+        assert_eq!(Expr::binary(Operator::Sub, sum.clone(), var("c")).to_string(), "a + b - c");
+        assert_eq!(Expr::cast(Type::Int { size: 4, signed: true }, sum.clone()).to_string(), "(int32_t)(a + b)");
+        assert_eq!(Expr::binary(Operator::Add, Expr::cast(Type::Int { size: 4, signed: true }, var("a")), Expr::Int(1)).to_string(), "(int32_t)a + 1");
+
+        // mixing in bitwise operators always gets brackets, even where it doesn't need them
+        assert_eq!(Expr::binary(Operator::BitAnd, sum, var("c")).to_string(), "(a + b) & c");
+        let shifted = Expr::binary(Operator::ShiftLeft, var("a"), Expr::Int(2));
+        assert_eq!(Expr::binary(Operator::Add, shifted, var("b")).to_string(), "(a << 2) + b");
+    }
+
+    #[test]
+    fn test_from_expression() {
+        use ABIRegister::*;
+
+        let expr = Expression::binary(BinaryOp::LessUnsigned, register(a0), Expression::binary(BinaryOp::Add, register(a1), Expression::Constant(-4)));
+        assert_eq!(Expr::from_expression(&expr).to_string(), "(uint64_t)a0 < (uint64_t)(a1 - 4)");
+
+        let load = Expression::load(Expression::binary(BinaryOp::Add, register(a0), Expression::Constant(8)), 4, false);
+        assert_eq!(Expr::from_expression(&load).to_string(), "*(uint32_t *)(a0 + 8)");
+
+        let high = Expression::binary(BinaryOp::MulHigh, register(a0), register(a1));
+        assert_eq!(Expr::from_expression(&high).to_string(), "mulh(a0, a1)");
+    }
+
+    #[test]
+    fn test_items() {
+        let body = vec![
+            Stmt::Goto("section_2".to_string()),
+            Stmt::While { condition: var("x"), body: vec![Stmt::If { condition: var("y"), then: vec![Stmt::Break], otherwise: Vec::new() }] },
+            Stmt::Label("section_2".to_string())
+        ];
+        let word = Type::Int { size: 8, signed: true };
+        let items = [
+            Item::Include("asha.h".to_string()),
+            Item::Prototype(Prototype { name: "g".to_string(), parameters: Some(vec![Declaration::new(word.clone(), "a0")]), returns: Some(word), variadic: false }),
+            Item::Extern(Declaration::unsized_array(Type::Int { size: 1, signed: false }, "table")),
+            Item::Struct { name: "s".to_string(), members: vec![Declaration::new(Type::Pointer(Box::new(Type::Struct("s".to_string()))), "next")] },
+            Item::Function { prototype: Prototype { name: "f".to_string(), parameters: Some(Vec::new()), returns: None, variadic: false }, body }
+        ];
+
+        // a label that ends a block still needs something after it
+        assert_eq!(Style::default().print(&items), [
+            "#include \"asha.h\"",
+            "",
+            "int64_t g(int64_t a0);",
+            "extern uint8_t table[];",
+            "",
+            "struct s {",
+            "\tstruct s *next;",
+            "};",
+            "",
+            "void f(void) {",
+            "\tgoto section_2;",
+            "\twhile (x) {",
+            "\t\tif (y) break;",
+            "\t}",
+            "\tsection_2:;",
+            "}"
+        ]);
+    }
+
+    #[test]
+    fn test_styles() {
+        let body = vec![
+            Stmt::If {
+                condition: var("x"),
+                then: vec![Stmt::Return(Some(Expr::Int(1)))],
+                otherwise: vec![Stmt::DoWhile { body: vec![Stmt::Expr(Expr::assign(var("x"), Expr::Int(0)))], condition: var("x") }]
+            }
+        ];
+        let word = Type::Int { size: 8, signed: true };
+        let prototype = Prototype { name: "f".to_string(), parameters: Some(vec![Declaration::new(word.clone(), "x")]), returns: Some(word), variadic: false };
+        let items = [Item::Function { prototype, body }];
+
+        assert_eq!(Style::default().print(&items), [
+            "int64_t f(int64_t x) {",
+            "\tif (x) {",
+            "\t\treturn 1;",
+            "\t} else {",
+            "\t\tdo {",
+            "\t\t\tx = 0;",
+            "\t\t} while (x);",
+            "\t}",
+            "}"
+        ]);
+
+        let style = Style { indent: Indent::Spaces(2), braces: Braces::NextLine, ..Style::default() };
+        assert_eq!(style.print(&items), [
+            "int64_t f(int64_t x)",
+            "{",
+            "  if (x)",
+            "  {",
+            "    return 1;",
+            "  }",
+            "  else",
+            "  {",
+            "    do",
+            "    {",
+            "      x = 0;",
+            "    }",
+            "    while (x);",
+            "  }",
+            "}"
+        ]);
+    }
+}
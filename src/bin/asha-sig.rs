@@ -0,0 +1,47 @@
+//! # asha-sig
+//! makes library signatures out of static libraries, so the functions linked in from them can be named in stripped binaries
+//!
+//! `asha-sig libc.sig libc.a libm.a` writes a pattern for every function in the archives to `libc.sig`,
+//! which is then loaded alongside a binary to name whatever it recognises
+
+use std::fs;
+use std::process::ExitCode;
+
+use asha::flirt::Library;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [output, archives @ ..] = &args[..] else {
+        eprintln!("usage: asha-sig <output.sig> <archive.a>...");
+        return ExitCode::FAILURE;
+    };
+    if archives.is_empty() {
+        eprintln!("usage: asha-sig <output.sig> <archive.a>...");
+        return ExitCode::FAILURE;
+    }
+
+    let mut library = Library::new();
+    for archive in archives {
+        let found = fs::read(archive)
+            .map_err(|e| e.to_string())
+            .and_then(|data| Library::from_archive(&data).map_err(|e| e.to_string()));
+
+        match found {
+            Ok(found) => {
+                eprintln!("{}: {} functions", archive, found.get_patterns().len());
+                library.extend(found);
+            },
+            Err(e) => {
+                eprintln!("asha-sig: {}: {}", archive, e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    if let Err(e) = fs::write(output, library.to_string()) {
+        eprintln!("asha-sig: {}: {}", output, e);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
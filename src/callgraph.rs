@@ -0,0 +1,544 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::decompilation::{generate_sections, SectionMap};
+use crate::flirt::Library;
+use crate::image::Image;
+use crate::instructions::{ABIRegister, InstructionType};
+use crate::jumptable::JumpTableMap;
+use crate::syscalls::SYSCALLS;
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// # a single function found in the binary
+/// a function covers every instruction from its start address up to the start of the next function
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    name: String,
+    start: u64,                                     // address of the first instruction
+    end: u64,                                       // address of the last instruction
+    instructions: BTreeMap<u64, InstructionType>,
+    calls: Vec<(u64, u64)>,                         // (call site, destination) for every direct call made
+    jump_tables: JumpTableMap                       // resolved indirect jumps within this function
+}
+
+impl Function {
+    fn new(name: String, instructions: BTreeMap<u64, InstructionType>) -> Self {
+        let start = instructions.keys().next().copied().unwrap_or(0);
+        let end = instructions.keys().last().copied().unwrap_or(0);
+
+        Function {
+            name,
+            start,
+            end,
+            instructions,
+            calls: Vec::new(),
+            jump_tables: BTreeMap::new()
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn get_end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn get_instructions(&self) -> &BTreeMap<u64, InstructionType> {
+        &self.instructions
+    }
+
+    /// every direct call made by this function, as (call site, destination) pairs
+    pub fn get_calls(&self) -> &[(u64, u64)] {
+        &self.calls
+    }
+
+    pub fn get_jump_tables(&self) -> &JumpTableMap {
+        &self.jump_tables
+    }
+
+    /// determine if a given address is in this function
+    pub fn contains(&self, address: u64) -> bool {
+        (address >= self.start) && (address <= self.end)
+    }
+
+    /// generate the control-flow graph of this function alone
+    pub fn cfg(&self) -> SectionMap {
+        generate_sections(self.instructions.clone(), &self.jump_tables)
+    }
+}
+
+// ----------------------------------------
+
+/// # Interprocedural call graph
+/// the vertices are the functions, keyed by their start address, and each edge is a caller calling a callee
+/// the roots are the functions that execution can start from, normally just the entry point
+#[derive(Clone, Debug, Default)]
+pub struct CallGraph {
+    functions: BTreeMap<u64, Function>,
+    callees: BTreeMap<u64, BTreeSet<u64>>,
+    callers: BTreeMap<u64, BTreeSet<u64>>,
+    roots: BTreeSet<u64>
+}
+
+impl CallGraph {
+    /// split the instructions into functions and link them by their calls
+    /// - `symbols` are the named addresses from the symbol table, these all mark function starts
+    /// - `entry` is the entry point of the executable, if it has one
+    /// - `jump_tables` are all the resolved indirect jumps, which get handed out to the functions they're in
+    pub fn new(instructions: &BTreeMap<u64, InstructionType>, symbols: &BTreeMap<u64, String>, entry: Option<u64>, jump_tables: &JumpTableMap) -> Self {
+        let mut functions = discover_functions(instructions, symbols, entry);
+
+        for function in functions.values_mut() {
+            function.jump_tables = jump_tables.range(function.start..=function.end)
+                .map(|(a, t)| (*a, t.clone()))
+                .collect();
+        }
+
+        let mut graph = CallGraph {
+            functions,
+            ..Default::default()
+        };
+
+        for (start, function) in graph.functions.iter() {
+            graph.callees.entry(*start).or_default();
+            graph.callers.entry(*start).or_default();
+
+            for (_, destination) in function.get_calls() {
+                graph.callees.entry(*start).or_default().insert(*destination);
+                graph.callers.entry(*destination).or_default().insert(*start);
+            }
+        }
+
+        // execution starts at the entry point
+        // if we don't know it, anything that nobody calls is the best guess we have
+        if let Some(entry) = entry.filter(|e| graph.functions.contains_key(e)) {
+            graph.roots.insert(entry);
+        } else {
+            graph.roots = graph.functions.keys()
+                .filter(|f| graph.get_callers(**f).is_empty())
+                .copied()
+                .collect();
+        }
+
+        graph
+    }
+
+    pub fn get_functions(&self) -> &BTreeMap<u64, Function> {
+        &self.functions
+    }
+
+    pub fn get_function(&self, start: u64) -> Option<&Function> {
+        self.functions.get(&start)
+    }
+
+    /// find the function that contains the given address
+    pub fn function_containing(&self, address: u64) -> Option<&Function> {
+        self.functions.range(..=address)
+            .next_back()
+            .map(|(_, f)| f)
+            .filter(|f| f.contains(address))
+    }
+
+    pub fn get_roots(&self) -> &BTreeSet<u64> {
+        &self.roots
+    }
+
+    /// # name the functions a library has patterns for
+    /// only the ones the symbol table didn't name, which go by their address, giving how many were
+    pub fn identify(&mut self, image: &Image, library: &Library) -> usize {
+        let mut named = 0;
+
+        for (start, function) in self.functions.iter_mut() {
+            if function.name != format!("sub_{:x}", start) {
+                continue;
+            }
+
+            if let Some(name) = library.identify(image, *start) {
+                function.name = name.to_string();
+                named += 1;
+            }
+        }
+
+        named
+    }
+
+    /// every function called by the given function
+    pub fn get_callees(&self, function: u64) -> BTreeSet<u64> {
+        self.callees.get(&function).cloned().unwrap_or_default()
+    }
+
+    /// every function that calls the given function
+    pub fn get_callers(&self, function: u64) -> BTreeSet<u64> {
+        self.callers.get(&function).cloned().unwrap_or_default()
+    }
+
+    /// all functions that can be reached by following calls from the roots
+    pub fn reachable(&self) -> BTreeSet<u64> {
+        let mut visited = BTreeSet::new();
+        let mut queue: VecDeque<u64> = self.roots.iter().copied().collect();
+
+        while let Some(function) = queue.pop_front() {
+            if !visited.insert(function) {
+                continue;
+            }
+
+            queue.extend(self.get_callees(function));
+        }
+
+        visited
+    }
+
+    /// all functions that can never be called, starting from the roots
+    pub fn unreachable(&self) -> BTreeSet<u64> {
+        let reachable = self.reachable();
+
+        self.functions.keys()
+            .filter(|f| !reachable.contains(f))
+            .copied()
+            .collect()
+    }
+
+    /// # find every group of recursive functions
+    /// these are the strongly connected components of the graph that contain a cycle,
+    /// so either more than one function, or a single function that calls itself
+    pub fn recursive_components(&self) -> Vec<Vec<u64>> {
+        self.strongly_connected_components()
+            .into_iter()
+            .filter(|c| c.len() > 1 || self.get_callees(c[0]).contains(&c[0]))
+            .collect()
+    }
+
+    /// determine if a function can end up calling itself, directly or otherwise
+    pub fn is_recursive(&self, function: u64) -> bool {
+        self.recursive_components()
+            .iter()
+            .any(|c| c.contains(&function))
+    }
+
+    /// # tarjan's strongly connected components algorithm
+    /// components are returned in reverse topological order, i.e. callees before their callers
+    pub fn strongly_connected_components(&self) -> Vec<Vec<u64>> {
+        let mut tarjan = Tarjan::default();
+
+        for function in self.functions.keys() {
+            if !tarjan.index.contains_key(function) {
+                tarjan.connect(self, *function);
+            }
+        }
+
+        tarjan.components
+    }
+}
+
+/// state for a run of tarjan's algorithm
+#[derive(Default)]
+struct Tarjan {
+    next_index: usize,
+    index: BTreeMap<u64, usize>,
+    low_link: BTreeMap<u64, usize>,
+    stack: Vec<u64>,
+    on_stack: BTreeSet<u64>,
+    components: Vec<Vec<u64>>
+}
+
+impl Tarjan {
+    fn connect(&mut self, graph: &CallGraph, function: u64) {
+        self.index.insert(function, self.next_index);
+        self.low_link.insert(function, self.next_index);
+        self.next_index += 1;
+
+        self.stack.push(function);
+        self.on_stack.insert(function);
+
+        for callee in graph.get_callees(function) {
+            if !self.index.contains_key(&callee) {
+                self.connect(graph, callee);
+                let low = self.low_link[&function].min(self.low_link[&callee]);
+                self.low_link.insert(function, low);
+            } else if self.on_stack.contains(&callee) {
+                let low = self.low_link[&function].min(self.index[&callee]);
+                self.low_link.insert(function, low);
+            }
+        }
+
+        // if this is the root of a component, pop the whole thing off the stack
+        if self.low_link[&function] == self.index[&function] {
+            let mut component = Vec::new();
+
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(&member);
+                component.push(member);
+
+                if member == function {
+                    break;
+                }
+            }
+
+            component.sort();
+            self.components.push(component);
+        }
+    }
+}
+
+// -----------------------------------
+// functions
+// -----------------------------------
+
+/// # find the destination of a direct call, if this instruction is one
+/// `jal` with a link register is a call, `j` (`jal zero, ...`) is just a jump
+fn call_destination(address: u64, instruction: &InstructionType) -> Option<u64> {
+    match instruction {
+        InstructionType::J { name: "jal", rd, imm } if *rd != ABIRegister::zero => {
+            address.checked_add_signed(*imm as i64)
+        },
+        _ => None
+    }
+}
+
+/// # split the disassembled instructions into functions
+/// a function starts at
+/// - the entry point
+/// - any address named in the symbol table
+/// - the destination of any direct call
+/// - the very first instruction, so that nothing is left out
+///
+/// and runs up until the start of the next function, or until it leaves the program, whichever comes first
+fn discover_functions(instructions: &BTreeMap<u64, InstructionType>, symbols: &BTreeMap<u64, String>, entry: Option<u64>) -> BTreeMap<u64, Function> {
+    let mut starts: BTreeSet<u64> = BTreeSet::new();
+
+    if let Some(first) = instructions.keys().next() {
+        starts.insert(*first);
+    }
+
+    starts.extend(entry);
+    starts.extend(symbols.keys());
+
+    for (address, instruction) in instructions.iter() {
+        starts.extend(call_destination(*address, instruction));
+    }
+
+    // only keep starts that actually point at code
+    starts.retain(|s| instructions.contains_key(s));
+
+    let mut functions = BTreeMap::new();
+    let starts: Vec<u64> = starts.into_iter().collect();
+
+    for (i, start) in starts.iter().enumerate() {
+        let body: BTreeMap<u64, InstructionType> = match starts.get(i + 1) {
+            Some(next) => instructions.range(*start..*next),
+            None => instructions.range(*start..)
+        }.map(|(a, inst)| (*a, inst.clone())).collect();
+
+        // whatever's after leaving isn't part of it, and is often its data
+        let body = match leaves_at(&body) {
+            Some(exit) => body.range(..=exit).map(|(a, inst)| (*a, inst.clone())).collect(),
+            None => body
+        };
+
+        let name = symbols.get(start)
+            .cloned()
+            .unwrap_or_else(|| format!("sub_{:x}", start));
+
+        let mut function = Function::new(name, body);
+
+        function.calls = function.instructions.iter()
+            .filter_map(|(a, inst)| call_destination(*a, inst).map(|d| (*a, d)))
+            .filter(|(_, d)| instructions.contains_key(d))
+            .collect();
+
+        functions.insert(*start, function);
+    }
+
+    functions
+}
+
+/// # where a function leaves the program
+/// the first `ecall` to exit or exit_group, with its number put straight into `a7` beforehand,
+/// as long as nothing before it could jump past it to more of the function
+fn leaves_at(body: &BTreeMap<u64, InstructionType>) -> Option<u64> {
+    let targets: BTreeSet<u64> = body.iter()
+        .filter_map(|(address, instruction)| match instruction {
+            InstructionType::B { imm, .. } => address.checked_add_signed(*imm as i64),
+            InstructionType::J { rd: ABIRegister::zero, imm, .. } => address.checked_add_signed(*imm as i64),
+            _ => None
+        })
+        .collect();
+
+    let mut number = None;
+    let mut furthest = 0;
+
+    for (address, instruction) in body.iter() {
+        // somewhere jumped to could have anything in a7
+        if targets.contains(address) {
+            number = None;
+        }
+
+        match instruction {
+            // the same test as lifting uses to tell an ecall from an ebreak
+            instruction if instruction.get_name() == "syscall" && instruction.get_imm() != 1 => {
+                let leaves = number.and_then(|n: i16| SYSCALLS.get(&(n as u64))).is_some_and(|syscall| syscall.leaves());
+                if leaves && furthest <= *address {
+                    return Some(*address);
+                }
+            },
+            InstructionType::I { name: "addi", rd: ABIRegister::a7, rs1: ABIRegister::zero, imm } => number = Some(*imm),
+            // an indirect jump could go anywhere
+            InstructionType::I { name: "jalr", rd: ABIRegister::zero, rs1, .. } if *rs1 != ABIRegister::ra => return None,
+            InstructionType::I { name: "jalr", .. } => number = None,
+            InstructionType::B { imm, .. } => {
+                furthest = furthest.max(address.saturating_add_signed(*imm as i64));
+                number = None;
+            },
+            // and a call could change it
+            InstructionType::J { rd, imm, .. } => {
+                if *rd == ABIRegister::zero {
+                    furthest = furthest.max(address.saturating_add_signed(*imm as i64));
+                }
+                number = None;
+            },
+            instruction if instruction.get_rd() == ABIRegister::a7 => number = None,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{addi, branch, ecall, jal, program, ret};
+    use crate::flirt::Pattern;
+
+    /// - _start (0x100) calls a (0x110), then loops forever
+    /// - a (0x110) calls b (0x11c) and itself
+    /// - b (0x11c) calls a
+    /// - c (0x128) is never called
+    fn create_program() -> (BTreeMap<u64, InstructionType>, BTreeMap<u64, String>) {
+        let mut instructions = BTreeMap::new();
+
+        instructions.insert(0x100, addi(ABIRegister::a0, ABIRegister::zero, 1));
+        instructions.insert(0x104, jal(ABIRegister::ra, 0xc));              // call a
+        instructions.insert(0x108, addi(ABIRegister::a0, ABIRegister::a0, 1));
+        instructions.insert(0x10c, jal(ABIRegister::zero, -4));             // j 0x108
+
+        instructions.insert(0x110, jal(ABIRegister::ra, 0xc));              // call b
+        instructions.insert(0x114, jal(ABIRegister::ra, -4));               // call a
+        instructions.insert(0x118, ret());
+
+        instructions.insert(0x11c, addi(ABIRegister::a0, ABIRegister::a0, -1));
+        instructions.insert(0x120, jal(ABIRegister::ra, -0x10));            // call a
+        instructions.insert(0x124, ret());
+
+        instructions.insert(0x128, addi(ABIRegister::a0, ABIRegister::zero, 0));
+        instructions.insert(0x12c, ret());
+
+        let mut symbols = BTreeMap::new();
+        symbols.insert(0x100, "_start".to_string());
+        symbols.insert(0x128, "c".to_string());
+
+        (instructions, symbols)
+    }
+
+    #[test]
+    fn test_discover_functions() {
+        let (instructions, symbols) = create_program();
+        let graph = CallGraph::new(&instructions, &symbols, Some(0x100), &BTreeMap::new());
+
+        let starts: Vec<u64> = graph.get_functions().keys().copied().collect();
+        assert_eq!(starts, vec![0x100, 0x110, 0x11c, 0x128]);
+
+        // unnamed functions are named after their address
+        assert_eq!(graph.get_function(0x100).unwrap().get_name(), "_start");
+        assert_eq!(graph.get_function(0x11c).unwrap().get_name(), "sub_11c");
+
+        // the jump at 0x10c is not a call
+        assert_eq!(graph.get_function(0x100).unwrap().get_calls(), &[(0x104, 0x110)]);
+        assert_eq!(graph.get_function(0x110).unwrap().get_end(), 0x118);
+        assert_eq!(graph.function_containing(0x120).unwrap().get_start(), 0x11c);
+    }
+
+    #[test]
+    fn test_leaving() {
+        // a function that leaves, with its data after, and one that might branch past where it'd leave
+        let instructions = program(vec![
+            addi(ABIRegister::a7, ABIRegister::zero, 93),
+            ecall(),
+            jal(ABIRegister::s8, 0x2772),
+            branch("beq", ABIRegister::a0, ABIRegister::zero, 0xc),
+            addi(ABIRegister::a7, ABIRegister::zero, 94),
+            ecall(),
+            ret()
+        ]);
+        let symbols = BTreeMap::from([(0x100, "leaves".to_string()), (0x10c, "might".to_string())]);
+        let graph = CallGraph::new(&instructions, &symbols, Some(0x100), &BTreeMap::new());
+
+        assert_eq!(graph.get_function(0x100).unwrap().get_end(), 0x104);
+        assert_eq!(graph.get_function(0x10c).unwrap().get_end(), 0x118);
+    }
+
+    #[test]
+    fn test_callers_and_callees() {
+        let (instructions, symbols) = create_program();
+        let graph = CallGraph::new(&instructions, &symbols, Some(0x100), &BTreeMap::new());
+
+        assert_eq!(graph.get_callees(0x110), BTreeSet::from([0x110, 0x11c]));
+        assert_eq!(graph.get_callers(0x110), BTreeSet::from([0x100, 0x110, 0x11c]));
+        assert!(graph.get_callers(0x128).is_empty());
+        assert_eq!(graph.get_roots(), &BTreeSet::from([0x100]));
+    }
+
+    #[test]
+    fn test_identify() {
+        let (instructions, symbols) = create_program();
+        let mut graph = CallGraph::new(&instructions, &symbols, Some(0x100), &BTreeMap::new());
+
+        // what's at b, and the same again at _start, which is already named
+        let bytes: Vec<u8> = (0..0x30).collect();
+        let mut image = Image::new();
+        image.add_section(".text", 0x100, bytes.clone(), true);
+
+        let mut library = Library::new();
+        library.add(Pattern::new("b", &bytes[0x1c..0x2c], &BTreeSet::from([4, 5, 6, 7])));
+        library.add(Pattern::new("start", &bytes[..0x10], &BTreeSet::new()));
+
+        assert_eq!(graph.identify(&image, &library), 1);
+        assert_eq!(graph.get_function(0x11c).unwrap().get_name(), "b");
+        assert_eq!(graph.get_function(0x100).unwrap().get_name(), "_start");
+        assert_eq!(graph.get_function(0x110).unwrap().get_name(), "sub_110");
+    }
+
+    #[test]
+    fn test_recursion() {
+        let (instructions, symbols) = create_program();
+        let graph = CallGraph::new(&instructions, &symbols, Some(0x100), &BTreeMap::new());
+
+        assert_eq!(graph.recursive_components(), vec![vec![0x110, 0x11c]]);
+        assert!(graph.is_recursive(0x11c));
+        assert!(!graph.is_recursive(0x100));
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let (instructions, symbols) = create_program();
+        let graph = CallGraph::new(&instructions, &symbols, Some(0x100), &BTreeMap::new());
+
+        assert_eq!(graph.unreachable(), BTreeSet::from([0x128]));
+
+        // without an entry point, anything uncalled is a root
+        let graph = CallGraph::new(&instructions, &symbols, None, &BTreeMap::new());
+        assert_eq!(graph.get_roots(), &BTreeSet::from([0x100, 0x128]));
+        assert!(graph.unreachable().is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+//! # boolean conditions
+//! the conditions that branches test, as a tree rather than a string,
+//! so that they can be negated and combined when branches are merged into `&&` and `||`
+
+use std::fmt;
+
+use crate::ir::{BinaryOp, Expression};
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// the comparisons a b-type instruction can make
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    GreaterEqual,
+    Greater,
+    LessEqual
+}
+
+impl Comparison {
+    /// the comparison that holds whenever this one doesn't
+    pub fn opposite(self) -> Self {
+        match self {
+            Comparison::Equal => Comparison::NotEqual,
+            Comparison::NotEqual => Comparison::Equal,
+            Comparison::Less => Comparison::GreaterEqual,
+            Comparison::GreaterEqual => Comparison::Less,
+            Comparison::Greater => Comparison::LessEqual,
+            Comparison::LessEqual => Comparison::Greater
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::GreaterEqual => ">=",
+            Comparison::Greater => ">",
+            Comparison::LessEqual => "<="
+        };
+
+        write!(f, "{}", symbol)
+    }
+}
+
+/// # a boolean expression
+/// leaves are comparisons between two operands, or variables that a condition was saved to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Constant(bool),
+    Compare { op: Comparison, lhs: Expression, rhs: Expression, unsigned: bool },
+    Variable(String),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>)
+}
+
+impl Condition {
+    /// the condition a lifted branch tests, if it's a comparison
+    pub fn from_expression(expr: &Expression) -> Option<Self> {
+        let Expression::Binary(op, lhs, rhs) = expr else {
+            return None;
+        };
+
+        let (op, unsigned) = match op {
+            BinaryOp::Equal => (Comparison::Equal, false),
+            BinaryOp::NotEqual => (Comparison::NotEqual, false),
+            BinaryOp::Less => (Comparison::Less, false),
+            BinaryOp::LessUnsigned => (Comparison::Less, true),
+            BinaryOp::GreaterEqual => (Comparison::GreaterEqual, false),
+            BinaryOp::GreaterEqualUnsigned => (Comparison::GreaterEqual, true),
+            _ => return None
+        };
+
+        Some(Condition::Compare { op, lhs: *lhs.clone(), rhs: *rhs.clone(), unsigned })
+    }
+
+    pub fn variable(name: &str) -> Self {
+        Condition::Variable(name.to_string())
+    }
+
+    /// # the opposite condition
+    /// comparisons flip, double negatives cancel, and de morgan's laws push it down through `&&` and `||`
+    pub fn negate(&self) -> Self {
+        match self {
+            Condition::Constant(value) => Condition::Constant(!value),
+            Condition::Compare { op, lhs, rhs, unsigned } => Condition::Compare {
+                op: op.opposite(),
+                lhs: lhs.clone(),
+                rhs: rhs.clone(),
+                unsigned: *unsigned
+            },
+            Condition::Variable(_) => Condition::Not(Box::new(self.clone())),
+            Condition::Not(inner) => *inner.clone(),
+            Condition::And(a, b) => Condition::or(a.negate(), b.negate()),
+            Condition::Or(a, b) => Condition::and(a.negate(), b.negate())
+        }
+    }
+
+    /// both conditions, dropping any that always hold
+    pub fn and(a: Condition, b: Condition) -> Self {
+        match (a, b) {
+            (Condition::Constant(true), other) | (other, Condition::Constant(true)) => other,
+            (Condition::Constant(false), _) | (_, Condition::Constant(false)) => Condition::Constant(false),
+            (a, b) => Condition::And(Box::new(a), Box::new(b))
+        }
+    }
+
+    /// either condition, dropping any that never hold
+    pub fn or(a: Condition, b: Condition) -> Self {
+        match (a, b) {
+            (Condition::Constant(false), other) | (other, Condition::Constant(false)) => other,
+            (Condition::Constant(true), _) | (_, Condition::Constant(true)) => Condition::Constant(true),
+            (a, b) => Condition::Or(Box::new(a), Box::new(b))
+        }
+    }
+
+    pub fn is_true(&self) -> bool {
+        *self == Condition::Constant(true)
+    }
+
+    /// whether this prints without needing brackets around it when it's inside something else
+    fn is_simple(&self) -> bool {
+        matches!(self, Condition::Constant(_) | Condition::Variable(_) | Condition::Not(_))
+    }
+}
+
+impl fmt::Display for Condition {
+    /// `&&` and `||` inside one another are always bracketed, so nobody has to remember which binds tighter
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bracketed = |inner: &Condition, outer_and: bool| match inner {
+            Condition::Or(..) if outer_and => format!("({})", inner),
+            Condition::And(..) if !outer_and => format!("({})", inner),
+            _ => inner.to_string()
+        };
+
+        match self {
+            Condition::Constant(value) => write!(f, "{}", value),
+            Condition::Compare { op, lhs, rhs, .. } => write!(f, "{} {} {}", lhs, op, rhs),
+            Condition::Variable(name) => write!(f, "{}", name),
+            Condition::Not(inner) if inner.is_simple() => write!(f, "!{}", inner),
+            Condition::Not(inner) => write!(f, "!({})", inner),
+            Condition::And(a, b) => write!(f, "{} && {}", bracketed(a, true), bracketed(b, true)),
+            Condition::Or(a, b) => write!(f, "{} || {}", bracketed(a, false), bracketed(b, false))
+        }
+    }
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::instructions::{ABIRegister, InstructionType};
+    use crate::ir::{lift, Statement};
+
+    /// the condition of a lifted branch
+    fn from_branch(inst: InstructionType) -> Option<Condition> {
+        match lift(0x100, &inst).pop()? {
+            Statement::Branch { condition: Some(condition), .. } => Condition::from_expression(&condition),
+            _ => None
+        }
+    }
+
+    fn compare(name: &'static str) -> Condition {
+        from_branch(InstructionType::B { name, rs1: ABIRegister::a0, rs2: ABIRegister::a1, imm: 8 }).unwrap()
+    }
+
+    #[test]
+    fn test_from_expression() {
+        assert_eq!(compare("blt").to_string(), "a0 < a1");
+        assert_eq!(compare("bgeu").to_string(), "a0 >= a1");
+        assert!(from_branch(InstructionType::J { name: "jal", rd: ABIRegister::zero, imm: 8 }).is_none());
+    }
+
+    #[test]
+    fn test_negate() {
+        // comparisons flip rather than getting a ! in front
+        assert_eq!(compare("beq").negate(), compare("bne"));
+        assert_eq!(compare("blt").negate().negate(), compare("blt"));
+
+        let both = Condition::and(compare("beq"), Condition::variable("cond_1"));
+        assert_eq!(both.negate().to_string(), "a0 != a1 || !cond_1");
+    }
+
+    #[test]
+    fn test_constants_fold() {
+        let cond = Condition::variable("cond_0");
+
+        assert_eq!(Condition::and(Condition::Constant(true), cond.clone()), cond);
+        assert!(Condition::or(cond.clone(), Condition::Constant(true)).is_true());
+    }
+
+    #[test]
+    fn test_display_brackets() {
+        let a = Condition::variable("a");
+        let b = Condition::variable("b");
+        let c = Condition::variable("c");
+
+        let cond = Condition::or(a.clone(), Condition::and(b.negate(), c.clone()));
+        assert_eq!(cond.to_string(), "a || (!b && c)");
+
+        let cond = Condition::Not(Box::new(Condition::and(a, b)));
+        assert_eq!(cond.to_string(), "!(a && b)");
+    }
+}
@@ -0,0 +1,438 @@
+//! # constant propagation
+//! sparse conditional constant propagation over the ssa form of a function, after wegman and zadeck,
+//! so a value is only constant if it's the same along every path that can actually be taken,
+//! and a branch on a constant only lets the way it goes count
+//!
+//! this is what resolves the `lui`/`auipc` and `addi` pairs building constants and addresses across blocks,
+//! and anything read relative to `gp`, whose value comes from the linker
+//! whatever ends up pointing at text is written out as the text, as is anything passed where a prototype says text goes,
+//! and whatever else points at something in the symbol table is given its name
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::dataflow::{instruction_facts, solve, Clobbered};
+use crate::decompilation::SectionMap;
+use crate::dominators::section_successors;
+use crate::image::Image;
+use crate::instructions::ABIRegister;
+use crate::ir::{extend, Expression, Statement, Type, Variable};
+use crate::signatures::SignatureMap;
+use crate::strings::string_at;
+use crate::ssa::SsaFunction;
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// # what's known about a value
+/// values only ever go down, from not known yet, to a single constant, to anything at all
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Value {
+    Unknown,
+    Constant(i64),
+    Varying
+}
+
+impl Value {
+    /// the value where two paths meet
+    fn meet(self, other: Value) -> Value {
+        match (self, other) {
+            (Value::Unknown, value) | (value, Value::Unknown) => value,
+            (Value::Constant(a), Value::Constant(b)) if a == b => Value::Constant(a),
+            _ => Value::Varying
+        }
+    }
+}
+
+/// the value of every version worked out so far
+struct Propagator<'a> {
+    image: &'a Image,
+    values: BTreeMap<Variable, Value>
+}
+
+impl Propagator<'_> {
+    /// # read a version
+    /// anything a call might have changed since can't be trusted, and everything the function is called with could be anything,
+    /// apart from `gp`, which is always the same
+    fn read(&self, variable: &Variable, clobbered: &BTreeSet<ABIRegister>) -> Value {
+        match variable {
+            Variable::Version(register, _) if clobbered.contains(register) => Value::Varying,
+            Variable::Version(ABIRegister::gp, 0) => self.image.get_global_pointer().map_or(Value::Varying, |gp| Value::Constant(gp as i64)),
+            Variable::Version(_, 0) => Value::Varying,
+            Variable::Version(..) => self.values.get(variable).copied().unwrap_or(Value::Unknown),
+            _ => Value::Varying
+        }
+    }
+
+    /// work out a value, as far as is known
+    /// nothing in memory is assumed to stay the same
+    fn evaluate(&self, expr: &Expression, clobbered: &BTreeSet<ABIRegister>) -> Value {
+        match expr {
+            Expression::Constant(c) => Value::Constant(*c),
+            Expression::Variable(variable) => self.read(variable, clobbered),
+            Expression::Binary(op, lhs, rhs) => match (self.evaluate(lhs, clobbered), self.evaluate(rhs, clobbered)) {
+                (Value::Constant(a), Value::Constant(b)) => Value::Constant(op.apply(a, b)),
+                (Value::Varying, _) | (_, Value::Varying) => Value::Varying,
+                _ => Value::Unknown
+            },
+            Expression::Extend { value, bits, signed } => match self.evaluate(value, clobbered) {
+                Value::Constant(c) => Value::Constant(extend(c, *bits, *signed)),
+                value => value
+            },
+            Expression::Load { .. } | Expression::Deref(_) | Expression::Field { .. } | Expression::Cast { .. } | Expression::Symbol { .. } | Expression::String { .. } => Value::Varying
+        }
+    }
+
+    /// move a version down to meet a new value, giving whether it moved
+    fn lower(&mut self, variable: &Variable, value: Value) -> bool {
+        let current = self.values.get(variable).copied().unwrap_or(Value::Unknown);
+        let lowered = current.meet(value);
+
+        self.values.insert(variable.clone(), lowered);
+        lowered != current
+    }
+
+    /// # put the constants into a statement
+    /// then take it back out of ssa form, which only needs the versions dropping as nothing has moved
+    /// a branch that always goes the same way keeps its condition, since the structuring still needs to know which way that is
+    fn resolve(&self, statement: &Statement, clobbered: &BTreeSet<ABIRegister>) -> Statement {
+        let mut resolved = statement.clone();
+
+        for expr in resolved.get_expressions_mut() {
+            let mut constants: Vec<(Variable, i64)> = Vec::new();
+            expr.for_each_variable(&mut |variable| {
+                if let Value::Constant(c) = self.read(variable, clobbered) {
+                    constants.push((variable.clone(), c));
+                }
+            });
+
+            for (variable, c) in constants {
+                *expr = expr.substitute(&variable, &Expression::Constant(c));
+            }
+        }
+
+        if let (Statement::Branch { condition: Some(condition), .. }, Statement::Branch { condition: Some(original), .. }) = (&mut resolved, statement) {
+            if matches!(condition, Expression::Constant(_)) {
+                *condition = original.clone();
+            }
+        }
+
+        let unversion = |variable: &mut Variable| {
+            if let Variable::Version(register, _) = variable {
+                *variable = Variable::Register(register.clone());
+            }
+        };
+        for expr in resolved.get_expressions_mut() {
+            expr.for_each_variable_mut(&mut |variable| unversion(variable));
+        }
+        if let Some(dst) = resolved.get_def_mut() {
+            unversion(dst);
+        }
+
+        resolved
+    }
+}
+
+// ----------------------------------------
+// propagation
+// ----------------------------------------
+
+/// # propagate constants through a function
+/// the values and the blocks that can be reached are worked out together, going round in reverse postorder until neither changes,
+/// then every version found to be constant is replaced by its value wherever it's read
+/// gives whether anything changed
+pub fn propagate_constants(sections: &mut SectionMap, image: &Image) -> bool {
+    if sections.is_empty() {
+        return false;
+    }
+
+    let ssa = SsaFunction::build(sections);
+    let successors = section_successors(sections);
+
+    // what a call might have changed, at each instruction and at the end of each block
+    let solution = solve(&Clobbered, sections);
+    let clobbered: BTreeMap<usize, BTreeMap<u64, BTreeSet<ABIRegister>>> = ssa.get_order().iter()
+        .map(|id| (*id, instruction_facts(&Clobbered, &sections[id], &solution)))
+        .collect();
+
+    let mut propagator = Propagator { image, values: BTreeMap::new() };
+    let mut executable: BTreeSet<(usize, usize)> = BTreeSet::new();
+    let mut reachable: BTreeSet<usize> = ssa.get_order().first().copied().into_iter().collect();
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for id in ssa.get_order().iter() {
+            if !reachable.contains(id) {
+                continue;
+            }
+
+            let block = &ssa.get_blocks()[id];
+
+            // a phi only takes from the edges that can be taken
+            for phi in block.get_phis() {
+                let value = phi.get_args().iter()
+                    .filter(|(pred, _)| executable.contains(&(**pred, *id)))
+                    .map(|(pred, arg)| propagator.read(arg, solution.get_out(*pred).unwrap()))
+                    .fold(Value::Unknown, Value::meet);
+
+                changed |= propagator.lower(phi.get_dst(), value);
+            }
+
+            let mut condition = None;
+            for (address, statements) in block.get_statements() {
+                let clobbered = &clobbered[id][address];
+
+                for statement in statements {
+                    match statement {
+                        Statement::Assign { dst, value } => {
+                            let value = propagator.evaluate(value, clobbered);
+                            changed |= propagator.lower(dst, value);
+                        },
+                        Statement::Call { dst: Some(dst), .. } | Statement::Intrinsic { dst: Some(dst), .. } => changed |= propagator.lower(dst, Value::Varying),
+                        Statement::Branch { condition: Some(tested), .. } => condition = Some(propagator.evaluate(tested, clobbered)),
+                        _ => {}
+                    }
+                }
+            }
+
+            // a branch on a constant only goes the one way, and a branch on something not known yet doesn't go anywhere yet
+            let section = &sections[id];
+            let targets: Vec<usize> = match condition {
+                Some(Value::Unknown) => Vec::new(),
+                Some(Value::Constant(c)) if c != 0 => section.get_taken().into_iter().collect(),
+                Some(Value::Constant(_)) => section.get_fallthrough().into_iter().collect(),
+                _ => successors.get(id).cloned().unwrap_or_default()
+            };
+
+            for target in targets {
+                changed |= executable.insert((*id, target));
+                reachable.insert(target);
+            }
+        }
+    }
+
+    // put the constants in, leaving anything that can't be reached alone
+    let mut resolved = false;
+
+    for id in reachable {
+        let section = sections.get_mut(&id).unwrap();
+
+        // the index of a jump table has to survive to where the table is used
+        if section.get_jump_table().is_some() {
+            continue;
+        }
+
+        let mut statements = section.get_statements().clone();
+        for (address, versioned) in ssa.get_blocks()[&id].get_statements() {
+            let replaced: Vec<Statement> = versioned.iter()
+                .map(|statement| propagator.resolve(statement, &clobbered[&id][address]))
+                .collect();
+
+            if replaced != statements[address] {
+                statements.insert(*address, replaced);
+                resolved = true;
+            }
+        }
+
+        section.set_statements(statements);
+    }
+
+    resolved
+}
+
+// ----------------------------------------
+// symbols
+// ----------------------------------------
+
+/// # write out text where its address is used
+/// a value that's the address of text in the data is that text, as long as it's used whole rather than as part of some arithmetic,
+/// and anything passed to a function declared as taking a pointer to `char` there is text, however short it is
+pub fn label_strings(sections: &mut SectionMap, image: &Image, signatures: &SignatureMap) {
+    let declared_text = [Type::Char, Type::Const(Box::new(Type::Char))].map(|ty| Type::Pointer(Box::new(ty)));
+
+    for section in sections.values_mut() {
+        let mut statements = section.get_statements().clone();
+
+        for statement in statements.values_mut().flatten() {
+            let declared = match statement {
+                Statement::Call { target: Expression::Symbol { name, offset: 0 }, .. } => signatures.values()
+                    .find(|signature| signature.get_name() == name)
+                    .and_then(|signature| signature.get_declared())
+                    .and_then(|prototype| prototype.parameters.clone())
+                    .unwrap_or_default(),
+                _ => Vec::new()
+            };
+
+            let values: Vec<&mut Expression> = match statement {
+                Statement::Assign { value, .. } | Statement::Store { value, .. } | Statement::Write { value, .. } => vec![value],
+                Statement::Call { args: Some(args), .. } | Statement::Intrinsic { args, .. } => args.iter_mut().collect(),
+                Statement::Return { values: Some(values) } => values.iter_mut().collect(),
+                _ => Vec::new()
+            };
+
+            for (index, value) in values.into_iter().enumerate() {
+                let Expression::Constant(address) = value else {
+                    continue;
+                };
+                let address = *address as u64;
+
+                if let Some(text) = string_at(image, address) {
+                    *value = text.to_expression();
+                } else if declared.get(index).is_some_and(|parameter| declared_text.contains(parameter.get_type())) {
+                    if let Some(text) = image.read_string(address) {
+                        *value = Expression::String { text: text.to_string(), wide: false };
+                    }
+                }
+            }
+        }
+
+        section.set_statements(statements);
+    }
+}
+
+/// # name the addresses the symbol table knows about
+/// every constant pointing into something named becomes a reference to it,
+/// apart from where branches go, which the structuring takes care of
+pub fn label_symbols(sections: &mut SectionMap, image: &Image) {
+    for section in sections.values_mut() {
+        let mut statements = section.get_statements().clone();
+
+        for statement in statements.values_mut().flatten() {
+            if matches!(statement, Statement::Branch { .. }) {
+                continue;
+            }
+
+            for expr in statement.get_expressions_mut() {
+                label(expr, image);
+            }
+        }
+
+        section.set_statements(statements);
+    }
+}
+
+fn label(expr: &mut Expression, image: &Image) {
+    match expr {
+        Expression::Constant(c) => {
+            if let Some(symbol) = image.symbol_containing(*c as u64) {
+                let offset = (*c as u64).wrapping_sub(symbol.get_address()) as i64;
+                *expr = Expression::Symbol { name: symbol.get_name().to_string(), offset };
+            }
+        },
+        Expression::Binary(_, lhs, rhs) => {
+            label(lhs, image);
+            label(rhs, image);
+        },
+        Expression::Load { addr, .. } | Expression::Deref(addr) | Expression::Field { base: addr, .. } => label(addr, image),
+        Expression::Extend { value, .. } | Expression::Cast { value, .. } => label(value, image),
+        Expression::Variable(_) | Expression::Symbol { .. } | Expression::String { .. } => {}
+    }
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{addi, ret, sections, statements};
+    use crate::instructions::InstructionType;
+    use crate::propagation::simplify;
+    use ABIRegister::*;
+
+    /// simplify some instructions with the constants propagated, giving whatever statements are left
+    fn simplified(instructions: Vec<InstructionType>, image: &Image) -> Vec<String> {
+        let mut sections = sections(instructions);
+        simplify(&mut sections, image);
+        label_strings(&mut sections, image, &SignatureMap::new());
+        label_symbols(&mut sections, image);
+
+        statements(&sections)
+    }
+
+    #[test]
+    fn test_across_blocks() {
+        // a5 is the same whichever way the branch goes, so the sum after is too
+        let output = simplified(vec![
+            InstructionType::U { name: "lui", rd: a5, imm: 0x12 },
+            InstructionType::B { name: "beq", rs1: a0, rs2: zero, imm: 0x8 },
+            addi(a1, a1, 1),
+            addi(a0, a5, 0x34),
+            ret()
+        ], &Image::new());
+
+        assert_eq!(output, ["if (a0 == 0) goto 0x10c", "a1 = a1 + 1", "a0 = 0x12034", "return"]);
+    }
+
+    #[test]
+    fn test_conditional() {
+        // a5 is never 0, so the second value of a0 can't reach the add, and a0 only has the one value there
+        let output = simplified(vec![
+            addi(a5, zero, 1),
+            addi(a0, zero, 7),
+            InstructionType::B { name: "bne", rs1: a5, rs2: zero, imm: 0x8 },
+            addi(a0, zero, 9),
+            InstructionType::R { name: "add", rd: a0, rs1: a0, rs2: a0 },
+            ret()
+        ], &Image::new());
+
+        assert_eq!(output.last().map(String::as_str), Some("return"));
+        assert!(output.contains(&"a0 = 14".to_string()));
+    }
+
+    #[test]
+    fn test_calls_clobber() {
+        // a0 could be anything once the call returns, but s1 is kept for us
+        let output = simplified(vec![
+            addi(a0, zero, 5),
+            addi(s1, zero, 6),
+            InstructionType::J { name: "jal", rd: ra, imm: 0x40 },
+            InstructionType::R { name: "add", rd: a0, rs1: a0, rs2: s1 },
+            ret()
+        ], &Image::new());
+
+        assert_eq!(output, ["a0 = 5", "s1 = 6", "sub_148()", "a0 = a0 + 6", "return"]);
+    }
+
+    #[test]
+    fn test_symbols_labelled() {
+        let mut image = Image::new();
+        image.add_section(".text", 0x100, vec![0; 0x40], true);
+        image.add_section(".sdata", 0x2000, vec![0; 0x1000], false);
+        image.add_symbol("counter", 0x2010, 4);
+        image.add_symbol("table", 0x2800, 0x40);
+        image.add_symbol("helper", 0x120, 0x10);
+        image.set_global_pointer(0x2800);
+
+        let output = simplified(vec![
+            // reached through gp, through an address built by auipc, and called
+            InstructionType::I { name: "lw", rd: a0, rs1: gp, imm: 8 },
+            InstructionType::U { name: "auipc", rd: t0, imm: 0x2 },
+            InstructionType::S { name: "sw", rs1: t0, rs2: a0, imm: -0xf4 },
+            InstructionType::J { name: "jal", rd: ra, imm: 0x14 },
+            ret()
+        ], &image);
+
+        assert_eq!(output, ["a0 = *(int32_t *)(&table + 8)", "*(int32_t *)&counter = a0", "helper()", "return"]);
+    }
+
+    #[test]
+    fn test_strings_labelled() {
+        let mut image = Image::new();
+        image.add_section(".text", 0x100, vec![0; 0x40], true);
+        image.add_section(".rodata", 0x2000, b"hello world\0".to_vec(), false);
+
+        let output = simplified(vec![
+            // stored whole it's the text, but loaded from it's still an address
+            InstructionType::U { name: "lui", rd: a0, imm: 0x2 },
+            InstructionType::S { name: "sd", rs1: sp, rs2: a0, imm: 0 },
+            InstructionType::I { name: "lbu", rd: a1, rs1: a0, imm: 6 },
+            ret()
+        ], &image);
+
+        assert_eq!(output, ["a0 = \"hello world\"", "*(int64_t *)sp = \"hello world\"", "a1 = *(uint8_t *)0x2006", "return"]);
+    }
+}
@@ -0,0 +1,497 @@
+//! # data-flow analysis
+//! a generic worklist solver over the control-flow graph, for any analysis going forwards or backwards
+//! whose facts form a lattice, with the in and out facts of every block as the result
+//!
+//! liveness and reaching definitions are the first analyses built on it,
+//! and def-use chains are read straight off reaching definitions
+//! the registers a call might have changed are worked out the same way, for anything that goes by ssa versions
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::decompilation::{InstructionSection, SectionMap};
+use crate::dominators::{invert, reverse_postorder, section_successors};
+use crate::instructions::ABIRegister;
+use crate::ir::{Expression, Statement, Variable};
+use crate::syscalls::{Syscall, PASSED};
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// which way facts flow through the graph
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Backward
+}
+
+/// # the values an analysis works out
+/// facts only ever go up the lattice, which is what makes the solver stop
+pub trait Lattice: Clone + PartialEq {
+    /// the fact for somewhere nothing has reached yet, which joining leaves unchanged
+    fn bottom() -> Self;
+
+    /// combine the facts where paths meet, giving whether this changed
+    fn join(&mut self, other: &Self) -> bool;
+}
+
+/// sets join by union, which covers most analyses of the "may" kind
+impl<T: Ord + Clone> Lattice for BTreeSet<T> {
+    fn bottom() -> Self {
+        BTreeSet::new()
+    }
+
+    fn join(&mut self, other: &Self) -> bool {
+        let before = self.len();
+        self.extend(other.iter().cloned());
+        self.len() != before
+    }
+}
+
+/// # a data-flow analysis
+/// described by how one instruction changes a fact, with the solver handling the rest
+pub trait Analysis {
+    type Fact: Lattice;
+
+    fn direction(&self) -> Direction;
+
+    /// the fact at the entry going forwards, or at the end of an exit going backwards
+    fn boundary(&self, _section: &InstructionSection) -> Self::Fact {
+        Self::Fact::bottom()
+    }
+
+    /// apply the statements an instruction lifted to, in the direction of the analysis
+    fn transfer(&self, address: u64, statements: &[Statement], fact: &mut Self::Fact);
+}
+
+/// # the solved facts
+/// `in` is always at the start of a block and `out` at its end, whichever way the analysis goes
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solution<F> {
+    ins: BTreeMap<usize, F>,
+    outs: BTreeMap<usize, F>
+}
+
+impl<F: Lattice> Solution<F> {
+    pub fn get_in(&self, block: usize) -> Option<&F> {
+        self.ins.get(&block)
+    }
+
+    pub fn get_out(&self, block: usize) -> Option<&F> {
+        self.outs.get(&block)
+    }
+}
+
+/// # solve an analysis
+/// blocks are visited in reverse postorder (or the reverse of it going backwards), and revisited whenever what flows into them changes
+pub fn solve<A: Analysis>(analysis: &A, sections: &SectionMap) -> Solution<A::Fact> {
+    let successors = section_successors(sections);
+    let predecessors = invert(&successors);
+    let root = sections.keys().next().copied().unwrap_or(0);
+
+    // anything unreachable still gets solved, it just goes last
+    let mut order = reverse_postorder(root, &successors);
+    order.extend(sections.keys().filter(|id| !order.contains(id)).collect::<Vec<_>>());
+    if analysis.direction() == Direction::Backward {
+        order.reverse();
+    }
+
+    // where facts come from, and where they go next
+    let (sources, targets) = match analysis.direction() {
+        Direction::Forward => (&predecessors, &successors),
+        Direction::Backward => (&successors, &predecessors)
+    };
+
+    let mut before: BTreeMap<usize, A::Fact> = sections.keys().map(|id| (*id, A::Fact::bottom())).collect();
+    let mut after = before.clone();
+
+    let mut worklist: VecDeque<usize> = order.iter().copied().collect();
+    let mut queued: BTreeSet<usize> = order.iter().copied().collect();
+
+    while let Some(id) = worklist.pop_front() {
+        queued.remove(&id);
+
+        // the root going forwards, and any exit going backwards, starts from the boundary
+        let incoming: Vec<usize> = sources.get(&id).into_iter().flatten().filter(|s| sections.contains_key(s)).copied().collect();
+        let mut fact = if (analysis.direction() == Direction::Forward && id == root) || (analysis.direction() == Direction::Backward && incoming.is_empty()) {
+            analysis.boundary(&sections[&id])
+        } else {
+            A::Fact::bottom()
+        };
+        for source in incoming {
+            fact.join(&after[&source]);
+        }
+
+        before.insert(id, fact.clone());
+        transfer_block(analysis, &sections[&id], &mut fact);
+
+        if fact != after[&id] {
+            after.insert(id, fact);
+
+            for target in targets.get(&id).into_iter().flatten() {
+                if sections.contains_key(target) && queued.insert(*target) {
+                    worklist.push_back(*target);
+                }
+            }
+        }
+    }
+
+    match analysis.direction() {
+        Direction::Forward => Solution { ins: before, outs: after },
+        Direction::Backward => Solution { ins: after, outs: before }
+    }
+}
+
+/// run a fact through every instruction of a block, in the direction of the analysis
+fn transfer_block<A: Analysis>(analysis: &A, section: &InstructionSection, fact: &mut A::Fact) {
+    let statements = section.get_statements();
+
+    match analysis.direction() {
+        Direction::Forward => statements.iter().for_each(|(address, s)| analysis.transfer(*address, s, fact)),
+        Direction::Backward => statements.iter().rev().for_each(|(address, s)| analysis.transfer(*address, s, fact))
+    }
+}
+
+/// # the fact at each instruction of a block
+/// this is the fact flowing into the instruction in the direction of the analysis,
+/// so what reaches it going forwards, and what's needed after it going backwards
+pub fn instruction_facts<A: Analysis>(analysis: &A, section: &InstructionSection, solution: &Solution<A::Fact>) -> BTreeMap<u64, A::Fact> {
+    let mut facts = BTreeMap::new();
+    let statements = section.get_statements();
+
+    let (mut fact, order): (A::Fact, Vec<(&u64, &Vec<Statement>)>) = match analysis.direction() {
+        Direction::Forward => (solution.get_in(section.get_id()).cloned().unwrap_or(A::Fact::bottom()), statements.iter().collect()),
+        Direction::Backward => (solution.get_out(section.get_id()).cloned().unwrap_or(A::Fact::bottom()), statements.iter().rev().collect())
+    };
+
+    for (address, statements) in order {
+        facts.insert(*address, fact.clone());
+        analysis.transfer(*address, statements, &mut fact);
+    }
+
+    facts
+}
+
+// ----------------------------------------
+// what leaves the function
+// ----------------------------------------
+
+/// the registers arguments are passed in
+pub const ARGUMENTS: [ABIRegister; 8] = [
+    ABIRegister::a0, ABIRegister::a1, ABIRegister::a2, ABIRegister::a3,
+    ABIRegister::a4, ABIRegister::a5, ABIRegister::a6, ABIRegister::a7
+];
+
+/// the registers a caller can rely on being the same after a call
+pub const PRESERVED: [ABIRegister; 16] = [
+    ABIRegister::ra, ABIRegister::sp, ABIRegister::gp, ABIRegister::tp,
+    ABIRegister::s0, ABIRegister::s1, ABIRegister::s2, ABIRegister::s3, ABIRegister::s4, ABIRegister::s5,
+    ABIRegister::s6, ABIRegister::s7, ABIRegister::s8, ABIRegister::s9, ABIRegister::s10, ABIRegister::s11
+];
+
+/// # what the rest of the program might read once the function has left
+/// a return leaves the return values and everything callee-saved, along with `ra` to get back
+/// leaving the program leaves nothing to read it
+/// anything else might be a tail call, so without knowing the signature that's every register apart from the temporaries
+fn live_at_exit(section: &InstructionSection) -> BTreeSet<Variable> {
+    let last = section.get_statements().values().flatten().last();
+
+    if let Some(Statement::Call { target: Expression::Symbol { name, offset: 0 }, dst: None, .. }) = last {
+        if Syscall::named(name).is_some_and(|syscall| syscall.leaves()) {
+            return BTreeSet::new();
+        }
+    }
+
+    // once it's known what's returned, the return reads it itself
+    if matches!(last, Some(Statement::Return { values: Some(_) })) {
+        PRESERVED.into_iter().map(Variable::Register).collect()
+    } else if matches!(last, Some(Statement::Return { .. })) {
+        [ABIRegister::a0, ABIRegister::a1].into_iter()
+            .chain(PRESERVED)
+            .map(Variable::Register)
+            .collect()
+    } else {
+        (1..32_u8)
+            .map(ABIRegister::from)
+            .filter(|r| !matches!(r, ABIRegister::t0 | ABIRegister::t1 | ABIRegister::t2 | ABIRegister::t3 | ABIRegister::t4 | ABIRegister::t5 | ABIRegister::t6))
+            .map(Variable::Register)
+            .collect()
+    }
+}
+
+/// # everything a statement reads
+/// calls to unknown functions might read any of the argument registers, and system calls any of theirs, which the statement itself doesn't say
+pub fn reads(statement: &Statement) -> Vec<Variable> {
+    let mut uses = statement.get_uses();
+
+    match statement {
+        Statement::Intrinsic { name: "ecall", .. } => uses.extend(PASSED.iter().cloned().map(Variable::Register)),
+        Statement::Call { args: None, .. } | Statement::Intrinsic { .. } => uses.extend(ARGUMENTS.iter().cloned().map(Variable::Register)),
+        _ => {}
+    }
+
+    uses
+}
+
+// ----------------------------------------
+// analyses
+// ----------------------------------------
+
+/// # liveness
+/// the variables that might still be read before they're next written
+pub struct Liveness;
+
+impl Analysis for Liveness {
+    type Fact = BTreeSet<Variable>;
+
+    fn direction(&self) -> Direction {
+        Direction::Backward
+    }
+
+    fn boundary(&self, section: &InstructionSection) -> Self::Fact {
+        live_at_exit(section)
+    }
+
+    fn transfer(&self, _address: u64, statements: &[Statement], fact: &mut Self::Fact) {
+        for statement in statements.iter().rev() {
+            if let Some(dst) = statement.get_def() {
+                fact.remove(dst);
+            }
+            fact.extend(reads(statement));
+        }
+    }
+}
+
+/// # clobbered registers
+/// the registers a call or system call might have changed since they were last written
+/// ssa versions don't change across a call, so anything going by them has to check this before trusting one
+pub struct Clobbered;
+
+impl Analysis for Clobbered {
+    type Fact = BTreeSet<ABIRegister>;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn transfer(&self, _address: u64, statements: &[Statement], fact: &mut Self::Fact) {
+        for statement in statements {
+            if matches!(statement, Statement::Call { .. } | Statement::Intrinsic { .. }) {
+                fact.extend((1..32_u8).map(ABIRegister::from).filter(|r| !PRESERVED.contains(r)));
+            }
+            if let Some(register) = statement.get_def().and_then(|dst| dst.get_register()) {
+                fact.remove(register);
+            }
+        }
+    }
+}
+
+/// # one place a variable is written
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Definition {
+    address: u64,
+    variable: Variable
+}
+
+impl Definition {
+    pub fn new(address: u64, variable: Variable) -> Self {
+        Definition { address, variable }
+    }
+
+    pub fn get_address(&self) -> u64 {
+        self.address
+    }
+
+    pub fn get_variable(&self) -> &Variable {
+        &self.variable
+    }
+}
+
+/// # reaching definitions
+/// the definitions that might still hold when a point is reached
+/// anything read with no definition reaching it is whatever it held when the function was called
+pub struct ReachingDefinitions;
+
+impl Analysis for ReachingDefinitions {
+    type Fact = BTreeSet<Definition>;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn transfer(&self, address: u64, statements: &[Statement], fact: &mut Self::Fact) {
+        for dst in statements.iter().filter_map(|s| s.get_def()) {
+            fact.retain(|definition| definition.variable != *dst);
+            fact.insert(Definition::new(address, dst.clone()));
+        }
+    }
+}
+
+/// # def-use chains
+/// for each definition, the instructions that might read it, and the other way around
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DefUse {
+    uses: BTreeMap<Definition, BTreeSet<u64>>,
+    definitions: BTreeMap<(u64, Variable), BTreeSet<Definition>>
+}
+
+impl DefUse {
+    pub fn new(sections: &SectionMap) -> Self {
+        let solution = solve(&ReachingDefinitions, sections);
+        let mut chains = DefUse::default();
+
+        for section in sections.values() {
+            let reaching = instruction_facts(&ReachingDefinitions, section, &solution);
+
+            for (address, statements) in section.get_statements() {
+                // the definitions an instruction sees are the ones from before it, even if it writes what it reads
+                let read: BTreeSet<Variable> = statements.iter().flat_map(reads).collect();
+
+                for variable in read {
+                    let defs: BTreeSet<Definition> = reaching[address].iter()
+                        .filter(|definition| definition.variable == variable)
+                        .cloned()
+                        .collect();
+
+                    for definition in defs.iter() {
+                        chains.uses.entry(definition.clone()).or_default().insert(*address);
+                    }
+                    chains.definitions.insert((*address, variable), defs);
+                }
+
+                // definitions nothing reads still get an entry
+                for dst in statements.iter().filter_map(|s| s.get_def()) {
+                    chains.uses.entry(Definition::new(*address, dst.clone())).or_default();
+                }
+            }
+        }
+
+        chains
+    }
+
+    /// the instructions that might read a definition
+    pub fn get_uses(&self, definition: &Definition) -> BTreeSet<u64> {
+        self.uses.get(definition).cloned().unwrap_or_default()
+    }
+
+    /// the definitions an instruction might be reading when it reads a variable
+    pub fn get_definitions(&self, address: u64, variable: &Variable) -> BTreeSet<Definition> {
+        self.definitions.get(&(address, variable.clone())).cloned().unwrap_or_default()
+    }
+
+    /// the definition made by an instruction, if it makes one
+    pub fn definition_at(&self, address: u64) -> Option<&Definition> {
+        self.uses.keys().find(|definition| definition.address == address)
+    }
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{addi, ecall, register, sections};
+    use crate::instructions::InstructionType;
+    use ABIRegister::*;
+
+    /// count a5 down to 0, with t0 only used inside the loop
+    fn counting_loop() -> SectionMap {
+        sections(vec![
+            addi(a5, zero, 10),                                                 // section 0
+            addi(t0, a5, 1),                                                    // section 1
+            addi(a5, a5, -1),
+            InstructionType::B { name: "bne", rs1: a5, rs2: zero, imm: -0x8 },
+            InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 }      // section 2
+        ])
+    }
+
+    #[test]
+    fn test_liveness() {
+        let cfg = counting_loop();
+        let solution = solve(&Liveness, &cfg);
+
+        // a5 is live round the loop, and t0 is dead as soon as it's written
+        assert!(solution.get_in(1).unwrap().contains(&register(a5)));
+        assert!(!solution.get_out(0).unwrap().contains(&register(t0)));
+
+        let facts = instruction_facts(&Liveness, &cfg[&1], &solution);
+        assert!(!facts[&0x104].contains(&register(t0)));
+        assert!(facts[&0x108].contains(&register(a5)));
+
+        // a5 is written before the loop, so it isn't live going into the function
+        assert!(!solution.get_in(0).unwrap().contains(&register(a5)));
+    }
+
+    #[test]
+    fn test_reaching_definitions() {
+        let cfg = counting_loop();
+        let solution = solve(&ReachingDefinitions, &cfg);
+
+        // both definitions of a5 reach the top of the loop, but only the decrement leaves it
+        let header: BTreeSet<u64> = solution.get_in(1).unwrap().iter().filter(|d| d.variable == register(a5)).map(|d| d.address).collect();
+        assert_eq!(header, BTreeSet::from([0x100, 0x108]));
+
+        let exit: BTreeSet<u64> = solution.get_in(2).unwrap().iter().filter(|d| d.variable == register(a5)).map(|d| d.address).collect();
+        assert_eq!(exit, BTreeSet::from([0x108]));
+    }
+
+    #[test]
+    fn test_def_use_chains() {
+        let cfg = counting_loop();
+        let chains = DefUse::new(&cfg);
+
+        // the first a5 is read by the t0 and the decrement, and the decrement by itself on the way back round too
+        assert_eq!(chains.get_uses(&Definition::new(0x100, register(a5))), BTreeSet::from([0x104, 0x108]));
+        assert_eq!(chains.get_uses(&Definition::new(0x108, register(a5))), BTreeSet::from([0x104, 0x108, 0x10c]));
+        assert!(chains.get_uses(&Definition::new(0x104, register(t0))).is_empty());
+
+        assert_eq!(chains.get_definitions(0x10c, &register(a5)).len(), 1);
+        assert_eq!(chains.definition_at(0x104), Some(&Definition::new(0x104, register(t0))));
+    }
+
+    #[test]
+    fn test_calls_read_arguments() {
+        let cfg = sections(vec![
+            addi(a0, zero, 1),
+            addi(t1, zero, 2),
+            InstructionType::J { name: "jal", rd: ra, imm: 0x100 },
+            InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 }
+        ]);
+        let chains = DefUse::new(&cfg);
+
+        assert_eq!(chains.get_uses(&Definition::new(0x100, register(a0))), BTreeSet::from([0x108]));
+        assert!(chains.get_uses(&Definition::new(0x104, register(t1))).is_empty());
+
+        // a system call only reads its number and the six registers it's passed
+        let cfg = sections(vec![
+            addi(a7, zero, 64),
+            addi(a6, zero, 2),
+            ecall(),
+            InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 }
+        ]);
+        let chains = DefUse::new(&cfg);
+
+        assert_eq!(chains.get_uses(&Definition::new(0x100, register(a7))), BTreeSet::from([0x108]));
+        assert!(chains.get_uses(&Definition::new(0x104, register(a6))).is_empty());
+    }
+
+    #[test]
+    fn test_clobbered() {
+        let cfg = sections(vec![
+            addi(a0, zero, 1),
+            addi(s1, zero, 2),
+            InstructionType::J { name: "jal", rd: ra, imm: 0x100 },
+            addi(a0, a0, 3),
+            InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 }
+        ]);
+        let solution = solve(&Clobbered, &cfg);
+
+        // nothing's been called yet, then a0 isn't to be trusted until it's written again, and s1 always is
+        assert!(instruction_facts(&Clobbered, &cfg[&0], &solution)[&0x108].is_empty());
+        assert!(solution.get_in(1).unwrap().contains(&a0));
+        assert!(!solution.get_in(1).unwrap().contains(&s1));
+        assert!(!solution.get_out(1).unwrap().contains(&a0));
+    }
+}
@@ -1,24 +1,48 @@
 use core::fmt;
 use std::sync::Arc;
-use std::collections::{BTreeMap, VecDeque, HashSet};
+use std::collections::{BTreeMap, BTreeSet, VecDeque, HashSet};
 
 use log::{info, log_enabled, Level};
 
-use crate::instructions::InstructionType;
+use crate::ast::{Declaration, Expr, Item, Prototype, Stmt};
+use crate::conditions::Condition;
+use crate::constants::{label_strings, label_symbols};
+use crate::dominators::{DominatorTree, Successors, VIRTUAL_EXIT};
+use crate::headers::libc;
+use crate::image::Image;
+use crate::instructions::{ABIRegister, InstructionType};
+use crate::ir::{lift, Expression, Statement, Type, Variable};
+use crate::jumptable::{JumpTable, JumpTableMap};
+use crate::loops::{LoopForest, LoopKind};
+use crate::propagation::simplify;
+use crate::signatures::{resolve_calls, Signature, SignatureMap};
+use crate::stack::{recover_stack_frame, slot_size};
+use crate::syscalls::{name_syscalls, pass_syscall_arguments, resolve_syscalls, Syscall};
+use crate::ssa::recover_variables;
+use crate::types::{FieldNames, Types};
 
 // ----------------------------------------
 // structures and methods
 // ----------------------------------------
 
 #[derive(Clone, Debug, PartialEq)]
-enum BranchType { Conditional, Unconditional }
+enum BranchType {
+    Conditional,
+    Unconditional,
+    Indirect,       // a `jr` through a register, which has a jump table if we could resolve it
+    Return          // a `ret`, which leaves the function
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct InstructionSection {
     id: usize,
     instructions: BTreeMap<u64, InstructionType>,
     branches: Vec<Arc<InstructionSection>>,
+    taken: Option<usize>,                           // which of the branches a conditional one goes to when its condition holds
     branch_type: Option<BranchType>,
+    jump_table: Option<JumpTable>,                  // the cases of an indirect jump, if it was resolved
+    statements: BTreeMap<u64, Vec<Statement>>,      // what each instruction does, in terms of variables once they've been recovered
+    switch_on: Option<Variable>,                    // the variable a jump table selects on, once it's been recovered
     start: u64,                                     // lower bound of block addresses
     end: u64                                        // upper bound for block addresses
 }
@@ -29,7 +53,11 @@ impl InstructionSection {
             id,      
             instructions: BTreeMap::new(),
             branches: Vec::new(),
+            taken: None,
             branch_type: None,
+            jump_table: None,
+            statements: BTreeMap::new(),
+            switch_on: None,
             start: 0,
             end: 0
         }
@@ -60,6 +88,56 @@ impl InstructionSection {
         &self.branches
     }
 
+    /// the section a conditional branch goes to when its condition holds, if that's inside the function
+    pub fn get_taken(&self) -> Option<usize> {
+        self.taken
+    }
+
+    /// the section a conditional branch goes to otherwise, the one straight after it
+    pub fn get_fallthrough(&self) -> Option<usize> {
+        if self.branch_type != Some(BranchType::Conditional) {
+            return None;
+        }
+
+        // a branch to the very next instruction has the same section both ways
+        let others: Vec<usize> = self.branches.iter()
+            .map(|b| b.get_id())
+            .filter(|id| Some(*id) != self.taken)
+            .collect();
+
+        others.first().copied().or(self.taken.filter(|_| self.branches.len() > 1))
+    }
+
+    pub fn get_jump_table(&self) -> Option<&JumpTable> {
+        self.jump_table.as_ref()
+    }
+
+    pub fn get_statements(&self) -> &BTreeMap<u64, Vec<Statement>> {
+        &self.statements
+    }
+
+    pub fn set_statements(&mut self, statements: BTreeMap<u64, Vec<Statement>>) {
+        self.statements = statements;
+    }
+
+    /// what a switch on this section's jump table selects on
+    pub fn get_switch_on(&self) -> Option<Variable> {
+        self.switch_on.clone()
+            .or_else(|| self.jump_table.as_ref().map(|table| Variable::Register(table.get_index())))
+    }
+
+    pub fn set_switch_on(&mut self, variable: Variable) {
+        self.switch_on = Some(variable);
+    }
+
+    /// the condition a conditional branch at the end of this section tests
+    fn get_condition(&self) -> Option<Condition> {
+        match self.statements.values().last()?.last()? {
+            Statement::Branch { condition: Some(condition), .. } => Condition::from_expression(condition),
+            _ => None
+        }
+    }
+
     /// extend range covered by codeblock
     fn add_to_range(&mut self, address: u64) {
         if self.start == 0 {
@@ -84,11 +162,26 @@ impl fmt::Display for InstructionSection {
             block_str.push_str(&format!("{:>#8x}: {}\n", address, instruction));
         }
 
-        if !self.branches.is_empty() {
+        if let Some(table) = &self.jump_table {
+            for (case, target) in table.get_targets().iter().enumerate() {
+                if let Some(branch) = self.branches.iter().find(|b| b.start == *target) {
+                    block_str.push_str(&format!("\tcase {}: jump to section {}\n", case, branch.get_id()));
+                }
+            }
+        } else if self.branch_type == Some(BranchType::Return) {
+            block_str.push_str("\treturn\n");
+        } else if self.branch_type == Some(BranchType::Indirect) {
+            block_str.push_str("\tunresolved indirect jump\n");
+        } else if !self.branches.is_empty() {
             let branches = self.get_branches();
             if self.branch_type == Some(BranchType::Conditional) {
-                block_str.push_str(&format!("\ttrue: jump to section {}\n", branches.first().unwrap().get_id()));
-                block_str.push_str(&format!("\tfalse: jump to section {}\n", branches.get(1).unwrap().get_id()));
+                let label = |section: Option<usize>| match section {
+                    Some(id) => format!("section {}", id),
+                    None => "outside the function".to_string()
+                };
+
+                block_str.push_str(&format!("\ttrue: jump to {}\n", label(self.get_taken())));
+                block_str.push_str(&format!("\tfalse: jump to {}\n", label(self.get_fallthrough())));
             } else {
                 block_str.push_str(&format!("\tjump to section {}\n", branches.first().unwrap().get_id()));
             }
@@ -105,7 +198,6 @@ pub type SectionMap = BTreeMap<usize, InstructionSection>;
 /// map of abstract sections
 pub type AbstractMap = BTreeMap<usize, AbstractSection>;
 
-// TODO: find a way to nest them
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AbstractSectionType {
     Unbranching,    // a sequence of instructions with no logical branches
@@ -116,14 +208,20 @@ pub enum AbstractSectionType {
     DoWhile,        // a do-while loop
     Break,          // a section that breaks from a while loop
     Continue,       // a section that continues to the next part of a while loop
-    Acyclic         // an acyclic single-entry, single-exit complex section
+    Acyclic,        // an acyclic single-entry, single-exit complex section
+    Switch          // a jump table, with one nested section per distinct case
 }
 
 #[derive(Clone, Debug)]
 pub struct AbstractSection {
     section_type: AbstractSectionType,
     concrete_section: usize,                                // index of the concrete instruction section that this represents
-    abstract_sections: Vec<AbstractSection>                 // for nesting purposes
+    vertex: usize,                                          // the vertex this was in the graph, only different from the above for copies
+    abstract_sections: Vec<AbstractSection>,                // for nesting purposes
+    sequence_start: usize,                                  // nested sections from here on come after the construct, rather than being part of it
+    target: Option<usize>,                                  // where a break, continue, or while loop leaves for
+    region_edges: Vec<(usize, usize)>,                      // edges between the nested sections of an acyclic region, or switch cases falling through
+    branch: Option<(Condition, usize)>                      // what the branch at the end tests, and the vertex it goes to when that holds
 }
 
 impl AbstractSection {
@@ -131,12 +229,22 @@ impl AbstractSection {
         AbstractSection {
             section_type,
             concrete_section: idx,                        
-            abstract_sections: Vec::new()
+            vertex: idx,
+            abstract_sections: Vec::new(),
+            sequence_start: 0,
+            target: None,
+            region_edges: Vec::new(),
+            branch: None
         }
     }
 
-    fn add_section(&mut self, section: usize) {
-        self.concrete_section = section;
+    /// wrap a section in a construct, e.g. a loop around its header
+    fn wrap(section_type: AbstractSectionType, inner: AbstractSection) -> Self {
+        let mut wrapper = AbstractSection::new(section_type, inner.get_id());
+        wrapper.vertex = inner.vertex;
+        wrapper.abstract_sections.push(inner);
+        wrapper.sequence_start = 1;
+        wrapper
     }
 
     fn nest_section(&mut self, section: AbstractSection) {
@@ -147,6 +255,37 @@ impl AbstractSection {
         self.abstract_sections.clone()
     }
 
+    /// the nested sections that make up the construct itself
+    fn get_construct(&self) -> &[AbstractSection] {
+        &self.abstract_sections[..self.sequence_start]
+    }
+
+    /// the nested sections that follow the construct
+    fn get_sequence(&self) -> &[AbstractSection] {
+        &self.abstract_sections[self.sequence_start..]
+    }
+
+    /// a single block, that hasn't had anything reduced into it
+    fn is_plain(&self) -> bool {
+        self.section_type == AbstractSectionType::Unbranching && self.abstract_sections.is_empty()
+    }
+
+    /// the last section in the sequence, which is where any branch out of this one is
+    fn tail(&self) -> &AbstractSection {
+        match self.get_sequence().last() {
+            Some(last) => last.tail(),
+            None => self
+        }
+    }
+
+    fn tail_mut(&mut self) -> &mut AbstractSection {
+        if self.sequence_start < self.abstract_sections.len() {
+            self.abstract_sections.last_mut().unwrap().tail_mut()
+        } else {
+            self
+        }
+    }
+
     fn get_type(&self) -> AbstractSectionType {
         self.section_type
     }
@@ -156,6 +295,11 @@ impl AbstractSection {
         self.concrete_section
     }
 
+    /// get the vertex this section was in the graph
+    fn get_vertex(&self) -> usize {
+        self.vertex
+    }
+
     /// set the type of the section
     /// this is for printing purposes for the high-level pseudocode
     /// using Unbranching as the default
@@ -171,22 +315,36 @@ impl AbstractSection {
 
 /// # Graph of all AbstractSections
 /// simply a map of the sections corresponding to vertices, and a list of the edges
+/// vertices are keyed by their concrete section, apart from copies made when splitting a node, which get new keys
 pub struct AbstractGraph {
     vertices: AbstractMap,
-    edges: Vec<(usize, usize)>
+    edges: Vec<(usize, usize)>,
+    switches: BTreeSet<usize>,      // sections that end in a resolved jump table
+    endless: BTreeSet<usize>,       // loop headers that don't test anything, the edge out of them stands in for the breaks
+    tests_only: BTreeSet<usize>,    // sections with nothing in them but a conditional branch
+    copies: BTreeMap<usize, usize>, // vertices made by splitting, and the concrete section they are a copy of
+    next_vertex: usize,             // key for the next copy, past anything that has ever been in the graph
+    splits_left: usize,             // how many more copies can be made, so that splitting can't go on forever
+    breaks: bool                    // whether exits from loops can be taken out as breaks and continues
 }
 
 enum Direction {
     Incoming,
-    Outgoing,
-    All
+    Outgoing
 }
 
 impl AbstractGraph {
     fn new() -> Self {
         AbstractGraph {
             vertices: BTreeMap::new(),
-            edges: Vec::new()
+            edges: Vec::new(),
+            switches: BTreeSet::new(),
+            endless: BTreeSet::new(),
+            tests_only: BTreeSet::new(),
+            copies: BTreeMap::new(),
+            next_vertex: 0,
+            splits_left: 0,
+            breaks: true
         }
     }
 
@@ -221,13 +379,6 @@ impl AbstractGraph {
                     .filter(|&(_, (src, _))| *src == index )
                     .map(|(edge_index, _)| edge_index)
                     .collect()
-            },
-            Direction::All => {
-                self.edges.iter()
-                    .enumerate()
-                    .filter(|&(_, (src, dst))| *src == index || *dst == index )
-                    .map(|(edge_index, _)| edge_index)
-                    .collect()
             }
         }
     }
@@ -241,17 +392,72 @@ impl AbstractGraph {
         self.edges.contains(&(src, dst))
     }
 
+    /// get the destinations of every outgoing edge from a node, without repeats
+    fn get_children(&self, index: usize) -> Vec<usize> {
+        let mut children = Vec::new();
+        for edge in self.get_edges(index, Direction::Outgoing) {
+            let (_, dst) = self.edges[edge];
+            if !children.contains(&dst) {
+                children.push(dst);
+            }
+        }
+        children
+    }
+
+    /// whether a section ends in a two-way branch that a construct can be built around
+    /// jump tables and loop headers with nothing to test don't count
+    fn is_conditional(&self, index: usize) -> bool {
+        !self.switches.contains(&index) && !self.endless.contains(&index) &&
+        self.vertices.get(&index).is_some_and(|v| v.tail().is_plain())
+    }
+
+    /// the concrete section a vertex stands for
+    fn concrete(&self, index: usize) -> usize {
+        self.copies.get(&index).copied().unwrap_or(index)
+    }
+
+    /// the first vertex is always the entry, as nothing ever gets reduced into a section it dominates
+    fn get_root(&self) -> usize {
+        self.vertices.keys().next().copied().unwrap_or(0)
+    }
+
+    /// the remaining graph as a map of successors, for the dominator analyses
+    fn successors(&self) -> Successors {
+        self.vertices.keys()
+            .map(|v| (*v, self.get_children(*v)))
+            .collect()
+    }
+
+    /// dominator tree of the graph as it currently stands
+    fn dominators(&self) -> DominatorTree {
+        DominatorTree::new(self.get_root(), &self.successors())
+    }
+
+    /// post-dominator tree of the graph as it currently stands
+    fn post_dominators(&self) -> DominatorTree {
+        DominatorTree::post_dominators(&self.successors())
+    }
+
+    /// loops left in the graph as it currently stands
+    fn loops(&self) -> LoopForest {
+        LoopForest::new(self.get_root(), &self.successors())
+    }
+
     /// # delete an existing node
     /// add the current abstract section to its parent as a nested subsection
     /// if the node has a child, redirect any incoming edges to the child
     /// else, delete those edges
+    ///
+    /// where it goes depends on the type of construct
+    /// - sequential sections go on the end of the parent
+    /// - loops wrap the parent, as the whole thing runs on every iteration
+    /// - anything else goes on the last section of the parent, as that's where the branch is
     fn reduce_node(&mut self, id: usize, parent_id: usize, section_type: AbstractSectionType) {
-        // if we're changing to a do-while loop, no need to do any of this
-        // just change the type and remove that edge
+        // if we're changing to a single-block loop, no need to do any of this
+        // just wrap it and remove that edge
         if id == parent_id {
-            let block = self.vertices.get_mut(&id).unwrap();
-
-            block.set_type(AbstractSectionType::DoWhile);
+            let block = self.vertices.remove(&id).unwrap();
+            self.vertices.insert(id, AbstractSection::wrap(section_type, block));
 
             for edge in self.get_edges(id, Direction::Incoming) {
                 let (src, dest) = self.edges.get(edge).unwrap();
@@ -265,13 +471,35 @@ impl AbstractGraph {
         }
 
         let to_reduce = self.vertices.remove(&id).unwrap();
-        let parent = self.vertices.get_mut(&parent_id).unwrap();
 
-        // add section as subsection in the parent
-        parent.nest_section(to_reduce);
+        match section_type {
+            AbstractSectionType::Unbranching => {
+                self.vertices.get_mut(&parent_id).unwrap().nest_section(to_reduce);
+            },
+            AbstractSectionType::SingleWhile | AbstractSectionType::While | AbstractSectionType::DoWhile => {
+                let parent = self.vertices.remove(&parent_id).unwrap();
+
+                let mut wrapper = AbstractSection::wrap(section_type, parent);
+                wrapper.nest_section(to_reduce);
+                wrapper.sequence_start = 2;
+
+                self.vertices.insert(parent_id, wrapper);
+            },
+            _ => {
+                let tail = self.vertices.get_mut(&parent_id).unwrap().tail_mut();
+
+                // if-else and switch nest more than one section in the same construct
+                let extending = tail.get_type() == section_type && tail.get_sequence().is_empty();
+
+                if !extending {
+                    tail.set_type(section_type);
+                    tail.sequence_start = tail.abstract_sections.len();
+                }
 
-        // set the parent section type
-        parent.set_type(section_type);
+                tail.abstract_sections.insert(tail.sequence_start, to_reduce);
+                tail.sequence_start += 1;
+            }
+        }
 
         // if outgoing edge exists in set (node has a child)
         // if i was reducing the graph properly, there would only be one edge, but just to be sure
@@ -294,19 +522,114 @@ impl AbstractGraph {
         }
 
         // delete any self-edges to the parent, these occur when creating while loops
-        for edge in self.get_edges(parent_id, Direction::Outgoing) {
-            let (src, dest) = self.edges.get(edge).unwrap();
-            if *src == *dest {
-                self.edges.remove(edge);
-                break;
+        // a sequential one is still a loop, so leave it for the loop reductions
+        if section_type != AbstractSectionType::Unbranching {
+            for edge in self.get_edges(parent_id, Direction::Outgoing) {
+                let (src, dest) = self.edges.get(edge).unwrap();
+                if *src == *dest {
+                    self.edges.remove(edge);
+                    break;
+                }
+            }
+        }
+
+        // deduplicate these, edges are unique, wherever the copies are in the list
+        let mut seen = BTreeSet::new();
+        self.edges.retain(|edge| seen.insert(*edge));
+    }
+
+    /// # turn an edge out of a loop into a break or continue
+    /// the edge is removed, and the branch at the end of the section becomes a guarded break or continue
+    /// a loop that only leaves through breaks gets an edge from its header to where they go, so that still follows it
+    fn reduce_jump(&mut self, id: usize, target: usize, header: usize, section_type: AbstractSectionType) {
+        if let Some(edge) = self.edges.iter().position(|e| *e == (id, target)) {
+            self.edges.remove(edge);
+        }
+
+        let tail = self.vertices.get_mut(&id).unwrap().tail_mut();
+        tail.set_type(section_type);
+        tail.target = Some(target);
+
+        if section_type == AbstractSectionType::Break && !self.contains_edge(header, target) {
+            self.edges.push((header, target));
+            self.endless.insert(header);
+        }
+    }
+
+    /// # collapse an acyclic region into its entry
+    /// the sections are nested in the order given, which has to be topological
+    /// the edges between them are kept so that the conditions for reaching each one can be worked out later
+    fn reduce_region(&mut self, order: &[usize], exit: Option<usize>) {
+        let entry = order[0];
+
+        let mut wrapper = AbstractSection::new(AbstractSectionType::Acyclic, self.concrete(entry));
+        wrapper.vertex = entry;
+        for id in order {
+            wrapper.nest_section(self.vertices.remove(id).unwrap());
+        }
+        wrapper.sequence_start = order.len();
+        wrapper.region_edges = self.edges.iter()
+            .filter(|(src, _)| order.contains(src))
+            .copied()
+            .collect();
+
+        let leaves = wrapper.region_edges.iter().any(|(_, dst)| Some(*dst) == exit);
+
+        self.edges.retain(|(src, _)| !order.contains(src));
+        if let Some(exit) = exit.filter(|_| leaves) {
+            self.edges.push((entry, exit));
+        }
+
+        self.vertices.insert(entry, wrapper);
+    }
+
+    /// # split a node in two
+    /// the given parents go to a copy of it instead, which has the same children
+    /// the code gets output twice, but it means a shared node no longer ties two constructs together
+    fn split_node(&mut self, id: usize, parents: &[usize]) -> usize {
+        let copy_id = self.next_vertex;
+        self.next_vertex += 1;
+        self.splits_left = self.splits_left.saturating_sub(1);
+
+        let mut copy = self.vertices[&id].clone();
+        copy.vertex = copy_id;
+        self.vertices.insert(copy_id, copy);
+        self.copies.insert(copy_id, self.concrete(id));
+
+        if self.switches.contains(&id) {
+            self.switches.insert(copy_id);
+        }
+        if self.endless.contains(&id) {
+            self.endless.insert(copy_id);
+        }
+        if self.tests_only.contains(&id) {
+            self.tests_only.insert(copy_id);
+        }
+
+        for edge in self.get_edges(id, Direction::Outgoing) {
+            let (_, dst) = self.edges[edge];
+            self.edges.push((copy_id, dst));
+        }
+
+        for edge in self.edges.iter_mut() {
+            if edge.1 == id && edge.0 != id && parents.contains(&edge.0) {
+                edge.1 = copy_id;
+            }
+        }
+
+        // and their branches go there now too
+        for parent in parents {
+            if let Some((_, taken)) = self.vertices.get_mut(parent).and_then(|p| p.tail_mut().branch.as_mut()) {
+                if *taken == id {
+                    *taken = copy_id;
+                }
             }
         }
 
-        // deduplicate these, edges are unique
-        self.edges.dedup();
+        copy_id
     }
 
-    fn traverse(&self) -> ReverseInorderIterator {
+    fn traverse(&self) -> ReverseInorderIterator<'_> {
         ReverseInorderIterator::new(self)
     }
 }
@@ -377,6 +700,10 @@ impl fmt::Debug for AbstractGraph {
         f.debug_struct("AbstractGraph")
             .field("vertices", &self.vertices)
             .field("edges", &self.edges)
+            .field("switches", &self.switches)
+            .field("endless", &self.endless)
+            .field("tests_only", &self.tests_only)
+            .field("copies", &self.copies)
             .finish()
     }
 }
@@ -397,15 +724,27 @@ impl fmt::Debug for AbstractGraph {
 /// - else if instruction == valid instruction
 ///     - add to current 
 /// - else 
-///     while !eof continue;
-fn make_blocks(instructions: BTreeMap<u64, InstructionType>) -> Vec<InstructionSection> {
+///   while !eof continue;
+///
+/// any address that something jumps to also starts a new section, so that nothing can jump into the middle of one
+fn make_blocks(instructions: BTreeMap<u64, InstructionType>, jump_tables: &JumpTableMap) -> Vec<InstructionSection> {
     let mut sections: Vec<InstructionSection> = Vec::new();
     let mut curr_section = InstructionSection::new(0);
     let mut section_id = 1;
 
+    let leaders = find_leaders(&instructions, jump_tables);
+
     for address in instructions.keys() {
         let curr = instructions.get(address).unwrap();
 
+        // something jumps here, so start a new section
+        if leaders.contains(address) && !curr_section.instructions.is_empty() {
+            sections.push(curr_section.clone());
+            curr_section = InstructionSection::new(section_id);
+
+            section_id += 1;
+        }
+
         // B- and J-type instructions cause a branch
         match *curr {
             InstructionType::B {..} | InstructionType::J {..} => {
@@ -431,6 +770,26 @@ fn make_blocks(instructions: BTreeMap<u64, InstructionType>) -> Vec<InstructionS
 
                 // TODO: sequential jumps?
             }
+            // jalr without a link register never comes back, it's either a return or a computed jump
+            InstructionType::I { name: "jalr", rd: ABIRegister::zero, ref rs1, .. } => {
+                if let Some(table) = jump_tables.get(address) {
+                    curr_section.set_branch_type(BranchType::Indirect);
+                    curr_section.jump_table = Some(table.clone());
+                } else if *rs1 == ABIRegister::ra {
+                    curr_section.set_branch_type(BranchType::Return);
+                } else {
+                    curr_section.set_branch_type(BranchType::Indirect);
+                }
+
+                curr_section.push(*address, curr.clone());
+                curr_section.add_to_range(*address);
+
+                let new_section = InstructionSection::new(section_id);
+                sections.push(curr_section.clone());
+                curr_section = new_section;
+
+                section_id += 1;
+            }
             _ => {
                 // normal instruction, add to current block
                 curr_section.push(*address, curr.clone());
@@ -439,11 +798,36 @@ fn make_blocks(instructions: BTreeMap<u64, InstructionType>) -> Vec<InstructionS
         }
     }
 
-    sections.push(curr_section);
+    // don't leave an empty section behind if the last instruction was a jump
+    if !curr_section.instructions.is_empty() {
+        sections.push(curr_section);
+    }
 
     sections
 }
 
+/// find every address that starts a section
+/// these are the destinations of any branch, jump, or jump table case within the instructions given
+fn find_leaders(instructions: &BTreeMap<u64, InstructionType>, jump_tables: &JumpTableMap) -> BTreeSet<u64> {
+    let mut leaders = BTreeSet::new();
+
+    for (address, instruction) in instructions.iter() {
+        let destination = match instruction {
+            InstructionType::B { imm, .. } => address.checked_add_signed(*imm as i64),
+            InstructionType::J { rd: ABIRegister::zero, imm, .. } => address.checked_add_signed(*imm as i64),
+            _ => None
+        };
+
+        leaders.extend(destination.filter(|d| instructions.contains_key(d)));
+    }
+
+    for table in jump_tables.values() {
+        leaders.extend(table.get_targets());
+    }
+
+    leaders
+}
+
 /// # determine what children each section has
 /// - if a block can branch, add an edge to that destination, and another to the immediate next block (fallthrough)
 /// - if a block always branches, and its child is within this function, add an edge to it
@@ -495,6 +879,7 @@ fn resolve_jumps(sections: &mut [InstructionSection]) {
                             if let Some(target_index) = find_section(sections, destination_addr) {
                                 let target_section = sections.get(target_index).unwrap();
                                 (*section_ptr.add(i)).add_branch(Arc::new(target_section.clone()));
+                                (*section_ptr.add(i)).taken = Some(target_section.get_id());
                             }
                             // in either case, add fallthrough edge if later blocks exist
                             if i + 1 < sections.len() {
@@ -507,7 +892,7 @@ fn resolve_jumps(sections: &mut [InstructionSection]) {
                         // get offset from last instruction
                         let last_inst = (*section_ptr.add(i)).instructions.values().last().unwrap();
                         println!("{}", last_inst);
-                        if let InstructionType::J { imm, .. } = last_inst {
+                        if let InstructionType::J { rd, imm, .. } = last_inst {
                             // find actual destination by adding the offset
                             let pc = (*section_ptr.add(i)).end;
                             println!("pc: {}, imm: {}", pc, *imm);
@@ -515,8 +900,11 @@ fn resolve_jumps(sections: &mut [InstructionSection]) {
                             // this can crash if labels get involved
                             let destination_addr = pc.checked_add_signed(*imm as i64).unwrap_or(0);
 
+                            // a call comes back to the next instruction, even when it's to the start of this function
+                            let call = *rd == ABIRegister::ra;
+
                             // if destination in within this function, add it as a branch
-                            if let Some(target_index) = find_section(sections, destination_addr) {
+                            if let Some(target_index) = find_section(sections, destination_addr).filter(|_| !call) {
                                 let target_section = sections.get(target_index).unwrap();
                                 (*section_ptr.add(i)).add_branch(Arc::new(target_section.clone()));
                             } else if i + 1 < sections.len() {
@@ -525,6 +913,27 @@ fn resolve_jumps(sections: &mut [InstructionSection]) {
                                 (*section_ptr.add(i)).add_branch(Arc::new(target_section.clone()));
                             }
                         }
+                    },
+                    BranchType::Indirect => {
+                        // one edge for each distinct case, in case order
+                        // unresolved jumps go who-knows-where, so they get no edges at all
+                        if let Some(table) = (*section_ptr.add(i)).jump_table.clone() {
+                            let mut seen = Vec::new();
+                            for target in table.get_targets() {
+                                if seen.contains(target) {
+                                    continue;
+                                }
+                                seen.push(*target);
+
+                                if let Some(target_index) = find_section(sections, *target) {
+                                    let target_section = sections.get(target_index).unwrap();
+                                    (*section_ptr.add(i)).add_branch(Arc::new(target_section.clone()));
+                                }
+                            }
+                        }
+                    },
+                    BranchType::Return => {
+                        // leaves the function, so no edges
                     }
                 }
             } else {
@@ -550,12 +959,16 @@ fn find_section(sections: &[InstructionSection], address: u64) -> Option<usize>
 
 // MAYBE: change the name of this idk
 /// generate the control-flow graph of the program
-pub fn generate_sections(instructions: BTreeMap<u64, InstructionType>) -> SectionMap {
-    let mut sections = make_blocks(instructions);
+/// any resolved jump tables add an edge for each of their cases
+pub fn generate_sections(instructions: BTreeMap<u64, InstructionType>, jump_tables: &JumpTableMap) -> SectionMap {
+    let mut sections = make_blocks(instructions, jump_tables);
     resolve_jumps(&mut sections);
 
     let mut graph: SectionMap = BTreeMap::new();
-    for s in sections.into_iter() {
+    for mut s in sections.into_iter() {
+        s.statements = s.instructions.iter()
+            .map(|(address, inst)| (*address, lift(*address, inst)))
+            .collect();
         graph.insert(s.get_id(), s);
     }
 
@@ -575,17 +988,43 @@ fn build_abstract_graph(sections: &SectionMap) -> Option<AbstractGraph> {
     let mut graph = AbstractGraph::new();
 
     // insert vertices
-    for (id, _) in sections.iter() {
-        graph.vertices.insert(*id, AbstractSection::new(AbstractSectionType::Unbranching, *id));
+    // a branch that's taken to somewhere outside the function is left with the virtual exit as its destination
+    for (id, section) in sections.iter() {
+        let mut vertex = AbstractSection::new(AbstractSectionType::Unbranching, *id);
+
+        // a branch that goes to the same place either way doesn't need testing
+        let targets: BTreeSet<usize> = section.get_branches().iter().map(|b| b.get_id()).collect();
+
+        if !section.get_instructions().is_empty() && targets.len() != 1 {
+            let destination = section.get_taken().unwrap_or(VIRTUAL_EXIT);
+
+            vertex.branch = section.get_condition().map(|cond| (cond, destination));
+
+            if vertex.branch.is_some() && section.get_instructions().len() == 1 {
+                graph.tests_only.insert(*id);
+            }
+        }
+
+        graph.vertices.insert(*id, vertex);
     }
 
     // insert edges
     for section in sections.values() {
         for edge in section.get_branches() {
-            graph.edges.push((section.get_id(), edge.get_id()));
+            if !graph.contains_edge(section.get_id(), edge.get_id()) {
+                graph.edges.push((section.get_id(), edge.get_id()));
+            }
+        }
+
+        if section.get_jump_table().is_some() {
+            graph.switches.insert(section.get_id());
         }
     }
 
+    // every section can be copied once over, which is plenty for anything a compiler puts out
+    graph.next_vertex = sections.keys().last().unwrap() + 1;
+    graph.splits_left = sections.len();
+
     Some(graph)
 }
 
@@ -594,22 +1033,49 @@ fn build_abstract_graph(sections: &SectionMap) -> Option<AbstractGraph> {
 // r_ prefix used
 // ----------------------------------------
 
+/// # drop sections that nothing reaches
+/// like code after a call that never returns, or a split copy whose parents have all been reduced away
+/// they'd only be output after the rest of the function, where nothing can get to them
+fn r_unreachable(abstract_sections: &mut AbstractGraph) -> bool {
+    let dominators = abstract_sections.dominators();
+    let unreachable: Vec<usize> = abstract_sections.vertices.keys()
+        .filter(|id| !dominators.is_reachable(**id))
+        .copied()
+        .collect();
+
+    if unreachable.is_empty() {
+        return false;
+    }
+
+    abstract_sections.vertices.retain(|id, _| !unreachable.contains(id));
+    abstract_sections.edges.retain(|(src, dst)| !unreachable.contains(src) && !unreachable.contains(dst));
+
+    if log_enabled!(Level::Info) {
+        info!("dropped unreachable sections {:?}", unreachable);
+    }
+
+    true
+}
+
 /// # reduce all sequential, unbranching blocks
 fn r_sequential_blocks(abstract_sections: &mut AbstractGraph) -> bool {
     // if a section has one child, and that child has only one parent and <= 1 child, concatenate them
+    let dominators = abstract_sections.dominators();
 
     for a_section in abstract_sections.traverse() {
         // if this section only has one child, and that only has one parent (this current section), concatenate them
         let branches = abstract_sections.get_edges(a_section, Direction::Outgoing); 
 
-        if branches.len() == 1 {
+        if branches.len() == 1 && !abstract_sections.switches.contains(&a_section) {
             let child = abstract_sections.edges[*branches.first().unwrap()].1;              // (_, dst)
 
             let no_children = abstract_sections.get_edges(child, Direction::Outgoing).len();
             let no_parents = abstract_sections.get_edges(child, Direction::Incoming).len();
                 // no need to check that the parent is this, as we know it is a child of a_section already
 
-            if no_parents == 1 && no_children <= 1 {
+            // the child can only be entered through this section, so it is immediately dominated by it
+            // this also stops the entry from being swallowed by a loop back to it
+            if no_parents == 1 && no_children <= 1 && dominators.immediate_dominator(child) == Some(a_section) {
                 abstract_sections.reduce_node(child, a_section, AbstractSectionType::Unbranching);
 
                 if log_enabled!(Level::Info) {
@@ -624,17 +1090,73 @@ fn r_sequential_blocks(abstract_sections: &mut AbstractGraph) -> bool {
     false
 }
 
+/// # merge chains of branches into `&&` and `||`
+/// `if (a && b)` compiles to two branches that both skip the body, the second of which does nothing else
+/// so the second can be folded into the first, which then goes to the same two places with a compound condition
+fn r_short_circuit(abstract_sections: &mut AbstractGraph) -> bool {
+    // block_0 branches to block_1 and block_x
+    // block_1 is only entered from block_0, does nothing but test, and branches to block_x and block_y
+    // block_0 goes to block_x when either test says so, and to block_y otherwise
+    for a_section in abstract_sections.traverse() {
+        let children = abstract_sections.get_children(a_section);
+
+        if children.len() != 2 || !abstract_sections.is_conditional(a_section) {
+            continue;
+        }
+
+        for (test, shared) in [(children[0], children[1]), (children[1], children[0])] {
+            let test_children = abstract_sections.get_children(test);
+
+            if
+                test == a_section || !abstract_sections.tests_only.contains(&test) ||
+                !abstract_sections.vertices[&test].is_plain() || !abstract_sections.is_conditional(test) ||
+                abstract_sections.get_edges(test, Direction::Incoming).len() != 1 ||
+                test_children.len() != 2 || !test_children.contains(&shared)
+            {
+                continue;
+            }
+
+            let other = *test_children.iter().find(|c| **c != shared).unwrap();
+
+            let first = condition_towards(abstract_sections.vertices[&a_section].tail(), shared);
+            let second = condition_towards(&abstract_sections.vertices[&test], shared);
+            let (Some(first), Some(second)) = (first, second) else { continue; };
+
+            abstract_sections.vertices.remove(&test);
+            abstract_sections.vertices.get_mut(&a_section).unwrap().tail_mut().branch = Some((Condition::or(first, second), shared));
+
+            abstract_sections.edges.retain(|e| *e != (a_section, test) && *e != (test, shared));
+            for edge in abstract_sections.edges.iter_mut() {
+                if *edge == (test, other) {
+                    edge.0 = a_section;
+                }
+            }
+
+            if log_enabled!(Level::Info) {
+                info!("merged the test in {} into the branch at the end of {}", test, a_section);
+            }
+
+            return true;
+        }
+    }
+
+    false
+}
+
 /// reduce all single-block while loops
 /// for more complex loop reductions, see the paper "no more gotos"
 fn r_single_block_while(abstract_sections: &mut AbstractGraph) -> bool {
     // if block_0 has two children,
     // if one child has exactly one parent and child, both being block_0, they are the contents of its while loop
+    // block_0 is then the header of a natural loop with just the two of them in it, tested before each iteration
+    let loops = abstract_sections.loops();
 
     // traverse reverse-inorder
     for a_section in abstract_sections.traverse() {
         let children = abstract_sections.get_edges(a_section, Direction::Outgoing);
 
-        if children.len() == 2 {
+        // anything more than a single block at the head of the loop needs to go inside it, see r_while
+        if children.len() == 2 && abstract_sections.is_conditional(a_section) && abstract_sections.vertices[&a_section].is_plain() {
             let block_1 = abstract_sections.edges[*children.first().unwrap()].1;
             let block_2 = abstract_sections.edges[*children.last().unwrap()].1;
 
@@ -643,9 +1165,15 @@ fn r_single_block_while(abstract_sections: &mut AbstractGraph) -> bool {
             let no_children_1 = abstract_sections.get_edges(block_1, Direction::Outgoing).len();
             let no_children_2 = abstract_sections.get_edges(block_2, Direction::Outgoing).len();
 
+            let is_back_edge = |block: usize| {
+                loops.loop_with_header(a_section).is_some_and(|l| {
+                    l.is_reducible() && l.kind() == LoopKind::PreTested && l.get_latches() == [block] &&
+                    l.get_body().len() == 2 && l.contains(block)
+                })
+            };
+
             // have to check recursively, no guarantee as to which block is the loop contents
-            if no_parents_1 == 1 && no_children_1 == 1 &&
-               abstract_sections.contains_edge(block_1, a_section) {
+            if no_parents_1 == 1 && no_children_1 == 1 && is_back_edge(block_1) {
 
                 abstract_sections.reduce_node(block_1, a_section, AbstractSectionType::SingleWhile);
                 if log_enabled!(Level::Info) {
@@ -654,9 +1182,7 @@ fn r_single_block_while(abstract_sections: &mut AbstractGraph) -> bool {
 
                 return true;
             }
-            else if 
-                no_parents_2 == 1 && no_children_2 == 1 &&
-                abstract_sections.contains_edge(block_2, a_section) {
+            else if no_parents_2 == 1 && no_children_2 == 1 && is_back_edge(block_2) {
 
                 abstract_sections.reduce_node(block_2, a_section, AbstractSectionType::SingleWhile);
                 if log_enabled!(Level::Info) {
@@ -674,7 +1200,38 @@ fn r_single_block_while(abstract_sections: &mut AbstractGraph) -> bool {
 /// reduce do-while loops by subtracting an edge and changing its label 
 fn r_do_while(abstract_sections: &mut AbstractGraph) -> bool {
     // if a node has itself as a child, delete that edge and change its type
+    // if a node falls into a latch that tests whether to go back to it, nest the latch inside it first
+    let loops = abstract_sections.loops();
+
     for a_section in abstract_sections.traverse() {
+        // loops that only leave through breaks are handled by r_while
+        if abstract_sections.endless.contains(&a_section) {
+            continue;
+        }
+
+        if let Some(found) = loops.loop_with_header(a_section) {
+            let latch = *found.get_latches().first().unwrap();
+
+            // the condition has to be at the end of the latch
+            if
+                found.is_reducible() && found.kind() == LoopKind::PostTested &&
+                found.get_body().len() == 2 && latch != a_section &&
+                abstract_sections.get_children(a_section) == vec![latch] &&
+                abstract_sections.get_edges(latch, Direction::Incoming).len() == 1 &&
+                abstract_sections.get_children(latch).len() == 2 &&
+                !abstract_sections.switches.contains(&a_section) &&
+                abstract_sections.is_conditional(latch)
+            {
+                abstract_sections.reduce_node(latch, a_section, AbstractSectionType::DoWhile);
+
+                if log_enabled!(Level::Info) {
+                    info!("reduced header {} and latch {} to a do-while loop", a_section, latch);
+                }
+
+                return true;
+            }
+        }
+
         if abstract_sections.contains_edge(a_section, a_section) {
             let branches = abstract_sections.get_edges(a_section, Direction::Outgoing);
 
@@ -701,29 +1258,50 @@ fn r_do_while(abstract_sections: &mut AbstractGraph) -> bool {
 /// reduce if-then constructs to a single abstract section
 fn r_if_then(abstract_sections: &mut AbstractGraph) -> bool {
     // if block_0 has two children, block_1 and block_2
-    // block_1 has one parent and one child
-    // block_1's child is block_2
+    // block_2 is the merge point, the immediate post-dominator of block_0
+    // block_1 is only entered from block_0, and its only child is block_2
     // block_2 != block_0
+    // or, block_1 leaves the function (or the loop it's in) on its own, so there's nothing to merge with
+    let dominators = abstract_sections.dominators();
+    let post_dominators = abstract_sections.post_dominators();
+    let loops = abstract_sections.loops();
 
     for a_section in abstract_sections.traverse() {
         let children = abstract_sections.get_edges(a_section, Direction::Outgoing);
 
-        if children.len() == 2 {
+        if children.len() == 2 && abstract_sections.is_conditional(a_section) {
             // have to check recursively, no guarantee as to which block is the if
             let block_1 = abstract_sections.edges[*children.first().unwrap()].1;
             let block_2 = abstract_sections.edges[*children.last().unwrap()].1;
-            
-            let no_parents_1 = abstract_sections.get_edges(block_1, Direction::Incoming).len();
-            let no_parents_2 = abstract_sections.get_edges(block_2, Direction::Incoming).len();
-            let no_children_1 = abstract_sections.get_edges(block_1, Direction::Outgoing).len();
-            let no_children_2 = abstract_sections.get_edges(block_2, Direction::Outgoing).len();
+
+            let merge = post_dominators.immediate_dominator(a_section);
+
+            // a loop header's test is its condition, not an early exit
+            // and neither is leaving for the only place the loop goes, that's a break
+            let tests_loop = |body: usize| {
+                loops.loop_with_header(a_section).is_some() ||
+                loops.innermost_loop(a_section)
+                    .and_then(|index| loops.get_loop(index))
+                    .is_some_and(|l| l.exit_targets().len() == 1 && l.exit_targets().contains(&body))
+            };
+
+            // the body of the if is entered only from the condition, and leads only to the merge point
+            // a body that returns doesn't need one, unless the other side returns too, which is an if-else
+            let is_body = |body: usize, merge_point: usize| {
+                let no_parents = abstract_sections.get_edges(body, Direction::Incoming).len();
+                let body_children = abstract_sections.get_children(body);
+
+                let merges = merge_point != a_section && Some(merge_point) == merge && body_children == vec![merge_point];
+                let returns = body_children.is_empty() && !tests_loop(body) &&
+                    !(abstract_sections.get_children(merge_point).is_empty() && abstract_sections.get_edges(merge_point, Direction::Incoming).len() == 1);
+
+                body != a_section && (merges || returns) &&
+                dominators.immediate_dominator(body) == Some(a_section) &&
+                no_parents == 1
+            };
 
             // test if block_1 is the if inner
-            if 
-                no_parents_1 == 1 && no_children_1 <= 1 &&
-                abstract_sections.contains_edge(block_1, block_2) && 
-                block_2 != a_section
-            {
+            if is_body(block_1, block_2) {
                 abstract_sections.reduce_node(block_1, a_section, AbstractSectionType::If);
 
                 if log_enabled!(Level::Info) {
@@ -733,11 +1311,7 @@ fn r_if_then(abstract_sections: &mut AbstractGraph) -> bool {
                 return true;
             }
             // and then block_2
-            else if 
-                no_parents_2 == 1 && no_children_2 <= 1 &&
-                abstract_sections.contains_edge(block_2, block_1) && 
-                block_1 != a_section
-            {
+            else if is_body(block_2, block_1) {
                 abstract_sections.reduce_node(block_2, a_section, AbstractSectionType::If);
 
                 if log_enabled!(Level::Info) {
@@ -755,28 +1329,35 @@ fn r_if_then(abstract_sections: &mut AbstractGraph) -> bool {
 /// reduce an if-else "diamond" to a single abstract block
 fn r_if_else(abstract_sections: &mut AbstractGraph) -> bool {
     // if block_0 has 2 children
-    // block_1 and block_2 have block_0 as their only parent
-    // block_1 and block_2 have the same child (if any)
+    // block_1 and block_2 have block_0 as their only parent, so are immediately dominated by it
+    // block_1 and block_2 have the same child (if any), which is the merge point, the immediate post-dominator of block_0
+    let dominators = abstract_sections.dominators();
+    let post_dominators = abstract_sections.post_dominators();
 
     for a_section in abstract_sections.traverse() {
         let children = abstract_sections.get_edges(a_section, Direction::Outgoing);
 
-        if children.len() == 2 {
+        if children.len() == 2 && abstract_sections.is_conditional(a_section) {
             // no reflexivity here
             let block_1 = abstract_sections.edges[*children.first().unwrap()].1;
             let block_2 = abstract_sections.edges[*children.last().unwrap()].1;
 
-            let no_parents_1 = abstract_sections.get_edges(block_1, Direction::Incoming).len();
-            let no_parents_2 = abstract_sections.get_edges(block_2, Direction::Incoming).len();
-            let children_1 = abstract_sections.get_edges(block_1, Direction::Outgoing);
-            let no_children_1 = children_1.len();
-            let children_2 = abstract_sections.get_edges(block_2, Direction::Outgoing);
-            let no_children_2 = children_2.len();
-            
-            if
-                no_parents_1 == 1 && no_parents_2 == 1 &&
-                (no_children_1 == 0 && no_children_2 == 0) || 
-                (no_children_1 == 1 && no_children_2 == 1 && abstract_sections.edges[*children_1.first().unwrap()].1 == abstract_sections.edges[*children_2.first().unwrap()].1) {
+            let merge = post_dominators.immediate_dominator(a_section);
+
+            let is_branch = |block: usize| {
+                block != a_section &&
+                dominators.immediate_dominator(block) == Some(a_section) &&
+                abstract_sections.get_edges(block, Direction::Incoming).len() == 1
+            };
+
+            let children_1 = abstract_sections.get_children(block_1);
+            let children_2 = abstract_sections.get_children(block_2);
+
+            // either both branches leave the function, or both go on to the merge point
+            let joined = (children_1.is_empty() && children_2.is_empty()) ||
+                (children_1.len() == 1 && children_1 == children_2 && merge == children_1.first().copied());
+
+            if is_branch(block_1) && is_branch(block_2) && joined {
                     abstract_sections.reduce_node(block_1, a_section, AbstractSectionType::IfElse);
                     abstract_sections.reduce_node(block_2, a_section, AbstractSectionType::IfElse);
 
@@ -792,309 +1373,1054 @@ fn r_if_else(abstract_sections: &mut AbstractGraph) -> bool {
     false
 }
 
-// ----------------------------------------
+/// reduce a jump table and all of its cases to a single abstract section
+fn r_switch(abstract_sections: &mut AbstractGraph) -> bool {
+    // if block_0 ends in a jump table, and every case
+    // - has block_0 as its only parent, apart from the case before it falling through
+    // - has no children, the same single child as every other case, or falls through to the case after it
+    // then the cases can all be nested in the switch
 
-/// # iteratively reduce the control-flow graph to nested abstract sections
-/// we start with an abstract map that is a 1-1 representation of the concrete instruction sections
-/// we then apply a series of reductions to it, such that each transformation, if successfully applied, decrease the size of the graph
-/// because of this, it's guaranteed to stop
-/// this means i've basically solved the halting problem - take that, turing
-pub fn iterated_cfg_reduction(sections: SectionMap) -> Option<AbstractGraph> {
-    // generate 1-1 map of abstract sections
-    let mut abstract_graph = build_abstract_graph(&sections)?;
+    for a_section in abstract_sections.traverse() {
+        if !abstract_sections.switches.contains(&a_section) {
+            continue;
+        }
 
-    let mut processing = true;
+        // in address order, as that's the only way one can fall through to the next
+        let mut cases: Vec<usize> = abstract_sections.get_edges(a_section, Direction::Outgoing)
+            .iter()
+            .map(|e| abstract_sections.edges[*e].1)
+            .collect();
+        cases.sort_by_key(|case| abstract_sections.concrete(*case));
 
-    while processing {
-        processing = false;
+        if cases.is_empty() {
+            continue;
+        }
 
-        // we attempt to apply the following reductions
-        // - reduce sequential blocks to single blocks
-        processing = processing || r_sequential_blocks(&mut abstract_graph);
+        let mut follow = None;
+        let mut fallthroughs = Vec::new();
+        let reducible = cases.iter().enumerate().all(|(index, case)| {
+            let previous = index.checked_sub(1).map(|i| cases[i]);
+            let next = cases.get(index + 1).copied();
+
+            let parents_valid = abstract_sections.get_edges(*case, Direction::Incoming)
+                .iter()
+                .all(|e| {
+                    let src = abstract_sections.edges[*e].0;
+                    src == a_section || (Some(src) == previous && fallthroughs.contains(&(src, *case)))
+                });
+            let children: Vec<usize> = abstract_sections.get_edges(*case, Direction::Outgoing)
+                .iter()
+                .map(|e| abstract_sections.edges[*e].1)
+                .collect();
+
+            if *case == a_section || !parents_valid || children.len() > 1 {
+                return false;
+            }
 
-        // - reduce simple loop to while
-        processing = processing || r_single_block_while(&mut abstract_graph);
+            match children.first() {
+                Some(child) if Some(*child) == next => {
+                    fallthroughs.push((*case, *child));
+                    true
+                },
+                Some(child) if cases.contains(child) || *child == a_section => false,
+                Some(child) if follow.is_some_and(|f| f != *child) => false,
+                Some(child) => {
+                    follow = Some(*child);
+                    true
+                },
+                None => true
+            }
+        });
 
-        // - reduce self-loop to do-while
-        processing = processing || r_do_while(&mut abstract_graph);
+        if reducible {
+            abstract_sections.edges.retain(|e| !fallthroughs.contains(e));
 
-        // - reduce single-step branch to if
-        processing = processing || r_if_then(&mut abstract_graph);
+            for case in cases.iter() {
+                abstract_sections.reduce_node(*case, a_section, AbstractSectionType::Switch);
+            }
 
-        // - reduce "diamond" to if-else statement
-        processing = processing || r_if_else(&mut abstract_graph);
-    }
+            // the output needs to know which cases to leave the break off of
+            abstract_sections.vertices.get_mut(&a_section).unwrap().tail_mut().region_edges = fallthroughs;
 
-    Some(abstract_graph)
-}
+            if log_enabled!(Level::Info) {
+                info!("reduced parent {} and cases {:?} to switch", a_section, cases);
+            }
 
-// ----------------------------------------
+            return true;
+        }
+    }
 
-/// insert n tab characters
-macro_rules! indent {
-    ($n:expr) => {
-        "\t".repeat($n)
-    };
+    false
 }
 
-/// function to convert to a higher-level representation
-fn high_level_conversion(concrete_sections: SectionMap, abstract_sections: AbstractGraph) -> Vec<String> {
-    // traverse and output to a vector of strings, i think 
-    let mut indent = 0;
-    let mut output: Vec<String> = Vec::new();
+/// # reduce any other while loop
+/// this covers loops where the header is more than a single block, and loops that only leave through breaks
+/// the header runs on every iteration, so it goes inside the loop, before a break if the header is what tests for the exit
+fn r_while(abstract_sections: &mut AbstractGraph) -> bool {
+    // block_0 is the header of a loop with at most one other section, block_1, in its body
+    // block_1 has block_0 as its only parent and only child
+    // block_0 can have one other child, the follow
+    let loops = abstract_sections.loops();
 
-    // function signature
-    // this will be hardcoded because i'm not extracting it well enough from the raw bytes
-    output.push("void main() {".to_string());
-    indent += 1;
+    for a_section in abstract_sections.traverse() {
+        let Some(found) = loops.loop_with_header(a_section) else { continue; };
 
-    // call iteratively on any existing vertices
-    for (_, section) in abstract_sections.get_vertices() {
-        // get corresponding concrete section
-        convert_section(section, &mut output, &abstract_sections, &concrete_sections, &mut indent);
-    }
+        if !found.is_reducible() || found.get_body().len() > 2 || abstract_sections.switches.contains(&a_section) {
+            continue;
+        }
 
-    // closing brace
-    output.push("}".to_string());
+        let endless = abstract_sections.endless.contains(&a_section);
+        let children = abstract_sections.get_children(a_section);
+        let follow: Vec<usize> = children.iter().filter(|c| !found.contains(**c)).copied().collect();
 
-    output
+        // the header either tests for the exit, or it was turned endless when its breaks were taken out
+        let tested = follow.len() == 1 && children.len() == 2 && abstract_sections.is_conditional(a_section);
+        if follow.len() > 1 || !(endless || tested || follow.is_empty()) {
+            continue;
+        }
+
+        // a self-loop has no body of its own, and if it tests anything it's a do-while
+        if found.get_body().len() == 1 {
+            if tested && !endless {
+                continue;
+            }
+
+            abstract_sections.reduce_node(a_section, a_section, AbstractSectionType::While);
+            abstract_sections.vertices.get_mut(&a_section).unwrap().target = follow.first().copied();
+
+            if log_enabled!(Level::Info) {
+                info!("reduced {} to a while loop", a_section);
+            }
+
+            return true;
+        }
+
+        let body = *found.get_body().iter().find(|b| **b != a_section).unwrap();
+
+        if
+            abstract_sections.get_edges(body, Direction::Incoming).len() == 1 &&
+            abstract_sections.get_children(body) == vec![a_section]
+        {
+            abstract_sections.reduce_node(body, a_section, AbstractSectionType::While);
+            abstract_sections.vertices.get_mut(&a_section).unwrap().target = follow.first().copied();
+
+            if log_enabled!(Level::Info) {
+                info!("reduced header {} and body {} to a while loop", a_section, body);
+            }
+
+            return true;
+        }
+    }
+
+    false
 }
 
-// ----------------------------------------
+/// # take early exits out of loops
+/// a conditional jump out of the middle of a loop becomes a break, and one back to the header a continue
+/// this leaves the body with a single way in and out, so the other reductions can deal with it
+///
+/// a loop that leaves for more than one place gets its successors refined, as in "no more gotos"
+/// anything only reached from inside the loop that goes on to another exit is part of the way out,
+/// so it ends in a break to there instead, leaving a single successor
+fn r_loop_exits(abstract_sections: &mut AbstractGraph) -> bool {
+    if !abstract_sections.breaks {
+        return false;
+    }
 
-/// # Condition conversion helper function
-/// conditions: 
-/// - beq: c0 == c1
-/// - bne: c0 != c1
-/// - blt(u): c0 <  c1
-/// - bgt(u): c0 >  c1
-/// - ble(u): c0 <= c1
-/// - bgt(u): c0 >= c1:w
-fn condition(inst: &InstructionType) -> Option<String> {
-    // we know this must be a b-type instruction
-    match inst.get_name() {
-        "beq" => Some(format!("{} == {}", inst.get_rs1(), inst.get_rs2())),
-        "bne" => Some(format!("{} != {}", inst.get_rs1(), inst.get_rs2())),
-        "blt" | "bltu"
-            => Some(format!("{} < {}", inst.get_rs1(), inst.get_rs2())),
-        "bgt" | "bgtu"
-            => Some(format!("{} > {}", inst.get_rs1(), inst.get_rs2())),
-        "ble" | "bleu"
-            => Some(format!("{} <= {}", inst.get_rs1(), inst.get_rs2())),
-        "bge" | "bgeu"
-            => Some(format!("{} >= {}", inst.get_rs1(), inst.get_rs2())),
-        _ => None
+    let loops = abstract_sections.loops();
+
+    // innermost loops first, so a break always leaves the closest loop
+    for (index, found) in loops.get_loops().iter().enumerate() {
+        let header = found.get_header();
+
+        // a break or continue inside a nested loop would only leave that one
+        // and one in a section that's also reached from outside would end up wherever that gets copied to
+        let directly_in = |node: usize| {
+            loops.innermost_loop(node) == Some(index) &&
+            abstract_sections.get_edges(node, Direction::Incoming).iter().all(|e| found.contains(abstract_sections.edges[*e].0))
+        };
+
+        if !found.is_reducible() {
+            continue;
+        }
+
+        // exits that leave the function are left to the if reductions as early returns
+        // unless that's the only way out, or the header's test goes there, then it's where the loop goes
+        let exits = found.exit_targets();
+        let follows: Vec<usize> = exits.iter()
+            .filter(|t| {
+                exits.len() == 1 || abstract_sections.contains_edge(header, **t) ||
+                !abstract_sections.get_children(**t).is_empty() ||
+                abstract_sections.get_edges(**t, Direction::Incoming).len() > 1
+            })
+            .copied()
+            .collect();
+
+        // a loop needs one place to leave to, or a break wouldn't know where it ends up
+        if follows.len() > 1 {
+            for target in follows.iter() {
+                let children = abstract_sections.get_children(*target);
+                let inside = abstract_sections.get_edges(*target, Direction::Incoming)
+                    .iter()
+                    .all(|e| found.contains(abstract_sections.edges[*e].0));
+
+                if
+                    inside && children.len() == 1 && children[0] != *target && follows.contains(&children[0]) &&
+                    !abstract_sections.switches.contains(target) &&
+                    abstract_sections.vertices[target].tail().is_plain()
+                {
+                    abstract_sections.reduce_jump(*target, children[0], header, AbstractSectionType::Break);
+
+                    if log_enabled!(Level::Info) {
+                        info!("refined loop at {}, {} now breaks to {}", header, target, children[0]);
+                    }
+
+                    return true;
+                }
+            }
+
+            continue;
+        }
+
+        // the header's own test is the loop condition, and so is a latch's in a do-while
+        for (src, dst) in found.get_exits() {
+            if
+                follows.contains(dst) && directly_in(*src) &&
+                *src != header && !found.get_latches().contains(src) &&
+                abstract_sections.get_children(*src).len() == 2 &&
+                abstract_sections.is_conditional(*src)
+            {
+                abstract_sections.reduce_jump(*src, *dst, header, AbstractSectionType::Break);
+
+                if log_enabled!(Level::Info) {
+                    info!("reduced edge from {} to {} to a break", src, dst);
+                }
+
+                return true;
+            }
+        }
+
+        // a continue in a do-while would go to the test, not the header
+        if found.kind() == LoopKind::PostTested || found.get_latches().len() < 2 {
+            continue;
+        }
+
+        for latch in found.get_latches() {
+            let children = abstract_sections.get_children(*latch);
+
+            if
+                *latch != header && children.len() == 2 && directly_in(*latch) &&
+                children.iter().all(|c| found.contains(*c)) &&
+                abstract_sections.is_conditional(*latch)
+            {
+                abstract_sections.reduce_jump(*latch, header, header, AbstractSectionType::Continue);
+
+                if log_enabled!(Level::Info) {
+                    info!("reduced edge from {} to {} to a continue", latch, header);
+                }
+
+                return true;
+            }
+        }
     }
+
+    false
 }
 
-/// # Operator conversion helper function
-/// operators:
-/// - lb, lh, lw, lbu, lhu, lui, ld, kwu: dst = src
-/// - add, addw, addi, addiw: dst = op0 + op1
-/// - sub, subw, subi, subiw: dst = op0 - op1
-/// - and, andi: dst = op0 & op1
-/// - or, ori: dst = op0 | op1
-/// - xor, xori: dst = op0 ^ op1
-/// - mul, mulh, mulhsu, mulhu, mulw: dst = op0 * op1 
-/// - div, divu, divw, divuw: dst = op0 / op1
-/// - remw, remuw: dst = op0 % op1
-fn operator(inst: &InstructionType) -> Option<String> {
-    // these are all separated as some require register values, others immediates
-    match inst.get_name() {
-        "lb" | "lh" | "lw" | "lbu" | "lhu" | "ld" | "lwu" 
-            => Some(format!("{} = {}", inst.get_rd(), inst.get_rs1())),
-        "lui" 
-            => Some(format!("{} = {}", inst.get_rd(), inst.get_imm())),
-        "addi" | "addiw"
-            => Some(format!("{} = {} + {}", inst.get_rd(), inst.get_rs1(), inst.get_imm())),
-        "add" | "addw"
-            => Some(format!("{} = {} + {}", inst.get_rd(), inst.get_rs1(), inst.get_rs2())),
-        "subi" | "subiw"
-            => Some(format!("{} = {} - {}", inst.get_rd(), inst.get_rs1(), inst.get_imm())),
-        "sub" | "subw"
-            => Some(format!("{} = {} - {}", inst.get_rd(), inst.get_rs1(), inst.get_rs2())),
-        "ori"
-            => Some(format!("{} = {} | {}", inst.get_rd(), inst.get_rs1(), inst.get_imm())),
-        "or"
-            => Some(format!("{} = {} | {}", inst.get_rd(), inst.get_rs1(), inst.get_rs2())),
-        "xori"
-            => Some(format!("{} = {} | {}", inst.get_rd(), inst.get_rs1(), inst.get_imm())),
-        "xor"
-            => Some(format!("{} = {} | {}", inst.get_rd(), inst.get_rs1(), inst.get_rs2())),
-        "mul" | "mulh" | "mulhsu" | "mulhu" | "mulw"
-            => Some(format!("{} = {} * {}", inst.get_rd(), inst.get_rs1(), inst.get_rs2())),
-        "div" | "divu" | "divw" | "divuw"
-            => Some(format!("{} = {} / {}", inst.get_rd(), inst.get_rs1(), inst.get_rs2())),
-        "remw" | "remuw"
-            => Some(format!("{} = {} % {}", inst.get_rd(), inst.get_rs1(), inst.get_rs2())),
-        _ => None
+/// # reduce an acyclic single-entry, single-exit region
+/// this is the fallback for anything the if reductions can't manage, like two conditions sharing a block
+/// each section is output in turn, guarded by the condition for reaching it
+fn r_acyclic(abstract_sections: &mut AbstractGraph) -> bool {
+    // block_0 branches two ways, and everything it reaches before its immediate post-dominator
+    // - is dominated by block_0, so can't be entered from anywhere else
+    // - doesn't loop
+    // - has at most two children, so the conditions can be worked out
+    let dominators = abstract_sections.dominators();
+    let post_dominators = abstract_sections.post_dominators();
+
+    for a_section in abstract_sections.traverse() {
+        if abstract_sections.get_children(a_section).len() != 2 || !abstract_sections.is_conditional(a_section) {
+            continue;
+        }
+
+        let exit = post_dominators.immediate_dominator(a_section).filter(|e| *e != VIRTUAL_EXIT);
+
+        // everything reachable without going through the exit
+        let mut region = vec![a_section];
+        let mut index = 0;
+        while index < region.len() {
+            for child in abstract_sections.get_children(region[index]) {
+                if Some(child) != exit && !region.contains(&child) {
+                    region.push(child);
+                }
+            }
+            index += 1;
+        }
+
+        let valid = region.len() > 2 && region.iter().all(|id| {
+            let children = abstract_sections.get_children(*id);
+            let parents_inside = abstract_sections.get_edges(*id, Direction::Incoming)
+                .iter()
+                .all(|e| region.contains(&abstract_sections.edges[*e].0));
+
+            !abstract_sections.switches.contains(id) && !children.contains(&a_section) &&
+            (*id == a_section || (parents_inside && dominators.strictly_dominates(a_section, *id))) &&
+            (children.len() < 2 || (children.len() == 2 && abstract_sections.is_conditional(*id)))
+        });
+
+        if !valid {
+            continue;
+        }
+
+        // order it so every section comes after the ones that lead to it
+        let mut order: Vec<usize> = Vec::new();
+        while order.len() < region.len() {
+            let next = region.iter()
+                .filter(|id| !order.contains(id))
+                .find(|id| {
+                    abstract_sections.get_edges(**id, Direction::Incoming)
+                        .iter()
+                        .all(|e| **id == a_section || order.contains(&abstract_sections.edges[*e].0))
+                });
+
+            match next {
+                Some(id) => order.push(*id),
+                None => break
+            }
+        }
+
+        // if it couldn't be ordered, there's a cycle somewhere in it
+        if order.len() < region.len() {
+            continue;
+        }
+
+        abstract_sections.reduce_region(&order, exit);
+
+        if log_enabled!(Level::Info) {
+            info!("reduced region {:?} to an acyclic section", order);
+        }
+
+        return true;
     }
+
+    false
 }
 
-// ----------------------------------------
+/// # make an irreducible loop reducible by splitting its extra entries
+/// anything that jumps into the middle of the loop goes to its own copy of the section it jumps to instead
+/// the copy leads back into the loop, so it ends up with one way in through its header
+fn r_split_irreducible(abstract_sections: &mut AbstractGraph) -> bool {
+    if abstract_sections.splits_left == 0 {
+        return false;
+    }
 
-fn convert_section(section: AbstractSection, output: &mut Vec<String>, abstract_map: &AbstractGraph, concrete_sections: &SectionMap, indent: &mut usize) {
-    let concrete_section = concrete_sections.get(&section.get_id());
-    let instructions = concrete_section.unwrap().get_instructions();
-    let count = instructions.values().count();
+    let loops = abstract_sections.loops();
 
-    // get the last instruction to check the branch condition
-    let last_instruction = instructions.values().last().unwrap();
+    for found in loops.get_loops() {
+        if found.is_reducible() {
+            continue;
+        }
 
-    // based on type of section, wrap guard and call on next section
-    // pass in output to keep pushing
-    // remember that we may have more sections contained, that just means that more sections follow, as this has been reduced down multiple times
-    match section.get_type() {
-        AbstractSectionType::If => {
-            // stringify each instruction in the new language and push to the output vector
-            for (index, instruction) in instructions.values().enumerate() {
-                // the last instruction will be handled in the guard
-                if index < count - 1 {
-                    output.push(convert_instruction(instruction, *indent));
+        for entry in found.get_entries() {
+            let outside: Vec<usize> = abstract_sections.get_edges(*entry, Direction::Incoming)
+                .iter()
+                .map(|e| abstract_sections.edges[*e].0)
+                .filter(|src| !found.contains(*src))
+                .collect();
+
+            if outside.is_empty() || *entry == abstract_sections.get_root() {
+                continue;
+            }
+
+            let copy = abstract_sections.split_node(*entry, &outside);
+
+            if log_enabled!(Level::Info) {
+                info!("split entry {} of irreducible loop at {} into {} for {:?}", entry, found.get_header(), copy, outside);
+            }
+
+            return true;
+        }
+    }
+
+    false
+}
+
+/// # split a shared section when nothing else applies
+/// this is the last resort, for a section that two constructs both lead to, like a jump out of two loops at once
+/// the smallest one is picked, so that as little as possible gets copied, and one parent keeps the original
+fn r_split_shared(abstract_sections: &mut AbstractGraph) -> bool {
+    if abstract_sections.splits_left == 0 {
+        return false;
+    }
+
+    let dominators = abstract_sections.dominators();
+    let loops = abstract_sections.loops();
+
+    // how many sections a copy would drag along with it
+    let reach = |start: usize| {
+        let mut seen = vec![start];
+        let mut index = 0;
+        while index < seen.len() {
+            for child in abstract_sections.get_children(seen[index]) {
+                if !seen.contains(&child) {
+                    seen.push(child);
                 }
             }
+            index += 1;
+        }
+        seen.len()
+    };
 
-            output.push(format!("{}if ({}) {{", indent!(*indent), condition(last_instruction).unwrap_or("true".to_string())));
-            *indent += 1;
+    let candidate = abstract_sections.vertices.keys()
+        .filter(|id| **id != abstract_sections.get_root() && dominators.is_reachable(**id))
+        .filter(|id| loops.loop_with_header(**id).is_none())
+        .filter(|id| abstract_sections.get_edges(**id, Direction::Incoming).len() > 1)
+        .min_by_key(|id| reach(**id))
+        .copied();
 
-            // call function for if branch
-            convert_section(section.get_nested_sections().first().unwrap().clone(), output, abstract_map, concrete_sections, indent);
+    let Some(shared) = candidate else { return false; };
+
+    let parents: Vec<usize> = abstract_sections.get_edges(shared, Direction::Incoming)
+        .iter()
+        .skip(1)
+        .map(|e| abstract_sections.edges[*e].0)
+        .collect();
+    let copy = abstract_sections.split_node(shared, &parents[..1]);
+
+    if log_enabled!(Level::Info) {
+        info!("split shared section {} into {} for parent {}", shared, copy, parents[0]);
+    }
+
+    true
+}
+
+// ----------------------------------------
+
+/// # iteratively reduce the control-flow graph to nested abstract sections
+/// we start with an abstract map that is a 1-1 representation of the concrete instruction sections
+/// we then apply a series of reductions to it, such that each transformation, if successfully applied, decrease the size of the graph
+/// splitting a node is the exception, but there's only so many of those allowed
+/// because of this, it's guaranteed to stop
+/// this means i've basically solved the halting problem - take that, turing
+pub fn iterated_cfg_reduction(sections: SectionMap) -> Option<AbstractGraph> {
+    // generate 1-1 map of abstract sections
+    let mut abstract_graph = build_abstract_graph(&sections)?;
+
+    reduce_graph(&mut abstract_graph);
+
+    // a break only means anything inside the loop it leaves, and if that loop couldn't be reduced it isn't in one
+    // so it's done again without them, and those exits are left as gotos along with everything else that's left
+    if abstract_graph.vertices.values().any(|section| escapes(section, false)) {
+        abstract_graph = build_abstract_graph(&sections)?;
+        abstract_graph.breaks = false;
+
+        reduce_graph(&mut abstract_graph);
+    }
+
+    if log_enabled!(Level::Info) {
+        info!("reduced graph to {} sections and {} edges", abstract_graph.get_no_vertices(), abstract_graph.get_no_edges());
+    }
+
+    Some(abstract_graph)
+}
+
+/// whether a break or continue ended up somewhere other than in a loop
+/// only the construct of a loop is in it, whatever follows it isn't
+fn escapes(section: &AbstractSection, looped: bool) -> bool {
+    let inside = looped || matches!(section.get_type(), AbstractSectionType::SingleWhile | AbstractSectionType::While | AbstractSectionType::DoWhile);
+
+    (!looped && matches!(section.get_type(), AbstractSectionType::Break | AbstractSectionType::Continue)) ||
+    section.get_construct().iter().any(|nested| escapes(nested, inside)) ||
+    section.get_sequence().iter().any(|nested| escapes(nested, looped))
+}
+
+/// apply reductions until none of them do anything
+fn reduce_graph(abstract_graph: &mut AbstractGraph) {
+    let mut processing = true;
+
+    while processing {
+        processing = false;
+
+        // we attempt to apply the following reductions
+        // - drop whatever can't be reached
+        processing = processing || r_unreachable(abstract_graph);
+
+        // - reduce sequential blocks to single blocks
+        processing = processing || r_sequential_blocks(abstract_graph);
+
+        // - merge chains of branches to the same place into && and ||
+        processing = processing || r_short_circuit(abstract_graph);
 
-            *indent -= 1;
-            output.push(format!("{}}}", indent!(*indent)));
+        // - reduce simple loop to while
+        processing = processing || r_single_block_while(abstract_graph);
+
+        // - reduce any other loop with a single body section to while
+        processing = processing || r_while(abstract_graph);
+
+        // - reduce self-loop to do-while
+        processing = processing || r_do_while(abstract_graph);
+
+        // - reduce jump table to switch
+        processing = processing || r_switch(abstract_graph);
+
+        // - reduce single-step branch to if
+        processing = processing || r_if_then(abstract_graph);
+
+        // - reduce "diamond" to if-else statement
+        processing = processing || r_if_else(abstract_graph);
 
-            // we've already used the first in the if block
-            for remaining in section.get_nested_sections().iter().skip(1) {
-                convert_section(remaining.clone(), output, abstract_map, concrete_sections, indent);
+        // - take early exits out of loops as breaks and continues
+        processing = processing || r_loop_exits(abstract_graph);
+
+        // - reduce anything else acyclic with one way in and out to guarded sections
+        processing = processing || r_acyclic(abstract_graph);
+
+        // - copy the extra entries of irreducible loops, so they only have one
+        processing = processing || r_split_irreducible(abstract_graph);
+
+        // - copy whatever is shared between two constructs, if we're stuck
+        processing = processing || r_split_shared(abstract_graph);
+    }
+}
+
+// ----------------------------------------
+
+/// function to convert to a higher-level representation
+/// everything the function refers to is declared before it, so it compiles on its own
+fn high_level_conversion(concrete_sections: SectionMap, abstract_sections: AbstractGraph, signature: &Signature, types: &Types, signatures: &SignatureMap) -> Vec<Item> {
+    let mut items = vec![Item::Include("asha.h".to_string())];
+    let mut body: Vec<Stmt> = Vec::new();
+
+    // the structs it uses go first, so the function can refer to them
+    for (name, structure) in types.get_structs() {
+        items.push(Item::Struct { name: name.clone(), members: structure.members() });
+    }
+
+    // sections something still jumps to get a label to go to
+    // keyed by vertex rather than by the block it came from, as copies of a block are in different places
+    let vertices = abstract_sections.get_vertices();
+    let targets: BTreeSet<usize> = vertices.keys()
+        .flat_map(|id| abstract_sections.get_edges(*id, Direction::Outgoing))
+        .map(|idx| abstract_sections.get_edge(idx).unwrap().1)
+        .collect();
+
+    // call iteratively on any existing vertices
+    for (id, section) in vertices {
+        if targets.contains(&id) {
+            body.push(Stmt::Label(format!("section_{}", id)));
+        }
+
+        // get corresponding concrete section
+        convert_section(section, &mut body, &abstract_sections, &concrete_sections);
+
+        // if any outgoing edges from this section still exist
+        // goto that section, testing the branch first if it could go either way
+        // only the sections left in the graph can have any, everything nested has had its edges moved
+        let mut destinations: Vec<usize> = abstract_sections.get_children(id);
+        if destinations.len() == 2 && abstract_sections.is_conditional(id) {
+            if let Some((condition, taken)) = abstract_sections.vertices[&id].tail().branch.clone().filter(|(_, taken)| destinations.contains(taken)) {
+                destinations.retain(|dest| *dest != taken);
+                body.push(Stmt::If { condition: Expr::from_condition(&condition), then: vec![Stmt::Goto(format!("section_{}", taken))], otherwise: Vec::new() });
             }
-        },
-        AbstractSectionType::IfElse => {
-            // stringify each instruction in the new language and push to the output vector
-            for (index, instruction) in instructions.values().enumerate() {
-                // the last instruction will be handled in the guard
-                if index < count - 1 {
-                    output.push(convert_instruction(instruction, *indent));
+        }
+        for dest in destinations {
+            body.push(Stmt::Goto(format!("section_{}", dest)));
+        }
+    }
+
+    // falling off the end returns anyway
+    if body.last() == Some(&Stmt::Return(None)) {
+        body.pop();
+    }
+
+    let (called, symbols) = referenced(&concrete_sections);
+
+    // the functions it calls, other than itself, declared as the c library does if it has them and no header has said otherwise
+    // only what's known nowhere is left without any parameters
+    let library = libc();
+    for name in called.iter().filter(|name| **name != signature.get_name()) {
+        let callee = signatures.values().find(|other| other.get_name() == name);
+        let prototype = callee.and_then(|other| other.get_declared()).cloned()
+            .or_else(|| library.get(name).cloned())
+            .or_else(|| callee.map(|other| other.prototype()))
+            .or_else(|| Syscall::named(name).map(|syscall| syscall.prototype()))
+            .unwrap_or_else(|| Prototype { name: name.clone(), parameters: None, returns: Some(Type::Int { size: 8, signed: true }), variadic: false });
+        items.push(Item::Prototype(prototype));
+    }
+
+    // everything else it refers to by name is either in its frame or somewhere in the image
+    // neither has a type of its own, so both are just bytes
+    let mut locals = types.locals(signature);
+    for name in symbols.iter().filter(|name| !called.contains(*name) && **name != signature.get_name()) {
+        match slot_size(name) {
+            Some(size) => locals.push(Declaration::array(Type::Int { size: 1, signed: false }, name, size as u64)),
+            None => items.push(Item::Extern(Declaration::unsized_array(Type::Int { size: 1, signed: false }, name)))
+        }
+    }
+
+    // the branches saved in regions are only ever compared
+    let mut saved = BTreeSet::new();
+    for stmt in body.iter() {
+        stmt.walk(&mut |stmt| {
+            if let Stmt::Expr(Expr::Assign(dst, _)) = stmt {
+                if let Expr::Ident(name) = &**dst {
+                    if name.starts_with("cond_") {
+                        saved.insert(name.clone());
+                    }
                 }
             }
+        });
+    }
+    locals.extend(saved.into_iter().map(|name| Declaration::new(Type::Int { size: 4, signed: true }, name)));
 
-            output.push(format!("{}if ({}) {{", indent!(*indent), condition(last_instruction).unwrap_or("true".to_string())));
-            *indent += 1;
+    // everything else it uses is declared up front
+    let declarations = locals.into_iter().map(Stmt::Declare);
+    items.push(Item::Function { prototype: types.prototype(signature), body: declarations.chain(body).collect() });
 
-            // call function for if branch
-            convert_section(section.get_nested_sections().first().unwrap().clone(), output, abstract_map, concrete_sections, indent);
+    items
+}
 
-            *indent -= 1;
-            output.push(format!("{}}}", indent!(*indent)));
+/// the names of the functions a function calls, and of the symbols it refers to any other way
+fn referenced(sections: &SectionMap) -> (BTreeSet<String>, BTreeSet<String>) {
+    let mut called = BTreeSet::new();
+    let mut symbols = BTreeSet::new();
+
+    for statement in sections.values().flat_map(|section| section.get_statements().values().flatten()) {
+        let mut expressions = statement.get_expressions();
+
+        if let Statement::Call { target, .. } = statement {
+            match target {
+                Expression::Constant(address) => called.insert(format!("sub_{:x}", address)),
+                Expression::Symbol { name, offset: 0 } => called.insert(name.clone()),
+                _ => false
+            };
+            expressions.remove(0);
+        }
 
-            output.push(format!("{}else {{", indent!(*indent)));
-            *indent += 1;
+        for expr in expressions {
+            expr.for_each_symbol(&mut |name| {
+                symbols.insert(name.to_string());
+            });
+        }
+    }
+
+    (called, symbols)
+}
+
+// ----------------------------------------
+
+fn convert_section(section: AbstractSection, output: &mut Vec<Stmt>, abstract_map: &AbstractGraph, concrete_sections: &SectionMap) {
+    let concrete_section = concrete_sections.get(&section.get_id());
+    let instructions = concrete_section.unwrap().get_instructions();
+
+    // based on type of section, wrap guard and call on next section
+    // pass in output to keep pushing
+    // remember that we may have more sections contained, that just means that more sections follow, as this has been reduced down multiple times
+    let construct = section.get_construct();
+
+    match section.get_type() {
+        AbstractSectionType::If => {
+            // convert each instruction and push to the output
+            // the last instruction will be handled in the guard
+            convert_body(concrete_section.unwrap(), output);
+
+            // the branch is usually taken to skip the body, so this is often the negation of it
+            let guard = condition_towards(&section, construct[0].get_vertex()).unwrap_or(Condition::Constant(true));
 
             // call function for if branch
-            convert_section(section.get_nested_sections().get(1).unwrap().clone(), output, abstract_map, concrete_sections, indent);
+            let mut then = Vec::new();
+            convert_section(construct[0].clone(), &mut then, abstract_map, concrete_sections);
+
+            output.push(Stmt::If { condition: Expr::from_condition(&guard), then, otherwise: Vec::new() });
+        },
+        AbstractSectionType::IfElse => {
+            // convert each instruction and push to the output
+            // the last instruction will be handled in the guard
+            convert_body(concrete_section.unwrap(), output);
 
-            *indent -= 1;
-            output.push(format!("{}}}", indent!(*indent)));
+            let guard = condition_towards(&section, construct[0].get_vertex()).unwrap_or(Condition::Constant(true));
 
-            // this time we skip both of them
-            for remaining in section.get_nested_sections().iter().skip(2) {
-                convert_section(remaining.clone(), output, abstract_map, concrete_sections, indent);
-            }
+            // call function for if branch, then the else branch
+            let mut then = Vec::new();
+            convert_section(construct[0].clone(), &mut then, abstract_map, concrete_sections);
+            let mut otherwise = Vec::new();
+            convert_section(construct[1].clone(), &mut otherwise, abstract_map, concrete_sections);
+
+            output.push(Stmt::If { condition: Expr::from_condition(&guard), then, otherwise });
         },
         AbstractSectionType::SingleWhile => {
-            // stringify each instruction in the new language and push to the output vector
-            for (index, instruction) in instructions.values().enumerate() {
-                // the last instruction will be handled in the guard
-                if index < count - 1 {
-                    output.push(convert_instruction(instruction, *indent));
+            // the header is a single block, which stays in the loop while its test goes towards the body
+            let header = &construct[0];
+            let body = construct[1].clone();
+            let guard = last_instruction_of(header, concrete_sections);
+            let tested = concrete_sections.get(&header.tail().get_id()).unwrap().get_condition();
+            let test = condition_towards(header.tail(), body.get_vertex()).unwrap_or(Condition::Constant(true));
+
+            let header_instructions = concrete_sections.get(&header.get_id()).unwrap().get_instructions();
+            let body_section = concrete_sections.get(&body.get_id()).unwrap();
+            let body_instructions = body_section.get_instructions();
+
+            // the body either jumps back to the header at the end, or falls through into it
+            let jump = header_instructions.keys().next().and_then(|start| jump_back(body_section, *start));
+
+            // a plain body that ends by stepping a register the condition tests is a for loop
+            // only when there's just the one comparison, rather than a few merged together
+            let step = if body.get_nested_sections().is_empty() && tested.is_some_and(|c| c == test || c == test.negate()) {
+                induction_step(&guard, body_section, jump)
+            } else {
+                None
+            };
+
+            let mut inner = Vec::new();
+
+            if header_instructions.len() > 1 {
+                // anything else in the header has to run on every iteration, before the test
+                convert_section(header.clone(), &mut inner, abstract_map, concrete_sections);
+                inner.push(Stmt::If { condition: Expr::from_condition(&test.negate()), then: vec![Stmt::Break], otherwise: Vec::new() });
+                convert_section(body, &mut inner, abstract_map, concrete_sections);
+
+                output.push(Stmt::While { condition: Expr::ident("true"), body: inner });
+            } else if let Some(step_address) = step {
+                let step = body_section.get_statements()[&step_address].iter()
+                    .filter_map(Stmt::from_statement)
+                    .filter_map(|stmt| match stmt {
+                        Stmt::Expr(expr) => Some(expr),
+                        _ => None
+                    })
+                    .collect();
+
+                // the step is in the header now, and the jump back (if there is one) is the loop itself
+                for address in body_instructions.keys() {
+                    if *address != step_address && Some(*address) != jump {
+                        convert_instruction(body_section, *address, &mut inner);
+                    }
                 }
-            }
 
-            output.push(format!("{}while ({}) {{", indent!(*indent), condition(last_instruction).unwrap_or("true".to_string())));
-            *indent += 1;
+                output.push(Stmt::For { condition: Expr::from_condition(&test), step, body: inner });
+            } else {
+                convert_section(body, &mut inner, abstract_map, concrete_sections);
 
-            for inner in section.get_nested_sections() {
-                convert_section(inner.clone(), output, abstract_map, concrete_sections, indent);
+                output.push(Stmt::While { condition: Expr::from_condition(&test), body: inner });
             }
+        },
+        AbstractSectionType::While => {
+            // everything runs on every iteration, and the header's test (if it has one) becomes a break
+            let mut inner = Vec::new();
 
-            *indent -= 1;
-            output.push(format!("{}}}", indent!(*indent)));
+            let header = &construct[0];
+            convert_section(header.clone(), &mut inner, abstract_map, concrete_sections);
 
-            for remaining in section.get_nested_sections().iter().skip(1) {
-                convert_section(remaining.clone(), output, abstract_map, concrete_sections, indent);
+            if let Some(target) = section.target.filter(|_| !abstract_map.endless.contains(&section.get_vertex())) {
+                let guard = condition_towards(header.tail(), target).unwrap_or(Condition::Constant(true));
+                inner.push(Stmt::If { condition: Expr::from_condition(&guard), then: vec![Stmt::Break], otherwise: Vec::new() });
             }
+
+            for body in construct.iter().skip(1) {
+                convert_section(body.clone(), &mut inner, abstract_map, concrete_sections);
+            }
+
+            output.push(Stmt::While { condition: Expr::ident("true"), body: inner });
         },
         AbstractSectionType::DoWhile => {
             // no need to actually reduce the nodes, it's just logical in the output
             // since this is a do_while loop, it goes within its own while loop, and the branches come after
+            // the test is at the end of the last section in it, either the block itself or its latch
+            let mut inner = Vec::new();
+
+            for nested in construct {
+                convert_section(nested.clone(), &mut inner, abstract_map, concrete_sections);
+            }
 
-            output.push(format!("{}do {{", indent!(*indent)));
-            *indent += 1;
+            let guard = condition_towards(construct.last().unwrap().tail(), construct[0].get_vertex()).unwrap_or(Condition::Constant(true));
 
-            for (index, instruction) in instructions.values().enumerate() {
-                // the last instruction will be handled in the guard
-                if index < count - 1 {
-                    output.push(convert_instruction(instruction, *indent));
+            output.push(Stmt::DoWhile { body: inner, condition: Expr::from_condition(&guard) });
+        },
+        AbstractSectionType::Break | AbstractSectionType::Continue => {
+            convert_body(concrete_section.unwrap(), output);
+
+            // a section that only goes one way always leaves
+            let keyword = if section.get_type() == AbstractSectionType::Break { Stmt::Break } else { Stmt::Continue };
+            match condition_towards(&section, section.target.unwrap()) {
+                Some(guard) => output.push(Stmt::If { condition: Expr::from_condition(&guard), then: vec![keyword], otherwise: Vec::new() }),
+                None => output.push(keyword)
+            }
+        },
+        AbstractSectionType::Acyclic => {
+            convert_region(&section, output, abstract_map, concrete_sections);
+        },
+        AbstractSectionType::Switch => {
+            let table = concrete_section.unwrap().get_jump_table().unwrap();
+
+            // the table lookup is what the switch replaces, so leave it out
+            for address in instructions.keys() {
+                if !table.get_lookup().contains(address) {
+                    convert_instruction(concrete_section.unwrap(), *address, output);
                 }
             }
 
-            *indent -= 1;
-            output.push(format!("{}}} while ({});", indent!(*indent), condition(last_instruction).unwrap_or("true".to_string())));
+            // the construct is the cases, one for each distinct destination
+            let mut cases = Vec::new();
+            for case in construct {
+                let start = concrete_sections.get(&case.get_id()).unwrap().start;
+
+                let mut inner = Vec::new();
+                convert_section(case.clone(), &mut inner, abstract_map, concrete_sections);
 
-            for remaining in section.get_nested_sections().iter() {
-                convert_section(remaining.clone(), output, abstract_map, concrete_sections, indent);
+                // falling through to the next case is left as it is
+                if !section.region_edges.iter().any(|(src, _)| *src == case.get_vertex()) {
+                    inner.push(Stmt::Break);
+                }
+
+                cases.push((table.cases_for(start), inner));
             }
+
+            let on = Expr::Ident(concrete_section.unwrap().get_switch_on().unwrap().to_string());
+            output.push(Stmt::Switch { on, cases });
         }
         AbstractSectionType::Unbranching => {
-            // stringify each instruction in the new language and push to the output vector
-            for (index, instruction) in instructions.values().enumerate() {
-                // the last instruction will be handled in the guard
-                if index < count - 1 {
-                    output.push(convert_instruction(instruction, *indent));
+            // convert each instruction and push to the output
+            convert_body(concrete_section.unwrap(), output);
+        }
+    }
+
+    // anything else reduced into this section follows it
+    for remaining in section.get_sequence() {
+        convert_section(remaining.clone(), output, abstract_map, concrete_sections);
+    }
+}
+
+/// # output an acyclic region
+/// each section is guarded by the condition for reaching it from the entry
+/// each two-way branch is saved to a variable when it's reached, as the registers it tests could change before it's used
+///
+/// the guards then get refined, as in "no more gotos":
+/// sections in a row with the same guard share an if, and one with the opposite guard to the last becomes its else
+fn convert_region(section: &AbstractSection, output: &mut Vec<Stmt>, abstract_map: &AbstractGraph, concrete_sections: &SectionMap) {
+    let mut reaching: BTreeMap<usize, Condition> = BTreeMap::new();
+
+    // the guard of the if we're currently in, and the if itself, with whether that's the else part yet
+    let mut open: Option<(Condition, Stmt)> = None;
+    let mut in_else = false;
+
+    for (index, inner) in section.get_construct().iter().enumerate() {
+        let id = inner.get_vertex();
+
+        // combine the ways into this section
+        let guard = if index == 0 {
+            Condition::Constant(true)
+        } else {
+            section.region_edges.iter()
+                .filter(|(_, dst)| *dst == id)
+                .map(|(src, _)| {
+                    let from = reaching.get(src).cloned().unwrap_or(Condition::Constant(true));
+                    let branch = region_branch(section, *src, id).unwrap_or(Condition::Constant(true));
+
+                    Condition::and(from, branch)
+                })
+                .fold(Condition::Constant(false), Condition::or)
+        };
+
+        let guarded = !guard.is_true();
+        match open.take() {
+            Some((current, stmt)) if current == guard => open = Some((current, stmt)),
+            Some((current, stmt)) if !in_else && current.negate() == guard => {
+                open = Some((guard.clone(), stmt));
+                in_else = true;
+            },
+            current => {
+                if let Some((_, stmt)) = current {
+                    output.push(stmt);
                 }
-            }
 
-            for remaining in section.get_nested_sections() {
-                convert_section(remaining.clone(), output, abstract_map, concrete_sections, indent);
+                if guarded {
+                    open = Some((guard.clone(), Stmt::If { condition: Expr::from_condition(&guard), then: Vec::new(), otherwise: Vec::new() }));
+                }
+                in_else = false;
             }
-        },
-        _ => {
-            unimplemented!()
         }
-    }
 
-    // if any outgoing edges from this function still exist
-    // goto that section
-    let outgoing = abstract_map.get_edges(section.get_id(), Direction::Outgoing);
-    if !outgoing.is_empty() {
-        for idx in outgoing {
-            let (_, dest) = abstract_map.get_edge(idx).unwrap();
-            output.push(format!("{}GOTO section {};", indent!(*indent), dest));
+        // whichever part of the if it's in, if it's in one
+        let block = match open.as_mut() {
+            Some((_, Stmt::If { otherwise, .. })) if in_else => otherwise,
+            Some((_, Stmt::If { then, .. })) => then,
+            _ => &mut *output
+        };
+
+        convert_section(inner.clone(), block, abstract_map, concrete_sections);
+
+        // save the branch for the sections after it
+        let branches = section.region_edges.iter().filter(|(src, _)| *src == id).count();
+        if branches == 2 {
+            let saved = Expr::assign(Expr::Ident(format!("cond_{}", id)), Expr::from_condition(&branch_condition(inner)));
+            block.push(Stmt::Expr(saved));
         }
+
+        reaching.insert(id, guard);
+    }
+
+    if let Some((_, stmt)) = open {
+        output.push(stmt);
     }
 }
 
-/// process each single instruction
-fn convert_instruction(inst: &InstructionType, indent: usize) -> String {
-    // do the actual logic here
-    if let Some(op) = operator(inst) {
-        return format!("{}{};", indent!(indent), op);
+/// the condition on the edge from one section of a region to another, or none if it always goes there
+/// it's in terms of the variable the branch was saved to
+fn region_branch(section: &AbstractSection, src: usize, dst: usize) -> Option<Condition> {
+    let branches = section.region_edges.iter().filter(|(s, _)| *s == src).count();
+    if branches < 2 {
+        return None;
+    }
+
+    let from = section.get_construct().iter().find(|s| s.get_vertex() == src)?;
+    let (_, taken) = from.tail().branch.as_ref()?;
+    let saved = Condition::variable(&format!("cond_{}", src));
+
+    if *taken == dst {
+        Some(saved)
+    } else {
+        Some(saved.negate())
     }
+}
+
+/// # condition for a section to go to a given destination
+/// the branch at the end of its block is taken when its condition holds, and falls through otherwise
+fn condition_towards(section: &AbstractSection, destination: usize) -> Option<Condition> {
+    let (cond, taken) = section.branch.as_ref()?;
 
-    if inst.get_name() == "syscall" {
-        return format!("{}{};", indent!(indent), "ecall()");
+    if *taken == destination {
+        Some(cond.clone())
+    } else {
+        Some(cond.negate())
     }
+}
+
+/// the condition at the end of whatever a section runs last
+fn branch_condition(section: &AbstractSection) -> Condition {
+    section.tail().branch
+        .as_ref()
+        .map(|(cond, _)| cond.clone())
+        .unwrap_or(Condition::Constant(true))
+}
+
+/// the last instruction of a section, and its address
+fn last_of(section: &AbstractSection, concrete_sections: &SectionMap) -> (u64, InstructionType) {
+    concrete_sections.get(&section.get_id())
+        .unwrap()
+        .get_instructions()
+        .pop_last()
+        .unwrap()
+}
+
+/// the last instruction run by a section, which is where its branch is
+fn last_instruction_of(section: &AbstractSection, concrete_sections: &SectionMap) -> InstructionType {
+    last_of(section.tail(), concrete_sections).1
+}
+
+/// output the statements of a block, apart from any branch or jump, which the structure replaces
+/// a return is kept, as it could be anywhere in the structure now
+fn convert_body(section: &InstructionSection, output: &mut Vec<Stmt>) {
+    for address in section.get_statements().keys() {
+        convert_instruction(section, *address, output);
+    }
+}
+
+/// # find the jump at the end of a loop body
+/// the address of the last instruction, if it's an unconditional branch to the start of the header
+fn jump_back(body: &InstructionSection, header: u64) -> Option<u64> {
+    let (address, statements) = body.get_statements().iter().next_back()?;
+
+    match statements.as_slice() {
+        [Statement::Branch { condition: None, target: Expression::Constant(target) }] if *target as u64 == header => Some(*address),
+        _ => None
+    }
+}
+
+/// # find the step of a for loop
+/// the body has to add a constant to a register the condition tests, with nothing after that (other than the jump back) touching it,
+/// so the step can be moved to the end of every iteration without changing anything
+/// gives the address of the step
+fn induction_step(guard: &InstructionType, body: &InstructionSection, jump: Option<u64>) -> Option<u64> {
+    if !matches!(guard, InstructionType::B { .. }) {
+        return None;
+    }
+
+    let tested = [guard.get_rs1(), guard.get_rs2()];
+    let instructions = body.get_instructions();
+    let statements = body.get_statements();
+
+    let (address, inst) = instructions.iter().rev()
+        .filter(|(address, _)| Some(**address) != jump)
+        .find(|(_, inst)| matches!(inst.get_name(), "addi" | "addiw") && inst.get_rd() == inst.get_rs1() && tested.contains(&inst.get_rd()))?;
+    if inst.get_rd() == ABIRegister::zero {
+        return None;
+    }
+
+    // calls could change it without saying so
+    let register = Variable::Register(inst.get_rd());
+    let touched = statements.range(address + 1..)
+        .filter(|(later, _)| Some(**later) != jump)
+        .flat_map(|(_, statements)| statements)
+        .any(|statement| {
+            statement.get_uses().contains(&register) || statement.get_def() == Some(&register) ||
+            matches!(statement, Statement::Call { .. } | Statement::Intrinsic { .. })
+        });
+
+    (!touched).then_some(*address)
+}
 
-    // yes this is half-assed, this has to somewhat function in the next 4 hours
-    format!("{}{};", indent!(indent), inst)
+/// output whatever a single instruction does, other than where it goes next
+fn convert_instruction(section: &InstructionSection, address: u64, output: &mut Vec<Stmt>) {
+    output.extend(section.get_statements()[&address].iter().filter_map(Stmt::from_statement));
 }
 
 // ----------------------------------------
 
-/// function to be called by the main app
-pub fn output_decompiled_code(cfg: SectionMap) -> Vec<String> {
+/// # decompile a function into c
+/// the image gives the value of `gp` and names for the addresses the code refers to,
+/// and the signatures say what each function called takes and gives back, including this one
+/// a function missing from them is worked out on its own
+/// fields of the structs it uses can be given names instead of their offsets
+pub fn decompile(cfg: SectionMap, image: &Image, signatures: &SignatureMap, fields: &FieldNames) -> Vec<Item> {
+    decompile_typed(cfg, image, signatures, fields).0
+}
+
+/// decompile a function, along with the signature and types it was given
+pub fn decompile_typed(mut cfg: SectionMap, image: &Image, signatures: &SignatureMap, fields: &FieldNames) -> (Vec<Item>, Signature, Types) {
+    let start = cfg.values().next().and_then(|section| section.get_statements().keys().next().copied()).unwrap_or(0);
+    let signature = signatures.get(&start).cloned().unwrap_or_else(|| {
+        let mut resolved = cfg.clone();
+        resolve_syscalls(&mut resolved, image);
+        Signature::infer(format!("sub_{:x}", start), &resolved, signatures)
+    });
+
+    resolve_calls(&mut cfg, signatures);
+    pass_syscall_arguments(&mut cfg);
+    signature.resolve_returns(&mut cfg);
+    recover_stack_frame(&mut cfg);
+    simplify(&mut cfg, image);
+
+    // a system call takes fewer arguments once it's known, which leaves whatever else it was passed unread
+    if name_syscalls(&mut cfg) {
+        simplify(&mut cfg, image);
+    }
+    recover_variables(&mut cfg);
+    label_strings(&mut cfg, image, signatures);
+    label_symbols(&mut cfg, image);
+
+    let mut types = Types::infer(&cfg, &signature, signatures);
+    types.rename_fields(fields);
+    types.apply(&mut cfg);
+
     let reduced_graph = iterated_cfg_reduction(cfg.clone());
+    let items = high_level_conversion(cfg, reduced_graph.unwrap(), &signature, &types, signatures);
+
+    (items, signature, types)
+}
 
-    high_level_conversion(cfg, reduced_graph.unwrap())
+/// the decompiled function, printed in the default style
+#[cfg(test)]
+pub fn output_decompiled_code(cfg: SectionMap, image: &Image, signatures: &SignatureMap, fields: &FieldNames) -> Vec<String> {
+    crate::ast::Style::default().print(&decompile(cfg, image, signatures, fields))
 }
 
 // ----------------------------------------
@@ -1106,6 +2432,8 @@ pub fn output_decompiled_code(cfg: SectionMap) -> Vec<String> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::ast::HEADER;
+    use crate::fixtures::{addi, branch, ecall, jump, program, r, random_program, ret, sections};
 
     // helper functions to create graphs
 
@@ -1168,6 +2496,38 @@ mod test {
         graph
     }
 
+    /// a switch on a0 with three cases, read from a table of absolute addresses
+    /// case 0 and case 2 share a destination, and every case rejoins at 0x128
+    fn create_switch_program() -> (BTreeMap<u64, InstructionType>, JumpTableMap) {
+        use crate::image::Image;
+        use crate::jumptable::find_jump_tables;
+        use ABIRegister::*;
+
+        let mut instructions = BTreeMap::new();
+        instructions.insert(0x100, InstructionType::I { name: "sltiu", rd: a5, rs1: a0, imm: 3 });
+        instructions.insert(0x104, InstructionType::B { name: "beq", rs1: a5, rs2: zero, imm: 0x24 });
+        instructions.insert(0x108, InstructionType::I { name: "slli", rd: a0, rs1: a0, imm: 3 });
+        instructions.insert(0x10c, InstructionType::U { name: "lui", rd: a5, imm: 2 });
+        instructions.insert(0x110, InstructionType::R { name: "add", rd: a0, rs1: a0, rs2: a5 });
+        instructions.insert(0x114, InstructionType::I { name: "ld", rd: a0, rs1: a0, imm: 0 });
+        instructions.insert(0x118, InstructionType::I { name: "jalr", rd: zero, rs1: a0, imm: 0 });
+        instructions.insert(0x11c, InstructionType::J { name: "jal", rd: zero, imm: 0xc });
+        instructions.insert(0x120, InstructionType::I { name: "addi", rd: a1, rs1: zero, imm: 1 });
+        instructions.insert(0x124, InstructionType::J { name: "jal", rd: zero, imm: 0x4 });
+        instructions.insert(0x128, InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 });
+
+        let mut table = Vec::new();
+        for target in [0x11cu64, 0x120, 0x11c] {
+            table.extend_from_slice(&target.to_le_bytes());
+        }
+
+        let mut image = Image::new();
+        image.add_section(".rodata", 0x2000, table, false);
+
+        let tables = find_jump_tables(&instructions, &image);
+        (instructions, tables)
+    }
+
     // functions to test specific graph functionalities
     // part 1: constructed graph
 
@@ -1286,6 +2646,572 @@ mod test {
         assert_eq!(count, 10);
     }
 
+    #[test]
+    fn test_make_blocks_splits_at_destinations() {
+        use ABIRegister::*;
+
+        // a loop whose head is in the middle of straight-line code
+        let mut instructions = BTreeMap::new();
+        instructions.insert(0x100, InstructionType::I { name: "addi", rd: a0, rs1: zero, imm: 0 });
+        instructions.insert(0x104, InstructionType::I { name: "addi", rd: a0, rs1: a0, imm: 1 });
+        instructions.insert(0x108, InstructionType::B { name: "bne", rs1: a0, rs2: a1, imm: -4 });
+        instructions.insert(0x10c, InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 });
+
+        let sections = generate_sections(instructions, &BTreeMap::new());
+
+        // the loop head gets a section of its own, and nothing follows the return
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[&0].get_instructions().len(), 1);
+        assert_eq!(sections[&1].start, 0x104);
+        assert_eq!(sections[&1].get_branches().iter().map(|b| b.get_id()).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(sections[&2].branch_type, Some(BranchType::Return));
+        assert!(sections[&2].get_branches().is_empty());
+    }
+
+    #[test]
+    fn test_jump_table_edges() {
+        let (instructions, tables) = create_switch_program();
+        let sections = generate_sections(instructions, &tables);
+
+        // the jump gets one edge per distinct case
+        let switch = sections.values().find(|s| s.get_jump_table().is_some()).unwrap();
+        assert_eq!(switch.branch_type, Some(BranchType::Indirect));
+
+        let destinations: Vec<u64> = switch.get_branches().iter().map(|b| b.start).collect();
+        assert_eq!(destinations, vec![0x11c, 0x120]);
+    }
+
+    #[test]
+    fn test_r_switch() {
+        let (instructions, tables) = create_switch_program();
+        let sections = generate_sections(instructions, &tables);
+        let mut graph = build_abstract_graph(&sections).unwrap();
+
+        let switch = sections.values().find(|s| s.get_jump_table().is_some()).unwrap().get_id();
+
+        let reduced = r_switch(&mut graph);
+        assert!(reduced);
+
+        let modified = graph.vertices.get(&switch).unwrap();
+        assert_eq!(modified.get_type(), AbstractSectionType::Switch);
+        assert_eq!(modified.abstract_sections.len(), 2);
+    }
+
+    #[test]
+    fn test_switch_output() {
+        let (instructions, tables) = create_switch_program();
+        let output = output_decompiled_code(generate_sections(instructions, &tables), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+
+        let output: Vec<&str> = output.iter().map(|l| l.trim()).collect();
+        let expected = vec![
+            "switch (a0) {",
+            "case 0:",
+            "case 2:",
+            "break;",
+            "case 1:",
+            "a1 = 1;",
+            "break;",
+            "}"
+        ];
+        let start = output.iter().position(|l| *l == expected[0]).unwrap();
+        assert_eq!(output[start..start + expected.len()], expected);
+
+        // none of the table lookup is left
+        assert!(!output.iter().any(|l| l.contains("jalr")));
+    }
+
+    #[test]
+    fn test_r_do_while_two_blocks() {
+        // 1 falls into 2, which decides whether to go round again
+        let mut graph = AbstractGraph::new();
+        for i in 0..4 {
+            graph.vertices.insert(i, AbstractSection::new(AbstractSectionType::Unbranching, i));
+        }
+        graph.edges.push((0, 1));
+        graph.edges.push((1, 2));
+        graph.edges.push((2, 1));
+        graph.edges.push((2, 3));
+
+        assert!(r_do_while(&mut graph));
+
+        // the loop is wrapped around the header and latch
+        let modified = graph.vertices.get(&1).unwrap();
+        assert_eq!(modified.get_type(), AbstractSectionType::DoWhile);
+        let construct: Vec<usize> = modified.get_construct().iter().map(|s| s.get_id()).collect();
+        assert_eq!(construct, vec![1, 2]);
+        assert_eq!(graph.edges, vec![(0, 1), (1, 3)]);
+    }
+
+    /// graph from a list of edges, with a plain section for every vertex
+    fn create_graph(edges: &[(usize, usize)], no_vertices: usize) -> AbstractGraph {
+        let mut graph = AbstractGraph::new();
+        for i in 0..no_vertices {
+            graph.vertices.insert(i, AbstractSection::new(AbstractSectionType::Unbranching, i));
+        }
+        graph.edges.extend_from_slice(edges);
+        graph.next_vertex = no_vertices;
+        graph.splits_left = no_vertices;
+
+        graph
+    }
+
+    #[test]
+    fn test_sequence_keeps_construct() {
+        // an if-then, followed by a block that gets concatenated onto it
+        let mut graph = create_graph(&[(0, 1), (0, 2), (1, 2), (2, 3)], 4);
+
+        assert!(r_if_then(&mut graph));
+        while r_sequential_blocks(&mut graph) {}
+        assert_eq!(graph.get_no_vertices(), 1);
+
+        let modified = graph.vertices.get(&0).unwrap();
+        assert_eq!(modified.get_type(), AbstractSectionType::If);
+        assert_eq!(modified.get_construct().len(), 1);
+        assert_eq!(modified.get_sequence().first().unwrap().get_id(), 2);
+    }
+
+    #[test]
+    fn test_r_loop_exits_break() {
+        // 1 tests before each iteration, 2 can break out to 4 as well
+        let mut graph = create_graph(&[(0, 1), (1, 2), (1, 4), (2, 3), (2, 4), (3, 1)], 5);
+
+        assert!(r_loop_exits(&mut graph));
+        assert!(!graph.contains_edge(2, 4));
+        assert_eq!(graph.vertices[&2].get_type(), AbstractSectionType::Break);
+        assert_eq!(graph.vertices[&2].target, Some(4));
+
+        reduce_graph(&mut graph);
+        assert_eq!(graph.get_no_vertices(), 1);
+    }
+
+    #[test]
+    fn test_r_loop_exits_continue() {
+        // 2 can go back to the header early, as well as on to 3
+        let mut graph = create_graph(&[(0, 1), (1, 2), (1, 4), (2, 3), (2, 1), (3, 1)], 5);
+
+        assert!(r_loop_exits(&mut graph));
+        assert_eq!(graph.vertices[&2].get_type(), AbstractSectionType::Continue);
+
+        reduce_graph(&mut graph);
+        assert_eq!(graph.get_no_vertices(), 1);
+    }
+
+    #[test]
+    fn test_r_while_endless() {
+        // nothing tests at the top or bottom, the only way out is from the middle
+        let mut graph = create_graph(&[(0, 1), (1, 2), (2, 3), (2, 4), (3, 1)], 5);
+
+        reduce_graph(&mut graph);
+        assert_eq!(graph.get_no_vertices(), 1);
+
+        let loop_section = &graph.vertices[&0].get_sequence()[0];
+        assert_eq!(loop_section.get_type(), AbstractSectionType::While);
+        assert!(graph.endless.contains(&1));
+    }
+
+    #[test]
+    fn test_r_acyclic() {
+        // two conditions sharing a block, which no if reduction can handle
+        let mut graph = create_graph(&[(0, 1), (0, 2), (1, 2), (1, 3), (2, 3)], 4);
+
+        assert!(!r_if_then(&mut graph) && !r_if_else(&mut graph));
+        assert!(r_acyclic(&mut graph));
+
+        let region = &graph.vertices[&0];
+        assert_eq!(region.get_type(), AbstractSectionType::Acyclic);
+        let order: Vec<usize> = region.get_construct().iter().map(|s| s.get_id()).collect();
+        assert_eq!(order, vec![0, 1, 2]);
+        assert_eq!(graph.edges, vec![(0, 3)]);
+    }
+
+    #[test]
+    fn test_while_output() {
+        use ABIRegister::*;
+
+        // a loop with an if at the top, and the exit test in the middle
+        let mut instructions = BTreeMap::new();
+        instructions.insert(0x100, InstructionType::I { name: "addi", rd: a0, rs1: zero, imm: 0 });
+        instructions.insert(0x104, InstructionType::B { name: "blt", rs1: a0, rs2: a1, imm: 0x8 });
+        instructions.insert(0x108, InstructionType::I { name: "addi", rd: a0, rs1: a0, imm: 1 });
+        instructions.insert(0x10c, InstructionType::I { name: "addi", rd: a2, rs1: a2, imm: -1 });
+        instructions.insert(0x110, InstructionType::B { name: "beq", rs1: a2, rs2: zero, imm: 0xc });
+        instructions.insert(0x114, InstructionType::I { name: "addi", rd: a3, rs1: a3, imm: 1 });
+        instructions.insert(0x118, InstructionType::J { name: "jal", rd: zero, imm: -0x14 });
+        instructions.insert(0x11c, InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 });
+
+        let output = output_decompiled_code(generate_sections(instructions, &JumpTableMap::new()), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+        let output: Vec<&str> = output.iter().map(|l| l.trim()).collect();
+
+        let start = output.iter().position(|l| *l == "while (true) {").unwrap();
+        let expected = vec![
+            "while (true) {",
+            "if (a0 >= a1) {",
+            "a0 = a0 + 1;",
+            "}",
+            "a2 = a2 - 1;",
+            "if (a2 == 0) break;",
+            "a3 = a3 + 1;",
+            "}"
+        ];
+        assert_eq!(output[start..start + expected.len()], expected);
+        assert!(!output.iter().any(|l| l.contains("goto")));
+    }
+
+    #[test]
+    fn test_acyclic_output() {
+        use ABIRegister::*;
+
+        // if a0 is zero, or a1 + 1 == a2, do the last block
+        let mut instructions = BTreeMap::new();
+        instructions.insert(0x100, InstructionType::B { name: "beq", rs1: a0, rs2: zero, imm: 0xc });
+        instructions.insert(0x104, InstructionType::I { name: "addi", rd: a1, rs1: a1, imm: 1 });
+        instructions.insert(0x108, InstructionType::B { name: "bne", rs1: a1, rs2: a2, imm: 0xc });
+        instructions.insert(0x10c, InstructionType::I { name: "addi", rd: s1, rs1: s1, imm: 1 });
+        instructions.insert(0x110, InstructionType::I { name: "addi", rd: s2, rs1: s2, imm: 2 });
+        instructions.insert(0x114, InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 });
+
+        let output = output_decompiled_code(generate_sections(instructions, &JumpTableMap::new()), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+        let output: Vec<&str> = output.iter().map(|l| l.trim()).collect();
+
+        let expected = vec![
+            "#include \"asha.h\"",
+            "",
+            "void sub_100(int64_t a0, int64_t a1, int64_t a2) {",
+            "int64_t s1;",
+            "int64_t s2;",
+            "int32_t cond_0;",
+            "int32_t cond_1;",
+            "cond_0 = a0 == 0;",
+            "if (!cond_0) {",
+            "a1 = a1 + 1;",
+            "cond_1 = a1 != a2;",
+            "}",
+            "if (cond_0 || (!cond_0 && !cond_1)) {",
+            "s1 = s1 + 1;",
+            "s2 = s2 + 2;",
+            "}",
+            "}"
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_for_loop_output() {
+        use ABIRegister::*;
+
+        // for (a0 = 0; a0 < a1; a0++) a2 += a0;
+        let mut instructions = BTreeMap::new();
+        instructions.insert(0x100, InstructionType::I { name: "addi", rd: a0, rs1: zero, imm: 0 });
+        instructions.insert(0x104, InstructionType::B { name: "bge", rs1: a0, rs2: a1, imm: 0x10 });
+        instructions.insert(0x108, InstructionType::R { name: "add", rd: a2, rs1: a2, rs2: a0 });
+        instructions.insert(0x10c, InstructionType::I { name: "addi", rd: a0, rs1: a0, imm: 1 });
+        instructions.insert(0x110, InstructionType::J { name: "jal", rd: zero, imm: -0xc });
+        instructions.insert(0x114, InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 });
+
+        let output = output_decompiled_code(generate_sections(instructions, &JumpTableMap::new()), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+        let output: Vec<&str> = output.iter().map(|l| l.trim()).collect();
+
+        let start = output.iter().position(|l| *l == "for (; i < a1; i = i + 1) {").unwrap();
+        assert_eq!(output[start + 1..start + 3], ["a2 = a2 + i;", "}"]);
+        assert!(!output.iter().any(|l| l.contains("goto")));
+    }
+
+    #[test]
+    fn test_r_loop_exits_refinement() {
+        // the loop leaves for 5 from its header, and for 4 from the middle, which then goes on to 5
+        let mut graph = create_graph(&[(0, 1), (1, 2), (1, 5), (2, 3), (2, 4), (3, 1), (4, 5), (5, 6)], 7);
+
+        assert!(r_loop_exits(&mut graph));
+        assert_eq!(graph.vertices[&4].get_type(), AbstractSectionType::Break);
+        assert_eq!(graph.vertices[&4].target, Some(5));
+        assert!(graph.get_children(4).is_empty());
+
+        reduce_graph(&mut graph);
+        assert_eq!(graph.get_no_vertices(), 1);
+        assert!(graph.edges.is_empty());
+    }
+
+    #[test]
+    fn test_r_split_irreducible() {
+        // 1 and 2 jump to each other, and both can be entered from 0
+        let mut graph = create_graph(&[(0, 1), (0, 2), (1, 2), (2, 1), (2, 3)], 4);
+
+        assert!(r_split_irreducible(&mut graph));
+        assert_eq!(graph.copies, BTreeMap::from([(4, 2)]));
+        assert_eq!(graph.get_children(4), vec![1, 3]);
+        assert!(graph.loops().get_loops().iter().all(|l| l.is_reducible()));
+
+        reduce_graph(&mut graph);
+        assert_eq!(graph.get_no_vertices(), 1);
+        assert!(graph.edges.is_empty());
+    }
+
+    #[test]
+    fn test_r_split_shared() {
+        // 3 leaves both loops at once, for the same place the outer one goes
+        let mut graph = create_graph(&[(0, 1), (1, 2), (1, 5), (2, 3), (2, 4), (3, 2), (3, 5), (4, 1), (5, 6)], 7);
+
+        reduce_graph(&mut graph);
+        assert_eq!(graph.get_no_vertices(), 1);
+        assert!(graph.edges.is_empty());
+        assert!(graph.copies.values().all(|c| *c == 5 || *c == 6));
+    }
+
+    #[test]
+    fn test_r_switch_fallthrough() {
+        // case 1 falls through into case 2
+        let mut graph = create_graph(&[(0, 1), (0, 2), (0, 3), (1, 2), (2, 4), (3, 4)], 5);
+        graph.switches.insert(0);
+
+        assert!(r_switch(&mut graph));
+        assert_eq!(graph.vertices[&0].get_type(), AbstractSectionType::Switch);
+        assert_eq!(graph.vertices[&0].region_edges, vec![(1, 2)]);
+        assert_eq!(graph.edges, vec![(0, 4)]);
+    }
+
+    #[test]
+    fn test_early_return_output() {
+        use ABIRegister::*;
+
+        // look for a0 between 0 and a1, returning as soon as it's found
+        let mut instructions = BTreeMap::new();
+        instructions.insert(0x100, InstructionType::I { name: "addi", rd: a2, rs1: zero, imm: 0 });
+        instructions.insert(0x104, InstructionType::B { name: "bge", rs1: a2, rs2: a1, imm: 0x18 });
+        instructions.insert(0x108, InstructionType::B { name: "beq", rs1: a2, rs2: a0, imm: 0xc });
+        instructions.insert(0x10c, InstructionType::I { name: "addi", rd: a2, rs1: a2, imm: 1 });
+        instructions.insert(0x110, InstructionType::J { name: "jal", rd: zero, imm: -0xc });
+        instructions.insert(0x114, InstructionType::I { name: "addi", rd: a0, rs1: a2, imm: 0 });
+        instructions.insert(0x118, InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 });
+        instructions.insert(0x11c, InstructionType::I { name: "addi", rd: a0, rs1: zero, imm: -1 });
+        instructions.insert(0x120, InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 });
+
+        let output = output_decompiled_code(generate_sections(instructions, &JumpTableMap::new()), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+        let output: Vec<&str> = output.iter().map(|l| l.trim()).collect();
+
+        // both returns give back what was put in a0 just before them
+        let start = output.iter().position(|l| *l == "if (i == a0) {").unwrap();
+        assert_eq!(output[..3], ["#include \"asha.h\"", "", "int64_t sub_100(int64_t a0, int64_t a1) {"]);
+        assert_eq!(output[start..start + 5], ["if (i == a0) {", "return i;", "}", "i = i + 1;", "}"]);
+        assert_eq!(output[output.len() - 2..], ["return -1;", "}"]);
+        assert!(!output.iter().any(|l| l.contains("goto")));
+    }
+
+    /// # check the output has nothing after it leaves a block, other than a label, and only leaves loops from inside them
+    /// it's printed one statement to a line, so each block is whatever's between a line opening it and the one closing it
+    fn assert_well_formed(output: &[String]) {
+        let lines: Vec<&str> = output.iter().map(|l| l.trim()).collect();
+
+        // whether each block we're in is a loop, or a switch, which can be broken out of too
+        let mut blocks: Vec<(bool, bool)> = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            let leaves = line.starts_with("return") || line.starts_with("goto ") || *line == "break;" || *line == "continue;";
+            let next = lines.get(index + 1).copied().unwrap_or("}");
+            assert!(!leaves || next.starts_with('}') || next.trim_end_matches(';').ends_with(':'), "code after {} in {:#?}", line, lines);
+
+            if line.starts_with('}') {
+                blocks.pop();
+            }
+            if line.ends_with("break;") {
+                assert!(blocks.iter().any(|(looped, switched)| *looped || *switched), "break outside a loop in {:#?}", lines);
+            }
+            if line.ends_with("continue;") {
+                assert!(blocks.iter().any(|(looped, _)| *looped), "continue outside a loop in {:#?}", lines);
+            }
+            if line.ends_with('{') {
+                let looped = ["while ", "for ", "do "].iter().any(|keyword| line.starts_with(keyword));
+                blocks.push((looped, line.starts_with("switch ")));
+            }
+        }
+    }
+
+    /// # check the output is well formed, without any gotos
+    fn assert_structured(output: &[String]) {
+        assert!(!output.iter().any(|l| l.contains("goto")), "goto in {:#?}", output);
+        assert_well_formed(output);
+    }
+
+    #[test]
+    fn test_break_stays_in_loop() {
+        use ABIRegister::*;
+
+        // the loop leaves from 0x120, which is also where the dead code before it falls into
+        let output = output_decompiled_code(sections(vec![
+            branch("bgeu", a1, a2, 0x4),
+            addi(a3, a2, 2),
+            r("xor", a1, a2, a4),
+            branch("bgeu", a2, a3, 0x14),
+            jump(0x18),
+            ret(),
+            addi(a0, a0, 0),
+            addi(a2, a4, 0),
+            branch("bne", a2, a1, -0x10),
+            jump(-0x24),
+            ret(),
+            ret()
+        ]), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+
+        assert_structured(&output);
+    }
+
+    #[test]
+    fn test_unreachable_left_out() {
+        use ABIRegister::*;
+
+        // 0x110 and everything from 0x120 can't be reached, and 0x11c is where both can go
+        let output = output_decompiled_code(sections(vec![
+            r("xor", a1, a4, a4),
+            branch("bne", a1, a3, 0x18),
+            r("add", a0, a1, a2),
+            jump(0x10),
+            addi(a4, a4, -2),
+            r("slt", a0, a2, a0),
+            r("addw", a0, a2, a0),
+            ret(),
+            branch("bge", a4, a0, 0xc),
+            addi(a0, a0, -3),
+            branch("beq", a1, a0, -0xc),
+            ret()
+        ]), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+
+        assert_structured(&output);
+        assert_eq!(output[output.len() - 6..], ["\ta1 = a4 ^ a4;", "\tif (a1 == a3) {", "\t\ta0 = a1 + a2;", "\t}", "\treturn a0;", "}"]);
+    }
+
+    #[test]
+    fn test_goto_fallback() {
+        use ABIRegister::*;
+
+        // the loop from 0x10c goes back to its start two ways, and only leaves from the middle
+        // it can't be reduced, so the exit that was taken out as a break is left as a goto along with the rest
+        let output = output_decompiled_code(sections(vec![
+            branch("bge", a0, a1, 0),
+            addi(a0, a2, -8),
+            branch("bne", a1, a0, -0x4),
+            branch("bne", a2, a0, 0xc),
+            branch("blt", a3, a2, 0x10),
+            r("slt", a0, a3, a2),
+            addi(a2, a5, 8),
+            jump(-0x10),
+            r("mul", a3, a4, a0),
+            r("slt", a2, a0, a1),
+            r("sub", a0, a0, a3),
+            ret()
+        ]), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+        let lines: Vec<&str> = output.iter().map(|l| l.trim()).collect();
+
+        assert_well_formed(&output);
+
+        // what's still branching goes either way, depending on its condition
+        let start = lines.iter().position(|l| *l == "section_2:").unwrap();
+        assert_eq!(lines[start..start + 5], ["section_2:", "if (a2 != a0) {", "goto section_5;", "}", "goto section_3;"]);
+    }
+
+    #[test]
+    fn test_random_output_compiles() {
+        // whatever shape the graph is, what comes out has to be c, with gotos only for what can't be structured
+        let directory = std::env::temp_dir().join(format!("asha-syntax-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("asha.h"), HEADER).unwrap();
+        let source = directory.join("random.c");
+        let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+
+        for seed in 1..=200 {
+            let output = output_decompiled_code(sections(random_program(seed, 16, true)), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+            std::fs::write(&source, output.join("\n")).unwrap();
+
+            let compiled = std::process::Command::new(&compiler).args(["-fsyntax-only", "-w"]).arg(&source).output().unwrap();
+            assert!(compiled.status.success(), "seed {} didn't compile:\n{}\nfrom {:#?}", seed, String::from_utf8_lossy(&compiled.stderr), output);
+            assert_well_formed(&output);
+        }
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_refined_region_output() {
+        use ABIRegister::*;
+
+        // the two sides of the first branch both go on to test something else, then share a block
+        let mut instructions = BTreeMap::new();
+        instructions.insert(0x100, InstructionType::B { name: "beq", rs1: a0, rs2: zero, imm: 0x14 });
+        instructions.insert(0x104, InstructionType::I { name: "addi", rd: a1, rs1: a1, imm: 1 });
+        instructions.insert(0x108, InstructionType::B { name: "bne", rs1: a1, rs2: a2, imm: 0x14 });
+        instructions.insert(0x10c, InstructionType::I { name: "addi", rd: s1, rs1: s1, imm: 1 });
+        instructions.insert(0x110, InstructionType::J { name: "jal", rd: zero, imm: 0xc });
+        instructions.insert(0x114, InstructionType::I { name: "addi", rd: a5, rs1: a5, imm: -1 });
+        instructions.insert(0x118, InstructionType::B { name: "beq", rs1: a5, rs2: zero, imm: -0xc });
+        instructions.insert(0x11c, InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 });
+
+        let output = output_decompiled_code(generate_sections(instructions, &JumpTableMap::new()), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+        let output: Vec<&str> = output.iter().map(|l| l.trim()).collect();
+
+        // the two sides are reached on opposite conditions, so they make an if-else
+        let expected = vec![
+            "#include \"asha.h\"",
+            "",
+            "void sub_100(int64_t a0, int64_t a1, int64_t a2, int64_t a3, int64_t a4, int64_t a5) {",
+            "int64_t s1;",
+            "int32_t cond_0;",
+            "int32_t cond_1;",
+            "int32_t cond_3;",
+            "cond_0 = a0 == 0;",
+            "if (cond_0) {",
+            "a5 = a5 - 1;",
+            "cond_3 = a5 == 0;",
+            "} else {",
+            "a1 = a1 + 1;",
+            "cond_1 = a1 != a2;",
+            "}",
+            "if ((!cond_0 && !cond_1) || (cond_0 && cond_3)) {",
+            "s1 = s1 + 1;",
+            "}",
+            "}"
+        ];
+        assert_eq!(output, expected);
+    }
+
+    /// the graph of a program where the first two blocks test something and the third is guarded by them
+    fn create_short_circuit_graph(first: &'static str, first_imm: i16, second: &'static str) -> AbstractGraph {
+        use ABIRegister::*;
+
+        let mut instructions = BTreeMap::new();
+        instructions.insert(0x100, InstructionType::B { name: first, rs1: a0, rs2: zero, imm: first_imm });
+        instructions.insert(0x104, InstructionType::B { name: second, rs1: a1, rs2: zero, imm: 0x8 });
+        instructions.insert(0x108, InstructionType::I { name: "addi", rd: a2, rs1: zero, imm: 1 });
+        instructions.insert(0x10c, InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 });
+
+        build_abstract_graph(&generate_sections(instructions, &JumpTableMap::new())).unwrap()
+    }
+
+    #[test]
+    fn test_r_short_circuit_and() {
+        // if (a0 == 0 && a1 == 0), both branches skip to the end
+        let mut graph = create_short_circuit_graph("bne", 0xc, "bne");
+
+        assert!(r_short_circuit(&mut graph));
+        assert!(!graph.vertices.contains_key(&1));
+        assert_eq!(graph.get_children(0), vec![3, 2]);
+
+        let cond = condition_towards(&graph.vertices[&0], 2).unwrap();
+        assert_eq!(cond.to_string(), "a0 == 0 && a1 == 0");
+
+        reduce_graph(&mut graph);
+        assert_eq!(graph.get_no_vertices(), 1);
+        assert_eq!(graph.vertices[&0].get_type(), AbstractSectionType::If);
+    }
+
+    #[test]
+    fn test_r_short_circuit_or() {
+        // if (a0 == 0 || a1 == 0), the first branch goes straight to the body
+        let mut graph = create_short_circuit_graph("beq", 0x8, "bne");
+
+        assert!(r_short_circuit(&mut graph));
+        assert_eq!(graph.get_children(0), vec![2, 3]);
+
+        let cond = condition_towards(&graph.vertices[&0], 2).unwrap();
+        assert_eq!(cond.to_string(), "a0 == 0 || a1 == 0");
+    }
+
     // part 2: fibbonacci function graph
     #[test]
     fn test_reverse_inorder_traversal_fibb() {
@@ -1332,4 +3258,636 @@ mod test {
         assert_eq!(abstract_graph.get_no_vertices(), 1);
         assert_eq!(count, 5);
     }
+
+    // part 3: running the output against the program it came from
+    // a small interpreter for each, which records every register write in order
+    // if the control flow has been structured correctly, the two traces write the same values
+    // the output writes to variables rather than registers, but each of those has to be kept in one register
+
+    type Trace = Vec<(String, i64)>;
+
+    /// how many instructions or statements either one can run before it's assumed to be stuck
+    const STEP_LIMIT: usize = 10_000;
+
+    fn compare(name: &str, a: i64, b: i64) -> bool {
+        match name {
+            "beq" => a == b,
+            "bne" => a != b,
+            "blt" => a < b,
+            "bge" => a >= b,
+            "bltu" => (a as u64) < (b as u64),
+            "bgeu" => (a as u64) >= (b as u64),
+            _ => panic!("no comparison for {}", name)
+        }
+    }
+
+    /// run the instructions themselves, from the first one until a return
+    fn run_program(instructions: &BTreeMap<u64, InstructionType>, inputs: &[(&str, i64)]) -> Trace {
+        let mut registers: BTreeMap<String, i64> = inputs.iter().map(|(r, v)| (r.to_string(), *v)).collect();
+        let read = |registers: &BTreeMap<String, i64>, r: &ABIRegister| {
+            if *r == ABIRegister::zero { 0 } else { registers.get(&r.to_string()).copied().unwrap_or(0) }
+        };
+
+        let mut trace = Vec::new();
+        let mut pc = *instructions.keys().next().unwrap();
+
+        for _ in 0..STEP_LIMIT {
+            let mut next = pc + 4;
+
+            match &instructions[&pc] {
+                InstructionType::I { name: "jalr", .. } => return trace,
+                InstructionType::I { name: "addi", rd, rs1, imm } => {
+                    let value = read(&registers, rs1) + *imm as i64;
+                    registers.insert(rd.to_string(), value);
+                    trace.push((rd.to_string(), value));
+                },
+                InstructionType::R { name, rd, rs1, rs2 } => {
+                    let (a, b) = (read(&registers, rs1), read(&registers, rs2));
+                    let value = match *name { "add" => a + b, "sub" => a - b, _ => panic!("can't run {}", name) };
+                    registers.insert(rd.to_string(), value);
+                    trace.push((rd.to_string(), value));
+                },
+                InstructionType::B { name, rs1, rs2, imm } => {
+                    if compare(name, read(&registers, rs1), read(&registers, rs2)) {
+                        next = pc.wrapping_add(*imm as i64 as u64);
+                    }
+                },
+                InstructionType::J { imm, .. } => next = pc.wrapping_add(*imm as i64 as u64),
+                other => panic!("can't run {}", other)
+            }
+
+            pc = next;
+        }
+
+        panic!("program didn't return");
+    }
+
+    /// what a statement in the output can do to the ones around it
+    #[derive(PartialEq)]
+    enum Flow {
+        Normal,
+        Break,
+        Continue,
+        Return
+    }
+
+    enum Statement {
+        Assign(String, String),
+        If(String, Vec<Statement>, Vec<Statement>),
+        While(String, Vec<Statement>),
+        For(String, String, Vec<Statement>),
+        DoWhile(Vec<Statement>, String),
+        Jump(String),
+        Return(String)
+    }
+
+    /// parse statements until the line that closes the block they're in, which is left for the caller
+    fn parse_block(lines: &[&str], pos: &mut usize) -> Vec<Statement> {
+        let mut block = Vec::new();
+
+        while *pos < lines.len() {
+            let line = lines[*pos];
+            if line.starts_with('}') || line.starts_with("else") {
+                break;
+            }
+            *pos += 1;
+
+            let inner = |prefix: &str, suffix: &str| line.strip_prefix(prefix).and_then(|l| l.strip_suffix(suffix)).map(|l| l.to_string());
+
+            if let Some(cond) = inner("if (", ") break;") {
+                block.push(Statement::If(cond, vec![Statement::Jump("break".to_string())], Vec::new()));
+            } else if let Some(cond) = inner("if (", ") continue;") {
+                block.push(Statement::If(cond, vec![Statement::Jump("continue".to_string())], Vec::new()));
+            } else if let Some(cond) = inner("if (", ") {") {
+                let then = parse_block(lines, pos);
+                let mut otherwise = Vec::new();
+
+                // either "} else {" or "}" then "else {"
+                if lines[*pos] == "} else {" {
+                    *pos += 1;
+                    otherwise = parse_block(lines, pos);
+                } else if lines.get(*pos + 1) == Some(&"else {") {
+                    *pos += 2;
+                    otherwise = parse_block(lines, pos);
+                }
+                *pos += 1;
+
+                block.push(Statement::If(cond, then, otherwise));
+            } else if let Some(header) = inner("for (; ", ") {") {
+                let (cond, step) = header.split_once("; ").unwrap();
+                let body = parse_block(lines, pos);
+                *pos += 1;
+
+                block.push(Statement::For(cond.to_string(), step.to_string(), body));
+            } else if let Some(cond) = inner("while (", ") {") {
+                let body = parse_block(lines, pos);
+                *pos += 1;
+
+                block.push(Statement::While(cond, body));
+            } else if line == "do {" {
+                let body = parse_block(lines, pos);
+                let cond = lines[*pos].strip_prefix("} while (").and_then(|l| l.strip_suffix(");")).unwrap();
+                *pos += 1;
+
+                block.push(Statement::DoWhile(body, cond.to_string()));
+            } else if let Some(value) = inner("return ", ";") {
+                block.push(Statement::Return(value));
+            } else if (line.starts_with("int") || line.starts_with("uint")) && !line.contains(" = ") {
+                // a declaration, which doesn't do anything by itself
+            } else if matches!(line, "break;" | "continue;" | "return;") {
+                block.push(Statement::Jump(line.trim_end_matches(';').to_string()));
+            } else if let Some((dst, expr)) = line.strip_suffix(';').and_then(|l| l.split_once(" = ")) {
+                block.push(Statement::Assign(dst.to_string(), expr.to_string()));
+            } else {
+                panic!("can't run \"{}\"", line);
+            }
+        }
+
+        block
+    }
+
+    /// the state of the output while it's running
+    struct Machine {
+        values: BTreeMap<String, i64>,
+        trace: Trace,
+        steps: usize
+    }
+
+    impl Machine {
+        fn value(&self, operand: &str) -> i64 {
+            match operand {
+                "zero" | "false" => 0,
+                "true" => 1,
+                _ => operand.parse().unwrap_or_else(|_| self.values.get(operand).copied().unwrap_or(0))
+            }
+        }
+
+        fn expression(&self, expr: &str) -> i64 {
+            match expr.split(' ').collect::<Vec<_>>()[..] {
+                [a] => self.value(a),
+                [a, "+", b] => self.value(a) + self.value(b),
+                [a, "-", b] => self.value(a) - self.value(b),
+                _ => panic!("can't evaluate \"{}\"", expr)
+            }
+        }
+
+        /// evaluate a condition, with && binding tighter than ||
+        fn condition(&self, cond: &str) -> bool {
+            let tokens = tokenise(cond);
+            let mut pos = 0;
+            let result = self.disjunction(&tokens, &mut pos);
+            assert_eq!(pos, tokens.len(), "couldn't parse \"{}\"", cond);
+            result
+        }
+
+        fn disjunction(&self, tokens: &[String], pos: &mut usize) -> bool {
+            let mut result = self.conjunction(tokens, pos);
+            while tokens.get(*pos).is_some_and(|t| t == "||") {
+                *pos += 1;
+                let rhs = self.conjunction(tokens, pos);
+                result = result || rhs;
+            }
+            result
+        }
+
+        fn conjunction(&self, tokens: &[String], pos: &mut usize) -> bool {
+            let mut result = self.unary(tokens, pos);
+            while tokens.get(*pos).is_some_and(|t| t == "&&") {
+                *pos += 1;
+                let rhs = self.unary(tokens, pos);
+                result = result && rhs;
+            }
+            result
+        }
+
+        fn unary(&self, tokens: &[String], pos: &mut usize) -> bool {
+            let token = tokens[*pos].clone();
+            *pos += 1;
+
+            match token.as_str() {
+                "!" => !self.unary(tokens, pos),
+                "(" => {
+                    let result = self.disjunction(tokens, pos);
+                    assert_eq!(tokens[*pos], ")");
+                    *pos += 1;
+                    result
+                },
+                _ => {
+                    let lhs = self.value(&token);
+                    let op = tokens.get(*pos).cloned().unwrap_or_default();
+                    let name = match op.as_str() {
+                        "==" => "beq", "!=" => "bne", "<" => "blt", ">=" => "bge",
+                        _ => return lhs != 0
+                    };
+                    *pos += 1;
+                    let rhs = self.value(&tokens[*pos]);
+                    *pos += 1;
+                    compare(name, lhs, rhs)
+                }
+            }
+        }
+
+        fn run(&mut self, block: &[Statement]) -> Flow {
+            for statement in block {
+                self.steps += 1;
+                assert!(self.steps < STEP_LIMIT, "output didn't return");
+
+                let flow = match statement {
+                    Statement::Assign(dst, expr) if dst.starts_with("cond_") => {
+                        let value = self.condition(expr) as i64;
+                        self.values.insert(dst.clone(), value);
+                        Flow::Normal
+                    },
+                    Statement::Assign(dst, expr) => {
+                        let value = self.expression(expr);
+                        self.values.insert(dst.clone(), value);
+                        self.trace.push((dst.clone(), value));
+                        Flow::Normal
+                    },
+                    Statement::If(cond, then, otherwise) => {
+                        if self.condition(cond) { self.run(then) } else { self.run(otherwise) }
+                    },
+                    Statement::While(cond, body) => self.repeat(body, None, Some(cond), None),
+                    Statement::For(cond, step, body) => self.repeat(body, Some(step), Some(cond), None),
+                    Statement::DoWhile(body, cond) => self.repeat(body, None, None, Some(cond)),
+                    // returning anything but a0 itself is where the last write to it ended up
+                    Statement::Return(value) => {
+                        if value != "a0" {
+                            let value = self.expression(value);
+                            self.trace.push(("a0".to_string(), value));
+                        }
+                        Flow::Return
+                    },
+                    Statement::Jump(keyword) => match keyword.as_str() {
+                        "break" => Flow::Break,
+                        "continue" => Flow::Continue,
+                        _ => Flow::Return
+                    }
+                };
+
+                if flow != Flow::Normal {
+                    return flow;
+                }
+            }
+
+            Flow::Normal
+        }
+
+        /// any kind of loop, tested before or after the body, with a step after it
+        fn repeat(&mut self, body: &[Statement], step: Option<&String>, before: Option<&String>, after: Option<&String>) -> Flow {
+            loop {
+                self.steps += 1;
+                assert!(self.steps < STEP_LIMIT, "output didn't return");
+
+                if before.is_some_and(|c| !self.condition(c)) {
+                    return Flow::Normal;
+                }
+
+                match self.run(body) {
+                    Flow::Break => return Flow::Normal,
+                    Flow::Return => return Flow::Return,
+                    _ => {}
+                }
+
+                if let Some((dst, expr)) = step.and_then(|s| s.split_once(" = ")) {
+                    let value = self.expression(expr);
+                    self.values.insert(dst.to_string(), value);
+                    self.trace.push((dst.to_string(), value));
+                }
+
+                if after.is_some_and(|c| !self.condition(c)) {
+                    return Flow::Normal;
+                }
+            }
+        }
+    }
+
+    fn tokenise(cond: &str) -> Vec<String> {
+        let mut tokens = Vec::new();
+        let chars: Vec<char> = cond.chars().collect();
+        let mut index = 0;
+
+        while index < chars.len() {
+            let c = chars[index];
+            let pair: String = chars[index..(index + 2).min(chars.len())].iter().collect();
+
+            if c == ' ' {
+                index += 1;
+            } else if ["&&", "||", "==", "!=", "<=", ">="].contains(&pair.as_str()) {
+                tokens.push(pair);
+                index += 2;
+            } else if "()!<>".contains(c) {
+                tokens.push(c.to_string());
+                index += 1;
+            } else {
+                let start = index;
+                while index < chars.len() && (chars[index].is_alphanumeric() || chars[index] == '_' || chars[index] == '-') {
+                    index += 1;
+                }
+                tokens.push(chars[start..index].iter().collect());
+            }
+        }
+
+        tokens
+    }
+
+    /// decompile the program and run what comes out
+    fn run_output(instructions: &BTreeMap<u64, InstructionType>, inputs: &[(&str, i64)]) -> Trace {
+        let output = output_decompiled_code(generate_sections(instructions.clone(), &JumpTableMap::new()), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+        let lines: Vec<&str> = output.iter().map(|l| l.trim()).collect();
+
+        // everything between the signature and the closing brace, after whatever's declared before it
+        let mut pos = lines.iter().position(|l| l.ends_with(") {")).unwrap() + 1;
+        let block = parse_block(&lines[..lines.len() - 1], &mut pos);
+        assert_eq!(pos, lines.len() - 1, "unmatched brace in {:#?}", lines);
+
+        let mut machine = Machine {
+            values: inputs.iter().map(|(r, v)| (r.to_string(), *v)).collect(),
+            trace: Vec::new(),
+            steps: 0
+        };
+        machine.run(&block);
+
+        machine.trace
+    }
+
+    /// check the two agree for every combination of a few small values in a0 and a1
+    fn assert_agrees(instructions: Vec<InstructionType>) {
+        let instructions = program(instructions);
+
+        for a0 in -1..=3 {
+            for a1 in -1..=3 {
+                let inputs = [("a0", a0), ("a1", a1), ("a2", a1 - a0)];
+                let output = run_output(&instructions, &inputs);
+                let expected = run_program(&instructions, &inputs);
+
+                // the values written have to match, and each variable has to always stand for the same register
+                let values = |trace: &Trace| trace.iter().map(|(_, value)| *value).collect::<Vec<_>>();
+                let mut registers: BTreeMap<&str, &str> = BTreeMap::new();
+                let consistent = output.iter()
+                    .zip(expected.iter())
+                    .all(|((variable, _), (register, _))| registers.entry(variable).or_insert(register) == register);
+
+                assert!(
+                    values(&output) == values(&expected) && consistent,
+                    "disagree for {:?}, with {:?} against {:?} from output {:#?}", inputs, output, expected,
+                    output_decompiled_code(generate_sections(instructions.clone(), &JumpTableMap::new()), &Image::new(), &SignatureMap::new(), &FieldNames::new())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_callee_prototypes() {
+        use ABIRegister::*;
+
+        // write is declared as the c library has it, and exit_group, which it doesn't have, as taking a whole register
+        let instructions = program(vec![
+            addi(a7, zero, 64),
+            ecall(),
+            addi(a7, zero, 94),
+            ecall(),
+            ret()
+        ]);
+        let output = output_decompiled_code(generate_sections(instructions, &JumpTableMap::new()), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+
+        assert_eq!(output[2..4], ["void exit_group(int64_t a0);", "int64_t write(int32_t fd, const void *buf, uint64_t count);"]);
+    }
+
+    #[test]
+    fn test_memory_output() {
+        use ABIRegister::*;
+
+        let instructions = program(vec![
+            InstructionType::I { name: "lw", rd: a0, rs1: a1, imm: 8 },
+            InstructionType::S { name: "sw", rs1: a2, rs2: a0, imm: -4 },
+            InstructionType::R { name: "xor", rd: a0, rs1: a0, rs2: a1 },
+            ret()
+        ]);
+        let output = output_decompiled_code(generate_sections(instructions, &JumpTableMap::new()), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+
+        assert_eq!(output, [
+            "#include \"asha.h\"",
+            "",
+            "struct struct_0 {",
+            "\tuint8_t pad_0[8];",
+            "\tint32_t field_8;",
+            "};",
+            "",
+            "int64_t sub_100(int64_t a0, struct struct_0 *a1, int32_t *a2) {",
+            "\ta0 = a1->field_8;",
+            "\ta2[-1] = a0;",
+            "\ta0 = a0 ^ (uint64_t)a1;",
+            "\treturn a0;",
+            "}"
+        ]);
+    }
+
+    #[test]
+    fn test_stack_frame_output() {
+        use ABIRegister::*;
+
+        // the prologue and epilogue go, and the local kept below the frame pointer folds away
+        let instructions = program(vec![
+            addi(sp, sp, -32),
+            InstructionType::S { name: "sd", rs1: sp, rs2: ra, imm: 24 },
+            InstructionType::S { name: "sd", rs1: sp, rs2: s0, imm: 16 },
+            addi(s0, sp, 32),
+            InstructionType::S { name: "sw", rs1: s0, rs2: a0, imm: -20 },
+            InstructionType::I { name: "lw", rd: a5, rs1: s0, imm: -20 },
+            addi(a0, a5, 1),
+            InstructionType::I { name: "ld", rd: ra, rs1: sp, imm: 24 },
+            InstructionType::I { name: "ld", rd: s0, rs1: sp, imm: 16 },
+            addi(sp, sp, 32),
+            ret()
+        ]);
+        let output = output_decompiled_code(generate_sections(instructions, &JumpTableMap::new()), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+
+        assert_eq!(output, [
+            "#include \"asha.h\"",
+            "",
+            "int64_t sub_100(int32_t a0) {",
+            "\treturn (int64_t)a0 + 1;",
+            "}"
+        ]);
+    }
+
+    #[test]
+    fn test_agrees_if_then() {
+        use ABIRegister::*;
+
+        // the branch skips the body, so it runs when the condition doesn't hold
+        assert_agrees(vec![
+            branch("bne", a0, zero, 0x8),
+            addi(s1, s1, 1),
+            addi(s2, s2, 2),
+            ret()
+        ]);
+
+        // and here it falls through past the body
+        assert_agrees(vec![
+            branch("blt", a0, a1, 0x8),
+            jump(0x8),
+            addi(s1, s1, 1),
+            addi(s2, s2, 2),
+            ret()
+        ]);
+    }
+
+    #[test]
+    fn test_for_loop_falls_through() {
+        use ABIRegister::*;
+
+        // the body falls into the header rather than jumping back, and the add after the step reads it, so it can't be a for loop
+        let instructions = vec![
+            jump(0xc),
+            addi(a0, a0, 1),
+            InstructionType::R { name: "add", rd: a2, rs1: a2, rs2: a0 },
+            branch("blt", a0, a1, -0x8),
+            ret()
+        ];
+        assert_agrees(instructions.clone());
+
+        let output = output_decompiled_code(generate_sections(program(instructions), &JumpTableMap::new()), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+        assert!(!output.iter().any(|l| l.contains("for (")), "{:#?}", output);
+
+        // with the step last, nothing's lost by moving it, and there's no jump to leave out
+        let instructions = vec![
+            jump(0xc),
+            InstructionType::R { name: "add", rd: a2, rs1: a2, rs2: a0 },
+            addi(a0, a0, 1),
+            branch("blt", a0, a1, -0x8),
+            ret()
+        ];
+        assert_agrees(instructions.clone());
+
+        let output = output_decompiled_code(generate_sections(program(instructions), &JumpTableMap::new()), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+        let start = output.iter().position(|l| l.trim().starts_with("for (")).unwrap();
+        assert_eq!(output[start + 1].trim(), "a2 = a2 + a0;");
+    }
+
+    #[test]
+    fn test_agrees_if_else() {
+        use ABIRegister::*;
+
+        assert_agrees(vec![
+            branch("blt", a0, a1, 0xc),
+            addi(s2, zero, 1),
+            jump(0x8),
+            addi(s2, zero, 2),
+            ret()
+        ]);
+    }
+
+    #[test]
+    fn test_agrees_loops() {
+        use ABIRegister::*;
+
+        // for (a3 = 0; a3 < a1; a3++) s2 += 2
+        assert_agrees(vec![
+            addi(a3, zero, 0),
+            branch("bge", a3, a1, 0x10),
+            addi(s2, s2, 2),
+            addi(a3, a3, 1),
+            jump(-0xc),
+            ret()
+        ]);
+
+        // the same, but the header does something before testing
+        assert_agrees(vec![
+            addi(a3, zero, 0),
+            addi(s2, a3, 2),
+            branch("bge", a3, a1, 0xc),
+            addi(a3, a3, 1),
+            jump(-0xc),
+            ret()
+        ]);
+
+        // do { a0++; s2 += 3 } while (a0 < a1)
+        assert_agrees(vec![
+            addi(s2, zero, 0),
+            addi(a0, a0, 1),
+            addi(s2, s2, 3),
+            branch("blt", a0, a1, -0x8),
+            ret()
+        ]);
+    }
+
+    #[test]
+    fn test_agrees_loop_exits() {
+        use ABIRegister::*;
+
+        // a loop with an if at the top, and the exit test in the middle
+        assert_agrees(vec![
+            addi(a2, zero, 3),
+            branch("blt", a0, a1, 0x8),
+            addi(a0, a0, 1),
+            addi(a2, a2, -1),
+            branch("beq", a2, zero, 0xc),
+            addi(a3, a3, 1),
+            jump(-0x14),
+            ret()
+        ]);
+
+        // look for a0 between 0 and a1, returning as soon as it's found
+        assert_agrees(vec![
+            addi(a2, zero, 0),
+            branch("bge", a2, a1, 0x18),
+            branch("beq", a2, a0, 0xc),
+            addi(a2, a2, 1),
+            jump(-0xc),
+            addi(a0, a2, 0),
+            ret(),
+            addi(a0, zero, -1),
+            ret()
+        ]);
+    }
+
+    #[test]
+    fn test_agrees_short_circuit() {
+        use ABIRegister::*;
+
+        // if (a0 == 0 && a1 == 0)
+        assert_agrees(vec![
+            branch("bne", a0, zero, 0xc),
+            branch("bne", a1, zero, 0x8),
+            addi(s2, zero, 1),
+            ret()
+        ]);
+
+        // if (a0 == 0 || a1 == 0)
+        assert_agrees(vec![
+            branch("beq", a0, zero, 0x8),
+            branch("bne", a1, zero, 0x8),
+            addi(s2, zero, 1),
+            ret()
+        ]);
+    }
+
+    #[test]
+    fn test_agrees_regions() {
+        use ABIRegister::*;
+
+        // two conditions sharing a block
+        assert_agrees(vec![
+            branch("beq", a0, zero, 0xc),
+            addi(a1, a1, 1),
+            branch("bne", a1, a2, 0xc),
+            addi(s3, s3, 1),
+            addi(s4, s4, 2),
+            ret()
+        ]);
+
+        // both sides of the first branch test something else, then share a block
+        assert_agrees(vec![
+            branch("beq", a0, zero, 0x14),
+            addi(a1, a1, 1),
+            branch("bne", a1, a2, 0x14),
+            addi(s3, s3, 1),
+            jump(0xc),
+            addi(a2, a2, -1),
+            branch("beq", a2, zero, -0xc),
+            ret()
+        ]);
+    }
 }
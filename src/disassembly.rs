@@ -22,13 +22,18 @@ pub fn disassemble(instruction: Instruction) -> Option<InstructionType> {
                 name, 
                 rd: ABIRegister::from(retrieve!(rd instruction) as u8),
                 rs1: ABIRegister::from(retrieve!(rs1 instruction) as u8),
-                imm: retrieve!(iimm instruction) as i16
+                imm: if is_shift(name) {
+                    // the top bits tell srai and srli apart, only the shift amount is left
+                    (retrieve!(iimm instruction) & 0x3f) as i16
+                } else {
+                    convert_to_signed(retrieve!(iimm instruction) as usize, 12) as i16
+                }
             }),
             IT::S => Some(InstructionType::S { 
                 name, 
                 rs1: ABIRegister::from(retrieve!(rs1 instruction) as u8),
                 rs2: ABIRegister::from(retrieve!(rs2 instruction) as u8),
-                imm: retrieve!(simm instruction) as i16
+                imm: convert_to_signed(retrieve!(simm instruction) as usize, 12) as i16
             }),
             IT::B => Some(InstructionType::B { 
                 name, 
@@ -70,9 +75,13 @@ fn determine_name(instruction: &Instruction) -> Option<(&'static str, IT)> {
     if !(i_type == IT::U || i_type == IT::J) {
         funct3 = retrieve!(funct3 instruction).try_into().unwrap();
 
-        // only R uses funct7 (and also _one_ singular i-type)
-        if i_type == IT::R || opcode == 0b00100 {
+        // only R uses funct7 (and also the immediate shifts)
+        if i_type == IT::R {
             funct7 = retrieve!(funct7 instruction).try_into().unwrap();
+        } else if (opcode == 0b00100 || opcode == 0b00110) && (funct3 == 0b001 || funct3 == 0b101) {
+            // rv64 shifts use imm[5] for the shift amount, so only imm[11:6] tells them apart
+            let funct6: u8 = retrieve!(funct7 instruction).try_into().unwrap();
+            funct7 = funct6 & 0b1111110;
         } else {
             funct7 = 0;
         }
@@ -87,6 +96,8 @@ fn determine_name(instruction: &Instruction) -> Option<(&'static str, IT)> {
 }
 
 /// Determine the type of instruction, and therefore which fields to match on
+// the conditions are left in sum-of-products form so they match the karnaugh map
+#[allow(clippy::nonminimal_bool)]
 fn determine_type(opcode: u8) -> Option<IT> {
     let bf = OpcodeBitfield::from_opcode(opcode);
     
@@ -116,6 +127,11 @@ fn from_bits(opcode: u8, funct3: u8, funct7: u8) -> Option<&'static str> {
     INSTRUCTIONS.get(&key).cloned()
 }
 
+/// whether an i-type instruction is a shift by an immediate amount
+fn is_shift(name: &str) -> bool {
+    matches!(name, "slli" | "srli" | "srai" | "slliw" | "srliw" | "sraiw")
+}
+
 /// Convert from two's complement raw bits to isize
 /// takes the number of bits operating on, as this is always an unusual amount, and is sign extended
 fn convert_to_signed(value: usize, bits: usize) -> isize {
@@ -225,6 +241,21 @@ mod test {
             })
         );
 
+        // immediates that overlap funct7 shouldn't stop these from decoding
+        let addi = 0x04000893;      // addi a7, zero, 64
+        let srai = 0x4207d793;      // srai a5, a5, 32
+
+        assert_eq!(disassemble(addi).map(|i| i.get_name()), Some("addi"));
+        assert_eq!(disassemble(srai).map(|i| i.get_name()), Some("srai"));
+        assert_eq!(disassemble(srai).map(|i| i.get_imm()), Some(32));
+
+        // negative immediates are sign-extended
+        let addi = 0xfff50513;      // addi a0, a0, -1
+        let sd = 0xfe113c23;        // sd ra, -8(sp)
+
+        assert_eq!(disassemble(addi).map(|i| i.get_imm()), Some(-1));
+        assert_eq!(disassemble(sd).map(|i| i.get_imm()), Some(-8));
+
         assert_eq!(
             disassemble(j_type),
             Some(InstructionType::J { 
@@ -0,0 +1,394 @@
+//! # Dominator and post-dominator trees
+//!
+//! a node `d` dominates `n` if every path from the entry to `n` goes through `d`,
+//! and post-dominates it if every path from `n` to the exit goes through `d`
+//!
+//! these are computed with the iterative algorithm from Cooper, Harvey, and Kennedy's
+//! "A Simple, Fast Dominance Algorithm", which works on any graph given as a map of successors
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::decompilation::SectionMap;
+
+/// graph given as a map from each node to its successors
+pub type Successors = BTreeMap<usize, Vec<usize>>;
+
+/// the node added to stand in for every exit when computing post-dominators
+/// functions can return from several places, so there isn't always a single real exit
+pub const VIRTUAL_EXIT: usize = usize::MAX;
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// # Dominator tree of a graph
+/// also holds the dominance frontier of each node, i.e. the nodes where its dominance stops
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DominatorTree {
+    root: usize,
+    idom: BTreeMap<usize, usize>,                   // immediate dominator of each reachable node, the root is its own
+    children: BTreeMap<usize, Vec<usize>>,          // nodes immediately dominated by each node
+    frontiers: BTreeMap<usize, BTreeSet<usize>>,    // dominance frontier of each node
+    order: Vec<usize>                               // reachable nodes in reverse postorder
+}
+
+impl DominatorTree {
+    /// compute the dominators of every node reachable from the root
+    pub fn new(root: usize, successors: &Successors) -> Self {
+        let order = reverse_postorder(root, successors);
+        let position: BTreeMap<usize, usize> = order.iter()
+            .enumerate()
+            .map(|(i, n)| (*n, i))
+            .collect();
+
+        let predecessors = invert(successors);
+
+        // iterate until nothing changes
+        // nodes are processed in reverse postorder, so this normally only takes a couple of passes
+        let mut idom: BTreeMap<usize, usize> = BTreeMap::new();
+        idom.insert(root, root);
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for node in order.iter().skip(1) {
+                let mut new_idom: Option<usize> = None;
+
+                for pred in predecessors.get(node).into_iter().flatten() {
+                    if !idom.contains_key(pred) {
+                        continue;
+                    }
+
+                    new_idom = Some(match new_idom {
+                        None => *pred,
+                        Some(current) => intersect(&idom, &position, *pred, current)
+                    });
+                }
+
+                if let Some(new_idom) = new_idom {
+                    if idom.get(node) != Some(&new_idom) {
+                        idom.insert(*node, new_idom);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let mut children: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for node in order.iter() {
+            children.entry(*node).or_default();
+            if *node != root {
+                children.entry(idom[node]).or_default().push(*node);
+            }
+        }
+
+        // dominance frontiers, walking up from each predecessor of a join point
+        let mut frontiers: BTreeMap<usize, BTreeSet<usize>> = order.iter().map(|n| (*n, BTreeSet::new())).collect();
+        for node in order.iter() {
+            let preds: Vec<usize> = predecessors.get(node)
+                .into_iter()
+                .flatten()
+                .filter(|p| idom.contains_key(p))
+                .copied()
+                .collect();
+
+            if preds.len() < 2 {
+                continue;
+            }
+
+            for pred in preds {
+                let mut runner = pred;
+                while runner != idom[node] {
+                    frontiers.get_mut(&runner).unwrap().insert(*node);
+
+                    if runner == root {
+                        break;
+                    }
+                    runner = idom[&runner];
+                }
+            }
+        }
+
+        DominatorTree {
+            root,
+            idom,
+            children,
+            frontiers,
+            order
+        }
+    }
+
+    /// # compute post-dominators
+    /// this is the dominator tree of the reversed graph, rooted at a virtual exit that every real exit leads to
+    /// nodes stuck in an infinite loop never reach an exit, so the last node of each of those loops is treated as one
+    pub fn post_dominators(successors: &Successors) -> Self {
+        let mut reversed = invert(successors);
+        let exits = reversed.entry(VIRTUAL_EXIT).or_default();
+
+        for (node, succs) in successors.iter() {
+            if succs.is_empty() {
+                exits.push(*node);
+            }
+        }
+
+        // keep adding exits until everything can leave
+        loop {
+            let reachable: BTreeSet<usize> = reverse_postorder(VIRTUAL_EXIT, &reversed).into_iter().collect();
+
+            match successors.keys().rev().find(|n| !reachable.contains(n)) {
+                Some(stuck) => reversed.get_mut(&VIRTUAL_EXIT).unwrap().push(*stuck),
+                None => break
+            }
+        }
+
+        DominatorTree::new(VIRTUAL_EXIT, &reversed)
+    }
+
+    /// dominators of a control-flow graph, starting from its first section
+    pub fn from_sections(sections: &SectionMap) -> Self {
+        let successors = section_successors(sections);
+        let root = sections.keys().next().copied().unwrap_or(0);
+
+        DominatorTree::new(root, &successors)
+    }
+
+    /// post-dominators of a control-flow graph
+    pub fn post_dominators_from_sections(sections: &SectionMap) -> Self {
+        DominatorTree::post_dominators(&section_successors(sections))
+    }
+
+    pub fn get_root(&self) -> usize {
+        self.root
+    }
+
+    /// the closest strict dominator of a node, if it has one
+    pub fn immediate_dominator(&self, node: usize) -> Option<usize> {
+        self.idom.get(&node)
+            .copied()
+            .filter(|_| node != self.root)
+    }
+
+    /// whether the root can reach this node at all
+    pub fn is_reachable(&self, node: usize) -> bool {
+        self.idom.contains_key(&node)
+    }
+
+    /// whether every path to `node` goes through `dominator`
+    /// every node dominates itself
+    pub fn dominates(&self, dominator: usize, node: usize) -> bool {
+        if !self.is_reachable(node) || !self.is_reachable(dominator) {
+            return false;
+        }
+
+        let mut current = node;
+        loop {
+            if current == dominator {
+                return true;
+            }
+            if current == self.root {
+                return false;
+            }
+            current = self.idom[&current];
+        }
+    }
+
+    pub fn strictly_dominates(&self, dominator: usize, node: usize) -> bool {
+        dominator != node && self.dominates(dominator, node)
+    }
+
+    /// the nodes immediately dominated by this one
+    pub fn get_children(&self, node: usize) -> &[usize] {
+        self.children.get(&node).map(|c| c.as_slice()).unwrap_or(&[])
+    }
+
+    /// the nodes just outside the region dominated by this one
+    pub fn get_frontier(&self, node: usize) -> BTreeSet<usize> {
+        self.frontiers.get(&node).cloned().unwrap_or_default()
+    }
+
+    /// every reachable node, in reverse postorder (so every node comes before anything it dominates)
+    pub fn reverse_postorder(&self) -> &[usize] {
+        &self.order
+    }
+}
+
+// -----------------------------------
+// functions
+// -----------------------------------
+
+/// walk up the tree from both nodes until they meet
+fn intersect(idom: &BTreeMap<usize, usize>, position: &BTreeMap<usize, usize>, a: usize, b: usize) -> usize {
+    let mut finger_1 = a;
+    let mut finger_2 = b;
+
+    while finger_1 != finger_2 {
+        while position[&finger_1] > position[&finger_2] {
+            finger_1 = idom[&finger_1];
+        }
+        while position[&finger_2] > position[&finger_1] {
+            finger_2 = idom[&finger_2];
+        }
+    }
+
+    finger_1
+}
+
+/// order the nodes reachable from the root so that every node comes before its successors, back edges aside
+pub fn reverse_postorder(root: usize, successors: &Successors) -> Vec<usize> {
+    let mut visited = BTreeSet::new();
+    let mut postorder = Vec::new();
+
+    // iterative dfs, so large functions don't blow the stack
+    let mut stack: Vec<(usize, usize)> = vec![(root, 0)];
+    visited.insert(root);
+
+    while let Some((node, next_child)) = stack.pop() {
+        let succs = successors.get(&node).map(|s| s.as_slice()).unwrap_or(&[]);
+
+        if let Some(child) = succs.get(next_child) {
+            stack.push((node, next_child + 1));
+
+            if visited.insert(*child) {
+                stack.push((*child, 0));
+            }
+        } else {
+            postorder.push(node);
+        }
+    }
+
+    postorder.reverse();
+    postorder
+}
+
+/// turn a map of successors into a map of predecessors
+pub fn invert(successors: &Successors) -> Successors {
+    let mut predecessors: Successors = BTreeMap::new();
+
+    for (node, succs) in successors.iter() {
+        predecessors.entry(*node).or_default();
+
+        for succ in succs {
+            let preds = predecessors.entry(*succ).or_default();
+            if !preds.contains(node) {
+                preds.push(*node);
+            }
+        }
+    }
+
+    predecessors
+}
+
+/// the successors of each section in a control-flow graph
+pub fn section_successors(sections: &SectionMap) -> Successors {
+    sections.iter()
+        .map(|(id, section)| {
+            let mut succs: Vec<usize> = Vec::new();
+            for branch in section.get_branches() {
+                if !succs.contains(&branch.get_id()) {
+                    succs.push(branch.get_id());
+                }
+            }
+            (*id, succs)
+        })
+        .collect()
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn graph(edges: &[(usize, usize)], nodes: usize) -> Successors {
+        let mut successors: Successors = (0..nodes).map(|n| (n, Vec::new())).collect();
+        for (src, dst) in edges {
+            successors.get_mut(src).unwrap().push(*dst);
+        }
+        successors
+    }
+
+    /// the fibonacci function's graph, as used in the decompilation tests
+    /// 0 -> 1 -> 2 <-> 3, with 0, 1, and 2 all able to leave for 4
+    fn fibb() -> Successors {
+        graph(&[(0, 4), (0, 1), (1, 2), (1, 4), (2, 3), (2, 4), (3, 2)], 5)
+    }
+
+    #[test]
+    fn test_dominators() {
+        let tree = DominatorTree::new(0, &fibb());
+
+        assert_eq!(tree.immediate_dominator(0), None);
+        assert_eq!(tree.immediate_dominator(1), Some(0));
+        assert_eq!(tree.immediate_dominator(2), Some(1));
+        assert_eq!(tree.immediate_dominator(3), Some(2));
+        assert_eq!(tree.immediate_dominator(4), Some(0));
+
+        assert!(tree.dominates(1, 3));
+        assert!(tree.dominates(3, 3));
+        assert!(!tree.strictly_dominates(3, 3));
+        assert!(!tree.dominates(2, 4));
+        assert_eq!(tree.get_children(0), &[1, 4]);
+    }
+
+    #[test]
+    fn test_dominance_frontiers() {
+        let tree = DominatorTree::new(0, &fibb());
+
+        assert_eq!(tree.get_frontier(0), BTreeSet::new());
+        assert_eq!(tree.get_frontier(1), BTreeSet::from([4]));
+        assert_eq!(tree.get_frontier(2), BTreeSet::from([2, 4]));
+        assert_eq!(tree.get_frontier(3), BTreeSet::from([2]));
+    }
+
+    #[test]
+    fn test_post_dominators() {
+        // a diamond with an early exit from one side
+        // 0 -> 1 -> 3, 0 -> 2 -> 3, 2 -> 4
+        let tree = DominatorTree::post_dominators(&graph(&[(0, 1), (0, 2), (1, 3), (2, 3), (2, 4)], 5));
+
+        assert_eq!(tree.get_root(), VIRTUAL_EXIT);
+        assert_eq!(tree.immediate_dominator(1), Some(3));
+        assert_eq!(tree.immediate_dominator(3), Some(VIRTUAL_EXIT));
+        assert_eq!(tree.immediate_dominator(0), Some(VIRTUAL_EXIT));
+
+        // the fibonacci graph only leaves through 4
+        let tree = DominatorTree::post_dominators(&fibb());
+        assert_eq!(tree.immediate_dominator(0), Some(4));
+        assert_eq!(tree.immediate_dominator(3), Some(2));
+    }
+
+    #[test]
+    fn test_unreachable_nodes() {
+        // 2 can't be reached, and 3 can't leave
+        let successors = graph(&[(0, 1), (2, 1), (0, 3), (3, 3)], 4);
+
+        let tree = DominatorTree::new(0, &successors);
+        assert!(!tree.is_reachable(2));
+        assert!(!tree.dominates(0, 2));
+        assert_eq!(tree.immediate_dominator(1), Some(0));
+
+        // so 3 gets treated as an exit of its own
+        let tree = DominatorTree::post_dominators(&successors);
+        assert_eq!(tree.immediate_dominator(3), Some(VIRTUAL_EXIT));
+        assert_eq!(tree.immediate_dominator(0), Some(VIRTUAL_EXIT));
+    }
+
+    #[test]
+    fn test_infinite_loop() {
+        // 0 -> 1 -> 2 -> 3 -> 1, with an if-then at 1 -> 3
+        let successors = graph(&[(0, 1), (1, 2), (1, 3), (2, 3), (3, 1)], 4);
+        let tree = DominatorTree::post_dominators(&successors);
+
+        // the merge point of the if is still found
+        assert_eq!(tree.immediate_dominator(1), Some(3));
+        assert_eq!(tree.immediate_dominator(2), Some(3));
+    }
+
+    #[test]
+    fn test_reverse_postorder() {
+        assert_eq!(reverse_postorder(0, &fibb()), vec![0, 1, 2, 3, 4]);
+    }
+}
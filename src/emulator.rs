@@ -0,0 +1,312 @@
+//! # emulation
+//! runs the instructions of a function directly, without going through the lifted statements,
+//! so what it gives back can be held up against what the decompiled code does
+//!
+//! only the integer instructions are run, memory is only what's been mapped in,
+//! and the function is run until it returns to where it was called from
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::instructions::{ABIRegister, InstructionType};
+
+/// where the function returns to, which has nothing mapped at it
+const RETURN_ADDRESS: u64 = 0xdead_0000;
+
+/// the top of the stack, and how much of it there is below that
+const STACK_TOP: u64 = 0x7fff_0000;
+const STACK_SIZE: u64 = 0x10000;
+
+// ----------------------------------------
+
+/// # why a function stopped before returning
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// reading or writing memory that wasn't mapped in
+    Unmapped(u64),
+    /// going somewhere there's no instruction
+    NoInstruction(u64),
+    /// anything that hands control to the environment, or isn't an integer instruction
+    Unsupported(&'static str),
+    /// not returning within the limit given
+    StepLimit
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::Unmapped(address) => write!(f, "access to unmapped memory at {:#x}", address),
+            Fault::NoInstruction(address) => write!(f, "no instruction at {:#x}", address),
+            Fault::Unsupported(name) => write!(f, "can't run {}", name),
+            Fault::StepLimit => write!(f, "didn't return")
+        }
+    }
+}
+
+/// # an rv64im hart running one function
+/// registers start at zero, apart from the stack pointer and return address
+#[derive(Clone, Debug)]
+pub struct Emulator<'a> {
+    instructions: &'a BTreeMap<u64, InstructionType>,
+    registers: [u64; 32],
+    /// every mapped region by its start address
+    memory: BTreeMap<u64, Vec<u8>>
+}
+
+impl<'a> Emulator<'a> {
+    pub fn new(instructions: &'a BTreeMap<u64, InstructionType>) -> Self {
+        let mut emulator = Emulator { instructions, registers: [0; 32], memory: BTreeMap::new() };
+
+        emulator.map(STACK_TOP - STACK_SIZE, vec![0; STACK_SIZE as usize]);
+        emulator.set(&ABIRegister::sp, STACK_TOP);
+        emulator.set(&ABIRegister::ra, RETURN_ADDRESS);
+
+        emulator
+    }
+
+    pub fn get(&self, register: &ABIRegister) -> u64 {
+        match register {
+            ABIRegister::zero | ABIRegister::Unknown => 0,
+            register => self.registers[register.clone() as usize]
+        }
+    }
+
+    pub fn set(&mut self, register: &ABIRegister, value: u64) {
+        if !matches!(register, ABIRegister::zero | ABIRegister::Unknown) {
+            self.registers[register.clone() as usize] = value;
+        }
+    }
+
+    /// put some memory at an address, which shouldn't overlap anything already there
+    pub fn map(&mut self, address: u64, bytes: Vec<u8>) {
+        self.memory.insert(address, bytes);
+    }
+
+    /// the contents of the region mapped at an address
+    pub fn get_region(&self, address: u64) -> Option<&[u8]> {
+        self.memory.get(&address).map(|bytes| bytes.as_slice())
+    }
+
+    /// the region an access falls in, and where in it it starts
+    fn locate(&mut self, address: u64, size: u64) -> Result<(&mut Vec<u8>, usize), Fault> {
+        let (start, bytes) = self.memory.range_mut(..=address).next_back().ok_or(Fault::Unmapped(address))?;
+        let offset = address - start;
+
+        if offset + size > bytes.len() as u64 {
+            return Err(Fault::Unmapped(address));
+        }
+
+        Ok((bytes, offset as usize))
+    }
+
+    pub fn load(&mut self, address: u64, size: u8, signed: bool) -> Result<u64, Fault> {
+        let (bytes, offset) = self.locate(address, size as u64)?;
+
+        let mut value = [0; 8];
+        value[..size as usize].copy_from_slice(&bytes[offset..offset + size as usize]);
+
+        let value = u64::from_le_bytes(value);
+        Ok(extend(value, size * 8, signed))
+    }
+
+    pub fn store(&mut self, address: u64, value: u64, size: u8) -> Result<(), Fault> {
+        let (bytes, offset) = self.locate(address, size as u64)?;
+        bytes[offset..offset + size as usize].copy_from_slice(&value.to_le_bytes()[..size as usize]);
+
+        Ok(())
+    }
+
+    // ----------------------------------------
+
+    /// # run from an address until the function returns
+    /// giving up after so many instructions, in case it doesn't
+    pub fn run(&mut self, start: u64, limit: usize) -> Result<(), Fault> {
+        let mut pc = start;
+
+        for _ in 0..limit {
+            if pc == RETURN_ADDRESS {
+                return Ok(());
+            }
+
+            let instruction = self.instructions.get(&pc).ok_or(Fault::NoInstruction(pc))?;
+            pc = self.step(pc, instruction)?;
+        }
+
+        Err(Fault::StepLimit)
+    }
+
+    /// run a single instruction, giving where to go next
+    fn step(&mut self, pc: u64, instruction: &InstructionType) -> Result<u64, Fault> {
+        let name = instruction.get_name();
+        let rs1 = self.get(&instruction.get_rs1());
+        let rs2 = self.get(&instruction.get_rs2());
+        let imm = instruction.get_imm() as i64 as u64;
+        let relative = pc.wrapping_add(imm);
+
+        // the 32-bit versions of operations work on the low half, and sign-extend what they give
+        let word = |value: u64| extend(value, 32, true);
+
+        let value = match name {
+            // loads and stores
+            "lb" | "lh" | "lw" | "ld" | "lbu" | "lhu" | "lwu" => {
+                let size = match &name[1..2] { "b" => 1, "h" => 2, "w" => 4, _ => 8 };
+                self.load(rs1.wrapping_add(imm), size, !name.ends_with('u'))?
+            },
+            "sb" | "sh" | "sw" | "sd" => {
+                let size = match name { "sb" => 1, "sh" => 2, "sw" => 4, _ => 8 };
+                self.store(rs1.wrapping_add(imm), rs2, size)?;
+                return Ok(pc + 4);
+            },
+
+            // upper immediates
+            "lui" => ((imm as u32) << 12) as i32 as i64 as u64,
+            "auipc" => pc.wrapping_add(((imm as u32) << 12) as i32 as i64 as u64),
+
+            // immediate arithmetic
+            "addi" => rs1.wrapping_add(imm),
+            "slti" => ((rs1 as i64) < imm as i64) as u64,
+            "sltiu" => (rs1 < imm) as u64,
+            "xori" => rs1 ^ imm,
+            "ori" => rs1 | imm,
+            "andi" => rs1 & imm,
+            "slli" => rs1 << (imm & 63),
+            "srli" => rs1 >> (imm & 63),
+            "srai" => ((rs1 as i64) >> (imm & 63)) as u64,
+            "addiw" => word(rs1.wrapping_add(imm)),
+            "slliw" => word(rs1 << (imm & 31)),
+            "srliw" => word((rs1 as u32 >> (imm & 31)) as u64),
+            "sraiw" => ((rs1 as i32) >> (imm & 31)) as i64 as u64,
+
+            // register arithmetic
+            "add" => rs1.wrapping_add(rs2),
+            "sub" => rs1.wrapping_sub(rs2),
+            "sll" => rs1 << (rs2 & 63),
+            "slt" => ((rs1 as i64) < rs2 as i64) as u64,
+            "sltu" => (rs1 < rs2) as u64,
+            "xor" => rs1 ^ rs2,
+            "srl" => rs1 >> (rs2 & 63),
+            "sra" => ((rs1 as i64) >> (rs2 & 63)) as u64,
+            "or" => rs1 | rs2,
+            "and" => rs1 & rs2,
+            "addw" => word(rs1.wrapping_add(rs2)),
+            "subw" => word(rs1.wrapping_sub(rs2)),
+            "sllw" => word(rs1 << (rs2 & 31)),
+            "srlw" => word((rs1 as u32 >> (rs2 & 31)) as u64),
+            "sraw" => ((rs1 as i32) >> (rs2 & 31)) as i64 as u64,
+
+            // multiplication and division, where dividing by zero or overflowing gives a fixed answer rather than trapping
+            "mul" => rs1.wrapping_mul(rs2),
+            "mulh" => ((rs1 as i64 as i128 * rs2 as i64 as i128) >> 64) as u64,
+            "mulhsu" => ((rs1 as i64 as i128).wrapping_mul(rs2 as i128) >> 64) as u64,
+            "mulhu" => ((rs1 as u128 * rs2 as u128) >> 64) as u64,
+            "div" => divide(rs1 as i64, rs2 as i64).0 as u64,
+            "divu" => rs1.checked_div(rs2).unwrap_or(u64::MAX),
+            "rem" => divide(rs1 as i64, rs2 as i64).1 as u64,
+            "remu" => rs1.checked_rem(rs2).unwrap_or(rs1),
+            "mulw" => word(rs1.wrapping_mul(rs2)),
+            "divw" => divide(rs1 as i32 as i64, rs2 as i32 as i64).0 as i32 as i64 as u64,
+            "divuw" => word((rs1 as u32).checked_div(rs2 as u32).unwrap_or(u32::MAX) as u64),
+            "remw" => divide(rs1 as i32 as i64, rs2 as i32 as i64).1 as i32 as i64 as u64,
+            "remuw" => word((rs1 as u32).checked_rem(rs2 as u32).unwrap_or(rs1 as u32) as u64),
+
+            // control flow
+            "beq" | "bne" | "blt" | "bge" | "bltu" | "bgeu" => {
+                let taken = match name {
+                    "beq" => rs1 == rs2,
+                    "bne" => rs1 != rs2,
+                    "blt" => (rs1 as i64) < rs2 as i64,
+                    "bge" => rs1 as i64 >= rs2 as i64,
+                    "bltu" => rs1 < rs2,
+                    _ => rs1 >= rs2
+                };
+
+                return Ok(if taken { relative } else { pc + 4 });
+            },
+            "jal" => {
+                self.set(&instruction.get_rd(), pc + 4);
+                return Ok(relative);
+            },
+            "jalr" => {
+                self.set(&instruction.get_rd(), pc + 4);
+                return Ok(rs1.wrapping_add(imm) & !1);
+            },
+
+            name => return Err(Fault::Unsupported(name))
+        };
+
+        self.set(&instruction.get_rd(), value);
+        Ok(pc + 4)
+    }
+}
+
+/// signed division as risc-v does it, with the quotient and remainder when dividing by zero or overflowing
+fn divide(a: i64, b: i64) -> (i64, i64) {
+    match b {
+        0 => (-1, a),
+        -1 if a == i64::MIN => (a, 0),
+        b => (a / b, a % b)
+    }
+}
+
+/// the low bits of a value, extended to the whole register
+fn extend(value: u64, bits: u8, signed: bool) -> u64 {
+    crate::ir::extend(value as i64, bits, signed) as u64
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{program, ret};
+    use ABIRegister::*;
+
+    #[test]
+    fn test_arithmetic() {
+        // sum the numbers below a0, with the 32-bit add wrapping
+        let instructions = program(vec![
+            InstructionType::I { name: "addi", rd: a1, rs1: zero, imm: 0 },
+            InstructionType::B { name: "bge", rs1: zero, rs2: a0, imm: 0x10 },
+            InstructionType::R { name: "addw", rd: a1, rs1: a1, rs2: a0 },
+            InstructionType::I { name: "addi", rd: a0, rs1: a0, imm: -1 },
+            InstructionType::J { name: "jal", rd: zero, imm: -0xc },
+            InstructionType::R { name: "divu", rd: a0, rs1: a1, rs2: zero },
+            ret()
+        ]);
+
+        let mut emulator = Emulator::new(&instructions);
+        emulator.set(&a0, 10);
+        assert_eq!(emulator.run(0x100, 1000), Ok(()));
+        assert_eq!(emulator.get(&a1), 55);
+        // dividing by zero doesn't trap
+        assert_eq!(emulator.get(&a0), u64::MAX);
+    }
+
+    #[test]
+    fn test_memory() {
+        let instructions = program(vec![
+            InstructionType::I { name: "addi", rd: sp, rs1: sp, imm: -16 },
+            InstructionType::S { name: "sd", rs1: sp, rs2: a0, imm: 8 },
+            InstructionType::I { name: "lb", rd: a1, rs1: sp, imm: 8 },
+            InstructionType::I { name: "lbu", rd: a2, rs1: sp, imm: 8 },
+            InstructionType::I { name: "addi", rd: sp, rs1: sp, imm: 16 },
+            InstructionType::I { name: "ld", rd: a3, rs1: a4, imm: 0 },
+            ret()
+        ]);
+
+        let mut emulator = Emulator::new(&instructions);
+        emulator.set(&a0, 0xff);
+        emulator.set(&a4, 0x1000);
+
+        // the stack is there to start with, but nothing else is
+        assert_eq!(emulator.run(0x100, 1000), Err(Fault::Unmapped(0x1000)));
+        assert_eq!(emulator.get(&a1), u64::MAX);
+        assert_eq!(emulator.get(&a2), 0xff);
+
+        emulator.map(0x1000, 42u64.to_le_bytes().to_vec());
+        assert_eq!(emulator.run(0x114, 1000), Ok(()));
+        assert_eq!(emulator.get(&a3), 42);
+    }
+}
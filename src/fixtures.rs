@@ -0,0 +1,125 @@
+//! # test fixtures
+//! what the unit tests write their programs with,
+//! laid out one instruction after another from 0x100, as a function would be
+
+use std::collections::BTreeMap;
+
+use crate::decompilation::{generate_sections, SectionMap};
+use crate::instructions::{ABIRegister, InstructionType};
+use crate::ir::Variable;
+use crate::jumptable::JumpTableMap;
+
+/// where programs start, unless they say otherwise
+pub const START: u64 = 0x100;
+
+// ----------------------------------------
+// instructions
+// ----------------------------------------
+
+pub fn addi(rd: ABIRegister, rs1: ABIRegister, imm: i16) -> InstructionType {
+    InstructionType::I { name: "addi", rd, rs1, imm }
+}
+
+pub fn r(name: &'static str, rd: ABIRegister, rs1: ABIRegister, rs2: ABIRegister) -> InstructionType {
+    InstructionType::R { name, rd, rs1, rs2 }
+}
+
+pub fn ld(rd: ABIRegister, rs1: ABIRegister, imm: i16) -> InstructionType {
+    InstructionType::I { name: "ld", rd, rs1, imm }
+}
+
+pub fn sd(rs1: ABIRegister, rs2: ABIRegister, imm: i16) -> InstructionType {
+    InstructionType::S { name: "sd", rs1, rs2, imm }
+}
+
+pub fn branch(name: &'static str, rs1: ABIRegister, rs2: ABIRegister, imm: i16) -> InstructionType {
+    InstructionType::B { name, rs1, rs2, imm }
+}
+
+pub fn jal(rd: ABIRegister, imm: i32) -> InstructionType {
+    InstructionType::J { name: "jal", rd, imm }
+}
+
+/// a jump that doesn't save where it came from
+pub fn jump(imm: i32) -> InstructionType {
+    jal(ABIRegister::zero, imm)
+}
+
+pub fn ret() -> InstructionType {
+    InstructionType::I { name: "jalr", rd: ABIRegister::zero, rs1: ABIRegister::ra, imm: 0 }
+}
+
+pub fn ecall() -> InstructionType {
+    InstructionType::I { name: "syscall", rd: ABIRegister::zero, rs1: ABIRegister::zero, imm: 0 }
+}
+
+// ----------------------------------------
+// programs
+// ----------------------------------------
+
+/// lay out instructions one after the other from an address
+pub fn program_at(start: u64, instructions: Vec<InstructionType>) -> BTreeMap<u64, InstructionType> {
+    instructions.into_iter()
+        .enumerate()
+        .map(|(index, inst)| (start + 4 * index as u64, inst))
+        .collect()
+}
+
+/// lay out instructions one after the other from 0x100
+pub fn program(instructions: Vec<InstructionType>) -> BTreeMap<u64, InstructionType> {
+    program_at(START, instructions)
+}
+
+/// lay out instructions from 0x100 and split them into blocks
+pub fn sections(instructions: Vec<InstructionType>) -> SectionMap {
+    generate_sections(program(instructions), &JumpTableMap::new())
+}
+
+/// every statement, in the order of the blocks they're in
+pub fn statements(sections: &SectionMap) -> Vec<String> {
+    sections.values()
+        .flat_map(|section| section.get_statements().values().flatten().map(|s| s.to_string()).collect::<Vec<_>>())
+        .collect()
+}
+
+pub fn register(register: ABIRegister) -> Variable {
+    Variable::Register(register)
+}
+
+/// # a random function, the same one every time for the same seed
+/// arithmetic on the argument registers, with branches and jumps between it and returns scattered through
+/// without `loops` everything only goes forwards, so it always returns
+pub fn random_program(seed: u64, length: usize, loops: bool) -> Vec<InstructionType> {
+    use ABIRegister::*;
+
+    // xorshift
+    let mut state = seed.max(1);
+    let mut next = |bound: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % bound as u64) as usize
+    };
+
+    let registers = [a0, a1, a2, a3, a4, a5];
+    let operations = ["add", "sub", "xor", "and", "or", "slt", "sltu", "mul", "addw", "subw"];
+    let conditions = ["beq", "bne", "blt", "bge", "bltu", "bgeu"];
+
+    let mut instructions = Vec::new();
+    for index in 0..length - 1 {
+        let target = if loops { next(length) } else { index + 1 + next(length - index - 1) };
+        let offset = (target as i64 - index as i64) * 4;
+        let (rd, rs1, rs2) = (registers[next(6)].clone(), registers[next(6)].clone(), registers[next(6)].clone());
+
+        instructions.push(match next(10) {
+            0..=3 => r(operations[next(operations.len())], rd, rs1, rs2),
+            4 | 5 => addi(rd, rs1, next(17) as i16 - 8),
+            6 | 7 => branch(conditions[next(conditions.len())], rs1, rs2, offset as i16),
+            8 if offset != 0 => jump(offset as i32),
+            _ => ret()
+        });
+    }
+    instructions.push(ret());
+
+    instructions
+}
@@ -0,0 +1,347 @@
+//! # library signatures
+//! recognising the functions statically linked in from a library, after ida's flirt,
+//! so a stripped binary's `memcpy` is called `memcpy` rather than `sub_10a4c`
+//!
+//! every function in an archive becomes a pattern of its bytes, with wildcards wherever a relocation goes,
+//! as that's all that changes from one binary linking it to the next, along with a hash of the bytes that are left
+//! a function in a binary is named after whichever pattern its bytes match, as long as only the one name does
+//!
+//! linker relaxation can shrink a `call` to a `jal` and move everything after it, which nothing here can see past,
+//! so a function only matches when it was linked without relaxing it
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+
+use object::read::archive::ArchiveFile;
+use object::{elf, Architecture, Object, ObjectSection, ObjectSymbol, Relocation, RelocationFlags, SectionKind, SymbolKind};
+
+use crate::image::Image;
+
+/// anything shorter than this is too likely to turn up by chance to be worth naming
+const MINIMUM_LENGTH: usize = 16;
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// # the pattern of one library function
+/// written out as its hash, length, and bytes in hex with `..` for a wildcard, then its name
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    name: String,
+    bytes: Vec<Option<u8>>,             // none wherever a relocation goes
+    hash: u64                           // of every byte, with the wildcards as zero
+}
+
+impl Pattern {
+    /// a function's bytes, with the offsets of the ones to leave out
+    pub fn new(name: &str, bytes: &[u8], masked: &BTreeSet<usize>) -> Self {
+        let bytes: Vec<Option<u8>> = bytes.iter()
+            .enumerate()
+            .map(|(offset, byte)| Some(*byte).filter(|_| !masked.contains(&offset)))
+            .collect();
+
+        Pattern { name: name.to_string(), hash: hash(&bytes), bytes }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_length(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn get_hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// whether some bytes start with this function, other than where its relocations go
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        bytes.len() >= self.bytes.len() && self.bytes.iter().zip(bytes).all(|(expected, byte)| expected.map_or(true, |e| e == *byte))
+    }
+
+    /// the first word, if no relocation touches it, which is what patterns are looked up by
+    fn prefix(&self) -> Option<[u8; 4]> {
+        let prefix: Vec<u8> = self.bytes.iter().take(4).copied().collect::<Option<_>>()?;
+        prefix.try_into().ok()
+    }
+
+    fn parse(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [hash, length, pattern, name] = fields[..] else {
+            return Err(format!("expected a hash, length, pattern, and name in `{}`", line));
+        };
+
+        let hash = u64::from_str_radix(hash, 16).map_err(|e| format!("bad hash `{}`: {}", hash, e))?;
+        let length = usize::from_str_radix(length, 16).map_err(|e| format!("bad length `{}`: {}", length, e))?;
+        // the length is only as trustworthy as the file, so it might not even double
+        let digits = length.checked_mul(2).ok_or_else(|| format!("the length of {} is too long: {:#x}", name, length))?;
+        if pattern.len() != digits || !pattern.is_ascii() {
+            return Err(format!("the pattern for {} isn't {:#x} bytes long", name, length));
+        }
+
+        let bytes = (0..length)
+            .map(|index| match &pattern[2 * index..2 * index + 2] {
+                ".." => Ok(None),
+                byte => u8::from_str_radix(byte, 16).map(Some).map_err(|e| format!("bad byte `{}` in {}: {}", byte, name, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // anything that doesn't hash the same has been changed since it was written
+        if self::hash(&bytes) != hash {
+            return Err(format!("the pattern for {} doesn't match its hash", name));
+        }
+
+        Ok(Pattern { name: name.to_string(), bytes, hash })
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pattern: String = self.bytes.iter()
+            .map(|byte| byte.map_or("..".to_string(), |b| format!("{:02x}", b)))
+            .collect();
+
+        write!(f, "{:016x} {:04x} {} {}", self.hash, self.bytes.len(), pattern, self.name)
+    }
+}
+
+/// fnv-1a over a function's bytes, with the wildcards as zero
+fn hash(bytes: &[Option<u8>]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte.unwrap_or(0) as u64).wrapping_mul(0x100_0000_01b3))
+}
+
+// ----------------------------------------
+
+/// # the patterns of every function in some libraries
+/// kept by their first word where it's known, so only the few starting the same way have to be checked
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Library {
+    patterns: Vec<Pattern>,
+    by_prefix: BTreeMap<[u8; 4], Vec<usize>>,
+    unprefixed: Vec<usize>              // the ones starting with a relocation
+}
+
+impl Library {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// # read the functions out of an archive
+    /// every member has to be a risc-v object
+    pub fn from_archive(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let archive = ArchiveFile::parse(data)?;
+        let mut library = Library::new();
+
+        for member in archive.members() {
+            let member = member?;
+            let file = object::File::parse(member.data(data)?)?;
+
+            if !matches!(file.architecture(), Architecture::Riscv64 | Architecture::Riscv32) {
+                return Err(format!("{} isn't a risc-v object", String::from_utf8_lossy(member.name())).into());
+            }
+
+            library.add_object(&file)?;
+        }
+
+        Ok(library)
+    }
+
+    /// # add every function an object defines
+    /// where there's more than one name for a function, it goes by the one with the fewest underscores in front,
+    /// which is the one that'd be called, like `puts` rather than `_IO_puts`
+    pub fn add_object(&mut self, file: &object::File) -> Result<(), Box<dyn Error>> {
+        for section in file.sections().filter(|section| section.kind() == SectionKind::Text) {
+            let data = section.data()?;
+            let masked: BTreeSet<usize> = section.relocations()
+                .flat_map(|(offset, relocation)| offset as usize..offset as usize + relocated_size(&relocation))
+                .collect();
+
+            let mut functions: BTreeMap<u64, (String, u64)> = BTreeMap::new();
+            let defined = file.symbols().filter(|symbol| {
+                symbol.section_index() == Some(section.index())
+                    && symbol.is_global()
+                    && matches!(symbol.kind(), SymbolKind::Text | SymbolKind::Unknown)
+            });
+
+            for symbol in defined {
+                let name = symbol.name()?.to_string();
+                let (best, size) = functions.entry(symbol.address()).or_insert_with(|| (name.clone(), 0));
+
+                let underscores = |name: &str| name.len() - name.trim_start_matches('_').len();
+                if (underscores(&name), name.len()) < (underscores(best), best.len()) {
+                    *best = name;
+                }
+                *size = (*size).max(symbol.size());
+            }
+
+            for (address, (name, size)) in functions {
+                let (start, end) = (address as usize, (address + size) as usize);
+                let Some(bytes) = data.get(start..end).filter(|bytes| bytes.len() >= MINIMUM_LENGTH) else {
+                    continue;
+                };
+
+                let masked = masked.range(start..end).map(|offset| offset - start).collect();
+                self.add(Pattern::new(&name, bytes, &masked));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn add(&mut self, pattern: Pattern) {
+        let index = self.patterns.len();
+
+        match pattern.prefix() {
+            Some(prefix) => self.by_prefix.entry(prefix).or_default().push(index),
+            None => self.unprefixed.push(index)
+        }
+        self.patterns.push(pattern);
+    }
+
+    /// everything from another library too
+    pub fn extend(&mut self, other: Library) {
+        for pattern in other.patterns {
+            self.add(pattern);
+        }
+    }
+
+    pub fn get_patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// # read a library back from what it was written out as
+    /// blank lines and anything after a `#` are left out
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut library = Library::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            library.add(Pattern::parse(line).map_err(|e| format!("line {}: {}", number + 1, e))?);
+        }
+
+        Ok(library)
+    }
+
+    /// # the name of the library function at an address
+    /// only if every pattern matching it has the same name, as two different functions can compile to the same thing
+    pub fn identify(&self, image: &Image, address: u64) -> Option<&str> {
+        let prefix: Option<[u8; 4]> = image.read(address, 4).and_then(|word| word.try_into().ok());
+        let candidates = prefix.and_then(|p| self.by_prefix.get(&p)).into_iter().flatten().chain(&self.unprefixed);
+
+        let mut names: BTreeSet<&str> = BTreeSet::new();
+        for pattern in candidates.map(|index| &self.patterns[*index]) {
+            if image.read(address, pattern.get_length()).is_some_and(|bytes| pattern.matches(bytes)) {
+                names.insert(pattern.get_name());
+            }
+        }
+
+        match names.len() {
+            1 => names.pop_first(),
+            _ => None
+        }
+    }
+}
+
+/// one pattern to a line, after a comment saying what it is
+impl fmt::Display for Library {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# asha library signatures: hash, length, bytes with .. for relocations, name")?;
+        for pattern in self.patterns.iter() {
+            writeln!(f, "{}", pattern)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// how many bytes a relocation changes, which for most of risc-v is the whole instruction it's in,
+/// as the immediates are spread over it
+fn relocated_size(relocation: &Relocation) -> usize {
+    let RelocationFlags::Elf { r_type } = relocation.flags() else {
+        return (relocation.size() / 8) as usize;
+    };
+
+    match r_type {
+        // these only say what the linker's allowed to do
+        elf::R_RISCV_NONE | elf::R_RISCV_RELAX | elf::R_RISCV_ALIGN => 0,
+        // an `auipc` and the `jalr` after it
+        elf::R_RISCV_CALL | elf::R_RISCV_CALL_PLT => 8,
+        elf::R_RISCV_64 | elf::R_RISCV_ADD64 | elf::R_RISCV_SUB64 => 8,
+        elf::R_RISCV_RVC_BRANCH | elf::R_RISCV_RVC_JUMP | elf::R_RISCV_ADD16 | elf::R_RISCV_SUB16 | elf::R_RISCV_SET16 => 2,
+        elf::R_RISCV_ADD8 | elf::R_RISCV_SUB8 | elf::R_RISCV_SUB6 | elf::R_RISCV_SET6 | elf::R_RISCV_SET8 => 1,
+        _ => 4
+    }
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn library() -> Library {
+        Library::from_archive(&crate::read_compiled("executables/libsig.a")).unwrap()
+    }
+
+    #[test]
+    fn test_archive() {
+        let library = library();
+        let names: Vec<&str> = library.get_patterns().iter().map(|p| p.get_name()).collect();
+        assert_eq!(names, ["strlen", "puts"]);
+
+        // the call to strlen and the address of the newline are left out
+        let puts = &library.get_patterns()[1];
+        assert_eq!(puts.get_length(), 0x54);
+        let wildcards: Vec<usize> = puts.bytes.iter().enumerate().filter(|(_, b)| b.is_none()).map(|(i, _)| i).collect();
+        assert_eq!(wildcards, [0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x2c, 0x2d, 0x2e, 0x2f, 0x30, 0x31, 0x32, 0x33]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let library = library();
+        assert_eq!(Library::parse(&library.to_string()).unwrap(), library);
+
+        // anything changed by hand no longer hashes the same
+        let text = library.to_string().replacen("9305", "9306", 1);
+        assert!(Library::parse(&text).unwrap_err().contains("doesn't match its hash"));
+
+        // a length too long to have a pattern is an error rather than an overflow
+        let text = format!("0 {:x} 00 huge", usize::MAX);
+        assert!(Library::parse(&text).unwrap_err().contains("too long"));
+    }
+
+    #[test]
+    fn test_identify() {
+        let library = library();
+        let strlen = &library.get_patterns()[0];
+        let puts = &library.get_patterns()[1];
+
+        // puts linked after strlen, with its relocations filled in however the linker did
+        let mut text: Vec<u8> = strlen.bytes.iter().map(|b| b.unwrap()).collect();
+        text.extend(puts.bytes.iter().map(|b| b.unwrap_or(0x5a)));
+        let mut image = Image::new();
+        image.add_section(".text", 0x10000, text, true);
+
+        assert_eq!(library.identify(&image, 0x10000), Some("strlen"));
+        assert_eq!(library.identify(&image, 0x1001c), Some("puts"));
+        assert_eq!(library.identify(&image, 0x10004), None);
+
+        // the same bytes under two names could be either
+        let mut ambiguous = library.clone();
+        ambiguous.add(Pattern { name: "my_strlen".to_string(), ..strlen.clone() });
+        assert_eq!(ambiguous.identify(&image, 0x10000), None);
+    }
+}
@@ -0,0 +1,507 @@
+//! # c prototypes
+//! reads the functions declared in a c header, or in a file of nothing but prototypes,
+//! so that a call to one passes what it's declared to take, and its arguments and result have the declared types
+//!
+//! the preprocessor isn't run, so its lines are skipped, along with bodies and anything that isn't a function or a typedef
+//! headers are full of things that only make sense once they've been preprocessed, so a declaration that can't be read is left out
+//! rather than failing the whole header
+//!
+//! integers are as wide as they are on lp64, and a type name that isn't known is taken to be an opaque struct, like `FILE`
+//! nothing is passed in the floating point registers, so a function with a `float` or `double` in its prototype is left out too
+
+use std::collections::BTreeMap;
+
+use crate::ast::{Declaration, Prototype};
+use crate::ir::Type;
+
+/// prototypes by the name of the function they declare
+pub type PrototypeMap = BTreeMap<String, Prototype>;
+
+/// the common parts of the c library, for when nothing else has been loaded
+pub const LIBC: &str = include_str!("../include/libc.h");
+
+/// anything that qualifies a declaration without changing its type
+const QUALIFIERS: [&str; 15] = [
+    "extern", "static", "inline", "__inline", "__inline__", "register", "auto", "_Noreturn", "__extension__",
+    "volatile", "__volatile__", "restrict", "__restrict", "__restrict__", "_Thread_local"
+];
+
+/// extensions that take brackets after them, and mean nothing to the types
+const EXTENSIONS: [&str; 6] = ["__attribute__", "__attribute", "__asm__", "__asm", "asm", "__declspec"];
+
+// ----------------------------------------
+// tokens
+// ----------------------------------------
+
+/// # split a header into tokens
+/// words and punctuation, leaving out comments, preprocessor lines, and string and character literals
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+
+    while let Some(c) = chars.next() {
+        let starts_line = std::mem::replace(&mut line_start, false);
+
+        match c {
+            '\n' => line_start = true,
+            c if c.is_whitespace() => line_start = starts_line,
+            // a preprocessor line goes on for as long as its lines end in a backslash
+            '#' if starts_line => {
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    if c == '\n' && !escaped {
+                        break;
+                    }
+                    escaped = c == '\\';
+                }
+                line_start = true;
+            },
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                line_start = true;
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut star = false;
+                for c in chars.by_ref() {
+                    if star && c == '/' {
+                        break;
+                    }
+                    star = c == '*';
+                }
+            },
+            '"' | '\'' => {
+                let mut escaped = false;
+                for next in chars.by_ref() {
+                    if next == c && !escaped {
+                        break;
+                    }
+                    escaped = next == '\\' && !escaped;
+                }
+            },
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(next) = chars.next_if(|next| next.is_alphanumeric() || *next == '_') {
+                    word.push(next);
+                }
+                tokens.push(word);
+            },
+            '.' if chars.peek() == Some(&'.') => {
+                chars.next();
+                chars.next();
+                tokens.push("...".to_string());
+            },
+            c => tokens.push(c.to_string())
+        }
+    }
+
+    tokens
+}
+
+/// # split tokens into declarations
+/// each ends at a semicolon, or at the body of a function, and the bodies of anything else are left out
+fn declarations(tokens: Vec<String>) -> Vec<Vec<String>> {
+    let mut declarations = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    let mut tokens = tokens.into_iter();
+
+    while let Some(token) = tokens.next() {
+        match token.as_str() {
+            ";" => declarations.push(std::mem::take(&mut current)),
+            "{" => {
+                let mut depth = 1;
+                for token in tokens.by_ref() {
+                    match token.as_str() {
+                        "{" => depth += 1,
+                        "}" if depth == 1 => break,
+                        "}" => depth -= 1,
+                        _ => {}
+                    }
+                }
+
+                if current.last().is_some_and(|last| last == ")") {
+                    declarations.push(std::mem::take(&mut current));
+                }
+            },
+            _ => current.push(token)
+        }
+    }
+
+    declarations
+}
+
+/// leave out any extensions and whatever's in the brackets after them
+fn strip_extensions(tokens: &[String]) -> Vec<String> {
+    let mut stripped = Vec::new();
+    let mut tokens = tokens.iter().peekable();
+
+    while let Some(token) = tokens.next() {
+        if !EXTENSIONS.contains(&token.as_str()) {
+            stripped.push(token.clone());
+            continue;
+        }
+
+        let mut depth = 0;
+        while let Some(token) = tokens.next_if(|token| depth > 0 || *token == "(") {
+            match token.as_str() {
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+        }
+    }
+
+    stripped
+}
+
+// ----------------------------------------
+// types
+// ----------------------------------------
+
+/// the type a standard typedef names, which for the integers is what it is on lp64
+fn builtin(name: &str) -> Option<Type> {
+    let int = |size, signed| Some(Type::Int { size, signed });
+
+    match name {
+        "int8_t" => int(1, true),
+        "uint8_t" => int(1, false),
+        "int16_t" => int(2, true),
+        "uint16_t" => int(2, false),
+        "int32_t" | "wchar_t" | "pid_t" => int(4, true),
+        "uint32_t" | "wint_t" | "uid_t" | "gid_t" | "mode_t" | "socklen_t" => int(4, false),
+        "int64_t" | "intptr_t" | "intmax_t" | "ssize_t" | "ptrdiff_t" | "off_t" | "time_t" | "clock_t" => int(8, true),
+        "uint64_t" | "uintptr_t" | "uintmax_t" | "size_t" => int(8, false),
+        _ => None
+    }
+}
+
+/// what a declarator does to the type it's declared with, from the name outwards
+#[derive(Clone, Debug)]
+enum Derived {
+    Pointer,
+    Array,
+    Function(Vec<Declaration>, bool)
+}
+
+/// the type something's declared as, given what the declarator does to the base type
+fn build(base: Type, derived: &[Derived]) -> Type {
+    derived.iter().rev().fold(base, |ty, derived| match derived {
+        // arrays only come up as parameters, which are pointers anyway
+        Derived::Pointer | Derived::Array => Type::Pointer(Box::new(ty)),
+        Derived::Function(..) => Type::Function(Box::new(ty))
+    })
+}
+
+/// whether a token can name something
+fn is_word(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_alphabetic() || c == '_')
+}
+
+// ----------------------------------------
+// parsing
+// ----------------------------------------
+
+/// a declaration read from a header
+enum Declared {
+    Typedef(String, Type),
+    Function(Prototype)
+}
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    position: usize,
+    typedefs: &'a BTreeMap<String, Type>
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).map(|token| token.as_str())
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.position += 1;
+        self.tokens.get(self.position - 1).map(|token| token.as_str())
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let eaten = self.peek() == Some(token);
+        if eaten {
+            self.position += 1;
+        }
+        eaten
+    }
+
+    /// # the type a declaration starts with
+    /// along with whether it's a typedef, or none if it doesn't start with one or it's a float
+    fn specifiers(&mut self) -> Option<(Type, bool)> {
+        let (mut typedef, mut constant, mut signed) = (false, false, None);
+        let (mut short, mut longs, mut int, mut char, mut float) = (false, 0, false, false, false);
+        let mut base = None;
+
+        while let Some(token) = self.peek() {
+            let seen = short || longs > 0 || int || char || float || signed.is_some() || base.is_some();
+
+            match token {
+                "typedef" => typedef = true,
+                "const" | "__const" => constant = true,
+                "signed" | "__signed__" => signed = Some(true),
+                "unsigned" => signed = Some(false),
+                "short" => short = true,
+                "long" => longs += 1,
+                "int" => int = true,
+                "char" => char = true,
+                "float" | "double" => float = true,
+                "void" => base = Some(Type::Void),
+                "_Bool" | "bool" => base = Some(Type::Int { size: 1, signed: false }),
+                "struct" | "union" | "enum" => {
+                    let kind = self.next()?.to_string();
+                    let tag = self.peek().filter(|tag| is_word(tag))?.to_string();
+                    base = Some(match kind.as_str() {
+                        "enum" => Type::Int { size: 4, signed: true },
+                        _ => Type::Struct(tag)
+                    });
+                },
+                token if QUALIFIERS.contains(&token) => {},
+                // a name is only a type when nothing else has said what the type is
+                token if is_word(token) && !seen => {
+                    base = Some(self.typedefs.get(token).cloned()
+                        .or_else(|| builtin(token))
+                        .unwrap_or_else(|| Type::Struct(token.to_string())));
+                },
+                _ => break
+            }
+
+            self.position += 1;
+        }
+
+        if float {
+            return None;
+        }
+
+        let base = match (base, char, signed) {
+            (Some(base), ..) => base,
+            (None, true, None) => Type::Char,
+            (None, true, Some(signed)) => Type::Int { size: 1, signed },
+            (None, false, _) if short || longs > 0 || int || signed.is_some() => {
+                let size = if short { 2 } else if longs > 0 { 8 } else { 4 };
+                Type::Int { size, signed: signed.unwrap_or(true) }
+            },
+            _ => return None
+        };
+
+        Some((if constant { Type::Const(Box::new(base)) } else { base }, typedef))
+    }
+
+    /// # what's declared and how
+    /// the name, if there is one, and what's done to the base type to get to it, nearest the name first
+    fn declarator(&mut self) -> Option<(Option<String>, Vec<Derived>)> {
+        let mut pointers = 0;
+        while self.eat("*") {
+            pointers += 1;
+            // a const pointer is the same pointer as far as anything here goes
+            while self.peek().is_some_and(|token| token == "const" || QUALIFIERS.contains(&token)) {
+                self.position += 1;
+            }
+        }
+
+        let nested = self.peek() == Some("(") && self.tokens.get(self.position + 1).is_some_and(|token| token == "*");
+        let (name, mut derived) = if nested {
+            self.position += 1;
+            let inner = self.declarator()?;
+            self.eat(")").then_some(inner)?
+        } else if self.peek().is_some_and(is_word) {
+            (self.next().map(|name| name.to_string()), Vec::new())
+        } else {
+            (None, Vec::new())
+        };
+
+        loop {
+            if self.eat("[") {
+                while self.next()? != "]" {}
+                derived.push(Derived::Array);
+            } else if self.eat("(") {
+                let (parameters, variadic) = self.parameters()?;
+                derived.push(Derived::Function(parameters, variadic));
+            } else {
+                break;
+            }
+        }
+
+        derived.extend(std::iter::repeat(Derived::Pointer).take(pointers));
+        Some((name, derived))
+    }
+
+    /// # the parameters of a function, after its opening bracket
+    /// each named after the register it's passed in when it doesn't have a name, and whether there are more after them
+    fn parameters(&mut self) -> Option<(Vec<Declaration>, bool)> {
+        let mut parameters = Vec::new();
+
+        if self.peek() == Some("void") && self.tokens.get(self.position + 1).is_some_and(|token| token == ")") {
+            self.position += 1;
+        }
+
+        while !self.eat(")") {
+            if self.eat("...") {
+                return self.eat(")").then_some((parameters, true));
+            }
+
+            let (base, _) = self.specifiers()?;
+            let (name, derived) = self.declarator()?;
+
+            // a function passed as a parameter is a pointer to it
+            let ty = match build(base, &derived) {
+                ty @ Type::Function(_) => Type::Pointer(Box::new(ty)),
+                ty => ty
+            };
+            let name = name.unwrap_or_else(|| format!("a{}", parameters.len()));
+            parameters.push(Declaration::new(ty, name));
+
+            if !self.eat(",") && self.peek() != Some(")") {
+                return None;
+            }
+        }
+
+        Some((parameters, false))
+    }
+
+    /// every typedef and function a declaration declares
+    fn declaration(&mut self) -> Option<Vec<Declared>> {
+        let (base, typedef) = self.specifiers()?;
+        let mut declared = Vec::new();
+
+        loop {
+            let (Some(name), derived) = self.declarator()? else {
+                return None;
+            };
+
+            match derived.split_first() {
+                _ if typedef => declared.push(Declared::Typedef(name, build(base.clone(), &derived))),
+                Some((Derived::Function(parameters, variadic), rest)) => {
+                    let returns = Some(build(base.clone(), rest)).filter(|ty| *ty != Type::Void);
+                    declared.push(Declared::Function(Prototype { name, parameters: Some(parameters.clone()), returns, variadic: *variadic }));
+                },
+                _ => {}
+            }
+
+            // an initialiser ends anything worth reading
+            if !self.eat(",") {
+                break;
+            }
+        }
+
+        (self.position == self.tokens.len() || self.peek() == Some("=")).then_some(declared)
+    }
+}
+
+/// # read the prototypes out of a header
+/// along with any typedefs they use, which have to come before them the way they do in c
+pub fn parse_header(text: &str) -> PrototypeMap {
+    let mut typedefs = BTreeMap::new();
+    let mut prototypes = PrototypeMap::new();
+
+    for tokens in declarations(tokenize(text)) {
+        let tokens = strip_extensions(&tokens);
+        let mut parser = Parser { tokens: &tokens, position: 0, typedefs: &typedefs };
+
+        for declared in parser.declaration().into_iter().flatten() {
+            match declared {
+                Declared::Typedef(name, ty) => {
+                    typedefs.insert(name, ty);
+                },
+                Declared::Function(prototype) => {
+                    prototypes.insert(prototype.name.clone(), prototype);
+                }
+            }
+        }
+    }
+
+    prototypes
+}
+
+/// the prototypes of the common parts of the c library
+pub fn libc() -> PrototypeMap {
+    parse_header(LIBC)
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prototypes() {
+        let prototypes = parse_header("
+            #include <stddef.h>
+            #define EOF (-1)
+            #define max(a, b) \\
+                ((a) > (b) ? (a) : (b))
+
+            /* a typedef of a typedef */
+            typedef unsigned long size_t;
+            typedef size_t length;
+
+            // unnamed parameters, and more than it names
+            int printf(const char *, ...);
+            extern length strlen(const char *s) __attribute__((pure));
+            void qsort(void *base, size_t n, size_t size, int (*compare)(const void *, const void *));
+            char *fgets(char buf[], int n, FILE *stream);
+            unsigned char next(void);
+
+            struct point { int x; int y; };
+            static inline int square(int x) { return x * x; }
+            double sqrt(double x);
+            int errno;
+        ");
+
+        let printed: Vec<String> = prototypes.values().map(|prototype| prototype.to_string()).collect();
+        assert_eq!(printed, [
+            "char *fgets(char *buf, int32_t n, struct FILE *stream)",
+            "uint8_t next(void)",
+            "int32_t printf(const char *a0, ...)",
+            "void qsort(void *base, uint64_t n, uint64_t size, int32_t (*compare)())",
+            "int32_t square(int32_t x)",
+            "uint64_t strlen(const char *s)"
+        ]);
+    }
+
+    #[test]
+    fn test_declarators() {
+        // a function returning a pointer to a function
+        let prototypes = parse_header("void (*signal(int sig, void (*handler)(int)))(int);");
+        let signal = &prototypes["signal"];
+
+        assert_eq!(signal.returns, Some(Type::Pointer(Box::new(Type::Function(Box::new(Type::Void))))));
+        assert_eq!(signal.parameters.as_ref().map(|parameters| parameters.len()), Some(2));
+        assert_eq!(signal.to_string(), "void (*signal(int32_t sig, void (*handler)()))()");
+
+        // long long, and signedness on its own
+        let prototypes = parse_header("long long atoll(const char *s); unsigned f(signed char c, short s);");
+        assert_eq!(prototypes["atoll"].to_string(), "int64_t atoll(const char *s)");
+        assert_eq!(prototypes["f"].to_string(), "uint32_t f(int8_t c, int16_t s)");
+    }
+
+    #[test]
+    fn test_libc() {
+        let prototypes = libc();
+
+        assert_eq!(prototypes["printf"].to_string(), "int32_t printf(const char *format, ...)");
+        assert_eq!(prototypes["puts"].to_string(), "int32_t puts(const char *s)");
+        assert_eq!(prototypes["malloc"].to_string(), "void *malloc(uint64_t size)");
+        assert_eq!(prototypes["exit"].returns, None);
+
+        // everything it declares is read
+        let declared = LIBC.lines().filter(|line| line.ends_with(");")).count();
+        assert_eq!(prototypes.len(), declared);
+    }
+}
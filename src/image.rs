@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use object::{Object, ObjectSection, ObjectSymbol, SectionKind, SymbolKind};
+
+/// the symbol the linker sets `gp` to, so it can be used to reach data near it
+const GLOBAL_POINTER: &str = "__global_pointer$";
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// a single section of the image, placed at the address it is loaded at
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageSection {
+    name: String,
+    address: u64,
+    data: Vec<u8>,
+    executable: bool
+}
+
+impl ImageSection {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_address(&self) -> u64 {
+        self.address
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.executable
+    }
+
+    /// determine if a given address is in this section
+    pub fn contains(&self, address: u64) -> bool {
+        address >= self.address && address - self.address < self.data.len() as u64
+    }
+}
+
+/// a named address from the symbol table, covering `size` bytes if that's known
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    name: String,
+    address: u64,
+    size: u64
+}
+
+impl Symbol {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_address(&self) -> u64 {
+        self.address
+    }
+
+    /// determine if a given address is this symbol, or inside it
+    pub fn contains(&self, address: u64) -> bool {
+        address == self.address || (address > self.address && address - self.address < self.size)
+    }
+}
+
+// ----------------------------------------
+
+/// # Loaded memory image of an executable
+/// every allocated section with contents, so that data referenced by the code (jump tables, strings, etc.) can be read back,
+/// along with the names the symbol table gives to addresses in them
+#[derive(Clone, Debug, Default)]
+pub struct Image {
+    sections: Vec<ImageSection>,
+    symbols: BTreeMap<u64, Symbol>,
+    global_pointer: Option<u64>     // what `gp` is set to at startup, if the linker said
+}
+
+impl Image {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// load every allocated section of an object file
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let file = object::File::parse(bytes)?;
+        let mut image = Image::new();
+
+        for section in file.sections() {
+            // sections that don't get loaded have no address
+            if section.address() == 0 {
+                continue;
+            }
+
+            let executable = match section.kind() {
+                SectionKind::Text => true,
+                SectionKind::Data | SectionKind::ReadOnlyData | SectionKind::ReadOnlyDataWithRel | SectionKind::ReadOnlyString => false,
+                _ => continue
+            };
+
+            image.add_section(section.name()?, section.address(), section.data()?.to_vec(), executable);
+        }
+
+        // only functions and objects get names, mapping symbols (`$x`, `$d`) and the like don't
+        for symbol in file.symbols() {
+            let name = symbol.name()?;
+
+            if name == GLOBAL_POINTER {
+                image.global_pointer = Some(symbol.address());
+                continue;
+            }
+
+            if name.is_empty() || name.starts_with('$') || symbol.address() == 0 {
+                continue;
+            }
+
+            if matches!(symbol.kind(), SymbolKind::Text | SymbolKind::Data | SymbolKind::Unknown) {
+                image.add_symbol(name, symbol.address(), symbol.size());
+            }
+        }
+
+        Ok(image)
+    }
+
+    pub fn add_section(&mut self, name: &str, address: u64, data: Vec<u8>, executable: bool) {
+        self.sections.push(ImageSection {
+            name: name.to_string(),
+            address,
+            data,
+            executable
+        });
+    }
+
+    /// where there's more than one name for an address, the first one found is kept
+    pub fn add_symbol(&mut self, name: &str, address: u64, size: u64) {
+        self.symbols.entry(address).or_insert(Symbol {
+            name: name.to_string(),
+            address,
+            size
+        });
+    }
+
+    pub fn get_global_pointer(&self) -> Option<u64> {
+        self.global_pointer
+    }
+
+    pub fn set_global_pointer(&mut self, address: u64) {
+        self.global_pointer = Some(address);
+    }
+
+    /// # find the symbol naming an address
+    /// the address has to be loaded, and either be where the symbol starts or inside it
+    pub fn symbol_containing(&self, address: u64) -> Option<&Symbol> {
+        self.section_containing(address)?;
+
+        self.symbols.range(..=address)
+            .next_back()
+            .map(|(_, symbol)| symbol)
+            .filter(|symbol| symbol.contains(address))
+    }
+
+    /// find a symbol by its name
+    pub fn symbol_named(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.values().find(|symbol| symbol.name == name)
+    }
+
+    pub fn get_sections(&self) -> &[ImageSection] {
+        &self.sections
+    }
+
+    /// find the section that a given address is in
+    pub fn section_containing(&self, address: u64) -> Option<&ImageSection> {
+        self.sections.iter().find(|s| s.contains(address))
+    }
+
+    /// read `size` bytes starting at `address`, as long as they're all in the same section
+    pub fn read(&self, address: u64, size: usize) -> Option<&[u8]> {
+        let section = self.section_containing(address)?;
+        let start = (address - section.address) as usize;
+
+        section.data.get(start..start.checked_add(size)?)
+    }
+
+    /// # the text at an address
+    /// as long as it's valid utf-8 ended by a nul in the same section, with nothing in it that can't be printed other than whitespace
+    pub fn read_string(&self, address: u64) -> Option<&str> {
+        let section = self.section_containing(address)?;
+        let data = &section.data[(address - section.address) as usize..];
+        let text = std::str::from_utf8(&data[..data.iter().position(|byte| *byte == 0)?]).ok()?;
+
+        (!text.chars().any(|c| c.is_control() && !matches!(c, '\n' | '\t' | '\r'))).then_some(text)
+    }
+
+    /// read a little-endian integer of 1, 2, 4, or 8 bytes, extending it to 64 bits
+    pub fn read_int(&self, address: u64, size: usize, signed: bool) -> Option<u64> {
+        let bytes = self.read(address, size)?;
+
+        let mut raw = [0u8; 8];
+        raw[..size].copy_from_slice(bytes);
+        let value = u64::from_le_bytes(raw);
+
+        if signed && size < 8 {
+            let shift = 64 - 8 * size as u32;
+            Some((((value << shift) as i64) >> shift) as u64)
+        } else {
+            Some(value)
+        }
+    }
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let mut image = Image::new();
+        image.add_section(".rodata", 0x2000, vec![0xfc, 0xff, 0xff, 0xff, 0x10, 0x00, 0x00, 0x00], false);
+
+        assert_eq!(image.read(0x2004, 4), Some(&[0x10, 0x00, 0x00, 0x00][..]));
+        assert_eq!(image.read(0x2006, 4), None);
+        assert_eq!(image.read(0x1ffc, 4), None);
+
+        // sign and zero extension
+        assert_eq!(image.read_int(0x2000, 4, true), Some(-4i64 as u64));
+        assert_eq!(image.read_int(0x2000, 4, false), Some(0xfffffffc));
+        assert_eq!(image.read_int(0x2000, 8, false), Some(0x00000010fffffffc));
+        assert_eq!(image.read_int(0x2004, 1, true), Some(0x10));
+    }
+
+    #[test]
+    fn test_read_string() {
+        let mut image = Image::new();
+        image.add_section(".rodata", 0x2000, b"hi\tthere\n\0\x01\x02\0\xffno end".to_vec(), false);
+
+        assert_eq!(image.read_string(0x2000), Some("hi\tthere\n"));
+        assert_eq!(image.read_string(0x2009), Some(""));
+        // control characters, invalid utf-8, and no nul before the end of the section
+        assert_eq!(image.read_string(0x200a), None);
+        assert_eq!(image.read_string(0x200d), None);
+        assert_eq!(image.read_string(0x200e), None);
+    }
+
+    #[test]
+    fn test_symbol_containing() {
+        let mut image = Image::new();
+        image.add_section(".data", 0x3000, vec![0; 0x20], false);
+        image.add_symbol("table", 0x3000, 0x10);
+        image.add_symbol("flag", 0x3018, 0);
+
+        assert_eq!(image.symbol_containing(0x3008).map(|s| s.get_name()), Some("table"));
+        assert_eq!(image.symbol_containing(0x3018).map(|s| s.get_name()), Some("flag"));
+
+        // past the end of a symbol, or at an address that isn't loaded, there's nothing to name it
+        assert_eq!(image.symbol_containing(0x3010), None);
+        assert_eq!(image.symbol_containing(0x301c), None);
+        assert_eq!(image.symbol_containing(0x4000), None);
+    }
+}
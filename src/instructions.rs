@@ -19,7 +19,7 @@ use phf::phf_map;
 /// Each instruction encoding keeps the opcode, destination register, and first source register in the same place (if they exist)
 /// It should be noted that the only difference between the S and B formats is that the 12-bit immediate field is used to encode branch offsets in multiples of 2 in the B format. Similarly, the only difference between the U and J formats is that the 20-bit immediate is shifted left by 12 bits to form U immediates, and by 1 to form J immediates.
 /// NOTE: the zicsr and zifencei sets are also considered standard, may add them
-
+///
 /// # Instruction types
 /// The fields are as follows: (not to scale)
 /// | funct7                | rs2 | rs1 | funct3 | rd                   | opcode | R type |
@@ -148,7 +148,7 @@ pub type Instruction = u32;
 
 /// Enum to translate registers from binary value to ABI name
 #[allow(non_camel_case_types)]
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone)]
 pub enum ABIRegister {
     zero,   // hardcoded zero
     ra,     // return address
@@ -278,7 +278,7 @@ pub static INSTRUCTIONS: phf::Map<[u8; 3], &'static str> = phf_map! {
     [0b00100, 0b011, 0b0000000] => "sltiu",
     [0b00100, 0b100, 0b0000000] => "xori",
     [0b00100, 0b101, 0b0000000] => "srli",
-    [0b00100, 0b101, 0b0100000] => "srai",    // this is the only I-type to have a relevant funct7 (NOTE: WRONG! add an i-type with funct7 please)
+    [0b00100, 0b101, 0b0100000] => "srai",    // the shifts are the only I-types to have a relevant funct7
     [0b00100, 0b110, 0b0000000] => "ori",
     [0b00100, 0b111, 0b0000000] => "andi",
     [0b00101, 0b000, 0b0000000] => "auipc",
@@ -311,7 +311,7 @@ pub static INSTRUCTIONS: phf::Map<[u8; 3], &'static str> = phf_map! {
     [0b00110, 0b000, 0b0000000] => "addiw",
     [0b00110, 0b001, 0b0000000] => "slliw",
     [0b00110, 0b101, 0b0000000] => "srliw",
-    //[0b00110, 0b101, 0b0100000] => "addiw",   // sort this shit too
+    [0b00110, 0b101, 0b0100000] => "sraiw",
     [0b01000, 0b011, 0b0000000] => "sd",
     [0b01110, 0b000, 0b0000000] => "addw",
     [0b01110, 0b000, 0b0100000] => "subw",
@@ -359,7 +359,7 @@ pub static INSTRUCTIONS: phf::Map<[u8; 3], &'static str> = phf_map! {
 
 /// retrieve specified fields from raw instruction bytes
 // TODO: see if i need anything else for R4
-// NOTE: i don't actually handle sign extension, may change that
+// NOTE: these don't sign-extend, the disassembler does that once it knows the width
 macro_rules! retrieve {
     (opcode $inst:expr) => {
         (($inst >> 2) & 0x1f)
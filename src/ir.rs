@@ -0,0 +1,797 @@
+//! # lifted intermediate representation
+//! each instruction is lifted into statements over typed expressions, with its exact semantics spelled out
+//! so loads know their width and signedness, and the `*w` instructions truncate to 32 bits and sign-extend back
+//! everything after structuring works on these rather than on the instructions themselves
+
+use std::fmt;
+
+use crate::instructions::{ABIRegister, InstructionType};
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// the operations that take two values
+/// shifts only use as many bits of the amount as the instruction does, which lifting masks explicitly
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    MulHigh,                // upper 64 bits of signed * signed
+    MulHighSignedUnsigned,  // upper 64 bits of signed * unsigned
+    MulHighUnsigned,        // upper 64 bits of unsigned * unsigned
+    Div,
+    DivUnsigned,
+    Rem,
+    RemUnsigned,
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRightLogical,
+    ShiftRightArithmetic,
+    Equal,                  // the comparisons give 1 or 0
+    NotEqual,
+    Less,
+    LessUnsigned,
+    GreaterEqual,
+    GreaterEqualUnsigned
+}
+
+impl BinaryOp {
+    /// the operator in c, for the ones that have one
+    fn symbol(self) -> Option<&'static str> {
+        let symbol = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div | BinaryOp::DivUnsigned => "/",
+            BinaryOp::Rem | BinaryOp::RemUnsigned => "%",
+            BinaryOp::And => "&",
+            BinaryOp::Or => "|",
+            BinaryOp::Xor => "^",
+            BinaryOp::ShiftLeft => "<<",
+            BinaryOp::ShiftRightLogical | BinaryOp::ShiftRightArithmetic => ">>",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less | BinaryOp::LessUnsigned => "<",
+            BinaryOp::GreaterEqual | BinaryOp::GreaterEqualUnsigned => ">=",
+            _ => return None
+        };
+
+        Some(symbol)
+    }
+
+    /// the comparison that's true exactly when this one is false, for the ones that are comparisons
+    fn opposite(self) -> Option<BinaryOp> {
+        let opposite = match self {
+            BinaryOp::Equal => BinaryOp::NotEqual,
+            BinaryOp::NotEqual => BinaryOp::Equal,
+            BinaryOp::Less => BinaryOp::GreaterEqual,
+            BinaryOp::LessUnsigned => BinaryOp::GreaterEqualUnsigned,
+            BinaryOp::GreaterEqual => BinaryOp::Less,
+            BinaryOp::GreaterEqualUnsigned => BinaryOp::LessUnsigned,
+            _ => return None
+        };
+
+        Some(opposite)
+    }
+
+    /// # work out the result for two known operands
+    /// exactly as the hardware does, so dividing by zero gives all ones rather than failing
+    pub fn apply(self, a: i64, b: i64) -> i64 {
+        let (ua, ub) = (a as u64, b as u64);
+
+        match self {
+            BinaryOp::Add => a.wrapping_add(b),
+            BinaryOp::Sub => a.wrapping_sub(b),
+            BinaryOp::Mul => a.wrapping_mul(b),
+            BinaryOp::MulHigh => ((a as i128 * b as i128) >> 64) as i64,
+            BinaryOp::MulHighSignedUnsigned => ((a as i128 * ub as i128) >> 64) as i64,
+            BinaryOp::MulHighUnsigned => ((ua as u128 * ub as u128) >> 64) as i64,
+            BinaryOp::Div if b == 0 => -1,
+            BinaryOp::Div => a.wrapping_div(b),
+            BinaryOp::DivUnsigned if b == 0 => -1,
+            BinaryOp::DivUnsigned => (ua / ub) as i64,
+            BinaryOp::Rem if b == 0 => a,
+            BinaryOp::Rem => a.wrapping_rem(b),
+            BinaryOp::RemUnsigned if b == 0 => a,
+            BinaryOp::RemUnsigned => (ua % ub) as i64,
+            BinaryOp::And => a & b,
+            BinaryOp::Or => a | b,
+            BinaryOp::Xor => a ^ b,
+            BinaryOp::ShiftLeft => a.wrapping_shl(b as u32),
+            BinaryOp::ShiftRightLogical => ua.wrapping_shr(b as u32) as i64,
+            BinaryOp::ShiftRightArithmetic => a.wrapping_shr(b as u32),
+            BinaryOp::Equal => (a == b) as i64,
+            BinaryOp::NotEqual => (a != b) as i64,
+            BinaryOp::Less => (a < b) as i64,
+            BinaryOp::LessUnsigned => (ua < ub) as i64,
+            BinaryOp::GreaterEqual => (a >= b) as i64,
+            BinaryOp::GreaterEqualUnsigned => (ua >= ub) as i64
+        }
+    }
+
+    /// whether the operands are treated as unsigned, which c has to be told with a cast
+    fn is_unsigned(self) -> bool {
+        matches!(self,
+            BinaryOp::DivUnsigned | BinaryOp::RemUnsigned | BinaryOp::LessUnsigned | BinaryOp::GreaterEqualUnsigned
+        )
+    }
+}
+
+/// # somewhere a value is kept
+/// registers to begin with, then one version per definition in ssa form, then the variables recovered from those
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Variable {
+    Register(ABIRegister),
+    Version(ABIRegister, usize),
+    Named(String)
+}
+
+impl Variable {
+    /// the register this is kept in, until it's been given a name
+    pub fn get_register(&self) -> Option<&ABIRegister> {
+        match self {
+            Variable::Register(register) | Variable::Version(register, _) => Some(register),
+            Variable::Named(_) => None
+        }
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Variable::Register(register) => write!(f, "{}", register),
+            Variable::Version(register, version) => write!(f, "{}_{}", register, version),
+            Variable::Named(name) => write!(f, "{}", name)
+        }
+    }
+}
+
+/// # a value
+/// registers are 64 bits wide, so everything is an i64 until something narrows it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expression {
+    Variable(Variable),
+    Constant(i64),
+    Binary(BinaryOp, Box<Expression>, Box<Expression>),
+    /// `size` bytes read from memory, then extended to 64 bits
+    Load { addr: Box<Expression>, size: u8, signed: bool },
+    /// the low `bits` of a value, extended back to 64 bits
+    Extend { value: Box<Expression>, bits: u8, signed: bool },
+    /// an address that's been given a name by the symbol table, `offset` bytes into whatever it names
+    Symbol { name: String, offset: i64 },
+    /// what a typed pointer points at, where adding to the pointer goes in elements rather than bytes
+    Deref(Box<Expression>),
+    /// a value converted to a type c wouldn't convert it to by itself
+    Cast { value: Box<Expression>, ty: Type },
+    /// a field of the struct a pointer points at
+    Field { base: Box<Expression>, name: String },
+    /// the address of some text, written out as the text itself, which is utf-16 when it's wide
+    String { text: String, wide: bool }
+}
+
+impl Expression {
+    /// reading `zero` always gives 0, so it's lifted as a constant
+    pub fn register(register: &ABIRegister) -> Self {
+        if *register == ABIRegister::zero {
+            Expression::Constant(0)
+        } else {
+            Expression::Variable(Variable::Register(register.clone()))
+        }
+    }
+
+    /// # combine two values
+    /// only folds what can't change the result, like adding 0, so `li` and `mv` don't look like arithmetic,
+    /// and two constants, which are worked out there and then
+    /// comparisons are already 0 or 1, so comparing one against 0 again is the comparison itself or its opposite
+    pub fn binary(op: BinaryOp, lhs: Expression, rhs: Expression) -> Self {
+        match (op, lhs, rhs) {
+            (BinaryOp::Add | BinaryOp::Or | BinaryOp::Xor, Expression::Constant(0), other) |
+            (BinaryOp::Add | BinaryOp::Sub | BinaryOp::Or | BinaryOp::Xor | BinaryOp::ShiftLeft |
+             BinaryOp::ShiftRightLogical | BinaryOp::ShiftRightArithmetic, other, Expression::Constant(0)) => other,
+            (op, Expression::Constant(a), Expression::Constant(b)) => Expression::Constant(op.apply(a, b)),
+            (BinaryOp::NotEqual, Expression::Binary(inner, lhs, rhs), Expression::Constant(0)) if inner.opposite().is_some() => Expression::Binary(inner, lhs, rhs),
+            (BinaryOp::Equal, Expression::Binary(inner, lhs, rhs), Expression::Constant(0)) if inner.opposite().is_some() => Expression::Binary(inner.opposite().unwrap(), lhs, rhs),
+            (op, lhs, rhs) => Expression::Binary(op, Box::new(lhs), Box::new(rhs))
+        }
+    }
+
+    pub fn load(addr: Expression, size: u8, signed: bool) -> Self {
+        Expression::Load { addr: Box::new(addr), size, signed }
+    }
+
+    /// constants are extended straight away
+    pub fn extend(value: Expression, bits: u8, signed: bool) -> Self {
+        match value {
+            Expression::Constant(c) => Expression::Constant(extend(c, bits, signed)),
+            value => Expression::Extend { value: Box::new(value), bits, signed }
+        }
+    }
+
+    /// # put a value in place of a variable
+    /// built back up through the constructors, so whatever folds now that more is known does
+    pub fn substitute(&self, variable: &Variable, value: &Expression) -> Expression {
+        match self {
+            Expression::Variable(v) if v == variable => value.clone(),
+            Expression::Variable(_) | Expression::Constant(_) | Expression::Symbol { .. } | Expression::String { .. } => self.clone(),
+            Expression::Binary(op, lhs, rhs) => Expression::binary(*op, lhs.substitute(variable, value), rhs.substitute(variable, value)),
+            Expression::Load { addr, size, signed } => Expression::load(addr.substitute(variable, value), *size, *signed),
+            Expression::Extend { value: inner, bits, signed } => Expression::extend(inner.substitute(variable, value), *bits, *signed),
+            Expression::Deref(addr) => Expression::Deref(Box::new(addr.substitute(variable, value))),
+            Expression::Field { base, name } => Expression::Field { base: Box::new(base.substitute(variable, value)), name: name.clone() },
+            Expression::Cast { value: inner, ty } => Expression::Cast { value: Box::new(inner.substitute(variable, value)), ty: ty.clone() }
+        }
+    }
+
+    /// whether this prints without needing brackets around it when it's inside something else
+    fn is_simple(&self) -> bool {
+        matches!(self, Expression::Variable(_) | Expression::Constant(_) | Expression::Load { .. } | Expression::Symbol { offset: 0, .. } | Expression::Deref(_) | Expression::Field { .. } | Expression::String { .. })
+    }
+
+    /// every variable read, in the order they're printed
+    pub fn for_each_variable(&self, f: &mut impl FnMut(&Variable)) {
+        match self {
+            Expression::Variable(variable) => f(variable),
+            Expression::Constant(_) | Expression::Symbol { .. } | Expression::String { .. } => {},
+            Expression::Binary(_, lhs, rhs) => {
+                lhs.for_each_variable(f);
+                rhs.for_each_variable(f);
+            },
+            Expression::Load { addr, .. } | Expression::Deref(addr) | Expression::Field { base: addr, .. } => addr.for_each_variable(f),
+            Expression::Extend { value, .. } | Expression::Cast { value, .. } => value.for_each_variable(f)
+        }
+    }
+
+    /// the name of every symbol referred to
+    pub fn for_each_symbol(&self, f: &mut impl FnMut(&str)) {
+        match self {
+            Expression::Symbol { name, .. } => f(name),
+            Expression::Variable(_) | Expression::Constant(_) | Expression::String { .. } => {},
+            Expression::Binary(_, lhs, rhs) => {
+                lhs.for_each_symbol(f);
+                rhs.for_each_symbol(f);
+            },
+            Expression::Load { addr, .. } | Expression::Deref(addr) | Expression::Field { base: addr, .. } => addr.for_each_symbol(f),
+            Expression::Extend { value, .. } | Expression::Cast { value, .. } => value.for_each_symbol(f)
+        }
+    }
+
+    pub fn for_each_variable_mut(&mut self, f: &mut impl FnMut(&mut Variable)) {
+        match self {
+            Expression::Variable(variable) => f(variable),
+            Expression::Constant(_) | Expression::Symbol { .. } | Expression::String { .. } => {},
+            Expression::Binary(_, lhs, rhs) => {
+                lhs.for_each_variable_mut(f);
+                rhs.for_each_variable_mut(f);
+            },
+            Expression::Load { addr, .. } | Expression::Deref(addr) | Expression::Field { base: addr, .. } => addr.for_each_variable_mut(f),
+            Expression::Extend { value, .. } | Expression::Cast { value, .. } => value.for_each_variable_mut(f)
+        }
+    }
+
+    fn bracketed(&self) -> String {
+        if self.is_simple() {
+            self.to_string()
+        } else {
+            format!("({})", self)
+        }
+    }
+}
+
+/// extend the low `bits` of a value to 64 bits
+pub fn extend(value: i64, bits: u8, signed: bool) -> i64 {
+    if bits >= 64 {
+        value
+    } else if signed {
+        (value << (64 - bits)) >> (64 - bits)
+    } else {
+        value & ((1_i64 << bits) - 1)
+    }
+}
+
+/// # the type of a variable in c
+/// integers and pointers to them, as only the integer instructions are decoded,
+/// and structs put together from how they're accessed, which are only ever pointed at
+/// functions are only ever pointed at too, when they're called through a register, and take whatever they're given
+/// `char`, `const` and `void` only come from the prototypes functions are declared with, as nothing in the instructions says them
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Type {
+    Int { size: u8, signed: bool },
+    Char,                               // unsigned on risc-v, but kept apart from `uint8_t` as it's what text is made of
+    Pointer(Box<Type>),
+    Const(Box<Type>),
+    Struct(String),
+    Function(Box<Type>),
+    Void
+}
+
+impl Type {
+    /// how many bytes a value of this type takes, which isn't known for a struct
+    pub fn size(&self) -> u8 {
+        match self {
+            Type::Int { size, .. } => *size,
+            Type::Char => 1,
+            Type::Pointer(_) => 8,
+            Type::Const(ty) => ty.size(),
+            Type::Struct(_) | Type::Function(_) | Type::Void => 0
+        }
+    }
+
+    /// # declare something of this type
+    /// the name goes inside the type, which only matters for pointers, as in `int32_t *p`
+    pub fn declare(&self, name: &str) -> String {
+        match self {
+            Type::Int { size, signed } => format!("{} {}", type_name(*size, *signed), name),
+            Type::Char => format!("char {}", name),
+            Type::Pointer(pointee) => pointee.declare(&format!("*{}", name)),
+            Type::Const(ty) => format!("const {}", ty.declare(name)),
+            Type::Struct(structure) => format!("struct {} {}", structure, name),
+            Type::Function(returned) => returned.declare(&format!("({})()", name)),
+            Type::Void => format!("void {}", name)
+        }
+    }
+}
+
+/// the type on its own, as in a cast
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.declare("").trim_end())
+    }
+}
+
+/// # text as a c string literal
+/// with anything that can't go in one as it is escaped
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            // a hex escape goes on for as many hex digits as follow it, so the literal's split after one
+            c if c.is_control() => quoted.push_str(&format!("\\x{:02x}\"\"", c as u32)),
+            c => quoted.push(c)
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// the c type for an integer of this many bytes
+fn type_name(size: u8, signed: bool) -> &'static str {
+    match (size, signed) {
+        (1, true) => "int8_t",
+        (1, false) => "uint8_t",
+        (2, true) => "int16_t",
+        (2, false) => "uint16_t",
+        (4, true) => "int32_t",
+        (4, false) => "uint32_t",
+        (16, true) => "__int128",
+        (16, false) => "unsigned __int128",
+        (_, true) => "int64_t",
+        (_, false) => "uint64_t"
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Variable(variable) => write!(f, "{}", variable),
+            // small numbers read better in decimal, addresses and masks in hex
+            Expression::Constant(c) if c.unsigned_abs() < 0x1000 => write!(f, "{}", c),
+            Expression::Constant(c) if *c < 0 => write!(f, "-{:#x}", c.unsigned_abs()),
+            Expression::Constant(c) => write!(f, "{:#x}", c),
+            // subtracting reads better than adding a negative
+            Expression::Binary(BinaryOp::Add, lhs, rhs) if matches!(**rhs, Expression::Constant(c) if c < 0 && c != i64::MIN) => {
+                let Expression::Constant(c) = **rhs else { unreachable!() };
+                write!(f, "{} - {}", lhs.bracketed(), Expression::Constant(-c))
+            },
+            Expression::Binary(op, lhs, rhs) => match op.symbol() {
+                Some(symbol) if op.is_unsigned() => write!(f, "(uint64_t){} {} (uint64_t){}", lhs.bracketed(), symbol, rhs.bracketed()),
+                Some(symbol) if *op == BinaryOp::ShiftRightLogical => write!(f, "(uint64_t){} {} {}", lhs.bracketed(), symbol, rhs.bracketed()),
+                Some(symbol) => write!(f, "{} {} {}", lhs.bracketed(), symbol, rhs.bracketed()),
+                None => {
+                    let name = match op {
+                        BinaryOp::MulHigh => "mulh",
+                        BinaryOp::MulHighSignedUnsigned => "mulhsu",
+                        _ => "mulhu"
+                    };
+                    write!(f, "{}({}, {})", name, lhs, rhs)
+                }
+            },
+            Expression::Load { addr, size, signed } => write!(f, "*({} *){}", type_name(*size, *signed), addr.bracketed()),
+            Expression::Extend { value, bits, signed } => write!(f, "({}){}", type_name(bits / 8, *signed), value.bracketed()),
+            Expression::Symbol { name, offset: 0 } => write!(f, "&{}", name),
+            Expression::Symbol { name, offset } if *offset < 0 => write!(f, "&{} - {}", name, Expression::Constant(offset.wrapping_neg())),
+            Expression::Symbol { name, offset } => write!(f, "&{} + {}", name, Expression::Constant(*offset)),
+            // an element along reads best as an index
+            Expression::Deref(addr) => match &**addr {
+                Expression::Binary(BinaryOp::Add, base, index) => write!(f, "{}[{}]", base.bracketed(), index),
+                Expression::Binary(BinaryOp::Sub, base, index) if matches!(**index, Expression::Constant(_)) => write!(f, "{}[-{}]", base.bracketed(), index),
+                addr => write!(f, "*{}", addr.bracketed())
+            },
+            Expression::Cast { value, ty } => write!(f, "({}){}", ty, value.bracketed()),
+            Expression::Field { base, name } => write!(f, "{}->{}", base.bracketed(), name),
+            Expression::String { text, wide } => write!(f, "{}{}", if *wide { "u" } else { "" }, quote(text))
+        }
+    }
+}
+
+/// # a lifted statement
+/// an instruction lifts to at most a couple of these, and most to exactly one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Statement {
+    Assign { dst: Variable, value: Expression },
+    /// the low `size` bytes of the value written to memory
+    Store { addr: Expression, value: Expression, size: u8 },
+    /// a value written to wherever an expression refers to, which is what a store becomes once its pointer is typed
+    Write { dst: Expression, value: Expression },
+    /// the arguments and result are only known once the callee's signature is, until then the call could read any argument register
+    Call { target: Expression, args: Option<Vec<Expression>>, dst: Option<Variable> },
+    /// unconditional when there's no condition, the target is an address
+    Branch { condition: Option<Expression>, target: Expression },
+    /// what's returned is only known once the function's own signature is
+    Return { values: Option<Vec<Expression>> },
+    /// anything c can't say directly, like system calls and the control and status registers
+    Intrinsic { name: &'static str, dst: Option<Variable>, args: Vec<Expression> }
+}
+
+impl Statement {
+    /// the variable written, if there is one
+    pub fn get_def(&self) -> Option<&Variable> {
+        match self {
+            Statement::Assign { dst, .. } => Some(dst),
+            Statement::Call { dst, .. } | Statement::Intrinsic { dst, .. } => dst.as_ref(),
+            _ => None
+        }
+    }
+
+    pub fn get_def_mut(&mut self) -> Option<&mut Variable> {
+        match self {
+            Statement::Assign { dst, .. } => Some(dst),
+            Statement::Call { dst, .. } | Statement::Intrinsic { dst, .. } => dst.as_mut(),
+            _ => None
+        }
+    }
+
+    /// every expression read, which is everything but where the result goes
+    pub fn get_expressions(&self) -> Vec<&Expression> {
+        match self {
+            Statement::Assign { value, .. } => vec![value],
+            Statement::Store { addr, value, .. } => vec![addr, value],
+            Statement::Write { dst, value } => vec![dst, value],
+            Statement::Call { target, args, .. } => [target].into_iter().chain(args.iter().flatten()).collect(),
+            Statement::Branch { condition, target } => condition.iter().chain([target]).collect(),
+            Statement::Return { values } => values.iter().flatten().collect(),
+            Statement::Intrinsic { args, .. } => args.iter().collect()
+        }
+    }
+
+    pub fn get_expressions_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            Statement::Assign { value, .. } => vec![value],
+            Statement::Store { addr, value, .. } => vec![addr, value],
+            Statement::Write { dst, value } => vec![dst, value],
+            Statement::Call { target, args, .. } => [target].into_iter().chain(args.iter_mut().flatten()).collect(),
+            Statement::Branch { condition, target } => condition.iter_mut().chain([target]).collect(),
+            Statement::Return { values } => values.iter_mut().flatten().collect(),
+            Statement::Intrinsic { args, .. } => args.iter_mut().collect()
+        }
+    }
+
+    /// the variables read, in order, with repeats
+    pub fn get_uses(&self) -> Vec<Variable> {
+        let mut uses = Vec::new();
+        for expr in self.get_expressions() {
+            expr.for_each_variable(&mut |variable| uses.push(variable.clone()));
+        }
+        uses
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Assign { dst, value } => write!(f, "{} = {}", dst, value),
+            Statement::Store { addr, value, size } => write!(f, "*({} *){} = {}", type_name(*size, true), addr.bracketed(), value),
+            Statement::Write { dst, value } => write!(f, "{} = {}", dst, value),
+            Statement::Call { target, args, dst } => {
+                if let Some(dst) = dst {
+                    write!(f, "{} = ", dst)?;
+                }
+
+                let args = args.iter().flatten().map(|arg| arg.to_string()).collect::<Vec<_>>().join(", ");
+                match target {
+                    Expression::Constant(address) => write!(f, "sub_{:x}({})", address, args),
+                    Expression::Symbol { name, offset: 0 } => write!(f, "{}({})", name, args),
+                    target => write!(f, "((void (*)(void)){})({})", target.bracketed(), args)
+                }
+            },
+            Statement::Branch { condition, target } => {
+                if let Some(condition) = condition {
+                    write!(f, "if ({}) ", condition)?;
+                }
+
+                match target {
+                    Expression::Constant(address) => write!(f, "goto {:#x}", address),
+                    target => write!(f, "goto *{}", target.bracketed())
+                }
+            },
+            Statement::Return { values } => match values.as_deref() {
+                Some([value]) => write!(f, "return {}", value),
+                // a pair comes back as the two halves of something twice the size
+                Some([low, high]) => write!(f, "return ((__int128){} << 64) | (uint64_t){}", high.bracketed(), low.bracketed()),
+                _ => write!(f, "return")
+            },
+            Statement::Intrinsic { name, dst, args } => {
+                let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().join(", ");
+
+                match dst {
+                    Some(dst) => write!(f, "{} = {}({})", dst, name, args),
+                    None => write!(f, "{}({})", name, args)
+                }
+            }
+        }
+    }
+}
+
+// ----------------------------------------
+// lifting
+// ----------------------------------------
+
+/// # lift an instruction
+/// the address is needed for anything relative to the pc
+/// writes to `zero` are dropped, so an instruction can lift to nothing at all
+pub fn lift(address: u64, inst: &InstructionType) -> Vec<Statement> {
+    use BinaryOp::*;
+
+    let rs1 = || Expression::register(&inst.get_rs1());
+    let rs2 = || Expression::register(&inst.get_rs2());
+    let imm = || Expression::Constant(inst.get_imm() as i64);
+    let relative = || Expression::Constant(address.wrapping_add_signed(inst.get_imm() as i64) as i64);
+
+    // the 32-bit versions of operations, and their operands
+    let word = |value: Expression| Expression::extend(value, 32, true);
+    let word_unsigned = |value: Expression| Expression::extend(value, 32, false);
+    let masked = |amount: Expression, bits: i64| Expression::binary(And, amount, Expression::Constant(bits - 1));
+
+    let value = match inst.get_name() {
+        // loads and stores
+        "lb" => Expression::load(Expression::binary(Add, rs1(), imm()), 1, true),
+        "lh" => Expression::load(Expression::binary(Add, rs1(), imm()), 2, true),
+        "lw" => Expression::load(Expression::binary(Add, rs1(), imm()), 4, true),
+        "ld" => Expression::load(Expression::binary(Add, rs1(), imm()), 8, true),
+        "lbu" => Expression::load(Expression::binary(Add, rs1(), imm()), 1, false),
+        "lhu" => Expression::load(Expression::binary(Add, rs1(), imm()), 2, false),
+        "lwu" => Expression::load(Expression::binary(Add, rs1(), imm()), 4, false),
+        "sb" | "sh" | "sw" | "sd" => {
+            let size = match inst.get_name() { "sb" => 1, "sh" => 2, "sw" => 4, _ => 8 };
+            return vec![Statement::Store { addr: Expression::binary(Add, rs1(), imm()), value: rs2(), size }];
+        },
+
+        // upper immediates, which are shifted into place and sign-extended from bit 31
+        "lui" => Expression::Constant(((inst.get_imm() as u32) << 12) as i32 as i64),
+        "auipc" => Expression::Constant(address.wrapping_add_signed(((inst.get_imm() as u32) << 12) as i32 as i64) as i64),
+
+        // immediate arithmetic
+        "addi" => Expression::binary(Add, rs1(), imm()),
+        "slti" => Expression::binary(Less, rs1(), imm()),
+        "sltiu" => Expression::binary(LessUnsigned, rs1(), imm()),
+        "xori" => Expression::binary(Xor, rs1(), imm()),
+        "ori" => Expression::binary(Or, rs1(), imm()),
+        "andi" => Expression::binary(And, rs1(), imm()),
+        "slli" => Expression::binary(ShiftLeft, rs1(), imm()),
+        "srli" => Expression::binary(ShiftRightLogical, rs1(), imm()),
+        "srai" => Expression::binary(ShiftRightArithmetic, rs1(), imm()),
+        "addiw" => word(Expression::binary(Add, rs1(), imm())),
+        "slliw" => word(Expression::binary(ShiftLeft, rs1(), imm())),
+        "srliw" => word(Expression::binary(ShiftRightLogical, word_unsigned(rs1()), imm())),
+        "sraiw" => Expression::binary(ShiftRightArithmetic, word(rs1()), imm()),
+
+        // register arithmetic
+        "add" => Expression::binary(Add, rs1(), rs2()),
+        "sub" => Expression::binary(Sub, rs1(), rs2()),
+        "sll" => Expression::binary(ShiftLeft, rs1(), masked(rs2(), 64)),
+        "slt" => Expression::binary(Less, rs1(), rs2()),
+        "sltu" => Expression::binary(LessUnsigned, rs1(), rs2()),
+        "xor" => Expression::binary(Xor, rs1(), rs2()),
+        "srl" => Expression::binary(ShiftRightLogical, rs1(), masked(rs2(), 64)),
+        "sra" => Expression::binary(ShiftRightArithmetic, rs1(), masked(rs2(), 64)),
+        "or" => Expression::binary(Or, rs1(), rs2()),
+        "and" => Expression::binary(And, rs1(), rs2()),
+        "addw" => word(Expression::binary(Add, rs1(), rs2())),
+        "subw" => word(Expression::binary(Sub, rs1(), rs2())),
+        "sllw" => word(Expression::binary(ShiftLeft, rs1(), masked(rs2(), 32))),
+        "srlw" => word(Expression::binary(ShiftRightLogical, word_unsigned(rs1()), masked(rs2(), 32))),
+        "sraw" => Expression::binary(ShiftRightArithmetic, word(rs1()), masked(rs2(), 32)),
+
+        // multiplication and division
+        "mul" => Expression::binary(Mul, rs1(), rs2()),
+        "mulh" => Expression::binary(MulHigh, rs1(), rs2()),
+        "mulhsu" => Expression::binary(MulHighSignedUnsigned, rs1(), rs2()),
+        "mulhu" => Expression::binary(MulHighUnsigned, rs1(), rs2()),
+        "div" => Expression::binary(Div, rs1(), rs2()),
+        "divu" => Expression::binary(DivUnsigned, rs1(), rs2()),
+        "rem" => Expression::binary(Rem, rs1(), rs2()),
+        "remu" => Expression::binary(RemUnsigned, rs1(), rs2()),
+        "mulw" => word(Expression::binary(Mul, rs1(), rs2())),
+        "divw" => word(Expression::binary(Div, word(rs1()), word(rs2()))),
+        "divuw" => word(Expression::binary(DivUnsigned, word_unsigned(rs1()), word_unsigned(rs2()))),
+        "remw" => word(Expression::binary(Rem, word(rs1()), word(rs2()))),
+        "remuw" => word(Expression::binary(RemUnsigned, word_unsigned(rs1()), word_unsigned(rs2()))),
+
+        // control flow
+        "beq" | "bne" | "blt" | "bge" | "bltu" | "bgeu" => {
+            let op = match inst.get_name() {
+                "beq" => Equal,
+                "bne" => NotEqual,
+                "blt" => Less,
+                "bge" => GreaterEqual,
+                "bltu" => LessUnsigned,
+                _ => GreaterEqualUnsigned
+            };
+
+            return vec![Statement::Branch { condition: Some(Expression::binary(op, rs1(), rs2())), target: relative() }];
+        },
+        "jal" => return link(address, inst.get_rd(), relative()),
+        "jalr" => {
+            if inst.get_rd() == ABIRegister::zero && inst.get_rs1() == ABIRegister::ra && inst.get_imm() == 0 {
+                return vec![Statement::Return { values: None }];
+            }
+
+            // the lowest bit of the destination is always cleared
+            let target = Expression::binary(And, Expression::binary(Add, rs1(), imm()), Expression::Constant(-2));
+            return link(address, inst.get_rd(), target);
+        },
+
+        // everything else is left to the environment
+        "syscall" => {
+            let name = if inst.get_imm() == 1 { "ebreak" } else { "ecall" };
+            return vec![Statement::Intrinsic { name, dst: None, args: Vec::new() }];
+        },
+        name => {
+            // the csr instructions keep the csr number in the immediate, which is unsigned
+            let csr = Expression::Constant(inst.get_imm() as i64 & 0xfff);
+            let source = if name.ends_with('i') {
+                Expression::Constant(inst.get_rs1() as i64)
+            } else {
+                rs1()
+            };
+            let dst = Some(inst.get_rd()).filter(|rd| *rd != ABIRegister::zero).map(Variable::Register);
+
+            return vec![Statement::Intrinsic { name, dst, args: vec![csr, source] }];
+        }
+    };
+
+    if inst.get_rd() == ABIRegister::zero {
+        Vec::new()
+    } else {
+        vec![Statement::Assign { dst: Variable::Register(inst.get_rd()), value }]
+    }
+}
+
+/// # a jump that may save where it came from
+/// saving to `ra` is a call, saving nowhere is a plain jump, and anything else has to do both by hand
+fn link(address: u64, rd: ABIRegister, target: Expression) -> Vec<Statement> {
+    match rd {
+        ABIRegister::ra => vec![Statement::Call { target, args: None, dst: None }],
+        ABIRegister::zero => vec![Statement::Branch { condition: None, target }],
+        rd => vec![
+            Statement::Assign { dst: Variable::Register(rd), value: Expression::Constant(address.wrapping_add(4) as i64) },
+            Statement::Branch { condition: None, target }
+        ]
+    }
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use ABIRegister::*;
+
+    /// work out a value, with registers and memory given as maps
+    fn evaluate(expr: &Expression, registers: &BTreeMap<ABIRegister, i64>, memory: &BTreeMap<u64, u8>) -> i64 {
+        let eval = |e: &Expression| evaluate(e, registers, memory);
+
+        match expr {
+            Expression::Variable(v) => v.get_register().and_then(|r| registers.get(r)).copied().unwrap_or(0),
+            Expression::Constant(c) => *c,
+            Expression::Load { addr, size, signed } => {
+                let addr = eval(addr) as u64;
+                let raw = (0..*size as u64).fold(0_i64, |value, i| value | (memory.get(&(addr + i)).copied().unwrap_or(0) as i64) << (8 * i));
+                extend(raw, size * 8, *signed)
+            },
+            Expression::Extend { value, bits, signed } => extend(eval(value), *bits, *signed),
+            Expression::Binary(op, lhs, rhs) => op.apply(eval(lhs), eval(rhs)),
+            Expression::Symbol { .. } | Expression::String { .. } => panic!("symbols aren't lifted"),
+            Expression::Deref(_) | Expression::Cast { .. } | Expression::Field { .. } => panic!("types aren't lifted")
+        }
+    }
+
+    /// lift a single assignment and work out what it assigns
+    fn result(inst: InstructionType, registers: &[(ABIRegister, i64)], memory: &[(u64, u8)]) -> i64 {
+        let registers = registers.iter().cloned().collect();
+        let memory = memory.iter().copied().collect();
+
+        match &lift(0x100, &inst)[..] {
+            [Statement::Assign { value, .. }] => evaluate(value, &registers, &memory),
+            other => panic!("{} lifted to {:?}", inst, other)
+        }
+    }
+
+    fn r(name: &'static str) -> InstructionType {
+        InstructionType::R { name, rd: a0, rs1: a1, rs2: a2 }
+    }
+
+    fn i(name: &'static str, imm: i16) -> InstructionType {
+        InstructionType::I { name, rd: a0, rs1: a1, imm }
+    }
+
+    #[test]
+    fn test_lift_display() {
+        let lifted = |inst: InstructionType| lift(0x100, &inst).iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(lifted(i("lw", 8)), ["a0 = *(int32_t *)(a1 + 8)"]);
+        assert_eq!(lifted(i("addi", -1)), ["a0 = a1 - 1"]);
+        assert_eq!(lifted(InstructionType::I { name: "addi", rd: a0, rs1: zero, imm: 5 }), ["a0 = 5"]);
+        assert_eq!(lifted(r("xor")), ["a0 = a1 ^ a2"]);
+        assert_eq!(lifted(r("addw")), ["a0 = (int32_t)(a1 + a2)"]);
+        assert_eq!(lifted(InstructionType::S { name: "sd", rs1: sp, rs2: ra, imm: -8 }), ["*(int64_t *)(sp - 8) = ra"]);
+        assert_eq!(lifted(InstructionType::U { name: "auipc", rd: a5, imm: 2 }), ["a5 = 0x2100"]);
+        assert_eq!(lifted(InstructionType::J { name: "jal", rd: ra, imm: 0x100 }), ["sub_200()"]);
+        assert_eq!(lifted(InstructionType::B { name: "bltu", rs1: a0, rs2: a1, imm: -4 }), ["if ((uint64_t)a0 < (uint64_t)a1) goto 0xfc"]);
+        assert_eq!(lifted(InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 }), ["return"]);
+        assert_eq!(lifted(InstructionType::I { name: "syscall", rd: zero, rs1: zero, imm: 0 }), ["ecall()"]);
+
+        // writes to zero do nothing
+        assert!(lifted(InstructionType::I { name: "addi", rd: zero, rs1: zero, imm: 0 }).is_empty());
+    }
+
+    #[test]
+    fn test_loads_extend() {
+        let memory = [(0x1000, 0x80), (0x1001, 0xff)];
+
+        assert_eq!(result(i("lb", 0), &[(a1, 0x1000)], &memory), -128);
+        assert_eq!(result(i("lbu", 0), &[(a1, 0x1000)], &memory), 0x80);
+        assert_eq!(result(i("lh", 0), &[(a1, 0x1000)], &memory), -128);
+        assert_eq!(result(i("lhu", 0), &[(a1, 0x1000)], &memory), 0xff80);
+        assert_eq!(result(i("lbu", 1), &[(a1, 0x1000)], &memory), 0xff);
+    }
+
+    #[test]
+    fn test_word_truncation() {
+        // 0x7fffffff + 1 wraps around to the most negative 32-bit value
+        assert_eq!(result(i("addiw", 1), &[(a1, 0x7fff_ffff)], &[]), -0x8000_0000);
+        assert_eq!(result(i("addi", 1), &[(a1, 0x7fff_ffff)], &[]), 0x8000_0000);
+
+        // the upper bits are ignored going in
+        assert_eq!(result(i("srliw", 4), &[(a1, -1)], &[]), 0x0fff_ffff);
+        assert_eq!(result(i("sraiw", 4), &[(a1, 0x8000_0000)], &[]), -0x0800_0000);
+        assert_eq!(result(r("divw"), &[(a1, 0x1_0000_0008), (a2, 2)], &[]), 4);
+        assert_eq!(result(r("divuw"), &[(a1, -2), (a2, 2)], &[]), 0x7fff_ffff);
+
+        // shift amounts only use their low bits
+        assert_eq!(result(r("sllw"), &[(a1, 1), (a2, 33)], &[]), 2);
+        assert_eq!(result(r("sll"), &[(a1, 1), (a2, 65)], &[]), 2);
+    }
+
+    #[test]
+    fn test_upper_immediates() {
+        // lui sign-extends from bit 31
+        assert_eq!(result(InstructionType::U { name: "lui", rd: a0, imm: 0x80000 }, &[], &[]), -0x8000_0000);
+        assert_eq!(result(InstructionType::U { name: "lui", rd: a0, imm: 0x12345 }, &[], &[]), 0x1234_5000);
+        assert_eq!(result(InstructionType::U { name: "auipc", rd: a0, imm: 0xfffff }, &[], &[]), 0x100 - 0x1000);
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::{BTreeMap, HashMap};
+
+use log::{info, log_enabled, Level};
+
+use crate::image::Image;
+use crate::instructions::{ABIRegister, InstructionType};
+
+/// the largest table we're willing to believe in
+const MAX_CASES: u64 = 1024;
+
+/// how far back from an indirect jump we look for the code that computes its destination
+const WINDOW: usize = 16;
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// # a resolved jump table
+/// a `switch` statement compiles to something like
+/// ```riscv
+/// li      a5, 5
+/// bgtu    a0, a5, default     # bounds check
+/// slli    a0, a0, 2
+/// auipc   a5, %pcrel_hi(table)
+/// addi    a5, a5, %pcrel_lo(table)
+/// add     a0, a0, a5
+/// lw      a0, 0(a0)           # load the destination from the table
+/// jr      a0
+/// ```
+/// the table either holds the destinations themselves, or offsets to add to some base address
+#[derive(Clone, Debug, PartialEq)]
+pub struct JumpTable {
+    address: u64,                   // address of the indirect jump
+    table: u64,                     // address of the first entry in the table
+    index: ABIRegister,             // register that selects the case, at the bounds check
+    targets: Vec<u64>,              // destination of each case, in case order
+    lookup: Vec<u64>                // instructions that only exist to look up the destination
+}
+
+impl JumpTable {
+    pub fn get_address(&self) -> u64 {
+        self.address
+    }
+
+    pub fn get_table(&self) -> u64 {
+        self.table
+    }
+
+    pub fn get_index(&self) -> ABIRegister {
+        self.index.clone()
+    }
+
+    pub fn get_targets(&self) -> &[u64] {
+        &self.targets
+    }
+
+    /// the addresses of the instructions that index the table and jump
+    /// these don't need to be shown once the jump has become a `switch`
+    pub fn get_lookup(&self) -> &[u64] {
+        &self.lookup
+    }
+
+    /// every case value that jumps to the given destination
+    pub fn cases_for(&self, target: u64) -> Vec<u64> {
+        self.targets.iter()
+            .enumerate()
+            .filter(|(_, t)| **t == target)
+            .map(|(case, _)| case as u64)
+            .collect()
+    }
+}
+
+/// map of the indirect jump address to its table
+pub type JumpTableMap = BTreeMap<u64, JumpTable>;
+
+// ----------------------------------------
+
+/// what we know about the value of a register while walking towards the jump
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Unknown,
+    Constant(u64),
+    // the value the register had at the start of the window, shifted left
+    Index { register: ABIRegister, shift: u32 },
+    // whether a register is below some bound, as set by `sltu`
+    Bounded { register: ABIRegister, cases: u64 },
+    // address of an entry in a table
+    Address { table: u64, register: ABIRegister, shift: u32 },
+    // an entry loaded from a table, plus some base address
+    Entry { table: u64, register: ABIRegister, shift: u32, size: usize, signed: bool, base: u64 }
+}
+
+/// sign-extend a 12-bit immediate
+fn imm12(inst: &InstructionType) -> u64 {
+    ((inst.get_imm() << 20) >> 20) as i64 as u64
+}
+
+/// the value loaded by a `lui`, or added by an `auipc`
+fn upper(inst: &InstructionType) -> u64 {
+    ((inst.get_imm() as u32) << 12) as i32 as i64 as u64
+}
+
+/// size and signedness of each load
+fn load_size(name: &str) -> Option<(usize, bool)> {
+    match name {
+        "lb" => Some((1, true)),
+        "lbu" => Some((1, false)),
+        "lh" => Some((2, true)),
+        "lhu" => Some((2, false)),
+        "lw" => Some((4, true)),
+        "lwu" => Some((4, false)),
+        "ld" => Some((8, false)),
+        _ => None
+    }
+}
+
+/// # abstract interpreter for the instructions leading up to an indirect jump
+/// each register starts out as its own (unknown) index, and we track how it gets turned into an address
+struct Tracker {
+    registers: HashMap<ABIRegister, (Value, Vec<u64>)>,     // value and the instructions that produced it
+    bounds: HashMap<ABIRegister, u64>                       // number of cases allowed through by a bounds check
+}
+
+impl Tracker {
+    fn new() -> Self {
+        Tracker {
+            registers: HashMap::new(),
+            bounds: HashMap::new()
+        }
+    }
+
+    fn get(&self, register: &ABIRegister) -> (Value, Vec<u64>) {
+        if *register == ABIRegister::zero {
+            return (Value::Constant(0), Vec::new());
+        }
+
+        self.registers.get(register)
+            .cloned()
+            .unwrap_or((Value::Index { register: register.clone(), shift: 0 }, Vec::new()))
+    }
+
+    fn set(&mut self, register: ABIRegister, value: Value, mut sources: Vec<u64>, address: u64) {
+        sources.push(address);
+        self.registers.insert(register, (value, sources));
+    }
+
+    /// update the known values with a single instruction
+    fn step(&mut self, address: u64, inst: &InstructionType) {
+        let (rs1, sources_1) = self.get(&inst.get_rs1());
+        let (rs2, sources_2) = self.get(&inst.get_rs2());
+        let both = [sources_1.clone(), sources_2].concat();
+
+        let value = match (inst.get_name(), &rs1, &rs2) {
+            ("lui", ..) => Value::Constant(upper(inst)),
+            ("auipc", ..) => Value::Constant(address.wrapping_add(upper(inst))),
+            ("addi", Value::Constant(c), _) => Value::Constant(c.wrapping_add(imm12(inst))),
+            ("addi", Value::Address { table, register, shift }, _) =>
+                Value::Address { table: table.wrapping_add(imm12(inst)), register: register.clone(), shift: *shift },
+            ("addi" | "addiw", value, _) if imm12(inst) == 0 => value.clone(),
+            ("addiw", Value::Constant(c), _) => Value::Constant(c.wrapping_add(imm12(inst)) as i32 as i64 as u64),
+            ("slli", Value::Constant(c), _) => Value::Constant(c << (imm12(inst) & 0x3f)),
+            ("slli", Value::Index { register, shift }, _) =>
+                Value::Index { register: register.clone(), shift: shift + (imm12(inst) & 0x3f) as u32 },
+            ("sltiu", Value::Index { register, shift: 0 }, _) =>
+                Value::Bounded { register: register.clone(), cases: imm12(inst) },
+            ("sltu", Value::Index { register, shift: 0 }, Value::Constant(c)) =>
+                Value::Bounded { register: register.clone(), cases: *c },
+            ("add", Value::Constant(a), Value::Constant(b)) => Value::Constant(a.wrapping_add(*b)),
+            ("add", Value::Constant(c), Value::Index { register, shift }) |
+            ("add", Value::Index { register, shift }, Value::Constant(c)) =>
+                Value::Address { table: *c, register: register.clone(), shift: *shift },
+            ("add", Value::Constant(c), Value::Address { table, register, shift }) |
+            ("add", Value::Address { table, register, shift }, Value::Constant(c)) =>
+                Value::Address { table: table.wrapping_add(*c), register: register.clone(), shift: *shift },
+            ("add", Value::Constant(c), Value::Entry { table, register, shift, size, signed, base }) |
+            ("add", Value::Entry { table, register, shift, size, signed, base }, Value::Constant(c)) =>
+                Value::Entry { table: *table, register: register.clone(), shift: *shift, size: *size, signed: *signed, base: base.wrapping_add(*c) },
+            (name, Value::Address { table, register, shift }, _) if load_size(name).is_some() => {
+                let (size, signed) = load_size(name).unwrap();
+                Value::Entry { table: table.wrapping_add(imm12(inst)), register: register.clone(), shift: *shift, size, signed, base: 0 }
+            },
+            _ => Value::Unknown
+        };
+
+        match inst {
+            InstructionType::B { .. } => self.check_bounds(inst, &rs1, &rs2),
+            InstructionType::S { .. } => {},
+            _ => {
+                let rd = inst.get_rd();
+                if rd != ABIRegister::zero && rd != ABIRegister::Unknown {
+                    let sources = match value {
+                        Value::Unknown => Vec::new(),
+                        _ => both
+                    };
+                    self.set(rd, value, sources, address);
+                }
+            }
+        }
+    }
+
+    /// # record the bound placed on an index by a conditional branch
+    /// we don't know which side of the branch is the table, but either way the index has to be in range to get there
+    fn check_bounds(&mut self, inst: &InstructionType, rs1: &Value, rs2: &Value) {
+        let bound = match (inst.get_name(), rs1, rs2) {
+            // index >= n or index < n
+            ("bgeu" | "bltu", Value::Index { register, shift: 0 }, Value::Constant(n)) => Some((register, *n)),
+            // n < index or n >= index, i.e. index > n or index <= n
+            ("bltu" | "bgeu", Value::Constant(n), Value::Index { register, shift: 0 }) => Some((register, n + 1)),
+            // the result of an sltu against zero
+            ("beq" | "bne", Value::Bounded { register, cases }, Value::Constant(0)) |
+            ("beq" | "bne", Value::Constant(0), Value::Bounded { register, cases }) => Some((register, *cases)),
+            _ => None
+        };
+
+        if let Some((register, cases)) = bound {
+            self.bounds.insert(register.clone(), cases);
+        }
+    }
+}
+
+// -----------------------------------
+// functions
+// -----------------------------------
+
+/// # try to resolve a single indirect jump
+/// walk forward over the instructions just before it, and see if the destination was loaded from a bounded table
+fn resolve(instructions: &BTreeMap<u64, InstructionType>, address: u64, image: &Image) -> Option<JumpTable> {
+    let jump = instructions.get(&address)?;
+
+    // collect the straight-line code before the jump, stopping at anything that leaves for good
+    let mut window: Vec<(u64, &InstructionType)> = Vec::new();
+    for (a, inst) in instructions.range(..address).rev().take(WINDOW) {
+        let leaves = matches!(inst, InstructionType::J { rd: ABIRegister::zero, .. }) ||
+            (inst.get_name() == "jalr" && inst.get_rd() == ABIRegister::zero);
+
+        if leaves || window.last().is_some_and(|(prev, _)| prev - a != 4) {
+            break;
+        }
+        window.push((*a, inst));
+    }
+    window.reverse();
+
+    let mut tracker = Tracker::new();
+    for (a, inst) in window.iter() {
+        tracker.step(*a, inst);
+    }
+
+    let (destination, mut lookup) = tracker.get(&jump.get_rs1());
+    let Value::Entry { table, register, shift, size, signed, base } = destination else {
+        return None;
+    };
+
+    let cases = *tracker.bounds.get(&register)?;
+    if cases == 0 || cases > MAX_CASES {
+        return None;
+    }
+
+    let mut targets = Vec::new();
+    for case in 0..cases {
+        let entry = image.read_int(table.wrapping_add(case << shift), size, signed)?;
+        let target = entry.wrapping_add(base).wrapping_add(imm12(jump));
+
+        // every case has to land on an instruction, or this wasn't a jump table after all
+        if !instructions.contains_key(&target) {
+            return None;
+        }
+        targets.push(target);
+    }
+
+    lookup.push(address);
+    lookup.sort();
+    lookup.dedup();
+
+    if log_enabled!(Level::Info) {
+        info!("resolved jump table at {:#x} with {} cases, indexed by {}", table, cases, register);
+    }
+
+    Some(JumpTable {
+        address,
+        table,
+        index: register,
+        targets,
+        lookup
+    })
+}
+
+/// find every bounded indirect jump and read its destinations from the image
+/// returns (`ret`) are left alone
+pub fn find_jump_tables(instructions: &BTreeMap<u64, InstructionType>, image: &Image) -> JumpTableMap {
+    let mut tables = BTreeMap::new();
+
+    for (address, inst) in instructions.iter() {
+        if inst.get_name() == "jalr" && inst.get_rd() == ABIRegister::zero && inst.get_rs1() != ABIRegister::ra {
+            if let Some(table) = resolve(instructions, *address, image) {
+                tables.insert(*address, table);
+            }
+        }
+    }
+
+    tables
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn i(name: &'static str, rd: ABIRegister, rs1: ABIRegister, imm: i16) -> InstructionType {
+        InstructionType::I { name, rd, rs1, imm }
+    }
+
+    fn r(name: &'static str, rd: ABIRegister, rs1: ABIRegister, rs2: ABIRegister) -> InstructionType {
+        InstructionType::R { name, rd, rs1, rs2 }
+    }
+
+    /// a four case switch on a0, using a pc-relative table of offsets from the table
+    fn create_switch() -> (BTreeMap<u64, InstructionType>, Image) {
+        use ABIRegister::*;
+        let mut instructions = BTreeMap::new();
+
+        instructions.insert(0x100, i("addi", a5, zero, 3));
+        instructions.insert(0x104, InstructionType::B { name: "bltu", rs1: a5, rs2: a0, imm: 0x30 });    // bgtu a0, a5, 0x134
+        instructions.insert(0x108, i("slli", a0, a0, 2));
+        instructions.insert(0x10c, InstructionType::U { name: "auipc", rd: a5, imm: 1 });
+        instructions.insert(0x110, i("addi", a5, a5, 0xff4u16 as i16));                                   // a5 = 0x1100
+        instructions.insert(0x114, r("add", a0, a0, a5));
+        instructions.insert(0x118, i("lw", a0, a0, 0));
+        instructions.insert(0x11c, r("add", a0, a0, a5));
+        instructions.insert(0x120, i("jalr", zero, a0, 0));
+        instructions.insert(0x124, i("addi", a0, zero, 10));
+        instructions.insert(0x128, i("addi", a0, zero, 11));
+        instructions.insert(0x12c, i("addi", a0, zero, 12));
+        instructions.insert(0x130, i("addi", a0, zero, 13));
+        instructions.insert(0x134, i("jalr", zero, ra, 0));
+
+        // entries are relative to the table at 0x1100
+        let mut table = Vec::new();
+        for target in [0x124i64, 0x128, 0x124, 0x130] {
+            table.extend_from_slice(&((target - 0x1100) as i32).to_le_bytes());
+        }
+
+        let mut image = Image::new();
+        image.add_section(".rodata", 0x1100, table, false);
+
+        (instructions, image)
+    }
+
+    #[test]
+    fn test_find_jump_table() {
+        let (instructions, image) = create_switch();
+        let tables = find_jump_tables(&instructions, &image);
+
+        assert_eq!(tables.len(), 1);
+
+        let table = tables.get(&0x120).unwrap();
+        assert_eq!(table.get_table(), 0x1100);
+        assert_eq!(table.get_index(), ABIRegister::a0);
+        assert_eq!(table.get_targets(), &[0x124, 0x128, 0x124, 0x130]);
+        assert_eq!(table.cases_for(0x124), vec![0, 2]);
+        assert_eq!(table.get_lookup(), &[0x108, 0x10c, 0x110, 0x114, 0x118, 0x11c, 0x120]);
+    }
+
+    #[test]
+    fn test_unbounded_jump() {
+        let (mut instructions, image) = create_switch();
+
+        // without the bounds check we can't tell how big the table is
+        instructions.insert(0x104, i("addi", ABIRegister::a4, ABIRegister::a4, 1));
+
+        assert!(find_jump_tables(&instructions, &image).is_empty());
+    }
+
+    #[test]
+    fn test_target_outside_code() {
+        let (instructions, mut image) = create_switch();
+
+        // a table pointing at data is not a table
+        image = {
+            let mut bad = Image::new();
+            let mut data = image.read(0x1100, 16).unwrap().to_vec();
+            data[12] = 0x40;
+            bad.add_section(".rodata", 0x1100, data, false);
+            bad
+        };
+
+        assert!(find_jump_tables(&instructions, &image).is_empty());
+    }
+}
@@ -8,12 +8,37 @@ use std::collections::BTreeMap;
 use std::error::Error;
 use std::fs;
 
-use object::{Object, ObjectSection};
+use object::{Object, ObjectSection, ObjectSymbol, SectionKind, SymbolKind};
 
 #[macro_use]
 mod instructions;
 mod disassembly;
 mod decompilation;
+mod conditions;
+mod ir;
+pub mod callgraph;
+pub mod dominators;
+mod image;
+mod jumptable;
+pub mod loops;
+pub mod ssa;
+pub mod dataflow;
+pub mod propagation;
+pub mod constants;
+pub mod stack;
+pub mod signatures;
+pub mod syscalls;
+pub mod headers;
+pub mod strings;
+pub mod xrefs;
+pub mod flirt;
+pub mod types;
+pub mod ast;
+pub mod rust;
+pub mod emulator;
+pub mod recompile;
+#[cfg(test)]
+mod fixtures;
 mod app;
 
 pub fn launch_app() -> eframe::Result {
@@ -74,9 +99,75 @@ pub fn disassemble_file(bytes: Vec<u8>) -> Result<BTreeMap<u64, instructions::In
     Ok(out)
 }
 
+/// Read the names of all code addresses from the symbol table
+/// mapping symbols (`$x`, `$d`) are skipped, as is anything marked as data by a `$d` symbol
+pub fn read_symbols(bytes: Vec<u8>) -> Result<BTreeMap<u64, String>, Box<dyn Error>> {
+    let file = object::File::parse(&*bytes)?;
+    let mut out = BTreeMap::new();
+
+    let data_regions: Vec<u64> = file.symbols()
+        .filter(|s| s.name() == Ok("$d"))
+        .map(|s| s.address())
+        .collect();
+
+    for symbol in file.symbols() {
+        let name = symbol.name()?;
+
+        if name.is_empty() || name.starts_with('$') || data_regions.contains(&symbol.address()) {
+            continue;
+        }
+
+        if !matches!(symbol.kind(), SymbolKind::Text | SymbolKind::Unknown) {
+            continue;
+        }
+
+        // only keep symbols that point into an executable section
+        let in_code = symbol.section_index()
+            .and_then(|i| file.section_by_index(i).ok())
+            .is_some_and(|s| s.kind() == SectionKind::Text);
+
+        if in_code {
+            out.insert(symbol.address(), name.to_string());
+        }
+    }
+
+    Ok(out)
+}
+
+/// Load every allocated section of an executable at its address, so data can be read back by address
+pub fn load_image(bytes: Vec<u8>) -> Result<image::Image, Box<dyn Error>> {
+    image::Image::from_bytes(&bytes)
+}
+
+/// Read the library signatures `asha-sig` wrote, for naming the functions in stripped binaries
+pub fn load_signatures(filepath: &str) -> Result<flirt::Library, Box<dyn Error>> {
+    Ok(flirt::Library::parse(&fs::read_to_string(filepath)?)?)
+}
+
+/// Read the prototypes of the functions a c header declares
+pub fn load_headers(filepath: &str) -> Result<headers::PrototypeMap, Box<dyn Error>> {
+    Ok(headers::parse_header(&fs::read_to_string(filepath)?))
+}
+
+/// Split an executable into functions and build the call graph between them
+pub fn generate_call_graph(bytes: Vec<u8>) -> Result<callgraph::CallGraph, Box<dyn Error>> {
+    let entry = object::File::parse(&*bytes)?.entry();
+    let symbols = read_symbols(bytes.clone())?;
+    let image = load_image(bytes.clone())?;
+    let instructions = disassemble_file(bytes)?;
+
+    // an entry point of 0 means there isn't one, e.g. in relocatable object files
+    let entry = Some(entry).filter(|e| *e != 0);
+
+    let jump_tables = jumptable::find_jump_tables(&instructions, &image);
+
+    Ok(callgraph::CallGraph::new(&instructions, &symbols, entry, &jump_tables))
+}
+
 /// Output the raw bytes as 4-byte hex words, the address of the current 32-bit word, and the disassembled instructions
+/// with a comment after any instruction that has one, by its address
 // TODO: refactor this to take a vector disassembled instructions
-pub fn output_assembly(bytes: Vec<u8>) -> Result<String, Box<dyn Error>> {
+pub fn output_assembly(bytes: Vec<u8>, comments: &BTreeMap<u64, String>) -> Result<String, Box<dyn Error>> {
     let file = object::File::parse(&*bytes)?;
     let mut out = String::new();
 
@@ -99,10 +190,12 @@ pub fn output_assembly(bytes: Vec<u8>) -> Result<String, Box<dyn Error>> {
             out.push_str(&format!("{:0>8x}", raw));
             
             if let Some(instruction) = disassembly::disassemble(raw) {
-                out.push_str(&format!("    {}\n", instruction));
-            } else {
-                out.push('\n');
+                out.push_str(&format!("    {}", instruction));
             }
+            if let Some(comment) = comments.get(&(address - 4)) {
+                out.push_str(&format!("    # {}", comment));
+            }
+            out.push('\n');
         }
     } else {
         for section in file.sections() {
@@ -117,10 +210,12 @@ pub fn output_assembly(bytes: Vec<u8>) -> Result<String, Box<dyn Error>> {
                 out.push_str(&format!("{:0>8x}", raw));
                 
                 if let Some(instruction) = disassembly::disassemble(raw) {
-                    out.push_str(&format!("    {}\n", instruction));
-                } else {
-                    out.push('\n');
+                    out.push_str(&format!("    {}", instruction));
+                }
+                if let Some(comment) = comments.get(&(address - 4)) {
+                    out.push_str(&format!("    # {}", comment));
                 }
+                out.push('\n');
             }
         }
     }
@@ -0,0 +1,473 @@
+//! # Loop nesting forest
+//!
+//! every loop in a graph, along with which loops sit inside which
+//!
+//! loops are found with Havlak's algorithm from "Nesting of Reducible and Irreducible Loops",
+//! which also picks up loops with more than one entry that a compiler can produce with gotos or tail merging.
+//! back edges into a header that dominates their source make a natural loop, anything else is irreducible
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::decompilation::SectionMap;
+use crate::dominators::{invert, section_successors, DominatorTree, Successors};
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// where a loop decides whether to keep going
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopKind {
+    PreTested,      // the header can leave the loop, so a while or for loop
+    PostTested,     // a latch can leave the loop, so a do-while loop
+    Endless         // neither can, so it only exits (if at all) from the middle of the body
+}
+
+/// # A single loop
+/// the header is the node every iteration starts from, and the latches are the nodes that jump back to it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Loop {
+    header: usize,
+    latches: Vec<usize>,
+    body: BTreeSet<usize>,              // every node in the loop, including the header and any nested loops
+    exits: Vec<(usize, usize)>,         // edges leaving the loop, from a node in the body to one outside it
+    entries: BTreeSet<usize>,           // nodes other than the header that can be entered from outside
+    reducible: bool,
+    parent: Option<usize>,              // index of the closest enclosing loop
+    children: Vec<usize>,               // indices of the loops directly inside this one
+    depth: usize                        // 1 for an outermost loop
+}
+
+impl Loop {
+    pub fn get_header(&self) -> usize {
+        self.header
+    }
+
+    pub fn get_latches(&self) -> &[usize] {
+        &self.latches
+    }
+
+    pub fn get_body(&self) -> &BTreeSet<usize> {
+        &self.body
+    }
+
+    pub fn get_exits(&self) -> &[(usize, usize)] {
+        &self.exits
+    }
+
+    /// the nodes outside the loop that it can leave to
+    pub fn exit_targets(&self) -> BTreeSet<usize> {
+        self.exits.iter().map(|(_, dst)| *dst).collect()
+    }
+
+    pub fn get_entries(&self) -> &BTreeSet<usize> {
+        &self.entries
+    }
+
+    /// whether this loop can only be entered through its header
+    pub fn is_reducible(&self) -> bool {
+        self.reducible
+    }
+
+    pub fn get_parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    pub fn get_children(&self) -> &[usize] {
+        &self.children
+    }
+
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn contains(&self, node: usize) -> bool {
+        self.body.contains(&node)
+    }
+
+    pub fn kind(&self) -> LoopKind {
+        if self.exits.iter().any(|(src, _)| *src == self.header) {
+            LoopKind::PreTested
+        } else if self.exits.iter().any(|(src, _)| self.latches.contains(src)) {
+            LoopKind::PostTested
+        } else {
+            LoopKind::Endless
+        }
+    }
+}
+
+// ----------------------------------------
+
+/// # Every loop in a graph
+/// loops are stored innermost first, so every loop comes before the one containing it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LoopForest {
+    loops: Vec<Loop>,
+    innermost: BTreeMap<usize, usize>,      // innermost loop containing each node, if any
+    back_edges: Vec<(usize, usize)>         // edges to a node that dominates their source
+}
+
+impl LoopForest {
+    /// find the loops reachable from the root
+    pub fn new(root: usize, successors: &Successors) -> Self {
+        let predecessors = invert(successors);
+        let dominators = DominatorTree::new(root, successors);
+
+        // ---- number the nodes in depth-first preorder ----
+        // `last` is the highest number in each node's dfs subtree, so ancestry is a range check
+        let mut nodes: Vec<usize> = Vec::new();
+        let mut number: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut last: Vec<usize> = Vec::new();
+
+        let mut stack: Vec<(usize, usize)> = vec![(root, 0)];
+        number.insert(root, 0);
+        nodes.push(root);
+        last.push(0);
+
+        while let Some((node, next_child)) = stack.pop() {
+            let succs = successors.get(&node).map(|s| s.as_slice()).unwrap_or(&[]);
+
+            if let Some(child) = succs.get(next_child) {
+                stack.push((node, next_child + 1));
+
+                if !number.contains_key(child) {
+                    number.insert(*child, nodes.len());
+                    nodes.push(*child);
+                    last.push(0);
+                    stack.push((*child, 0));
+                }
+            } else {
+                last[number[&node]] = nodes.len() - 1;
+            }
+        }
+
+        let is_ancestor = |w: usize, v: usize| w <= v && v <= last[w];
+
+        // ---- split each node's predecessors into back edges and everything else ----
+        let size = nodes.len();
+        let mut back_preds: Vec<Vec<usize>> = vec![Vec::new(); size];
+        let mut non_back_preds: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); size];
+
+        for (w, node) in nodes.iter().enumerate() {
+            for pred in predecessors.get(node).into_iter().flatten() {
+                // unreachable predecessors can't be part of anything
+                let Some(v) = number.get(pred) else { continue; };
+
+                if is_ancestor(w, *v) {
+                    back_preds[w].push(*v);
+                } else {
+                    non_back_preds[w].insert(*v);
+                }
+            }
+        }
+
+        // ---- collapse loops, innermost first ----
+        let mut union_find: Vec<usize> = (0..size).collect();
+        let mut header_of: Vec<Option<usize>> = vec![None; size];      // dfs number of the innermost enclosing header
+        let mut loop_of: BTreeMap<usize, usize> = BTreeMap::new();      // dfs number of a header -> loop index
+        let mut members: Vec<Vec<usize>> = Vec::new();                  // nodes directly in each loop, not in a nested one
+
+        let mut loops: Vec<Loop> = Vec::new();
+
+        for w in (0..size).rev() {
+            let mut pool: BTreeSet<usize> = BTreeSet::new();
+            let mut self_loop = false;
+
+            for v in back_preds[w].iter() {
+                if *v == w {
+                    self_loop = true;
+                } else {
+                    pool.insert(find(&mut union_find, *v));
+                }
+            }
+
+            // walk backwards from the latches, stopping at the header
+            // reaching something the header isn't an ancestor of means there's a second way in
+            let mut worklist: Vec<usize> = pool.iter().copied().collect();
+            let mut irreducible = false;
+
+            while let Some(x) = worklist.pop() {
+                for y in non_back_preds[x].clone() {
+                    let y_dash = find(&mut union_find, y);
+
+                    if !is_ancestor(w, y_dash) {
+                        irreducible = true;
+                        non_back_preds[w].insert(y_dash);
+                    } else if y_dash != w && pool.insert(y_dash) {
+                        worklist.push(y_dash);
+                    }
+                }
+            }
+
+            if pool.is_empty() && !self_loop {
+                continue;
+            }
+
+            let index = loops.len();
+            loop_of.insert(w, index);
+
+            let mut direct = Vec::new();
+            let mut children = Vec::new();
+            for x in pool.iter() {
+                header_of[*x] = Some(w);
+                union_find[*x] = w;
+
+                match loop_of.get(x) {
+                    Some(inner) => {
+                        loops[*inner].parent = Some(index);
+                        children.push(*inner);
+                    }
+                    None => direct.push(nodes[*x])
+                }
+            }
+            members.push(direct);
+
+            // the rest gets filled in once every loop has been found
+            loops.push(Loop {
+                header: nodes[w],
+                latches: Vec::new(),
+                body: BTreeSet::new(),
+                exits: Vec::new(),
+                entries: BTreeSet::new(),
+                reducible: !irreducible,
+                parent: None,
+                children,
+                depth: 0
+            });
+        }
+
+        // ---- fill in the bodies, latches, exits and entries ----
+        // children always come before their parents, so their bodies are ready to be merged in
+        for index in 0..loops.len() {
+            let mut body: BTreeSet<usize> = members[index].iter().copied().collect();
+            body.insert(loops[index].header);
+            for inner in loops[index].children.clone() {
+                body.extend(loops[inner].body.iter().copied());
+            }
+
+            let header = loops[index].header;
+
+            let latches: Vec<usize> = predecessors.get(&header)
+                .into_iter()
+                .flatten()
+                .filter(|p| body.contains(p))
+                .copied()
+                .collect();
+
+            let mut exits = Vec::new();
+            let mut entries = BTreeSet::new();
+            for node in body.iter() {
+                for succ in successors.get(node).into_iter().flatten() {
+                    if !body.contains(succ) {
+                        exits.push((*node, *succ));
+                    }
+                }
+
+                let entered = predecessors.get(node)
+                    .into_iter()
+                    .flatten()
+                    .any(|p| !body.contains(p) && dominators.is_reachable(*p));
+
+                if *node != header && entered {
+                    entries.insert(*node);
+                }
+            }
+
+            // a loop is only natural if its header dominates every latch
+            let natural = latches.iter().all(|l| dominators.dominates(header, *l));
+
+            let current = &mut loops[index];
+            current.body = body;
+            current.latches = latches;
+            current.exits = exits;
+            current.entries = entries;
+            current.reducible = current.reducible && natural;
+        }
+
+        // parents always come after their children, so go backwards for depth
+        for index in (0..loops.len()).rev() {
+            loops[index].depth = match loops[index].parent {
+                Some(parent) => loops[parent].depth + 1,
+                None => 1
+            };
+        }
+
+        let mut innermost: BTreeMap<usize, usize> = BTreeMap::new();
+        for (w, header) in header_of.iter().enumerate() {
+            if let Some(index) = loop_of.get(&w) {
+                innermost.insert(nodes[w], *index);
+            } else if let Some(header) = header {
+                innermost.insert(nodes[w], loop_of[header]);
+            }
+        }
+
+        let mut back_edges = Vec::new();
+        for (node, succs) in successors.iter() {
+            for succ in succs {
+                if dominators.dominates(*succ, *node) {
+                    back_edges.push((*node, *succ));
+                }
+            }
+        }
+
+        LoopForest {
+            loops,
+            innermost,
+            back_edges
+        }
+    }
+
+    /// loops of a control-flow graph, starting from its first section
+    pub fn from_sections(sections: &SectionMap) -> Self {
+        let root = sections.keys().next().copied().unwrap_or(0);
+
+        LoopForest::new(root, &section_successors(sections))
+    }
+
+    pub fn get_loops(&self) -> &[Loop] {
+        &self.loops
+    }
+
+    pub fn get_loop(&self, index: usize) -> Option<&Loop> {
+        self.loops.get(index)
+    }
+
+    /// the loop a node is the header of, if it is one
+    pub fn loop_with_header(&self, node: usize) -> Option<&Loop> {
+        self.loops.iter().find(|l| l.header == node)
+    }
+
+    /// index of the innermost loop containing a node
+    pub fn innermost_loop(&self, node: usize) -> Option<usize> {
+        self.innermost.get(&node).copied()
+    }
+
+    /// how many loops a node is inside of
+    pub fn depth(&self, node: usize) -> usize {
+        self.innermost_loop(node)
+            .map(|index| self.loops[index].depth)
+            .unwrap_or(0)
+    }
+
+    /// indices of the loops that aren't inside any other
+    pub fn outermost(&self) -> Vec<usize> {
+        (0..self.loops.len())
+            .filter(|index| self.loops[*index].parent.is_none())
+            .collect()
+    }
+
+    /// every edge that goes back to a node dominating its source
+    pub fn get_back_edges(&self) -> &[(usize, usize)] {
+        &self.back_edges
+    }
+}
+
+// -----------------------------------
+// functions
+// -----------------------------------
+
+/// union-find lookup with path compression
+fn find(union_find: &mut [usize], node: usize) -> usize {
+    let mut root = node;
+    while union_find[root] != root {
+        root = union_find[root];
+    }
+
+    let mut current = node;
+    while union_find[current] != root {
+        let next = union_find[current];
+        union_find[current] = root;
+        current = next;
+    }
+
+    root
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn graph(edges: &[(usize, usize)], nodes: usize) -> Successors {
+        let mut successors: Successors = (0..nodes).map(|n| (n, Vec::new())).collect();
+        for (src, dst) in edges {
+            successors.get_mut(src).unwrap().push(*dst);
+        }
+        successors
+    }
+
+    #[test]
+    fn test_fibb_loop() {
+        // 0 -> 1 -> 2 <-> 3, with 0, 1, and 2 all able to leave for 4
+        let forest = LoopForest::new(0, &graph(&[(0, 4), (0, 1), (1, 2), (1, 4), (2, 3), (2, 4), (3, 2)], 5));
+
+        assert_eq!(forest.get_loops().len(), 1);
+        assert_eq!(forest.get_back_edges(), &[(3, 2)]);
+
+        let found = forest.loop_with_header(2).unwrap();
+        assert_eq!(found.get_latches(), &[3]);
+        assert_eq!(found.get_body(), &BTreeSet::from([2, 3]));
+        assert_eq!(found.get_exits(), &[(2, 4)]);
+        assert_eq!(found.kind(), LoopKind::PreTested);
+        assert!(found.is_reducible());
+
+        assert_eq!(forest.depth(3), 1);
+        assert_eq!(forest.depth(1), 0);
+    }
+
+    #[test]
+    fn test_nested_loops() {
+        // an outer loop 1 -> 2 -> 3 -> 4 -> 1, around an inner do-while 2 -> 3 -> 2 and a self-loop at 4
+        let forest = LoopForest::new(0, &graph(&[(0, 1), (1, 2), (1, 5), (2, 3), (3, 2), (3, 4), (4, 4), (4, 1)], 6));
+
+        assert_eq!(forest.get_loops().len(), 3);
+        assert_eq!(forest.outermost().len(), 1);
+
+        let outer = forest.loop_with_header(1).unwrap();
+        assert_eq!(outer.get_body(), &BTreeSet::from([1, 2, 3, 4]));
+        assert_eq!(outer.get_children().len(), 2);
+        assert_eq!(outer.get_depth(), 1);
+
+        let inner = forest.loop_with_header(2).unwrap();
+        assert_eq!(inner.get_body(), &BTreeSet::from([2, 3]));
+        assert_eq!(inner.get_depth(), 2);
+        assert_eq!(inner.kind(), LoopKind::PostTested);
+        assert_eq!(forest.get_loop(inner.get_parent().unwrap()), Some(outer));
+
+        let self_loop = forest.loop_with_header(4).unwrap();
+        assert_eq!(self_loop.get_body(), &BTreeSet::from([4]));
+        assert_eq!(self_loop.get_latches(), &[4]);
+
+        assert_eq!(forest.depth(3), 2);
+        assert_eq!(forest.depth(1), 1);
+        assert_eq!(forest.depth(5), 0);
+    }
+
+    #[test]
+    fn test_irreducible_loop() {
+        // 1 and 2 form a cycle that can be entered at either
+        let forest = LoopForest::new(0, &graph(&[(0, 1), (0, 2), (1, 2), (2, 1), (2, 3)], 4));
+
+        assert_eq!(forest.get_loops().len(), 1);
+        assert!(forest.get_back_edges().is_empty());
+
+        let found = &forest.get_loops()[0];
+        assert!(!found.is_reducible());
+        assert_eq!(found.get_body(), &BTreeSet::from([1, 2]));
+        assert_eq!(found.get_entries().len(), 1);
+        assert_eq!(found.exit_targets(), BTreeSet::from([3]));
+    }
+
+    #[test]
+    fn test_endless_loop() {
+        // nothing leaves 1 -> 2 -> 3 -> 1 except from the middle
+        let forest = LoopForest::new(0, &graph(&[(0, 1), (1, 2), (2, 3), (2, 4), (3, 1)], 5));
+
+        let found = forest.loop_with_header(1).unwrap();
+        assert_eq!(found.kind(), LoopKind::Endless);
+        assert_eq!(found.get_exits(), &[(2, 4)]);
+    }
+}
@@ -0,0 +1,297 @@
+//! # expression propagation
+//! folds values into where they're used and drops whatever's left unread, so a c expression
+//! spread over a handful of instructions reads as one again
+//!
+//! runs over the lifted statements before variables are recovered, with everything in terms of registers,
+//! and only ever moves a value forwards within a block, where nothing can come in between
+//! constants are the exception, which go as far as they can through the whole function
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::constants::propagate_constants;
+use crate::dataflow::{instruction_facts, reads, solve, Definition, DefUse, Liveness};
+use crate::decompilation::SectionMap;
+use crate::image::Image;
+use crate::ir::{Expression, Statement, Variable};
+
+// ----------------------------------------
+// propagation
+// ----------------------------------------
+
+/// # simplify a function's statements
+/// propagation leaves definitions dead, and removing them can let more propagate, so they all go until none changes anything
+pub fn simplify(sections: &mut SectionMap, image: &Image) {
+    loop {
+        let propagated = propagate(sections);
+        let constants = propagate_constants(sections, image);
+        let eliminated = eliminate_dead_code(sections);
+
+        if !propagated && !constants && !eliminated {
+            break;
+        }
+    }
+}
+
+/// # fold definitions into their uses
+/// constants (which is what `lui` and `auipc` lift to) and copies can go anywhere they're read,
+/// but anything else only goes into a single use, so nothing is worked out twice
+///
+/// each instruction is only changed once a round, so what was worked out for the round still holds
+fn propagate(sections: &mut SectionMap) -> bool {
+    let chains = DefUse::new(sections);
+    let liveness = solve(&Liveness, sections);
+    let mut changed = false;
+
+    for section in sections.values_mut() {
+        // the index of a jump table has to survive to where the table is used
+        if section.get_jump_table().is_some() {
+            continue;
+        }
+
+        let live_after = instruction_facts(&Liveness, section, &liveness);
+        let mut statements = section.get_statements().clone();
+        let mut touched: BTreeSet<u64> = BTreeSet::new();
+
+        for (address, statement) in section.get_statements().iter() {
+            let [Statement::Assign { dst, value }] = statement.as_slice() else {
+                continue;
+            };
+
+            let definition = Definition::new(*address, dst.clone());
+            let uses = chains.get_uses(&definition);
+            let Some(last) = uses.last().copied() else {
+                continue;
+            };
+
+            // every use has to be later in this block, and reading nothing but this definition
+            let in_block = uses.iter().all(|using| *using > *address && statements.contains_key(using));
+            let only_definition = uses.iter().all(|using| chains.get_definitions(*using, dst) == BTreeSet::from([definition.clone()]));
+            let copyable = matches!(value, Expression::Constant(_) | Expression::Variable(_));
+
+            if !in_block || !only_definition || (uses.len() > 1 && !copyable) {
+                continue;
+            }
+            if statements.range(*address..=last).any(|(a, _)| touched.contains(a)) {
+                continue;
+            }
+
+            // gone once it's been folded in, unless something further on still reads it
+            let killed = statements[&last].iter().any(|s| s.get_def() == Some(dst));
+            if live_after[&last].contains(dst) && !killed {
+                continue;
+            }
+
+            if !can_move(value, dst, &statements, *address, last) {
+                continue;
+            }
+
+            for using in uses.iter() {
+                for statement in statements.get_mut(using).unwrap().iter_mut() {
+                    for expr in statement.get_expressions_mut() {
+                        *expr = expr.substitute(dst, value);
+                    }
+                }
+            }
+            statements.insert(*address, Vec::new());
+
+            touched.extend(statements.range(*address..=last).map(|(a, _)| *a));
+            changed = true;
+        }
+
+        section.set_statements(statements);
+    }
+
+    changed
+}
+
+/// # whether a value still means the same thing further on
+/// nothing in between can write what it reads, or memory if it loads, and calls could do either
+fn can_move(value: &Expression, dst: &Variable, statements: &BTreeMap<u64, Vec<Statement>>, from: u64, to: u64) -> bool {
+    let mut operands: BTreeSet<Variable> = BTreeSet::new();
+    value.for_each_variable(&mut |variable| { operands.insert(variable.clone()); });
+
+    // the value would be read after it had overwritten itself
+    if operands.contains(dst) {
+        return false;
+    }
+
+    let loads = has_load(value);
+
+    for (address, statements) in statements.range(from + 1..=to) {
+        for (index, statement) in statements.iter().enumerate() {
+            // whatever the last use writes comes after it's read
+            let last = *address == to && index == statements.len() - 1;
+
+            let writes = statement.get_def().is_some_and(|def| operands.contains(def)) && !last;
+            // a call reads what it's passed before it does anything else, but it could read anything else at any point
+            let clobbers = matches!(statement, Statement::Call { .. } | Statement::Intrinsic { .. }) && !(last && statement.get_uses().contains(dst));
+            let stores = loads && matches!(statement, Statement::Store { .. }) && !last;
+
+            if writes || clobbers || stores {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+/// whether reading a value reads memory
+fn has_load(value: &Expression) -> bool {
+    match value {
+        Expression::Load { .. } | Expression::Deref(_) | Expression::Field { .. } => true,
+        Expression::Binary(_, lhs, rhs) => has_load(lhs) || has_load(rhs),
+        Expression::Extend { value, .. } | Expression::Cast { value, .. } => has_load(value),
+        Expression::Variable(_) | Expression::Constant(_) | Expression::Symbol { .. } | Expression::String { .. } => false
+    }
+}
+
+// ----------------------------------------
+// dead code
+// ----------------------------------------
+
+/// # remove assignments nothing reads
+/// going backwards through each block with what's live after every statement
+/// intrinsics and calls are kept whatever happens to their result, since they do more than work it out
+fn eliminate_dead_code(sections: &mut SectionMap) -> bool {
+    let liveness = solve(&Liveness, sections);
+    let mut changed = false;
+
+    for section in sections.values_mut() {
+        let live_after = instruction_facts(&Liveness, section, &liveness);
+        let mut statements = section.get_statements().clone();
+
+        for (address, instruction) in statements.iter_mut() {
+            let mut live = live_after[address].clone();
+            let mut kept = Vec::new();
+
+            for mut statement in instruction.drain(..).rev() {
+                if let Statement::Assign { dst, .. } = &statement {
+                    if !live.contains(dst) {
+                        changed = true;
+                        continue;
+                    }
+                }
+
+                // a call still has to happen when its result isn't used
+                if let Statement::Call { dst, .. } = &mut statement {
+                    if dst.as_ref().is_some_and(|dst| !live.contains(dst)) {
+                        *dst = None;
+                        changed = true;
+                    }
+                }
+
+                if let Some(dst) = statement.get_def() {
+                    live.remove(dst);
+                }
+                live.extend(reads(&statement));
+                kept.push(statement);
+            }
+
+            kept.reverse();
+            *instruction = kept;
+        }
+
+        section.set_statements(statements);
+    }
+
+    changed
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{addi, ret, sections, statements};
+    use crate::instructions::{ABIRegister, InstructionType};
+    use ABIRegister::*;
+
+    /// simplify some instructions, giving whatever statements are left
+    fn simplified(instructions: Vec<InstructionType>) -> Vec<String> {
+        let mut sections = sections(instructions);
+        simplify(&mut sections, &Image::new());
+
+        statements(&sections)
+    }
+
+    #[test]
+    fn test_temporaries_folded() {
+        // a0 = (a0 + a1) * 2 - a2
+        let output = simplified(vec![
+            InstructionType::R { name: "add", rd: a5, rs1: a0, rs2: a1 },
+            InstructionType::I { name: "slli", rd: a5, rs1: a5, imm: 1 },
+            InstructionType::R { name: "sub", rd: a0, rs1: a5, rs2: a2 },
+            ret()
+        ]);
+
+        assert_eq!(output, ["a0 = ((a0 + a1) << 1) - a2", "return"]);
+    }
+
+    #[test]
+    fn test_comparisons_folded() {
+        // a comparison tested against zero is just the comparison
+        let output = simplified(vec![
+            InstructionType::R { name: "slt", rd: a5, rs1: a0, rs2: a1 },
+            InstructionType::B { name: "beq", rs1: a5, rs2: zero, imm: 0x8 },
+            addi(a0, zero, 1),
+            ret()
+        ]);
+
+        assert_eq!(output[0], "if (a0 >= a1) goto 0x10c");
+    }
+
+    #[test]
+    fn test_constants_combined() {
+        let output = simplified(vec![
+            InstructionType::U { name: "lui", rd: a0, imm: 0x12345 },
+            addi(a0, a0, 0x678),
+            InstructionType::U { name: "auipc", rd: a5, imm: 0x2 },
+            InstructionType::I { name: "ld", rd: a1, rs1: a5, imm: -0x10 },
+            ret()
+        ]);
+
+        assert_eq!(output, ["a0 = 0x12345678", "a1 = *(int64_t *)0x20f8", "return"]);
+    }
+
+    #[test]
+    fn test_copies_propagated() {
+        // the copy is read twice, and the temporary it came from is dead afterwards
+        let output = simplified(vec![
+            addi(a5, a0, 0),
+            InstructionType::R { name: "mul", rd: a0, rs1: a5, rs2: a5 },
+            addi(t0, a2, 0),
+            InstructionType::S { name: "sd", rs1: a1, rs2: t0, imm: 0 },
+            ret()
+        ]);
+
+        assert_eq!(output, ["a0 = a0 * a0", "*(int64_t *)a1 = a2", "return"]);
+    }
+
+    #[test]
+    fn test_order_kept() {
+        let output = simplified(vec![
+            // a store in between means the load can't move past it
+            InstructionType::I { name: "ld", rd: a5, rs1: a0, imm: 0 },
+            InstructionType::S { name: "sd", rs1: a0, rs2: zero, imm: 0 },
+            addi(a0, a5, 1),
+            // and a call in between could change anything
+            addi(a1, a2, 3),
+            InstructionType::J { name: "jal", rd: ra, imm: 0x40 },
+            InstructionType::R { name: "add", rd: a1, rs1: a1, rs2: a0 },
+            ret()
+        ]);
+
+        assert_eq!(output, [
+            "a5 = *(int64_t *)a0",
+            "*(int64_t *)a0 = 0",
+            "a0 = a5 + 1",
+            "a1 = a2 + 3",
+            "sub_150()",
+            "a1 = a1 + a0",
+            "return"
+        ]);
+    }
+}
@@ -0,0 +1,582 @@
+//! # recompilation
+//! a leaf function that only works on its arguments and whatever they point at decompiles to c that compiles by itself,
+//! so it can be built for the host and run against the original instructions in the emulator
+//!
+//! both are given the same random arguments, with each pointer pointing into a buffer of the same random bytes,
+//! and have to give back the same value and leave the buffers the same,
+//! which checks everything from lifting the instructions to structuring the output at once
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::ast::{Item, Style, HEADER};
+use crate::dataflow::ARGUMENTS;
+use crate::decompilation::{decompile_typed, SectionMap};
+use crate::emulator::{Emulator, Fault};
+use crate::image::Image;
+use crate::instructions::{ABIRegister, InstructionType};
+use crate::ir::{extend, Expression, Statement, Type, Variable};
+use crate::signatures::{Returns, SignatureMap};
+use crate::types::FieldNames;
+
+/// how many bytes each pointer argument points into, with the pointer in the middle so it can go either way
+const BUFFER_SIZE: usize = 256;
+
+/// where the buffers go in the emulator, one after another
+const BUFFER_BASE: u64 = 0x1000_0000;
+const BUFFER_STRIDE: u64 = 0x10000;
+
+/// how long either side gets before it's taken as not returning
+const STEP_LIMIT: usize = 1_000_000;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// ----------------------------------------
+
+/// # why a function can't be recompiled on its own
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Unrecompilable {
+    /// it isn't a leaf
+    Calls,
+    /// it goes somewhere that isn't known until it runs, other than through a jump table
+    IndirectJump,
+    /// it hands control to the environment
+    Environment(&'static str),
+    /// it refers to memory that isn't its own or its arguments'
+    Globals(Vec<String>)
+}
+
+impl fmt::Display for Unrecompilable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unrecompilable::Calls => write!(f, "calls other functions"),
+            Unrecompilable::IndirectJump => write!(f, "jumps somewhere only known when it runs"),
+            Unrecompilable::Environment(name) => write!(f, "uses {}", name),
+            Unrecompilable::Globals(names) => write!(f, "refers to {}", names.join(", "))
+        }
+    }
+}
+
+/// what a function gives back, as far as comparing it goes
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Returned {
+    Nothing,
+    Value(Type),
+    Pair
+}
+
+/// # a function whose decompilation compiles by itself
+/// along with the types of what it takes and gives back, which the harness around it needs
+#[derive(Clone, Debug)]
+pub struct Recompilable {
+    items: Vec<Item>,
+    name: String,
+    parameters: Vec<Type>,
+    returned: Returned
+}
+
+impl Recompilable {
+    /// # decompile a leaf function so it can be compiled on its own
+    /// anything it relies on outside its arguments and its frame stops it being compiled by itself, so it's refused
+    pub fn new(cfg: &SectionMap, image: &Image, signatures: &SignatureMap) -> Result<Self, Unrecompilable> {
+        for section in cfg.values() {
+            for statement in section.get_statements().values().flatten() {
+                match statement {
+                    Statement::Call { .. } => return Err(Unrecompilable::Calls),
+                    Statement::Intrinsic { name, .. } => return Err(Unrecompilable::Environment(name)),
+                    Statement::Branch { target, .. } if !matches!(target, Expression::Constant(_)) && section.get_jump_table().is_none() => {
+                        return Err(Unrecompilable::IndirectJump);
+                    },
+                    _ => {}
+                }
+            }
+        }
+
+        let (items, signature, types) = decompile_typed(cfg.clone(), image, signatures, &FieldNames::new());
+
+        let globals: Vec<String> = items.iter()
+            .filter_map(|item| match item {
+                Item::Extern(declaration) => Some(declaration.to_string()),
+                _ => None
+            })
+            .collect();
+        if !globals.is_empty() {
+            return Err(Unrecompilable::Globals(globals));
+        }
+
+        let parameters = signature.get_arguments().iter()
+            .map(|register| types.get(&Variable::Register(register.clone())))
+            .collect();
+        let returned = match signature.get_returns() {
+            Returns::Nothing => Returned::Nothing,
+            Returns::Single => Returned::Value(types.get_returned().cloned().unwrap_or(Type::Int { size: 8, signed: true })),
+            Returns::Double => Returned::Pair
+        };
+
+        Ok(Recompilable { items, name: signature.get_name().to_string(), parameters, returned })
+    }
+
+    pub fn get_items(&self) -> &[Item] {
+        &self.items
+    }
+
+    /// # the function with a harness around it
+    /// the harness runs it on one of the inputs given, picked by its first argument,
+    /// and prints what it gave back and what's in each buffer afterwards
+    pub fn source(&self, inputs: &[Input]) -> String {
+        let mut lines = vec!["#include <stdio.h>".to_string(), "#include <stdlib.h>".to_string(), "#include <string.h>".to_string(), String::new()];
+
+        // the function is renamed so it can't clash with the harness or the library
+        lines.push(format!("#define {} recompiled", self.name));
+        lines.extend(Style::default().print(&self.items));
+        lines.push(format!("#undef {}", self.name));
+        lines.push(String::new());
+
+        let pointers = self.pointers();
+        if !self.parameters.is_empty() {
+            lines.push(format!("static const uint64_t args[][{}] = {{", self.parameters.len()));
+            for input in inputs {
+                let args = input.args.iter().map(|arg| format!("{:#x}ull", arg)).collect::<Vec<_>>().join(", ");
+                lines.push(format!("\t{{ {} }},", args));
+            }
+            lines.push("};".to_string());
+        }
+        if pointers > 0 {
+            lines.push(format!("static const uint8_t memory[][{}][{}] = {{", pointers, BUFFER_SIZE));
+            for input in inputs {
+                let buffers = input.buffers.iter()
+                    .map(|buffer| format!("{{ {} }}", buffer.iter().map(|byte| byte.to_string()).collect::<Vec<_>>().join(", ")))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                lines.push(format!("\t{{ {} }},", buffers));
+            }
+            lines.push("};".to_string());
+        }
+        lines.push(String::new());
+
+        lines.push("int main(int argc, char **argv) {".to_string());
+        lines.push("\tint run = argc > 1 ? atoi(argv[1]) : 0;".to_string());
+        if pointers > 0 {
+            lines.push(format!("\tstatic uint8_t buffers[{}][{}];", pointers, BUFFER_SIZE));
+            lines.push("\tmemcpy(buffers, memory[run], sizeof buffers);".to_string());
+        }
+
+        let mut buffer = 0;
+        let args: Vec<String> = self.parameters.iter().enumerate()
+            .map(|(index, ty)| match ty {
+                Type::Pointer(_) => {
+                    buffer += 1;
+                    format!("({})(buffers[{}] + {})", ty, buffer - 1, BUFFER_SIZE / 2)
+                },
+                ty => format!("({})args[run][{}]", ty, index)
+            })
+            .collect();
+        let call = format!("recompiled({})", args.join(", "));
+
+        match &self.returned {
+            Returned::Value(ty @ Type::Int { .. }) => {
+                lines.push(format!("\t{} = {};", ty.declare("result"), call));
+                lines.push("\tprintf(\"%lld\", (long long)result);".to_string());
+            },
+            Returned::Pair => {
+                lines.push(format!("\t__int128 result = {};", call));
+                lines.push("\tprintf(\"%lld %lld\", (long long)(uint64_t)result, (long long)(uint64_t)(result >> 64));".to_string());
+            },
+            _ => {
+                lines.push(format!("\t{};", call));
+                lines.push("\tprintf(\"-\");".to_string());
+            }
+        }
+
+        if pointers > 0 {
+            lines.push(format!("\tfor (int i = 0; i < {}; i++) {{", pointers));
+            lines.push("\t\tprintf(\" \");".to_string());
+            lines.push(format!("\t\tfor (int j = 0; j < {}; j++) printf(\"%02x\", buffers[i][j]);", BUFFER_SIZE));
+            lines.push("\t}".to_string());
+        }
+        lines.push("\tprintf(\"\\n\");".to_string());
+        lines.push("\treturn 0;".to_string());
+        lines.push("}".to_string());
+
+        lines.join("\n") + "\n"
+    }
+
+    /// how many of the parameters are pointers, which each get a buffer
+    fn pointers(&self) -> usize {
+        self.parameters.iter().filter(|ty| matches!(ty, Type::Pointer(_))).count()
+    }
+
+    /// # run the original instructions on an input
+    /// giving the line the harness should print for it
+    pub fn emulate(&self, instructions: &BTreeMap<u64, InstructionType>, input: &Input) -> Result<String, Fault> {
+        let start = instructions.keys().next().copied().unwrap_or(0);
+        let mut emulator = Emulator::new(instructions);
+        let mut buffers = input.buffers.iter().enumerate();
+
+        for ((register, ty), arg) in ARGUMENTS.iter().zip(self.parameters.iter()).zip(input.args.iter()) {
+            match ty {
+                Type::Pointer(_) => {
+                    let (index, buffer) = buffers.next().unwrap();
+                    let address = BUFFER_BASE + index as u64 * BUFFER_STRIDE;
+                    emulator.map(address, buffer.clone());
+                    emulator.set(register, address + BUFFER_SIZE as u64 / 2);
+                },
+                _ => emulator.set(register, *arg)
+            }
+        }
+
+        emulator.run(start, STEP_LIMIT)?;
+
+        let a0 = emulator.get(&ABIRegister::a0);
+        let mut line = match &self.returned {
+            Returned::Value(Type::Int { size, signed }) => extend(a0 as i64, size * 8, *signed).to_string(),
+            Returned::Pair => format!("{} {}", a0 as i64, emulator.get(&ABIRegister::a1) as i64),
+            _ => "-".to_string()
+        };
+
+        for index in 0..input.buffers.len() {
+            let buffer = emulator.get_region(BUFFER_BASE + index as u64 * BUFFER_STRIDE).unwrap();
+            line.push(' ');
+            line.extend(buffer.iter().map(|byte| format!("{:02x}", byte)));
+        }
+
+        Ok(line)
+    }
+
+    // ----------------------------------------
+
+    /// # random inputs for the function
+    /// each argument is made the width its parameter is, the way a caller would pass it
+    pub fn inputs(&self, runs: usize, seed: u64) -> Vec<Input> {
+        let mut random = Random::new(seed);
+
+        (0..runs)
+            .map(|_| {
+                let args = self.parameters.iter()
+                    .map(|ty| match ty {
+                        // 32-bit values are always passed sign-extended, whatever their sign
+                        Type::Int { size: 4, .. } => extend(random.value() as i64, 32, true) as u64,
+                        Type::Int { size, signed } => extend(random.value() as i64, size * 8, *signed) as u64,
+                        _ => 0
+                    })
+                    .collect();
+                let buffers = (0..self.pointers())
+                    .map(|_| (0..BUFFER_SIZE).map(|_| random.next() as u8).collect())
+                    .collect();
+
+                Input { args, buffers }
+            })
+            .collect()
+    }
+}
+
+/// # one set of arguments
+/// with a buffer for each pointer, which the pointer's argument is ignored for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Input {
+    args: Vec<u64>,
+    buffers: Vec<Vec<u8>>
+}
+
+impl Input {
+    pub fn get_args(&self) -> &[u64] {
+        &self.args
+    }
+}
+
+/// a xorshift generator, so the inputs are the same every time for the same seed
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Random(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// mostly small numbers, either side of zero, as that's what conditions tend to test against
+    fn value(&mut self) -> u64 {
+        let value = self.next();
+        match value % 4 {
+            0 | 1 => ((value >> 8) % 33).wrapping_sub(16),
+            2 => ((value >> 8) % 513).wrapping_sub(256),
+            _ => self.next()
+        }
+    }
+}
+
+// ----------------------------------------
+// differential execution
+// ----------------------------------------
+
+/// # what the recompiled function did differently on an input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub input: Input,
+    pub expected: String,
+    pub actual: String
+}
+
+/// # how well the recompiled function agreed with the original
+/// runs where the original faulted, like reading memory that isn't there, aren't compared
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Agreement {
+    pub runs: usize,
+    pub agreed: usize,
+    pub faulted: usize,
+    pub mismatches: Vec<Mismatch>
+}
+
+impl Agreement {
+    /// whether every run that could be compared agreed
+    pub fn is_perfect(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for Agreement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of {} runs agreed", self.agreed, self.runs - self.faulted)?;
+        if self.faulted > 0 {
+            write!(f, ", {} faulted in the emulator", self.faulted)?;
+        }
+        Ok(())
+    }
+}
+
+/// # check a recompiled function against the original
+/// compiles it with the host's c compiler, `cc` unless `CC` says otherwise, then runs both on random inputs
+pub fn differential(cfg: &SectionMap, recompilable: &Recompilable, runs: usize, seed: u64) -> Result<Agreement, String> {
+    let instructions: BTreeMap<u64, InstructionType> = cfg.values().flat_map(|section| section.get_instructions()).collect();
+    let inputs = recompilable.inputs(runs, seed);
+
+    let directory = scratch_directory();
+    fs::create_dir_all(&directory).map_err(|e| e.to_string())?;
+    let result = build_and_compare(&directory, &instructions, recompilable, &inputs);
+    let _ = fs::remove_dir_all(&directory);
+
+    result
+}
+
+fn build_and_compare(directory: &Path, instructions: &BTreeMap<u64, InstructionType>, recompilable: &Recompilable, inputs: &[Input]) -> Result<Agreement, String> {
+    let source = directory.join("recompiled.c");
+    let executable = directory.join("recompiled");
+    fs::write(directory.join("asha.h"), HEADER).map_err(|e| e.to_string())?;
+    fs::write(&source, recompilable.source(inputs)).map_err(|e| e.to_string())?;
+
+    // it's run as c would be on risc-v, where signed arithmetic wraps
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let compiled = Command::new(&compiler)
+        .args(["-O1", "-w", "-fwrapv", "-fno-strict-aliasing", "-o"])
+        .arg(&executable)
+        .arg(&source)
+        .output()
+        .map_err(|e| format!("couldn't run {}: {}", compiler, e))?;
+    if !compiled.status.success() {
+        return Err(format!("didn't compile:\n{}", String::from_utf8_lossy(&compiled.stderr)));
+    }
+
+    let mut agreement = Agreement { runs: inputs.len(), ..Default::default() };
+
+    for (run, input) in inputs.iter().enumerate() {
+        let expected = match recompilable.emulate(instructions, input) {
+            Ok(expected) => expected,
+            Err(_) => {
+                agreement.faulted += 1;
+                continue;
+            }
+        };
+        let actual = run_native(&executable, run)?;
+
+        if actual == expected {
+            agreement.agreed += 1;
+        } else {
+            agreement.mismatches.push(Mismatch { input: input.clone(), expected, actual });
+        }
+    }
+
+    Ok(agreement)
+}
+
+/// run the harness on one input, giving what it printed, or why it didn't print anything
+fn run_native(executable: &Path, run: usize) -> Result<String, String> {
+    let mut child = Command::new(executable)
+        .arg(run.to_string())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    let start = Instant::now();
+    loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) if status.success() => break,
+            Some(status) => return Ok(format!("crashed ({})", status)),
+            None if start.elapsed() > TIMEOUT => {
+                let _ = child.kill();
+                return Ok("didn't return".to_string());
+            },
+            None => std::thread::sleep(Duration::from_millis(1))
+        }
+    }
+
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim_end().to_string())
+}
+
+/// somewhere to build in, which nothing else running at the same time will use
+fn scratch_directory() -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!("asha-{}-{}", std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed)))
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{addi, r, random_program, ret, sections};
+    use ABIRegister::*;
+
+    /// recompile a program and check it agrees with itself on every input that doesn't fault
+    fn assert_agrees(instructions: Vec<InstructionType>) {
+        let cfg = sections(instructions);
+        let recompilable = Recompilable::new(&cfg, &Image::new(), &SignatureMap::new()).unwrap();
+        let agreement = differential(&cfg, &recompilable, 64, 0x5eed).unwrap();
+
+        assert!(agreement.is_perfect(), "{}, with {:#?}\nfrom {}", agreement, agreement.mismatches, recompilable.source(&[]));
+        assert!(agreement.agreed > 0);
+    }
+
+    #[test]
+    fn test_refused() {
+        let calls = sections(vec![InstructionType::J { name: "jal", rd: ra, imm: 0x100 }, ret()]);
+        assert_eq!(Recompilable::new(&calls, &Image::new(), &SignatureMap::new()).unwrap_err(), Unrecompilable::Calls);
+
+        let environment = sections(vec![InstructionType::I { name: "syscall", rd: zero, rs1: zero, imm: 0 }, ret()]);
+        assert_eq!(Recompilable::new(&environment, &Image::new(), &SignatureMap::new()).unwrap_err(), Unrecompilable::Environment("ecall"));
+    }
+
+    #[test]
+    fn test_disagreement() {
+        // the original adds two, but what's compiled only adds one
+        let original = sections(vec![addi(a0, a0, 2), ret()]);
+        let decompiled = sections(vec![addi(a0, a0, 1), ret()]);
+
+        let recompilable = Recompilable::new(&decompiled, &Image::new(), &SignatureMap::new()).unwrap();
+        let agreement = differential(&original, &recompilable, 16, 1).unwrap();
+
+        assert_eq!((agreement.agreed, agreement.mismatches.len()), (0, 16));
+        let Mismatch { input, expected, actual } = &agreement.mismatches[0];
+        assert_eq!(expected.parse::<i64>().unwrap() - 1, actual.parse::<i64>().unwrap());
+        assert_eq!(input.get_args().len(), 1);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        // the larger of two values, times the smaller, with the high half added in
+        assert_agrees(vec![
+            InstructionType::B { name: "blt", rs1: a0, rs2: a1, imm: 0xc },
+            addi(a2, a0, 0),
+            InstructionType::J { name: "jal", rd: zero, imm: 0xc },
+            addi(a2, a1, 0),
+            addi(a1, a0, 0),
+            r("mul", a0, a2, a1),
+            r("mulhu", a1, a2, a1),
+            r("add", a0, a0, a1),
+            ret()
+        ]);
+
+        // unsigned 32-bit division, which risc-v doesn't trap on, so the divisor is kept away from zero
+        assert_agrees(vec![
+            InstructionType::I { name: "ori", rd: a1, rs1: a1, imm: 1 },
+            r("divuw", a0, a0, a1),
+            InstructionType::I { name: "srai", rd: a0, rs1: a0, imm: 3 },
+            ret()
+        ]);
+    }
+
+    #[test]
+    fn test_loops() {
+        // sum the numbers from a0 up to a1, in 32 bits
+        assert_agrees(vec![
+            addi(a2, zero, 0),
+            InstructionType::B { name: "bge", rs1: a0, rs2: a1, imm: 0x10 },
+            r("addw", a2, a2, a0),
+            addi(a0, a0, 1),
+            InstructionType::J { name: "jal", rd: zero, imm: -0xc },
+            addi(a0, a2, 0),
+            ret()
+        ]);
+    }
+
+    #[test]
+    fn test_memory() {
+        // swap two words either side of a pointer, giving back their difference
+        assert_agrees(vec![
+            InstructionType::I { name: "lw", rd: a1, rs1: a0, imm: 8 },
+            InstructionType::I { name: "lw", rd: a2, rs1: a0, imm: -4 },
+            InstructionType::S { name: "sw", rs1: a0, rs2: a1, imm: -4 },
+            InstructionType::S { name: "sw", rs1: a0, rs2: a2, imm: 8 },
+            r("subw", a0, a1, a2),
+            ret()
+        ]);
+
+        // a local kept in the frame
+        assert_agrees(vec![
+            addi(sp, sp, -16),
+            InstructionType::S { name: "sd", rs1: sp, rs2: a0, imm: 8 },
+            InstructionType::I { name: "lbu", rd: a1, rs1: sp, imm: 8 },
+            InstructionType::I { name: "ld", rd: a0, rs1: sp, imm: 8 },
+            r("xor", a0, a0, a1),
+            addi(sp, sp, 16),
+            ret()
+        ]);
+    }
+
+    #[test]
+    fn test_widening() {
+        // a word added to itself, which can carry past 32 bits
+        assert_agrees(vec![
+            InstructionType::I { name: "lw", rd: a1, rs1: a0, imm: 0 },
+            r("add", a0, a1, a1),
+            ret()
+        ]);
+
+        // a 32-bit sum, doubled in 64 bits
+        assert_agrees(vec![
+            r("addw", a0, a0, a1),
+            r("add", a0, a0, a0),
+            ret()
+        ]);
+
+        // and one that's 64 bits once the 32-bit difference has something added to it
+        assert_agrees(vec![
+            r("subw", a0, a2, a3),
+            r("add", a0, a0, a3),
+            ret()
+        ]);
+    }
+
+    #[test]
+    fn test_random_programs() {
+        // straight-line arithmetic with branches forward, each its own seed
+        for seed in 1..=40 {
+            let cfg = sections(random_program(seed, 16, false));
+            let recompilable = Recompilable::new(&cfg, &Image::new(), &SignatureMap::new()).unwrap();
+            let agreement = differential(&cfg, &recompilable, 32, seed).unwrap();
+
+            assert!(agreement.is_perfect(), "seed {}: {}, with {:#?}\nfrom {}", seed, agreement, agreement.mismatches, recompilable.source(&[]));
+        }
+    }
+}
@@ -0,0 +1,515 @@
+//! # rust-like syntax
+//! the same syntax tree as the c output, printed the way it'd be written in rust instead,
+//! with the arithmetic that can overflow wrapping, every cast an `as`, and anything through a pointer in `unsafe`
+//!
+//! it's for reading and porting from rather than compiling, as the types are only as good as what's inferred,
+//! and anything that's still a goto is left as a comment
+//!
+//! whatever's declared as coming from c is unsafe to touch too, so calls to it and reads of it get `unsafe` as well,
+//! and the statics, which are arrays of bytes, are used by their address as they would be in c
+
+use std::collections::BTreeSet;
+
+use crate::ast::{Declaration, Expr, Item, Operator, Printer, Prototype, Stmt, UnaryOp, ASSIGNMENT, POSTFIX, PREFIX, PRIMARY};
+use crate::ir::{quote, Type};
+
+/// precedence of a cast, which binds less tightly than anything in front of a value
+const CAST: u8 = 13;
+
+// ----------------------------------------
+// types and expressions
+// ----------------------------------------
+
+/// # a type in rust
+/// pointers are mutable unless they've been declared to point at something const
+pub fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Int { size, signed } => format!("{}{}", if *signed { "i" } else { "u" }, *size as u32 * 8),
+        Type::Char => "c_char".to_string(),
+        // a function pointer is already a pointer
+        Type::Pointer(pointee) if matches!(**pointee, Type::Function(_)) => type_name(pointee),
+        Type::Pointer(pointee) => match &**pointee {
+            Type::Const(pointee) => format!("*const {}", type_name(pointee)),
+            pointee => format!("*mut {}", type_name(pointee))
+        },
+        Type::Const(ty) => type_name(ty),
+        Type::Struct(name) => name.clone(),
+        Type::Function(returned) if **returned == Type::Void => "extern \"C\" fn()".to_string(),
+        Type::Function(returned) => format!("extern \"C\" fn() -> {}", type_name(returned)),
+        Type::Void => "c_void".to_string()
+    }
+}
+
+/// something declared, as in a `let` or a struct, with arrays of unknown length as empty ones
+pub fn declaration(declaration: &Declaration) -> String {
+    let ty = type_name(declaration.get_type());
+
+    match declaration.get_length() {
+        None => format!("{}: {}", declaration.get_name(), ty),
+        Some(length) => format!("{}: [{}; {}]", declaration.get_name(), ty, length.unwrap_or(0))
+    }
+}
+
+/// the signature of a function, which takes anything when its parameters aren't known
+pub fn prototype(prototype: &Prototype) -> String {
+    signature(prototype, "")
+}
+
+/// the signature a function's defined with, where its parameters are variables like any other
+fn definition(prototype: &Prototype) -> String {
+    signature(prototype, "mut ")
+}
+
+fn signature(prototype: &Prototype, binding: &str) -> String {
+    let mut parameters = match &prototype.parameters {
+        None => vec!["...".to_string()],
+        Some(parameters) => parameters.iter().map(|p| format!("{}{}", binding, declaration(p))).collect()
+    };
+    if prototype.variadic && prototype.parameters.is_some() {
+        parameters.push("...".to_string());
+    }
+    let parameters = parameters.join(", ");
+
+    match &prototype.returns {
+        Some(returns) => format!("fn {}({}) -> {}", prototype.name, parameters, type_name(returns)),
+        None => format!("fn {}({})", prototype.name, parameters)
+    }
+}
+
+/// # an expression in rust
+/// reading through a pointer gets an `unsafe` block of its own
+pub fn expression(expr: &Expr) -> String {
+    Externs::default().expression(expr)
+}
+
+/// whether it writes somewhere through a pointer, which needs the whole assignment to be unsafe
+fn writes_memory(expr: &Expr) -> bool {
+    match expr {
+        Expr::Assign(dst, _) => matches!(**dst, Expr::Unary(UnaryOp::Deref, _) | Expr::Index(..) | Expr::Arrow(..)),
+        _ => false
+    }
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Int(c) if *c < 0 => PREFIX,
+        Expr::Ident(_) | Expr::Int(_) => PRIMARY,
+        // arithmetic is a method call, and so is getting a pointer to a string
+        Expr::Binary(Operator::Add | Operator::Sub | Operator::Mul, ..) | Expr::Call(..) | Expr::Str(..) => POSTFIX,
+        Expr::Unary(UnaryOp::Negate, _) => POSTFIX,
+        // these are blocks outside an unsafe one, and dereferences in one
+        Expr::Unary(..) | Expr::Index(..) | Expr::Arrow(..) => PREFIX,
+        Expr::Cast(..) => CAST,
+        Expr::Binary(op, ..) => binding(*op),
+        Expr::Assign(..) => ASSIGNMENT
+    }
+}
+
+/// how tightly an operator binds, which differs from c in that comparisons come after the bitwise operators
+fn binding(op: Operator) -> u8 {
+    match op {
+        Operator::Mul | Operator::Div | Operator::Rem => 12,
+        Operator::Add | Operator::Sub => 11,
+        Operator::ShiftLeft | Operator::ShiftRight => 10,
+        Operator::BitAnd => 9,
+        Operator::BitXor => 8,
+        Operator::BitOr => 7,
+        Operator::Less | Operator::Greater | Operator::LessEqual | Operator::GreaterEqual | Operator::Equal | Operator::NotEqual => 6,
+        Operator::And => 5,
+        Operator::Or => 4
+    }
+}
+
+fn is_comparison(op: Operator) -> bool {
+    binding(op) == 6
+}
+
+/// # what's declared as coming from c
+/// the functions and statics in the `extern` block, which it's unsafe to call or read
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Externs {
+    functions: BTreeSet<String>,
+    statics: BTreeSet<String>
+}
+
+impl Externs {
+    pub(crate) fn of(items: &[&Item]) -> Self {
+        let mut externs = Externs::default();
+        for item in items {
+            match item {
+                Item::Prototype(declared) => { externs.functions.insert(declared.name.clone()); },
+                Item::Extern(declared) => { externs.statics.insert(declared.get_name().to_string()); },
+                _ => {}
+            }
+        }
+        externs
+    }
+
+    /// whether anything in it calls or reads something from c
+    fn touches(&self, expr: &Expr) -> bool {
+        let touches = |expr: &Expr| self.touches(expr);
+
+        match expr {
+            Expr::Ident(_) => self.is_static(expr),
+            Expr::Int(_) | Expr::Str(..) => false,
+            Expr::Call(function, args) => {
+                matches!(&**function, Expr::Ident(name) if self.functions.contains(name)) || touches(function) || args.iter().any(touches)
+            },
+            Expr::Unary(_, value) | Expr::Cast(_, value) | Expr::Arrow(value, _) => touches(value),
+            Expr::Binary(_, lhs, rhs) | Expr::Assign(lhs, rhs) | Expr::Index(lhs, rhs) => touches(lhs) || touches(rhs)
+        }
+    }
+
+    fn is_static(&self, expr: &Expr) -> bool {
+        matches!(expr, Expr::Ident(name) if self.statics.contains(name))
+    }
+
+    /// # an expression on its own
+    /// in an `unsafe` block if it touches anything from c, otherwise only where it goes through a pointer
+    fn expression(&self, expr: &Expr) -> String {
+        if self.touches(expr) {
+            format!("unsafe {{ {} }}", self.render(expr, true))
+        } else {
+            self.render(expr, false)
+        }
+    }
+
+    /// a whole statement, which is all unsafe if any of it is
+    fn statement(&self, expr: &Expr) -> String {
+        if writes_memory(expr) || self.touches(expr) {
+            format!("unsafe {{ {}; }}", self.render(expr, true))
+        } else {
+            format!("{};", self.render(expr, false))
+        }
+    }
+
+    /// this, in brackets if it binds less tightly than where it's going needs
+    fn at(&self, expr: &Expr, precedence_needed: u8, safe: bool) -> String {
+        if precedence(expr) < precedence_needed {
+            format!("({})", self.render(expr, safe))
+        } else {
+            self.render(expr, safe)
+        }
+    }
+
+    /// an operand of an infix operator, bracketed where precedence or clarity needs it
+    fn operand(&self, expr: &Expr, op: Operator, precedence_needed: u8, safe: bool) -> String {
+        match expr {
+            // `a as u64 < b` would start a generic
+            Expr::Cast(..) => format!("({})", self.render(expr, safe)),
+            Expr::Binary(inner, ..) if *inner != op && (inner.is_misread() || op.is_misread()) && precedence(expr) < POSTFIX => format!("({})", self.render(expr, safe)),
+            _ => self.at(expr, precedence_needed, safe)
+        }
+    }
+
+    /// something a method's called on, where a bare number wouldn't have a type to find it on
+    fn receiver(&self, expr: &Expr, safe: bool) -> String {
+        match expr {
+            Expr::Int(c) if *c < 0 => format!("({}i64)", c),
+            Expr::Int(c) => format!("{}i64", c),
+            expr => self.at(expr, POSTFIX, safe)
+        }
+    }
+
+    /// an expression, given whether it's already inside an unsafe block
+    fn render(&self, expr: &Expr, safe: bool) -> String {
+        let deref = |inner: String| if safe { inner } else { format!("unsafe {{ {} }}", inner) };
+
+        match expr {
+            // a static's an array, which is used by its address the way c would
+            Expr::Ident(name) if self.is_static(expr) => format!("addr_of!({}).cast()", name),
+            Expr::Cast(ty, value) if self.is_static(value) => format!("addr_of!({}) as {}", value, type_name(ty)),
+            Expr::Unary(UnaryOp::AddressOf, value) if self.is_static(value) => format!("&raw mut {}", value),
+            Expr::Ident(_) | Expr::Int(_) => expr.to_string(),
+            // a rust string takes the same escapes as c does for anything that's read as text
+            Expr::Str(text, false) => format!("c{}.as_ptr()", quote(text)),
+            Expr::Str(text, true) => format!("u16cstr!({}).as_ptr()", quote(text)),
+            Expr::Unary(UnaryOp::Negate, value) => format!("{}.wrapping_neg()", self.receiver(value, safe)),
+            Expr::Unary(UnaryOp::Not, value) => format!("!{}", self.at(value, PREFIX, safe)),
+            Expr::Unary(UnaryOp::Deref, value) => deref(format!("*{}", self.at(value, PREFIX, true))),
+            Expr::Unary(UnaryOp::AddressOf, value) => format!("&raw mut {}", self.at(value, PREFIX, safe)),
+            Expr::Binary(op @ (Operator::Add | Operator::Sub | Operator::Mul), lhs, rhs) => {
+                let method = match op {
+                    Operator::Add => "wrapping_add",
+                    Operator::Sub => "wrapping_sub",
+                    _ => "wrapping_mul"
+                };
+                format!("{}.{}({})", self.receiver(lhs, safe), method, self.render(rhs, safe))
+            },
+            Expr::Binary(op, lhs, rhs) => {
+                // comparisons can't be chained, so neither side goes without brackets
+                let binding = binding(*op);
+                let left = if is_comparison(*op) { binding + 1 } else { binding };
+                format!("{} {} {}", self.operand(lhs, *op, left, safe), op.symbol(), self.operand(rhs, *op, binding + 1, safe))
+            },
+            Expr::Assign(dst, value) => format!("{} = {}", self.render(dst, safe), self.at(value, ASSIGNMENT, safe)),
+            Expr::Cast(ty, value) => format!("{} as {}", self.at(value, CAST, safe), type_name(ty)),
+            Expr::Call(function, args) => {
+                let args = args.iter().map(|arg| self.at(arg, ASSIGNMENT + 1, safe)).collect::<Vec<_>>().join(", ");
+                format!("{}({})", self.at(function, POSTFIX, safe), args)
+            },
+            Expr::Index(base, index) => deref(format!("*{}.offset({})", self.at(base, POSTFIX, true), self.render(index, true))),
+            Expr::Arrow(base, name) => deref(format!("(*{}).{}", self.at(base, PREFIX, true), name))
+        }
+    }
+}
+
+// ----------------------------------------
+// printing
+// ----------------------------------------
+
+impl Printer {
+    /// # a group of items
+    /// anything only declared is declared as coming from c, together in one block
+    pub(crate) fn rust_items(&mut self, items: &[&Item]) {
+        if !matches!(items, [Item::Prototype(_) | Item::Extern(_), ..]) {
+            items.iter().for_each(|item| self.rust_item(item));
+            return;
+        }
+
+        self.open("extern \"C\"");
+        for item in items {
+            match item {
+                Item::Prototype(declared) => self.line(format!("{};", prototype(declared))),
+                Item::Extern(declared) => self.line(format!("static mut {};", declaration(declared))),
+                _ => unreachable!()
+            }
+        }
+        self.close("");
+    }
+
+    fn rust_item(&mut self, item: &Item) {
+        match item {
+            Item::Struct { name, members } => {
+                self.line("#[repr(C)]");
+                self.open(format!("struct {}", name));
+                for member in members {
+                    self.line(format!("{},", declaration(member)));
+                }
+                self.close("");
+            },
+            Item::Function { prototype: declared, body } => {
+                self.open(definition(declared));
+                self.block(body);
+                self.close("");
+            },
+            _ => {}
+        }
+    }
+
+    pub(crate) fn rust_statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Declare(declared) => self.line(format!("let mut {};", declaration(declared))),
+            Stmt::Expr(expr) => self.line(self.externs.statement(expr)),
+            Stmt::If { condition, then, otherwise } if otherwise.is_empty() && matches!(then[..], [Stmt::Break] | [Stmt::Continue]) => {
+                let keyword = if then[0] == Stmt::Break { "break" } else { "continue" };
+                self.line(format!("if {} {{ {}; }}", self.externs.expression(condition), keyword));
+            },
+            Stmt::If { condition, then, otherwise } => {
+                self.open(format!("if {}", self.externs.expression(condition)));
+                self.block(then);
+                self.otherwise(otherwise);
+            },
+            Stmt::While { condition, body } => {
+                match condition {
+                    Expr::Ident(name) if name == "true" => self.open("loop"),
+                    condition => self.open(format!("while {}", self.externs.expression(condition)))
+                }
+                self.block(body);
+                self.close("");
+            },
+            Stmt::DoWhile { body, condition } => {
+                self.open("loop");
+                self.block(body);
+                let negated = Expr::Unary(UnaryOp::Not, Box::new(condition.clone()));
+                self.line(format!("if {} {{ break; }}", self.externs.expression(&negated)));
+                self.close("");
+            },
+            // the body of a for loop is only ever plain statements, so there's no continue that'd skip the step
+            Stmt::For { condition, step, body } => {
+                self.open(format!("while {}", self.externs.expression(condition)));
+                self.block(body);
+                for expr in step {
+                    self.rust_statement(&Stmt::Expr(expr.clone()));
+                }
+                self.close("");
+            },
+            Stmt::Switch { on, cases } => {
+                self.open(format!("match {}", self.externs.expression(on)));
+                for (index, (values, _)) in cases.iter().enumerate() {
+                    // nothing falls through in a match, so a case has everything it'd fall through to as well
+                    let mut body = Vec::new();
+                    for (_, rest) in &cases[index..] {
+                        body.extend(rest.iter().cloned());
+                        if body.last() == Some(&Stmt::Break) {
+                            body.pop();
+                            break;
+                        }
+                    }
+
+                    let pattern = values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(" | ");
+                    if body.is_empty() {
+                        self.line(format!("{} => {{}}", pattern));
+                    } else {
+                        self.open(format!("{} =>", pattern));
+                        self.block(&body);
+                        self.close("");
+                    }
+                }
+                self.line("_ => {}");
+                self.close("");
+            },
+            Stmt::Break => self.line("break;"),
+            Stmt::Continue => self.line("continue;"),
+            Stmt::Return(Some(value)) => self.line(format!("return {};", self.externs.expression(value))),
+            Stmt::Return(None) => self.line("return;"),
+            Stmt::Goto(label) => self.line(format!("// goto {};", label)),
+            Stmt::Label(name) => self.line(format!("// {}:", name))
+        }
+    }
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{Braces, Indent, Language, Style};
+
+    fn var(name: &str) -> Expr {
+        Expr::ident(name)
+    }
+
+    fn rust() -> Style {
+        Style { language: Language::Rust, ..Style::default() }
+    }
+
+    #[test]
+    fn test_expressions() {
+        let sum = Expr::binary(Operator::Add, var("a"), var("b"));
+        assert_eq!(expression(&Expr::binary(Operator::Mul, sum.clone(), var("c"))), "a.wrapping_add(b).wrapping_mul(c)");
+        assert_eq!(expression(&Expr::binary(Operator::Sub, Expr::Int(1), var("a"))), "1i64.wrapping_sub(a)");
+
+        // comparisons don't chain, and a cast before one would start a generic
+        let unsigned = Type::Int { size: 8, signed: false };
+        let less = Expr::binary(Operator::Less, Expr::cast(unsigned.clone(), var("a")), Expr::cast(unsigned, sum.clone()));
+        assert_eq!(expression(&less), "(a as u64) < (a.wrapping_add(b) as u64)");
+        assert_eq!(expression(&Expr::binary(Operator::Equal, less.clone(), var("c"))), "((a as u64) < (a.wrapping_add(b) as u64)) == c");
+
+        // bitwise operators bind more tightly than comparisons do in rust
+        let masked = Expr::binary(Operator::BitAnd, var("a"), Expr::Int(1));
+        assert_eq!(expression(&Expr::binary(Operator::Equal, masked, Expr::Int(0))), "(a & 1) == 0");
+
+        let pointer = Type::Pointer(Box::new(Type::Int { size: 4, signed: true }));
+        let load = Expr::Unary(UnaryOp::Deref, Box::new(Expr::cast(pointer, sum)));
+        assert_eq!(expression(&load), "unsafe { *(a.wrapping_add(b) as *mut i32) }");
+        assert_eq!(expression(&Expr::Index(Box::new(var("p")), Box::new(Expr::Int(2)))), "unsafe { *p.offset(2) }");
+
+        let function = Type::Pointer(Box::new(Type::Function(Box::new(Type::Int { size: 8, signed: true }))));
+        assert_eq!(expression(&Expr::call(Expr::cast(function, var("a5")), vec![var("a0")])), "(a5 as extern \"C\" fn() -> i64)(a0)");
+    }
+
+    #[test]
+    fn test_items() {
+        let byte = Type::Int { size: 1, signed: false };
+        let word = Type::Int { size: 8, signed: true };
+        let prototype = |name: &str| Prototype { name: name.to_string(), parameters: Some(vec![Declaration::new(word.clone(), "a0")]), returns: Some(word.clone()), variadic: false };
+
+        let store = Expr::assign(Expr::Arrow(Box::new(var("a0")), "next".to_string()), Expr::Int(0));
+        let body = vec![
+            Stmt::Declare(Declaration::array(byte.clone(), "local_10", 16)),
+            Stmt::Expr(store),
+            Stmt::Expr(Expr::assign(var("a1"), Expr::cast(word.clone(), var("table")))),
+            Stmt::Expr(Expr::call(var("g"), vec![var("table")])),
+            Stmt::DoWhile { body: vec![Stmt::Expr(Expr::assign(var("a0"), Expr::call(var("g"), vec![var("a0")])))], condition: var("a0") },
+            Stmt::Switch { on: var("a1"), cases: vec![
+                (vec![0, 2], vec![Stmt::Expr(Expr::assign(var("a0"), Expr::Int(1)))]),
+                (vec![1], vec![Stmt::Break]),
+                (vec![3], vec![Stmt::Goto("section_1".to_string())])
+            ] },
+            Stmt::Label("section_1".to_string()),
+            Stmt::Return(Some(var("a0")))
+        ];
+        let items = [
+            Item::Include("asha.h".to_string()),
+            Item::Struct { name: "s".to_string(), members: vec![Declaration::new(Type::Pointer(Box::new(Type::Struct("s".to_string()))), "next")] },
+            Item::Prototype(prototype("g")),
+            Item::Extern(Declaration::unsized_array(byte, "table")),
+            Item::Function { prototype: prototype("f"), body }
+        ];
+
+        // a case that falls through has the next one's body too, and anything from c is only touched in unsafe
+        assert_eq!(rust().print(&items), [
+            "#[repr(C)]",
+            "struct s {",
+            "\tnext: *mut s,",
+            "}",
+            "",
+            "extern \"C\" {",
+            "\tfn g(a0: i64) -> i64;",
+            "\tstatic mut table: [u8; 0];",
+            "}",
+            "",
+            "fn f(mut a0: i64) -> i64 {",
+            "\tlet mut local_10: [u8; 16];",
+            "\tunsafe { (*a0).next = 0; }",
+            "\tunsafe { a1 = addr_of!(table) as i64; }",
+            "\tunsafe { g(addr_of!(table).cast()); }",
+            "\tloop {",
+            "\t\tunsafe { a0 = g(a0); }",
+            "\t\tif !a0 { break; }",
+            "\t}",
+            "\tmatch a1 {",
+            "\t\t0 | 2 => {",
+            "\t\t\ta0 = 1;",
+            "\t\t}",
+            "\t\t1 => {}",
+            "\t\t3 => {",
+            "\t\t\t// goto section_1;",
+            "\t\t}",
+            "\t\t_ => {}",
+            "\t}",
+            "\t// section_1:",
+            "\treturn a0;",
+            "}"
+        ]);
+    }
+
+    #[test]
+    fn test_loops() {
+        let body = vec![
+            Stmt::While { condition: var("true"), body: vec![Stmt::If { condition: var("x"), then: vec![Stmt::Break], otherwise: Vec::new() }] },
+            Stmt::For {
+                condition: Expr::binary(Operator::Less, var("i"), Expr::Int(10)),
+                step: vec![Expr::assign(var("i"), Expr::binary(Operator::Add, var("i"), Expr::Int(1)))],
+                body: vec![Stmt::If { condition: var("x"), then: vec![Stmt::Return(None)], otherwise: vec![Stmt::Continue] }]
+            }
+        ];
+        let items = [Item::Function { prototype: Prototype { name: "f".to_string(), parameters: Some(Vec::new()), returns: None, variadic: false }, body }];
+
+        let style = Style { language: Language::Rust, indent: Indent::Spaces(4), braces: Braces::NextLine };
+        assert_eq!(style.print(&items), [
+            "fn f()",
+            "{",
+            "    loop",
+            "    {",
+            "        if x { break; }",
+            "    }",
+            "    while i < 10",
+            "    {",
+            "        if x",
+            "        {",
+            "            return;",
+            "        }",
+            "        else",
+            "        {",
+            "            continue;",
+            "        }",
+            "        i = i.wrapping_add(1);",
+            "    }",
+            "}"
+        ]);
+    }
+}
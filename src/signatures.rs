@@ -0,0 +1,612 @@
+//! # calling conventions
+//! works out what each function takes and gives back from how it uses the argument registers, going by the risc-v psabi for lp64
+//!
+//! a parameter is an argument register read before the function writes it, and a value is returned when the function leaves
+//! something of its own in `a0` on the way to a return, so that callers get `int64_t fib(int64_t a0)` rather than `void main()`
+//! functions are worked out callees first over the call graph, so every call they make already knows what it passes and gets back,
+//! and recursive functions go round together until none of them change
+//!
+//! a function with a declared prototype takes and gives back what it's declared to instead,
+//! and a call to one taking more after what it declares passes whichever argument registers are set just before it
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::callgraph::CallGraph;
+use crate::dataflow::{instruction_facts, reads, solve, Analysis, Direction, Lattice, ARGUMENTS, PRESERVED};
+use crate::decompilation::SectionMap;
+use crate::headers::PrototypeMap;
+use crate::image::Image;
+use crate::instructions::ABIRegister;
+use crate::ast::{Declaration, Prototype};
+use crate::ir::{Expression, Statement, Type, Variable};
+use crate::syscalls::resolve_syscalls;
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// # what a function gives back
+/// anything that fits in a register comes back in `a0`, and anything twice that size in `a0` and `a1`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Returns {
+    #[default]
+    Nothing,
+    Single,
+    Double
+}
+
+impl Returns {
+    /// the registers the value comes back in, lowest half first
+    pub fn registers(&self) -> &'static [ABIRegister] {
+        match self {
+            Returns::Nothing => &[],
+            Returns::Single => &[ABIRegister::a0],
+            Returns::Double => &[ABIRegister::a0, ABIRegister::a1]
+        }
+    }
+
+    /// the type of what comes back, which is a whole register for anything that fits in one
+    pub fn get_type(&self) -> Option<Type> {
+        match self {
+            Returns::Nothing => None,
+            Returns::Single => Some(Type::Int { size: 8, signed: true }),
+            Returns::Double => Some(Type::Int { size: 16, signed: true })
+        }
+    }
+}
+
+/// # the signature of a function
+/// parameters always start from `a0`, so a function reading `a2` takes three whether or not it reads the other two
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Signature {
+    name: String,
+    parameters: usize,                  // how many of the argument registers are passed
+    returns: Returns,
+    saved: BTreeSet<ABIRegister>,       // the callee-saved registers it uses, which it has to put back before returning
+    declared: Option<Prototype>         // the prototype from a header, which goes over what's inferred
+}
+
+/// the signature of every function, keyed by its start address
+pub type SignatureMap = BTreeMap<u64, Signature>;
+
+impl Signature {
+    /// a function that takes and gives back nothing
+    pub fn new(name: String) -> Self {
+        Signature {
+            name,
+            ..Default::default()
+        }
+    }
+
+    /// # infer a function's signature
+    /// from its control-flow graph, with the signatures already known for anything it calls
+    pub fn infer(name: String, sections: &SectionMap, signatures: &SignatureMap) -> Self {
+        let mut sections = sections.clone();
+        resolve_calls(&mut sections, signatures);
+
+        let Some(root) = sections.keys().next().copied() else {
+            return Signature::new(name);
+        };
+
+        // everything up to the last argument register read before it's written
+        let read = solve(&Arguments, &sections).get_in(root).cloned().unwrap_or_default();
+        let parameters = ARGUMENTS.iter().rposition(|r| read.contains(r)).map_or(0, |last| last + 1);
+
+        // what's been written at each return
+        let solution = solve(&Results, &sections);
+        let mut at_returns: Vec<Written> = Vec::new();
+        for section in sections.values() {
+            let facts = instruction_facts(&Results, section, &solution);
+
+            for (address, statements) in section.get_statements() {
+                if statements.iter().any(|s| matches!(s, Statement::Return { .. })) {
+                    at_returns.push(facts[address].clone());
+                }
+            }
+        }
+
+        // `a1` is easily a temporary, so it only counts when nothing reads what was left in it
+        let returns = if !at_returns.iter().any(|w| w.written.contains(&ABIRegister::a0)) {
+            Returns::Nothing
+        } else if at_returns.iter().any(|w| w.unread.contains(&ABIRegister::a1)) {
+            Returns::Double
+        } else {
+            Returns::Single
+        };
+
+        let saved = sections.values()
+            .flat_map(|section| section.get_statements().values().flatten())
+            .filter_map(|statement| statement.get_def().and_then(|dst| dst.get_register()))
+            .filter(|register| is_callee_saved(register))
+            .cloned()
+            .collect();
+
+        Signature { name, parameters, returns, saved, declared: None }
+    }
+
+    /// # take on a declared prototype
+    /// as many parameters as it declares, up to the number of argument registers, as anything past them goes on the stack
+    pub fn declare(&mut self, prototype: &Prototype) {
+        if let Some(parameters) = &prototype.parameters {
+            self.parameters = parameters.len().min(ARGUMENTS.len());
+        }
+        self.returns = match &prototype.returns {
+            None => Returns::Nothing,
+            Some(ty) if ty.size() > 8 => Returns::Double,
+            Some(_) => Returns::Single
+        };
+        self.declared = Some(prototype.clone());
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_parameters(&self) -> usize {
+        self.parameters
+    }
+
+    pub fn get_returns(&self) -> Returns {
+        self.returns
+    }
+
+    pub fn get_saved(&self) -> &BTreeSet<ABIRegister> {
+        &self.saved
+    }
+
+    pub fn get_declared(&self) -> Option<&Prototype> {
+        self.declared.as_ref()
+    }
+
+    /// whether it takes more after the parameters it declares
+    pub fn is_variadic(&self) -> bool {
+        self.declared.as_ref().is_some_and(|prototype| prototype.variadic)
+    }
+
+    /// the registers the parameters are passed in
+    pub fn get_arguments(&self) -> &[ABIRegister] {
+        &ARGUMENTS[..self.parameters]
+    }
+
+    /// # the prototype
+    /// each parameter is named after its register, which is what the variables in the body go by too,
+    /// unless it's been declared, when it's what it was declared as
+    pub fn prototype(&self) -> Prototype {
+        if let Some(declared) = &self.declared {
+            return declared.clone();
+        }
+
+        let parameters = self.get_arguments().iter()
+            .map(|r| Declaration::new(Type::Int { size: 8, signed: true }, r))
+            .collect();
+
+        Prototype { name: self.name.clone(), parameters: Some(parameters), returns: self.returns.get_type(), variadic: false }
+    }
+
+    /// # make each return give back what the function does
+    pub fn resolve_returns(&self, sections: &mut SectionMap) {
+        let values: Vec<Expression> = self.returns.registers().iter()
+            .map(|r| Expression::Variable(Variable::Register(r.clone())))
+            .collect();
+
+        for section in sections.values_mut() {
+            let mut statements = section.get_statements().clone();
+
+            for statement in statements.values_mut().flatten() {
+                if let Statement::Return { values: returned } = statement {
+                    *returned = Some(values.clone());
+                }
+            }
+
+            section.set_statements(statements);
+        }
+    }
+
+    /// take whichever needs more out of this and another guess, giving whether this changed
+    /// a declared signature only ever takes what it's declared to
+    fn widen(&mut self, other: &Signature) -> bool {
+        let before = self.clone();
+
+        if self.declared.is_none() {
+            self.parameters = self.parameters.max(other.parameters);
+            self.returns = self.returns.max(other.returns);
+        }
+        self.saved.extend(other.saved.iter().cloned());
+
+        *self != before
+    }
+}
+
+/// `s0` to `s11`, which unlike the rest of the preserved registers are free for a function to use
+fn is_callee_saved(register: &ABIRegister) -> bool {
+    PRESERVED.contains(register) && !matches!(register, ABIRegister::ra | ABIRegister::sp | ABIRegister::gp | ABIRegister::tp)
+}
+
+// ----------------------------------------
+// analyses
+// ----------------------------------------
+
+/// # the registers read before they're written
+/// liveness over the registers themselves, except that anything a call doesn't preserve
+/// is its own value afterwards, rather than the one it had before
+struct Arguments;
+
+impl Analysis for Arguments {
+    type Fact = BTreeSet<ABIRegister>;
+
+    fn direction(&self) -> Direction {
+        Direction::Backward
+    }
+
+    fn transfer(&self, _address: u64, statements: &[Statement], fact: &mut Self::Fact) {
+        for statement in statements.iter().rev() {
+            if let Some(register) = statement.get_def().and_then(|dst| dst.get_register()) {
+                fact.remove(register);
+            }
+            if matches!(statement, Statement::Call { .. }) {
+                fact.retain(|register| PRESERVED.contains(register));
+            }
+            fact.extend(reads(statement).iter().filter_map(|v| v.get_register().cloned()));
+        }
+    }
+}
+
+/// # the registers a function has written
+/// `written` is everything written along some path, and `unread` whatever of that hasn't been read since
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Written {
+    written: BTreeSet<ABIRegister>,
+    unread: BTreeSet<ABIRegister>
+}
+
+impl Lattice for Written {
+    fn bottom() -> Self {
+        Written::default()
+    }
+
+    fn join(&mut self, other: &Self) -> bool {
+        let written = self.written.join(&other.written);
+        let unread = self.unread.join(&other.unread);
+        written || unread
+    }
+}
+
+/// calls leave what they return, or maybe something in `a0` if that isn't known, and nothing else they don't preserve
+struct Results;
+
+impl Analysis for Results {
+    type Fact = Written;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn transfer(&self, _address: u64, statements: &[Statement], fact: &mut Self::Fact) {
+        for statement in statements {
+            for register in reads(statement).iter().filter_map(|v| v.get_register()) {
+                fact.unread.remove(register);
+            }
+
+            if let Statement::Call { args, .. } = statement {
+                fact.written.retain(|register| PRESERVED.contains(register));
+                fact.unread.retain(|register| PRESERVED.contains(register));
+
+                if args.is_none() {
+                    fact.written.insert(ABIRegister::a0);
+                    fact.unread.insert(ABIRegister::a0);
+                }
+            }
+
+            if let Some(register) = statement.get_def().and_then(|dst| dst.get_register()) {
+                fact.written.insert(register.clone());
+                fact.unread.insert(register.clone());
+            }
+        }
+    }
+}
+
+// ----------------------------------------
+// across the program
+// ----------------------------------------
+
+/// # give calls their arguments and results
+/// a call to a function with a known signature passes the registers it takes, and gets back what it returns in `a0`
+/// a variadic one passes the rest of the argument registers after those for as long as they've been set since the last call,
+/// which only goes back as far as the start of the section
+pub fn resolve_calls(sections: &mut SectionMap, signatures: &SignatureMap) {
+    for section in sections.values_mut() {
+        let mut statements = section.get_statements().clone();
+        let mut set = BTreeSet::new();
+
+        for statement in statements.values_mut().flatten() {
+            if let Statement::Call { target: Expression::Constant(address), .. } = statement {
+                if let Some(signature) = signatures.get(&(*address as u64)) {
+                    let mut arguments = signature.get_arguments().to_vec();
+                    if signature.is_variadic() {
+                        arguments.extend(ARGUMENTS[arguments.len()..].iter().take_while(|r| set.contains(*r)).cloned());
+                    }
+
+                    *statement = Statement::Call {
+                        target: Expression::Symbol { name: signature.name.clone(), offset: 0 },
+                        args: Some(arguments.into_iter().map(|r| Expression::Variable(Variable::Register(r))).collect()),
+                        dst: signature.returns.registers().first().map(|r| Variable::Register(r.clone()))
+                    };
+                }
+            }
+
+            if matches!(statement, Statement::Call { .. }) {
+                set.clear();
+            }
+            if let Some(register) = statement.get_def().and_then(|dst| dst.get_register()) {
+                set.insert(register.clone());
+            }
+        }
+
+        section.set_statements(statements);
+    }
+}
+
+/// # infer the signature of every function
+/// callees go before their callers, and each group of recursive functions starts from nothing
+/// and goes round until it settles, which it has to as the signatures only ever grow
+/// any function with a prototype takes on what it's declared as from the start
+pub fn infer_signatures(graph: &CallGraph, prototypes: &PrototypeMap) -> SignatureMap {
+    let mut signatures = SignatureMap::new();
+
+    for component in graph.strongly_connected_components() {
+        let functions: Vec<(u64, String, SectionMap)> = component.iter()
+            .filter_map(|start| graph.get_function(*start))
+            .map(|function| {
+                // what a system call reads is only known once it's been named
+                let mut cfg = function.cfg();
+                resolve_syscalls(&mut cfg, &Image::new());
+                (function.get_start(), function.get_name().to_string(), cfg)
+            })
+            .collect();
+
+        for (start, name, _) in functions.iter() {
+            let mut signature = Signature::new(name.clone());
+            if let Some(prototype) = prototypes.get(name) {
+                signature.declare(prototype);
+            }
+            signatures.insert(*start, signature);
+        }
+
+        loop {
+            let mut changed = false;
+
+            for (start, name, cfg) in functions.iter() {
+                let inferred = Signature::infer(name.clone(), cfg, &signatures);
+                changed |= signatures.get_mut(start).unwrap().widen(&inferred);
+            }
+
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    signatures
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::decompilation::{generate_sections, output_decompiled_code};
+    use crate::fixtures::{addi, jal, program_at, ret};
+    use crate::image::Image;
+    use crate::instructions::InstructionType;
+    use crate::jumptable::JumpTableMap;
+    use crate::types::FieldNames;
+    use ABIRegister::*;
+
+    fn infer(instructions: Vec<InstructionType>, signatures: &SignatureMap) -> Signature {
+        let sections = generate_sections(program_at(0x100, instructions), &JumpTableMap::new());
+        Signature::infer("f".to_string(), &sections, signatures)
+    }
+
+    /// - fib (0x100) calls itself twice, keeping `n` and the first result in s0 and s1
+    /// - main (0x148) calls fib with 10
+    fn fib() -> (BTreeMap<u64, InstructionType>, BTreeMap<u64, String>) {
+        let instructions = program_at(0x100, vec![
+            addi(sp, sp, -32),
+            InstructionType::S { name: "sd", rs1: sp, rs2: ra, imm: 24 },
+            InstructionType::S { name: "sd", rs1: sp, rs2: s0, imm: 16 },
+            InstructionType::S { name: "sd", rs1: sp, rs2: s1, imm: 8 },
+            addi(s0, a0, 0),
+            addi(a5, zero, 1),
+            InstructionType::B { name: "bge", rs1: a5, rs2: a0, imm: 0x1c },
+            addi(a0, s0, -1),
+            jal(ra, -0x20),
+            addi(s1, a0, 0),
+            addi(a0, s0, -2),
+            jal(ra, -0x2c),
+            InstructionType::R { name: "add", rd: a0, rs1: s1, rs2: a0 },
+            InstructionType::I { name: "ld", rd: ra, rs1: sp, imm: 24 },
+            InstructionType::I { name: "ld", rd: s0, rs1: sp, imm: 16 },
+            InstructionType::I { name: "ld", rd: s1, rs1: sp, imm: 8 },
+            addi(sp, sp, 32),
+            ret(),
+
+            addi(sp, sp, -16),
+            InstructionType::S { name: "sd", rs1: sp, rs2: ra, imm: 8 },
+            addi(a0, zero, 10),
+            jal(ra, -0x54),
+            InstructionType::I { name: "ld", rd: ra, rs1: sp, imm: 8 },
+            addi(sp, sp, 16),
+            ret()
+        ]);
+        let symbols = BTreeMap::from([(0x100, "fib".to_string()), (0x148, "main".to_string())]);
+
+        (instructions, symbols)
+    }
+
+    #[test]
+    fn test_parameters() {
+        // the gap at a1 is still a parameter
+        let signature = infer(vec![
+            InstructionType::R { name: "add", rd: a0, rs1: a0, rs2: a2 },
+            ret()
+        ], &SignatureMap::new());
+
+        assert_eq!(signature.get_parameters(), 3);
+        assert_eq!(signature.get_returns(), Returns::Single);
+        assert_eq!(signature.prototype().to_string(), "int64_t f(int64_t a0, int64_t a1, int64_t a2)");
+
+        // written before it's read isn't a parameter, and a store returns nothing
+        let signature = infer(vec![
+            addi(a1, zero, 1),
+            InstructionType::S { name: "sd", rs1: a0, rs2: a1, imm: 0 },
+            ret()
+        ], &SignatureMap::new());
+
+        assert_eq!(signature.prototype().to_string(), "void f(int64_t a0)");
+    }
+
+    #[test]
+    fn test_returns() {
+        // nothing reads a1 after it's set, so it has to be the top half of what's returned
+        let signature = infer(vec![
+            addi(a0, zero, 1),
+            addi(a1, zero, 0),
+            ret()
+        ], &SignatureMap::new());
+        assert_eq!(signature.get_returns(), Returns::Double);
+
+        // but a1 as a temporary isn't
+        let signature = infer(vec![
+            addi(a1, zero, 5),
+            InstructionType::R { name: "add", rd: a0, rs1: a0, rs2: a1 },
+            ret()
+        ], &SignatureMap::new());
+        assert_eq!(signature.get_returns(), Returns::Single);
+    }
+
+    #[test]
+    fn test_calls() {
+        // a0 is set up as the argument, and the call to a void function leaves nothing in it
+        let signatures = SignatureMap::from([(0x140, Signature { name: "g".to_string(), parameters: 1, ..Default::default() })]);
+        let signature = infer(vec![
+            addi(a0, zero, 1),
+            jal(ra, 0x3c),
+            ret()
+        ], &signatures);
+        assert_eq!(signature.prototype().to_string(), "void f(void)");
+
+        // a0 passed straight on is a parameter, but a1 read after the call is whatever the call left there
+        let signature = infer(vec![
+            jal(ra, 0x40),
+            addi(a0, a1, 0),
+            ret()
+        ], &signatures);
+        assert_eq!(signature.prototype().to_string(), "int64_t f(int64_t a0)");
+    }
+
+    #[test]
+    fn test_recursion() {
+        let (instructions, symbols) = fib();
+        let graph = CallGraph::new(&instructions, &symbols, Some(0x148), &BTreeMap::new());
+        let signatures = infer_signatures(&graph, &PrototypeMap::new());
+
+        assert_eq!(signatures[&0x100].prototype().to_string(), "int64_t fib(int64_t a0)");
+        assert_eq!(signatures[&0x100].get_saved(), &BTreeSet::from([s0, s1]));
+        assert_eq!(signatures[&0x148].prototype().to_string(), "int64_t main(void)");
+
+        // and the calls pass and get back what fib does
+        let mut sections = graph.get_function(0x100).unwrap().cfg();
+        resolve_calls(&mut sections, &signatures);
+        let calls: Vec<String> = sections.values()
+            .flat_map(|section| section.get_statements().values().flatten())
+            .filter(|statement| matches!(statement, Statement::Call { .. }))
+            .map(|statement| statement.to_string())
+            .collect();
+
+        assert_eq!(calls, ["a0 = fib(a0)", "a0 = fib(a0)"]);
+    }
+
+    #[test]
+    fn test_declared() {
+        // main (0x100) passes printf (0x120) its format and one more, and printf reads every argument register
+        let instructions = program_at(0x100, vec![
+            addi(sp, sp, -16),
+            InstructionType::S { name: "sd", rs1: sp, rs2: ra, imm: 8 },
+            InstructionType::U { name: "lui", rd: a0, imm: 0x2 },
+            addi(a1, zero, 3),
+            jal(ra, 0x10),
+            InstructionType::I { name: "ld", rd: ra, rs1: sp, imm: 8 },
+            addi(sp, sp, 16),
+            ret(),
+
+            InstructionType::R { name: "add", rd: a0, rs1: a0, rs2: a7 },
+            ret()
+        ]);
+        let symbols = BTreeMap::from([(0x100, "main".to_string()), (0x120, "printf".to_string())]);
+        let graph = CallGraph::new(&instructions, &symbols, Some(0x100), &BTreeMap::new());
+
+        let prototypes = crate::headers::parse_header("int printf(const char *format, ...);");
+        let signatures = infer_signatures(&graph, &prototypes);
+
+        assert_eq!(signatures[&0x120].get_parameters(), 1);
+        assert!(signatures[&0x120].is_variadic());
+        assert_eq!(signatures[&0x120].prototype().to_string(), "int32_t printf(const char *format, ...)");
+
+        // the call passes the format and a1, which is set just before it, and the format is written out
+        let mut image = Image::new();
+        image.add_section(".rodata", 0x2000, b"%d items\n\0".to_vec(), false);
+
+        let output = output_decompiled_code(graph.get_function(0x100).unwrap().cfg(), &image, &signatures, &FieldNames::new());
+        assert_eq!(output, [
+            "#include \"asha.h\"",
+            "",
+            "int32_t printf(const char *format, ...);",
+            "",
+            "int32_t main(void) {",
+            "\tint32_t a0;",
+            "\ta0 = printf(\"%d items\\n\", 3);",
+            "\treturn a0;",
+            "}"
+        ]);
+    }
+
+    #[test]
+    fn test_recursive_output() {
+        let (instructions, symbols) = fib();
+        let graph = CallGraph::new(&instructions, &symbols, Some(0x148), &BTreeMap::new());
+        let signatures = infer_signatures(&graph, &PrototypeMap::new());
+
+        let output = output_decompiled_code(graph.get_function(0x100).unwrap().cfg(), &Image::new(), &signatures, &FieldNames::new());
+        assert_eq!(output, [
+            "#include \"asha.h\"",
+            "",
+            "int64_t fib(int64_t a0) {",
+            "\tint64_t s0;",
+            "\tint64_t s1;",
+            "\ts0 = a0;",
+            "\tif (1 < a0) {",
+            "\t\ta0 = fib(s0 - 1);",
+            "\t\ts1 = a0;",
+            "\t\ta0 = fib(s0 - 2);",
+            "\t\ta0 = s1 + a0;",
+            "\t}",
+            "\treturn a0;",
+            "}"
+        ]);
+
+        let output = output_decompiled_code(graph.get_function(0x148).unwrap().cfg(), &Image::new(), &signatures, &FieldNames::new());
+        assert_eq!(output, [
+            "#include \"asha.h\"",
+            "",
+            "int64_t fib(int64_t a0);",
+            "",
+            "int64_t main(void) {",
+            "\tint64_t a0;",
+            "\ta0 = fib(10);",
+            "\treturn a0;",
+            "}"
+        ]);
+    }
+}
@@ -0,0 +1,639 @@
+//! # static single assignment
+//! puts the lifted statements of a function into ssa form, where every definition of a register gets its own version,
+//! and then takes them back out again with each group of versions that meet at a phi as one variable
+//!
+//! the phis go on the dominance frontiers of each definition, but only where the register is live,
+//! so a register reused for unrelated things in different places ends up as separate groups
+//! those are then named after what they do where that's obvious, like `i` for a loop counter,
+//! and otherwise keep the name of their register
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::decompilation::SectionMap;
+use crate::dominators::{invert, section_successors, DominatorTree, Successors};
+use crate::instructions::ABIRegister;
+use crate::ir::{BinaryOp, Expression, Statement, Variable};
+use crate::loops::LoopForest;
+
+/// registers that always hold the same thing, and so keep their names
+const FIXED_REGISTERS: [ABIRegister; 4] = [ABIRegister::ra, ABIRegister::sp, ABIRegister::gp, ABIRegister::tp];
+
+/// names handed out to loop counters, outermost first
+const COUNTER_NAMES: [&str; 3] = ["i", "j", "k"];
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// # a phi function
+/// at the start of a block, picks the version of a register coming from whichever predecessor was run
+#[derive(Clone, Debug, PartialEq)]
+pub struct Phi {
+    dst: Variable,
+    args: BTreeMap<usize, Variable>     // the version coming in from each predecessor
+}
+
+impl Phi {
+    pub fn get_dst(&self) -> &Variable {
+        &self.dst
+    }
+
+    pub fn get_args(&self) -> &BTreeMap<usize, Variable> {
+        &self.args
+    }
+}
+
+/// # a block in ssa form
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SsaBlock {
+    phis: Vec<Phi>,
+    statements: BTreeMap<u64, Vec<Statement>>
+}
+
+impl SsaBlock {
+    pub fn get_phis(&self) -> &[Phi] {
+        &self.phis
+    }
+
+    pub fn get_statements(&self) -> &BTreeMap<u64, Vec<Statement>> {
+        &self.statements
+    }
+}
+
+/// # a function in ssa form
+/// only the blocks reachable from the entry are in here
+#[derive(Clone, Debug, PartialEq)]
+pub struct SsaFunction {
+    blocks: BTreeMap<usize, SsaBlock>,
+    order: Vec<usize>,                      // the blocks in reverse postorder
+    switch_on: BTreeMap<usize, Variable>    // the version each jump table selects on
+}
+
+impl SsaFunction {
+    /// # put the sections of a function into ssa form
+    /// version 0 of a register is whatever it held when the function was called
+    pub fn build(sections: &SectionMap) -> Self {
+        let successors = section_successors(sections);
+        let dominators = DominatorTree::from_sections(sections);
+        let order = dominators.reverse_postorder().to_vec();
+
+        let mut blocks: BTreeMap<usize, SsaBlock> = order.iter()
+            .map(|id| (*id, SsaBlock { phis: Vec::new(), statements: sections[id].get_statements().clone() }))
+            .collect();
+
+        place_phis(&mut blocks, &successors, &dominators);
+
+        let mut renamer = Renamer {
+            sections,
+            successors: &successors,
+            dominators: &dominators,
+            stacks: BTreeMap::new(),
+            counters: BTreeMap::new(),
+            switch_on: BTreeMap::new()
+        };
+        renamer.rename(dominators.get_root(), &mut blocks);
+
+        SsaFunction { blocks, order, switch_on: renamer.switch_on }
+    }
+
+    pub fn get_blocks(&self) -> &BTreeMap<usize, SsaBlock> {
+        &self.blocks
+    }
+
+    /// the blocks in reverse postorder, starting from the entry
+    pub fn get_order(&self) -> &[usize] {
+        &self.order
+    }
+
+    /// # take the function back out of ssa form
+    /// the versions joined by phis all become one variable, which is exact as long as nothing has been moved around,
+    /// since the original registers are already an assignment where they never overlap
+    pub fn destruct(&self, sections: &mut SectionMap) {
+        let names = self.name_variables(&LoopForest::from_sections(sections));
+        let rename = |variable: &mut Variable| {
+            if let Some(name) = names.get(variable) {
+                *variable = name.clone();
+            }
+        };
+
+        for (id, block) in self.blocks.iter() {
+            let mut statements = block.statements.clone();
+
+            for statement in statements.values_mut().flatten() {
+                for expr in statement.get_expressions_mut() {
+                    expr.for_each_variable_mut(&mut |variable| rename(variable));
+                }
+                if let Some(dst) = statement.get_def_mut() {
+                    rename(dst);
+                }
+            }
+
+            let section = sections.get_mut(id).unwrap();
+            section.set_statements(statements);
+
+            if let Some(index) = self.switch_on.get(id) {
+                let mut index = index.clone();
+                rename(&mut index);
+                section.set_switch_on(index);
+            }
+        }
+    }
+
+    /// # work out a name for every version
+    /// the versions are grouped by the phis joining them, and each group gets one name
+    fn name_variables(&self, loops: &LoopForest) -> BTreeMap<Variable, Variable> {
+        // union each phi with its arguments
+        let mut groups = Groups::default();
+        for block in self.blocks.values() {
+            for phi in block.phis.iter() {
+                for arg in phi.args.values() {
+                    groups.union(&phi.dst, arg);
+                }
+            }
+        }
+
+        // every version, in the order they first appear
+        let mut versions: Vec<Variable> = Vec::new();
+        let mut definitions: BTreeMap<Variable, &Expression> = BTreeMap::new();
+        let mut pointers: BTreeSet<Variable> = BTreeSet::new();
+
+        for id in self.order.iter() {
+            let block = &self.blocks[id];
+            versions.extend(block.phis.iter().map(|phi| phi.dst.clone()));
+
+            for statement in block.statements.values().flatten() {
+                statement.get_expressions().iter().for_each(|expr| expr.for_each_variable(&mut |v| versions.push(v.clone())));
+
+                if let Some(dst) = statement.get_def() {
+                    versions.push(dst.clone());
+                }
+                if let Statement::Assign { dst, value } = statement {
+                    definitions.insert(dst.clone(), value);
+                }
+
+                // anything used as the base of an address is a pointer
+                let address = match statement {
+                    Statement::Store { addr, .. } => Some(addr),
+                    _ => None
+                };
+                for addr in address.into_iter().chain(statement.get_expressions().into_iter().filter_map(load_address)) {
+                    if let Some(base) = base_of(addr) {
+                        pointers.insert(groups.find(base));
+                    }
+                }
+            }
+        }
+
+        // loop counters and accumulators are phis at a loop header, updated by their own value on the way back round
+        let mut counters: Vec<Variable> = Vec::new();
+        let mut accumulators: BTreeSet<Variable> = BTreeSet::new();
+
+        for id in self.order.iter() {
+            let Some(found) = loops.loop_with_header(*id) else {
+                continue;
+            };
+
+            for phi in self.blocks[id].phis.iter() {
+                let group = groups.find(&phi.dst);
+
+                for arg in phi.args.iter().filter(|(pred, _)| found.contains(**pred)).map(|(_, arg)| arg) {
+                    match definitions.get(arg).map(|value| step_of(value)) {
+                        Some(Some((variable, true))) if groups.find(variable) == group && !counters.contains(&group) => counters.push(group.clone()),
+                        Some(Some((variable, false))) if groups.find(variable) == group => { accumulators.insert(group.clone()); },
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        // what's given back in a register that's also a parameter is kept apart from it, as the two could well be different types
+        let returned: BTreeSet<Variable> = self.blocks.values()
+            .flat_map(|block| block.statements.values().flatten())
+            .filter_map(|statement| match statement {
+                Statement::Return { values: Some(values) } => Some(values),
+                _ => None
+            })
+            .flatten()
+            .filter_map(|value| match value {
+                Expression::Variable(variable) => Some(groups.find(variable)),
+                _ => None
+            })
+            .collect();
+
+        // anything without a better name keeps its register's, which can't clash as every group of a register is disjoint
+        let mut names: BTreeMap<Variable, Variable> = BTreeMap::new();
+        let mut taken: BTreeSet<String> = BTreeSet::new();
+        let parameters: BTreeSet<ABIRegister> = versions.iter()
+            .filter_map(|version| match groups.find(version) {
+                Variable::Version(register, 0) => Some(register),
+                _ => None
+            })
+            .collect();
+
+        for version in versions.iter() {
+            let group = groups.find(version);
+            let Variable::Version(register, first) = &group else {
+                continue;
+            };
+            if names.contains_key(&group) {
+                continue;
+            }
+
+            // parameters and fixed registers always keep their names
+            // a pointer stepped through memory is still a pointer rather than a counter
+            let name = if *first == 0 || FIXED_REGISTERS.contains(register) {
+                None
+            } else if pointers.contains(&group) {
+                Some(claim(&mut taken, numbered("ptr")))
+            } else if let Some(position) = counters.iter().filter(|c| !pointers.contains(c)).position(|c| *c == group) {
+                let first = COUNTER_NAMES.get(position).map(|name| name.to_string());
+                Some(claim(&mut taken, first.into_iter().chain((1..).map(|n| format!("i{}", n)))))
+            } else if accumulators.contains(&group) {
+                Some(claim(&mut taken, numbered("sum")))
+            } else if returned.contains(&group) && parameters.contains(register) {
+                Some(claim(&mut taken, numbered("result")))
+            } else {
+                None
+            };
+
+            names.insert(group.clone(), name.map(Variable::Named).unwrap_or(Variable::Register(register.clone())));
+        }
+
+        // every version goes by the name of its group
+        versions.iter()
+            .map(|version| (version.clone(), names[&groups.find(version)].clone()))
+            .collect()
+    }
+}
+
+/// the first name that hasn't been used yet
+fn claim(taken: &mut BTreeSet<String>, mut candidates: impl Iterator<Item = String>) -> String {
+    let name = candidates.find(|name| !taken.contains(name)).unwrap();
+    taken.insert(name.clone());
+    name
+}
+
+/// a name, then the same name with a number after it, counting up from 2
+fn numbered(name: &str) -> impl Iterator<Item = String> + '_ {
+    std::iter::once(name.to_string()).chain((2..).map(move |n| format!("{}{}", name, n)))
+}
+
+/// the address a load reads from, if this is one
+fn load_address(expr: &Expression) -> Option<&Expression> {
+    match expr {
+        Expression::Load { addr, .. } => Some(addr),
+        Expression::Extend { value, .. } => load_address(value),
+        _ => None
+    }
+}
+
+/// the variable an address is an offset from
+pub fn base_of(addr: &Expression) -> Option<&Variable> {
+    match addr {
+        Expression::Variable(variable) => Some(variable),
+        Expression::Binary(BinaryOp::Add, lhs, rhs) if matches!(**rhs, Expression::Constant(_)) => base_of(lhs),
+        _ => None
+    }
+}
+
+/// # what a value adds to a variable
+/// `x + c` gives `x` and true, `x + y` gives `x` and false, with 32-bit additions treated the same
+fn step_of(value: &Expression) -> Option<(&Variable, bool)> {
+    match value {
+        Expression::Extend { value, .. } => step_of(value),
+        Expression::Binary(BinaryOp::Add | BinaryOp::Sub, lhs, rhs) => match (&**lhs, &**rhs) {
+            (Expression::Variable(variable), Expression::Constant(_)) => Some((variable, true)),
+            (Expression::Variable(variable), _) => Some((variable, false)),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+// ----------------------------------------
+// construction
+// ----------------------------------------
+
+/// the registers a statement reads
+fn registers_used(statement: &Statement) -> Vec<ABIRegister> {
+    statement.get_uses().iter().filter_map(|v| v.get_register().cloned()).collect()
+}
+
+/// the register a statement writes, if any
+fn register_defined(statement: &Statement) -> Option<ABIRegister> {
+    statement.get_def().and_then(|v| v.get_register().cloned())
+}
+
+/// # put phis wherever a register defined in one block is live where paths from different definitions meet
+/// liveness is worked out first, so no phi is placed for a register nothing reads afterwards
+fn place_phis(blocks: &mut BTreeMap<usize, SsaBlock>, successors: &Successors, dominators: &DominatorTree) {
+    // registers read before being written in each block, and registers written at all
+    let mut exposed: BTreeMap<usize, BTreeSet<ABIRegister>> = BTreeMap::new();
+    let mut defined: BTreeMap<usize, BTreeSet<ABIRegister>> = BTreeMap::new();
+
+    for (id, block) in blocks.iter() {
+        let exposed = exposed.entry(*id).or_default();
+        let defined = defined.entry(*id).or_default();
+
+        for statement in block.statements.values().flatten() {
+            for register in registers_used(statement) {
+                if !defined.contains(&register) {
+                    exposed.insert(register);
+                }
+            }
+            defined.extend(register_defined(statement));
+        }
+    }
+
+    // live registers going into each block, iterated backwards until nothing changes
+    let mut live_in: BTreeMap<usize, BTreeSet<ABIRegister>> = blocks.keys().map(|id| (*id, BTreeSet::new())).collect();
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for id in dominators.reverse_postorder().iter().rev() {
+            let mut live: BTreeSet<ABIRegister> = successors.get(id)
+                .into_iter()
+                .flatten()
+                .filter_map(|succ| live_in.get(succ))
+                .flatten()
+                .filter(|register| !defined[id].contains(register))
+                .cloned()
+                .collect();
+            live.extend(exposed[id].iter().cloned());
+
+            if live != live_in[id] {
+                live_in.insert(*id, live);
+                changed = true;
+            }
+        }
+    }
+
+    // then the usual iterated dominance frontier, for each register on its own
+    let predecessors = invert(successors);
+    let registers: BTreeSet<ABIRegister> = defined.values().flatten().cloned().collect();
+
+    for register in registers {
+        let mut has_phi: BTreeSet<usize> = BTreeSet::new();
+        let mut worklist: Vec<usize> = defined.iter()
+            .filter(|(_, defs)| defs.contains(&register))
+            .map(|(id, _)| *id)
+            .collect();
+
+        while let Some(id) = worklist.pop() {
+            for frontier in dominators.get_frontier(id) {
+                if has_phi.contains(&frontier) || !live_in.get(&frontier).is_some_and(|live| live.contains(&register)) {
+                    continue;
+                }
+                has_phi.insert(frontier);
+
+                let args = predecessors.get(&frontier)
+                    .into_iter()
+                    .flatten()
+                    .filter(|pred| dominators.is_reachable(**pred))
+                    .map(|pred| (*pred, Variable::Register(register.clone())))
+                    .collect();
+                blocks.get_mut(&frontier).unwrap().phis.push(Phi { dst: Variable::Register(register.clone()), args });
+
+                if !defined[&frontier].contains(&register) {
+                    worklist.push(frontier);
+                }
+            }
+        }
+    }
+}
+
+/// state carried down the dominator tree while renaming
+struct Renamer<'a> {
+    sections: &'a SectionMap,
+    successors: &'a Successors,
+    dominators: &'a DominatorTree,
+    stacks: BTreeMap<ABIRegister, Vec<usize>>,      // the version of each register currently in scope
+    counters: BTreeMap<ABIRegister, usize>,         // the last version handed out for each register
+    switch_on: BTreeMap<usize, Variable>
+}
+
+impl Renamer<'_> {
+    fn current(&self, register: &ABIRegister) -> Variable {
+        let version = self.stacks.get(register).and_then(|stack| stack.last()).copied().unwrap_or(0);
+        Variable::Version(register.clone(), version)
+    }
+
+    fn define(&mut self, register: &ABIRegister) -> Variable {
+        let counter = self.counters.entry(register.clone()).or_insert(0);
+        *counter += 1;
+
+        let version = *counter;
+        self.stacks.entry(register.clone()).or_default().push(version);
+        Variable::Version(register.clone(), version)
+    }
+
+    /// # rename a block, then everything it dominates
+    /// the versions defined here are popped again on the way back up
+    fn rename(&mut self, id: usize, blocks: &mut BTreeMap<usize, SsaBlock>) {
+        let mut defined: Vec<ABIRegister> = Vec::new();
+        let mut block = blocks.remove(&id).unwrap();
+
+        for phi in block.phis.iter_mut() {
+            let register = phi.dst.get_register().unwrap().clone();
+            phi.dst = self.define(&register);
+            defined.push(register);
+        }
+
+        let lookup = self.sections[&id].get_jump_table().and_then(|table| {
+            table.get_lookup().first().map(|address| (*address, table.get_index()))
+        });
+
+        for (address, statements) in block.statements.iter_mut() {
+            if let Some((_, index)) = lookup.as_ref().filter(|(start, _)| start == address) {
+                self.switch_on.insert(id, self.current(index));
+            }
+
+            for statement in statements.iter_mut() {
+                for expr in statement.get_expressions_mut() {
+                    expr.for_each_variable_mut(&mut |variable| {
+                        if let Some(register) = variable.get_register().cloned() {
+                            *variable = self.current(&register);
+                        }
+                    });
+                }
+
+                if let Some(dst) = statement.get_def_mut() {
+                    if let Some(register) = dst.get_register().cloned() {
+                        *dst = self.define(&register);
+                        defined.push(register);
+                    }
+                }
+            }
+        }
+
+        blocks.insert(id, block);
+
+        // fill in this block's side of the phis in each successor
+        for succ in self.successors.get(&id).into_iter().flatten() {
+            if let Some(succ_block) = blocks.get_mut(succ) {
+                for phi in succ_block.phis.iter_mut() {
+                    let register = phi.dst.get_register().unwrap().clone();
+                    let current = self.current(&register);
+                    if let Some(arg) = phi.args.get_mut(&id) {
+                        *arg = current;
+                    }
+                }
+            }
+        }
+
+        for child in self.dominators.get_children(id).to_vec() {
+            self.rename(child, blocks);
+        }
+
+        for register in defined {
+            self.stacks.get_mut(&register).unwrap().pop();
+        }
+    }
+}
+
+/// union-find over versions, where each group ends up as one variable
+#[derive(Default)]
+pub struct Groups {
+    parent: BTreeMap<Variable, Variable>
+}
+
+impl Groups {
+    pub fn find(&mut self, variable: &Variable) -> Variable {
+        let mut current = variable.clone();
+        while let Some(parent) = self.parent.get(&current) {
+            current = parent.clone();
+        }
+
+        // point everything on the way straight at the root
+        let mut next = variable.clone();
+        while let Some(parent) = self.parent.get(&next).cloned() {
+            self.parent.insert(next, current.clone());
+            next = parent;
+        }
+
+        current
+    }
+
+    /// the lower version becomes the root, so a group with version 0 in it is a parameter
+    pub fn union(&mut self, a: &Variable, b: &Variable) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            let (root, child) = if a < b { (a, b) } else { (b, a) };
+            self.parent.insert(child, root);
+        }
+    }
+}
+
+/// # recover variables for a function
+/// goes into ssa form and straight back out, leaving each section's statements in terms of variables
+pub fn recover_variables(sections: &mut SectionMap) {
+    if sections.is_empty() {
+        return;
+    }
+
+    SsaFunction::build(sections).destruct(sections);
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{addi, sections};
+    use crate::instructions::InstructionType;
+    use ABIRegister::*;
+
+    /// sum the words from a1 up to a2, counting how many there were
+    fn summing_loop() -> SectionMap {
+        sections(vec![
+            addi(a3, zero, 0),                                                              // section 0
+            addi(a4, zero, 0),
+            addi(a5, a1, 0),
+            InstructionType::B { name: "bgeu", rs1: a5, rs2: a2, imm: 0x18 },               // section 1
+            InstructionType::I { name: "lw", rd: a6, rs1: a5, imm: 0 },                     // section 2
+            InstructionType::R { name: "add", rd: a3, rs1: a3, rs2: a6 },
+            addi(a4, a4, 1),
+            addi(a5, a5, 4),
+            InstructionType::J { name: "jal", rd: zero, imm: -0x14 },
+            addi(a0, a3, 0),                                                                // section 3
+            InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 }
+        ])
+    }
+
+    #[test]
+    fn test_phis_placed() {
+        let ssa = SsaFunction::build(&summing_loop());
+        let header = &ssa.get_blocks()[&1];
+
+        // everything the loop changes meets at the header, but a6 is dead by then so gets nothing
+        let registers: Vec<&ABIRegister> = header.get_phis().iter().map(|phi| phi.get_dst().get_register().unwrap()).collect();
+        assert_eq!(registers, [&a3, &a4, &a5]);
+
+        // each one takes the version from before the loop and the one from the end of the body
+        let counter = &header.get_phis()[1];
+        assert_eq!(counter.get_args().get(&0), Some(&Variable::Version(a4, 1)));
+        assert_eq!(counter.get_args().get(&2), Some(&Variable::Version(a4, 3)));
+    }
+
+    #[test]
+    fn test_versions_renamed() {
+        let ssa = SsaFunction::build(&summing_loop());
+        let body = ssa.get_blocks()[&2].get_statements();
+
+        // the load reads the version from the phi, and the step defines a new one
+        assert_eq!(body[&0x110][0].to_string(), "a6_1 = *(int32_t *)a5_2");
+        assert_eq!(body[&0x11c][0].to_string(), "a5_3 = a5_2 + 4");
+
+        // registers nothing defined are read as they were when the function was called
+        let header = ssa.get_blocks()[&1].get_statements();
+        assert_eq!(header[&0x10c][0].to_string(), "if ((uint64_t)a5_2 >= (uint64_t)a2_0) goto 0x124");
+    }
+
+    #[test]
+    fn test_variables_named() {
+        let mut sections = summing_loop();
+        recover_variables(&mut sections);
+
+        let statements: Vec<String> = sections.values()
+            .flat_map(|section| section.get_statements().values().flatten().map(|s| s.to_string()).collect::<Vec<_>>())
+            .collect();
+
+        assert_eq!(statements, [
+            "sum = 0",
+            "i = 0",
+            "ptr = a1",
+            "if ((uint64_t)ptr >= (uint64_t)a2) goto 0x124",
+            "a6 = *(int32_t *)ptr",
+            "sum = sum + a6",
+            "i = i + 1",
+            "ptr = ptr + 4",
+            "goto 0x10c",
+            "a0 = sum",
+            "return"
+        ]);
+    }
+
+    #[test]
+    fn test_unrelated_uses_split() {
+        // a5 is a counter in the loop, and something else entirely afterwards
+        let mut sections = sections(vec![
+            addi(a5, zero, 0),
+            InstructionType::B { name: "bge", rs1: a5, rs2: a0, imm: 0xc },
+            addi(a5, a5, 1),
+            InstructionType::J { name: "jal", rd: zero, imm: -0x8 },
+            addi(a5, a1, 2),
+            InstructionType::R { name: "add", rd: a0, rs1: a5, rs2: a5 },
+            InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 }
+        ]);
+        recover_variables(&mut sections);
+
+        let last = sections.values().last().unwrap().get_statements();
+        assert_eq!(last[&0x110][0].to_string(), "a5 = a1 + 2");
+        assert_eq!(last[&0x114][0].to_string(), "a0 = a5 + a5");
+        assert_eq!(sections[&2].get_statements()[&0x108][0].to_string(), "i = i + 1");
+    }
+}
@@ -0,0 +1,543 @@
+//! # stack frames
+//! follows `sp` through a function as an offset from where it was on entry, along with anything else pointing into the frame like `s0`,
+//! so every access to the stack can be put down to a slot in the frame
+//!
+//! the slots the prologue saves registers to and the epilogue restores them from are taken out, along with the adjustments to `sp`,
+//! and everything else is a local named after how far below the entry `sp` it is, like `local_18`
+//! as long as the frame's address is never handed out these become ordinary variables, otherwise they stay in memory with their names
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::dataflow::{instruction_facts, solve, Analysis, Direction, Lattice, PRESERVED};
+use crate::decompilation::{InstructionSection, SectionMap};
+use crate::instructions::ABIRegister;
+use crate::ir::{BinaryOp, Expression, Statement, Variable};
+
+// ----------------------------------------
+// tracking the stack pointer
+// ----------------------------------------
+
+/// # where registers point into the frame
+/// as offsets from `sp` on entry, with `None` for somewhere nothing has reached yet
+/// a register missing from the map doesn't point anywhere known
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Offsets(Option<BTreeMap<ABIRegister, i64>>);
+
+impl Offsets {
+    /// the offset of a register, if it points into the frame
+    pub fn get(&self, register: &ABIRegister) -> Option<i64> {
+        self.0.as_ref()?.get(register).copied()
+    }
+}
+
+/// only the registers that point to the same place whichever way the frame was reached are kept
+impl Lattice for Offsets {
+    fn bottom() -> Self {
+        Offsets(None)
+    }
+
+    fn join(&mut self, other: &Self) -> bool {
+        match (&mut self.0, &other.0) {
+            (_, None) => false,
+            (None, Some(theirs)) => {
+                self.0 = Some(theirs.clone());
+                true
+            },
+            (Some(mine), Some(theirs)) => {
+                let before = mine.len();
+                mine.retain(|register, offset| theirs.get(register) == Some(offset));
+                mine.len() != before
+            }
+        }
+    }
+}
+
+/// # the stack pointer
+/// `sp` starts at offset 0, and anything set to it plus a constant is tracked from there
+/// calls keep `sp` and the saved registers as they were, but could leave anything else pointing anywhere
+pub struct StackPointer;
+
+impl Analysis for StackPointer {
+    type Fact = Offsets;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn boundary(&self, _section: &InstructionSection) -> Self::Fact {
+        Offsets(Some(BTreeMap::from([(ABIRegister::sp, 0)])))
+    }
+
+    fn transfer(&self, _address: u64, statements: &[Statement], fact: &mut Self::Fact) {
+        let Some(offsets) = fact.0.as_mut() else {
+            return;
+        };
+
+        for statement in statements {
+            if matches!(statement, Statement::Call { .. } | Statement::Intrinsic { .. }) {
+                offsets.retain(|register, _| PRESERVED.contains(register));
+            }
+
+            let Some(register) = statement.get_def().and_then(|dst| dst.get_register()) else {
+                continue;
+            };
+            let offset = match statement {
+                Statement::Assign { value, .. } => offset_of(value, offsets),
+                _ => None
+            };
+
+            match offset {
+                Some(offset) => offsets.insert(register.clone(), offset),
+                None => offsets.remove(register)
+            };
+        }
+    }
+}
+
+/// where a value points in the frame, if it's a register pointing there plus or minus a constant
+fn offset_of(value: &Expression, offsets: &BTreeMap<ABIRegister, i64>) -> Option<i64> {
+    match value {
+        Expression::Variable(Variable::Register(register)) => offsets.get(register).copied(),
+        Expression::Binary(BinaryOp::Add, lhs, rhs) => match **rhs {
+            Expression::Constant(c) => offset_of(lhs, offsets)?.checked_add(c),
+            _ => None
+        },
+        Expression::Binary(BinaryOp::Sub, lhs, rhs) => match **rhs {
+            Expression::Constant(c) => offset_of(lhs, offsets)?.checked_sub(c),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// whether an address is worked out from the stack pointer, whether or not it's known where that is
+fn based_on_sp(addr: &Expression) -> bool {
+    let mut found = false;
+    addr.for_each_variable(&mut |variable| found |= variable.get_register() == Some(&ABIRegister::sp));
+    found
+}
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// what a slot in the frame is used for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SlotKind {
+    Saved(ABIRegister),     // a callee-saved register, saved by the prologue and restored by the epilogue
+    Spill,                  // a register put aside and read back into the same register
+    Local                   // anything else
+}
+
+/// # a slot in the frame
+/// `offset` is from `sp` on entry, so the function's own slots are below 0 and anything passed on the stack is above
+#[derive(Clone, Debug, PartialEq)]
+pub struct Slot {
+    offset: i64,
+    size: u8,               // the widest access to it
+    kind: SlotKind,
+    promoted: bool          // whether it can be a variable rather than memory
+}
+
+impl Slot {
+    pub fn get_offset(&self) -> i64 {
+        self.offset
+    }
+
+    pub fn get_size(&self) -> u8 {
+        self.size
+    }
+
+    pub fn get_kind(&self) -> &SlotKind {
+        &self.kind
+    }
+
+    pub fn get_name(&self) -> String {
+        slot_name(self.offset)
+    }
+}
+
+/// locals are named after how far below the entry `sp` they are, and what was passed on the stack after how far above
+fn slot_name(offset: i64) -> String {
+    if offset < 0 {
+        format!("local_{:x}", offset.unsigned_abs())
+    } else {
+        format!("arg_{:x}", offset)
+    }
+}
+
+/// # how much of the frame a slot's name could cover
+/// a local reaches up to the entry `sp`, an argument is a single register's worth, and anything else isn't a slot
+pub fn slot_size(name: &str) -> Option<i64> {
+    if let Some(offset) = name.strip_prefix("local_") {
+        i64::from_str_radix(offset, 16).ok()
+    } else {
+        name.strip_prefix("arg_").and_then(|offset| i64::from_str_radix(offset, 16).ok()).map(|_| 8)
+    }
+}
+
+/// one load or store of the frame
+struct Access {
+    offset: i64,
+    size: u8,
+    store: bool,
+    register: Option<ABIRegister>,      // the register stored, or loaded into
+    in_entry: bool                      // whether it's in the entry block before the register it stores has been written
+}
+
+/// # the stack frame of a function
+/// how far `sp` goes down, what each slot is for, and where registers point into the frame at each instruction
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackFrame {
+    size: i64,
+    slots: BTreeMap<i64, Slot>,
+    escapes: bool,                                          // whether the frame's address is given to anything
+    offsets: BTreeMap<u64, Offsets>                         // before each instruction
+}
+
+impl StackFrame {
+    /// # work out the frame of a function
+    /// a store of a callee-saved register's value from on entry, only ever loaded back into that register, is where it's saved
+    pub fn new(sections: &SectionMap) -> Self {
+        let solution = solve(&StackPointer, sections);
+        let root = sections.keys().next().copied();
+
+        let mut offsets: BTreeMap<u64, Offsets> = BTreeMap::new();
+        for section in sections.values() {
+            offsets.extend(instruction_facts(&StackPointer, section, &solution));
+        }
+
+        let mut accesses: Vec<Access> = Vec::new();
+        let mut escapes = false;
+        let mut size = 0;
+
+        for section in sections.values() {
+            let mut written: BTreeSet<ABIRegister> = BTreeSet::new();
+
+            for (address, statements) in section.get_statements() {
+                let known = offsets[address].0.clone().unwrap_or_default();
+
+                for statement in statements {
+                    let in_entry = Some(section.get_id()) == root;
+                    let (access, others) = access_of(statement, &known);
+
+                    // the frame's address going anywhere but `sp` and the frame pointer means anything could change it
+                    let frame_pointer = matches!(statement.get_def(), Some(Variable::Register(ABIRegister::sp | ABIRegister::s0)));
+                    escapes |= others.iter().any(|expr| points_into_frame(expr, &known, frame_pointer));
+
+                    // neither can anything else touching the stack when it isn't known where `sp` is
+                    escapes |= statement.get_expressions().iter().any(|expr| unknown_access(expr, &known));
+                    if let Statement::Store { addr, .. } = statement {
+                        escapes |= based_on_sp(addr) && offset_of(addr, &known).is_none();
+                    }
+
+                    if let Some(mut access) = access {
+                        access.in_entry = in_entry && access.register.as_ref().is_some_and(|r| !written.contains(r));
+                        accesses.push(access);
+                    }
+
+                    if let Some(register) = statement.get_def().and_then(|dst| dst.get_register()) {
+                        written.insert(register.clone());
+                    }
+                    if let Some(offset) = offsets[address].get(&ABIRegister::sp) {
+                        size = size.max(-offset);
+                    }
+                }
+            }
+        }
+
+        // sp after the last instruction counts too, in case that's where it goes down
+        for section in sections.values() {
+            if let Some(offset) = solution.get_out(section.get_id()).and_then(|out| out.get(&ABIRegister::sp)) {
+                size = size.max(-offset);
+            }
+        }
+
+        let slots = classify(&accesses, escapes);
+
+        StackFrame { size, slots, escapes, offsets }
+    }
+
+    /// how far `sp` goes below where it was on entry
+    pub fn get_size(&self) -> i64 {
+        self.size
+    }
+
+    pub fn get_slots(&self) -> &BTreeMap<i64, Slot> {
+        &self.slots
+    }
+
+    /// whether the frame's address is passed on, so its slots have to stay in memory
+    pub fn escapes(&self) -> bool {
+        self.escapes
+    }
+
+    /// where `sp` is before an instruction, if that's known
+    pub fn sp_offset(&self, address: u64) -> Option<i64> {
+        self.offsets.get(&address)?.get(&ABIRegister::sp)
+    }
+
+    /// # rewrite a function in terms of its frame
+    /// the prologue and epilogue go, slots that can be are variables, and anything else pointing into the frame is named
+    pub fn apply(&self, sections: &mut SectionMap) {
+        let saved: BTreeSet<ABIRegister> = self.slots.values()
+            .filter_map(|slot| match &slot.kind { SlotKind::Saved(register) => Some(register.clone()), _ => None })
+            .collect();
+
+        for section in sections.values_mut() {
+            let mut statements = section.get_statements().clone();
+
+            for (address, instruction) in statements.iter_mut() {
+                let known = self.offsets[address].0.clone().unwrap_or_default();
+
+                *instruction = instruction.drain(..)
+                    .filter_map(|statement| self.rewrite(statement, &known, &saved))
+                    .collect();
+            }
+
+            section.set_statements(statements);
+        }
+    }
+
+    /// rewrite one statement, or give nothing if it's part of setting up or tearing down the frame
+    fn rewrite(&self, statement: Statement, known: &BTreeMap<ABIRegister, i64>, saved: &BTreeSet<ABIRegister>) -> Option<Statement> {
+        let slot = |addr: &Expression| offset_of(addr, known).and_then(|offset| self.slots.get(&offset));
+
+        match statement {
+            // moving `sp`, and pointing the frame pointer at the frame
+            Statement::Assign { dst: Variable::Register(ABIRegister::sp), ref value } if offset_of(value, known).is_some() => None,
+            Statement::Assign { dst: Variable::Register(ABIRegister::s0), ref value } if saved.contains(&ABIRegister::s0) && offset_of(value, known).is_some() => None,
+
+            // saving and restoring
+            Statement::Store { ref addr, .. } if slot(addr).is_some_and(|s| matches!(s.kind, SlotKind::Saved(_))) => None,
+            Statement::Assign { dst: Variable::Register(ref register), ref value }
+                if load_of(value).and_then(|(addr, _, _)| slot(addr)).is_some_and(|s| s.kind == SlotKind::Saved(register.clone())) => None,
+
+            Statement::Store { ref addr, ref value, .. } if slot(addr).is_some_and(|s| s.promoted) => {
+                let dst = Variable::Named(slot(addr).unwrap().get_name());
+                Some(Statement::Assign { dst, value: self.rewrite_expression(value, known) })
+            },
+
+            mut statement => {
+                for expr in statement.get_expressions_mut() {
+                    *expr = self.rewrite_expression(expr, known);
+                }
+                Some(statement)
+            }
+        }
+    }
+
+    /// loads of slots that are variables read the variable, and anything else pointing into the frame is the slot's address
+    fn rewrite_expression(&self, expr: &Expression, known: &BTreeMap<ABIRegister, i64>) -> Expression {
+        if let Some(offset) = offset_of(expr, known) {
+            return Expression::Symbol { name: slot_name(offset), offset: 0 };
+        }
+
+        match expr {
+            Expression::Load { addr, size, signed } => {
+                match offset_of(addr, known).and_then(|offset| self.slots.get(&offset)).filter(|slot| slot.promoted) {
+                    Some(slot) if *size == 8 => Expression::Variable(Variable::Named(slot.get_name())),
+                    Some(slot) => Expression::extend(Expression::Variable(Variable::Named(slot.get_name())), size * 8, *signed),
+                    None => Expression::load(self.rewrite_expression(addr, known), *size, *signed)
+                }
+            },
+            Expression::Binary(op, lhs, rhs) => Expression::binary(*op, self.rewrite_expression(lhs, known), self.rewrite_expression(rhs, known)),
+            Expression::Extend { value, bits, signed } => Expression::extend(self.rewrite_expression(value, known), *bits, *signed),
+            Expression::Variable(_) | Expression::Constant(_) | Expression::Symbol { .. } | Expression::String { .. } | Expression::Deref(_) | Expression::Field { .. } | Expression::Cast { .. } => expr.clone()
+        }
+    }
+}
+
+/// the address, size and signedness of a load, even once it's been extended
+fn load_of(value: &Expression) -> Option<(&Expression, u8, bool)> {
+    match value {
+        Expression::Load { addr, size, signed } => Some((addr, *size, *signed)),
+        Expression::Extend { value, .. } => load_of(value),
+        _ => None
+    }
+}
+
+/// # the access to the frame a statement makes, if it makes one
+/// along with every expression it reads other than the address it accesses, for checking whether the frame escapes
+fn access_of<'a>(statement: &'a Statement, known: &BTreeMap<ABIRegister, i64>) -> (Option<Access>, Vec<&'a Expression>) {
+    let register = |expr: &Expression| match expr {
+        Expression::Variable(Variable::Register(register)) => Some(register.clone()),
+        _ => None
+    };
+
+    match statement {
+        Statement::Store { addr, value, size } => match offset_of(addr, known) {
+            Some(offset) => (Some(Access { offset, size: *size, store: true, register: register(value), in_entry: false }), vec![value]),
+            None => (None, vec![addr, value])
+        },
+        Statement::Assign { dst, value } => match load_of(value).and_then(|(addr, size, _)| Some((offset_of(addr, known)?, size))) {
+            Some((offset, size)) => (Some(Access { offset, size, store: false, register: dst.get_register().cloned(), in_entry: false }), Vec::new()),
+            None => (None, vec![value])
+        },
+        _ => (None, statement.get_expressions())
+    }
+}
+
+/// whether a value read points into the frame, other than setting `sp` or the frame pointer from it
+fn points_into_frame(expr: &Expression, known: &BTreeMap<ABIRegister, i64>, frame_pointer: bool) -> bool {
+    if offset_of(expr, known).is_some() {
+        return !frame_pointer;
+    }
+
+    match expr {
+        // an address only loaded from doesn't go anywhere
+        Expression::Load { addr, .. } | Expression::Deref(addr) | Expression::Field { base: addr, .. } => offset_of(addr, known).is_none() && points_into_frame(addr, known, false),
+        Expression::Binary(_, lhs, rhs) => points_into_frame(lhs, known, false) || points_into_frame(rhs, known, false),
+        Expression::Extend { value, .. } | Expression::Cast { value, .. } => points_into_frame(value, known, false),
+        Expression::Variable(_) | Expression::Constant(_) | Expression::Symbol { .. } | Expression::String { .. } => false
+    }
+}
+
+/// whether a value loads from the stack somewhere it isn't known
+fn unknown_access(expr: &Expression, known: &BTreeMap<ABIRegister, i64>) -> bool {
+    match expr {
+        Expression::Load { addr, .. } | Expression::Deref(addr) | Expression::Field { base: addr, .. } => (based_on_sp(addr) && offset_of(addr, known).is_none()) || unknown_access(addr, known),
+        Expression::Binary(_, lhs, rhs) => unknown_access(lhs, known) || unknown_access(rhs, known),
+        Expression::Extend { value, .. } | Expression::Cast { value, .. } => unknown_access(value, known),
+        Expression::Variable(_) | Expression::Constant(_) | Expression::Symbol { .. } | Expression::String { .. } => false
+    }
+}
+
+/// # put each slot down to what it's used for
+/// a slot can only be a variable if nothing else could change it, nothing overlaps it without lining up with it,
+/// and nothing reads more of it than is written
+fn classify(accesses: &[Access], escapes: bool) -> BTreeMap<i64, Slot> {
+    let mut grouped: BTreeMap<i64, Vec<&Access>> = BTreeMap::new();
+    for access in accesses {
+        grouped.entry(access.offset).or_default().push(access);
+    }
+
+    let widest: BTreeMap<i64, u8> = grouped.iter()
+        .map(|(offset, accesses)| (*offset, accesses.iter().map(|a| a.size).max().unwrap_or(8)))
+        .collect();
+
+    grouped.iter().map(|(offset, accesses)| {
+        let size = widest[offset];
+        let (stores, loads): (Vec<&&Access>, Vec<&&Access>) = accesses.iter().partition(|a| a.store);
+
+        // the same register stored and loaded every time
+        let registers: BTreeSet<Option<&ABIRegister>> = accesses.iter().map(|a| a.register.as_ref()).collect();
+        let single = match registers.iter().collect::<Vec<_>>().as_slice() {
+            [Some(register)] => Some((*register).clone()),
+            _ => None
+        };
+
+        let kind = match single {
+            Some(register) if PRESERVED.contains(&register) && register != ABIRegister::sp && stores.iter().all(|s| s.in_entry) => SlotKind::Saved(register),
+            Some(_) if !stores.is_empty() && !loads.is_empty() => SlotKind::Spill,
+            _ => SlotKind::Local
+        };
+
+        let overlaps = widest.iter().any(|(other, other_size)| {
+            other != offset && *other < offset + size as i64 && *offset < other + *other_size as i64
+        });
+        let narrowest_store = stores.iter().map(|s| s.size).min().unwrap_or(0);
+        let widest_load = loads.iter().map(|l| l.size).max().unwrap_or(0);
+        let promoted = !escapes && !overlaps && widest_load <= narrowest_store;
+
+        (*offset, Slot { offset: *offset, size, kind, promoted })
+    }).collect()
+}
+
+/// # recover the stack frame of a function
+/// works out its frame and rewrites the function in terms of it, giving the frame
+pub fn recover_stack_frame(sections: &mut SectionMap) -> StackFrame {
+    let frame = StackFrame::new(sections);
+    frame.apply(sections);
+    frame
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{addi, ld, sd, sections, statements};
+    use crate::instructions::InstructionType;
+    use ABIRegister::*;
+
+    /// a frame pointer, two saved registers, and a local kept at -20 from it
+    fn framed() -> SectionMap {
+        sections(vec![
+            addi(sp, sp, -32),
+            sd(sp, ra, 24),
+            sd(sp, s0, 16),
+            addi(s0, sp, 32),
+            InstructionType::S { name: "sw", rs1: s0, rs2: a0, imm: -20 },
+            InstructionType::I { name: "lw", rd: a5, rs1: s0, imm: -20 },
+            addi(a0, a5, 1),
+            ld(ra, sp, 24),
+            ld(s0, sp, 16),
+            addi(sp, sp, 32),
+            InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 }
+        ])
+    }
+
+    #[test]
+    fn test_sp_tracked() {
+        let frame = StackFrame::new(&framed());
+
+        assert_eq!(frame.get_size(), 32);
+        assert_eq!(frame.sp_offset(0x100), Some(0));
+        assert_eq!(frame.sp_offset(0x104), Some(-32));
+        assert_eq!(frame.sp_offset(0x128), Some(0));
+    }
+
+    #[test]
+    fn test_slots_classified() {
+        let frame = StackFrame::new(&framed());
+        let kinds: Vec<(i64, SlotKind)> = frame.get_slots().values().map(|slot| (slot.get_offset(), slot.get_kind().clone())).collect();
+
+        assert_eq!(kinds, [(-20, SlotKind::Local), (-16, SlotKind::Saved(s0)), (-8, SlotKind::Saved(ra))]);
+        assert_eq!(frame.get_slots()[&-20].get_name(), "local_14");
+    }
+
+    #[test]
+    fn test_frame_removed() {
+        let mut cfg = framed();
+        recover_stack_frame(&mut cfg);
+
+        assert_eq!(statements(&cfg), ["local_14 = a0", "a5 = (int32_t)local_14", "a0 = a5 + 1", "return"]);
+    }
+
+    #[test]
+    fn test_spills() {
+        // a0 is put aside over a call and read back
+        let mut cfg = sections(vec![
+            addi(sp, sp, -16),
+            sd(sp, a0, 8),
+            InstructionType::J { name: "jal", rd: ra, imm: 0x40 },
+            ld(a0, sp, 8),
+            addi(sp, sp, 16),
+            InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 }
+        ]);
+        let frame = recover_stack_frame(&mut cfg);
+
+        assert_eq!(frame.get_slots()[&-8].get_kind(), &SlotKind::Spill);
+        assert_eq!(statements(&cfg), ["local_8 = a0", "sub_148()", "a0 = local_8", "return"]);
+    }
+
+    #[test]
+    fn test_escaping_frame() {
+        // the local's address is passed to a call, so it has to stay in memory
+        let mut cfg = sections(vec![
+            addi(sp, sp, -16),
+            InstructionType::S { name: "sw", rs1: sp, rs2: zero, imm: 4 },
+            addi(a0, sp, 4),
+            InstructionType::J { name: "jal", rd: ra, imm: 0x40 },
+            addi(sp, sp, 16),
+            InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 }
+        ]);
+        let frame = recover_stack_frame(&mut cfg);
+
+        assert!(frame.escapes());
+        assert_eq!(statements(&cfg), ["*(int32_t *)&local_c = 0", "a0 = &local_c", "sub_14c()", "return"]);
+    }
+}
@@ -0,0 +1,203 @@
+//! # strings
+//! finds the text in the data sections, so it can be listed along with where it's used,
+//! and written out as a literal wherever the code uses its address
+//!
+//! text is utf-8, which covers ascii, or little-endian utf-16, ended by a nul as wide as its characters
+//! anything shorter than a few characters is too likely to be some other data that happens to look like text,
+//! and wide text has to be mostly ascii, as almost any pair of bytes is some character in utf-16
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::image::Image;
+use crate::ir::Expression;
+use crate::xrefs::Xrefs;
+
+/// how many characters text needs before it's taken to be text
+pub const MINIMUM_LENGTH: usize = 4;
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// # some text found in the data
+/// along with how many bytes it takes up, nul included
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Text {
+    address: u64,
+    text: String,
+    wide: bool,                         // utf-16 rather than utf-8
+    size: usize
+}
+
+/// text by the address it starts at
+pub type StringMap = BTreeMap<u64, Text>;
+
+impl Text {
+    pub fn get_address(&self) -> u64 {
+        self.address
+    }
+
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_wide(&self) -> bool {
+        self.wide
+    }
+
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    /// whether an address is somewhere in it
+    pub fn contains(&self, address: u64) -> bool {
+        (self.address..self.address + self.size as u64).contains(&address)
+    }
+
+    /// the text as it'd be written in the code
+    pub fn to_expression(&self) -> Expression {
+        Expression::String { text: self.text.clone(), wide: self.wide }
+    }
+}
+
+/// anything that isn't a control character, apart from whitespace
+fn printable(c: char) -> bool {
+    !c.is_control() || matches!(c, '\n' | '\t' | '\r')
+}
+
+/// # utf-16 text at the start of some bytes
+/// taking up an even number of bytes, up to a nul that's two wide
+fn wide(data: &[u8]) -> Option<(String, usize)> {
+    let units: Vec<u16> = data.chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .take_while(|unit| *unit != 0)
+        .collect();
+    if units.len() * 2 + 2 > data.len() || units.iter().filter(|unit| **unit < 0x80).count() * 2 < units.len() {
+        return None;
+    }
+
+    let text: String = char::decode_utf16(units.iter().copied()).collect::<Result<_, _>>().ok()?;
+    text.chars().all(printable).then_some((text, units.len() * 2 + 2))
+}
+
+/// # the text starting at an address
+/// as long as there's enough of it, and it's somewhere other than in the code
+pub fn string_at(image: &Image, address: u64) -> Option<Text> {
+    let section = image.section_containing(address).filter(|section| !section.is_executable())?;
+    let data = &section.get_data()[(address - section.get_address()) as usize..];
+
+    let wide = wide(data).filter(|(text, _)| address % 2 == 0 && text.chars().count() >= MINIMUM_LENGTH);
+    let (text, wide, size) = match wide {
+        Some((text, size)) => (text, true, size),
+        None => {
+            let text = image.read_string(address)?;
+            (text.to_string(), false, text.len() + 1)
+        }
+    };
+
+    (text.chars().count() >= MINIMUM_LENGTH).then_some(Text { address, text, wide, size })
+}
+
+/// # find all the text in the data
+/// going through every section that isn't code, skipping over each string as it's found
+pub fn find_strings(image: &Image) -> StringMap {
+    let mut strings = StringMap::new();
+
+    for section in image.get_sections().iter().filter(|section| !section.is_executable()) {
+        let end = section.get_address() + section.get_data().len() as u64;
+        let mut address = section.get_address();
+
+        while address < end {
+            match string_at(image, address) {
+                Some(text) => {
+                    address += text.size as u64;
+                    strings.insert(text.address, text);
+                },
+                None => address += 1
+            }
+        }
+    }
+
+    strings
+}
+
+// ----------------------------------------
+// references
+// ----------------------------------------
+
+/// # where each string is used
+/// the instructions that refer to somewhere in it, by the address of the string
+pub fn find_references(xrefs: &Xrefs, strings: &StringMap) -> BTreeMap<u64, BTreeSet<u64>> {
+    let mut references: BTreeMap<u64, BTreeSet<u64>> = BTreeMap::new();
+
+    for (address, text) in strings.iter() {
+        let instructions: BTreeSet<u64> = xrefs.references_within(*address, *address + text.size as u64)
+            .filter(|(_, reference)| reference.is_code())
+            .map(|(_, reference)| reference.get_from())
+            .collect();
+
+        if !instructions.is_empty() {
+            references.insert(*address, instructions);
+        }
+    }
+
+    references
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::callgraph::CallGraph;
+    use crate::instructions::{ABIRegister::*, InstructionType};
+
+    fn image() -> Image {
+        let mut data = b"hello world\n\0abc\0\0".to_vec();
+        data.extend("wide text\0".encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+        data.extend(b"\x01\x02\x03\x04tail\0");
+
+        let mut image = Image::new();
+        image.add_section(".text", 0x1000, vec![0x13, 0, 0, 0], true);
+        image.add_section(".rodata", 0x2000, data, false);
+        image
+    }
+
+    #[test]
+    fn test_find_strings() {
+        let strings = find_strings(&image());
+        let strings: Vec<(u64, &str, bool)> = strings.values()
+            .map(|text| (text.get_address(), text.get_text(), text.is_wide()))
+            .collect();
+
+        // "abc" is too short, and the bytes before "tail" aren't text
+        assert_eq!(strings, [(0x2000, "hello world\n", false), (0x2012, "wide text", true), (0x202a, "tail", false)]);
+    }
+
+    #[test]
+    fn test_string_at() {
+        let image = image();
+
+        // partway into some text is still text, but code never is
+        assert_eq!(string_at(&image, 0x2006).map(|text| text.get_size()), Some(7));
+        assert_eq!(string_at(&image, 0x2012).map(|text| text.to_expression().to_string()), Some("u\"wide text\"".to_string()));
+        assert_eq!(string_at(&image, 0x1000), None);
+    }
+
+    #[test]
+    fn test_references() {
+        // the address is built in two instructions, and it's the second that makes it
+        let instructions = [
+            (0x1000, InstructionType::U { name: "lui", rd: a0, imm: 0x2 }),
+            (0x1004, InstructionType::I { name: "addi", rd: a0, rs1: a0, imm: 0x6 }),
+            (0x1008, InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 })
+        ].into_iter().collect();
+        let graph = CallGraph::new(&instructions, &BTreeMap::from([(0x1000, "f".to_string())]), Some(0x1000), &BTreeMap::new());
+
+        let image = image();
+        let references = find_references(&Xrefs::new(&graph, &image), &find_strings(&image));
+        assert_eq!(references, BTreeMap::from([(0x2000, BTreeSet::from([0x1004]))]));
+    }
+}
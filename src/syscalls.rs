@@ -0,0 +1,554 @@
+//! # linux system calls
+//! on riscv linux the number of a system call goes in `a7`, its arguments in `a0` to `a5`, and what it gives back comes in `a0`
+//!
+//! every `ecall` is passed all of those as if it were a call to `syscall`, so they're propagated into it along with everything else,
+//! then once `a7` has been found to be a constant it's named after the call it is and only passed what that takes
+
+use phf::phf_map;
+
+use crate::ast::{Declaration, Prototype};
+use crate::constants::propagate_constants;
+use crate::decompilation::SectionMap;
+use crate::image::Image;
+use crate::instructions::ABIRegister;
+use crate::ir::{Expression, Statement, Type, Variable};
+
+/// the registers a system call is passed, after its number in `a7`
+const REGISTERS: [ABIRegister; 6] = [ABIRegister::a0, ABIRegister::a1, ABIRegister::a2, ABIRegister::a3, ABIRegister::a4, ABIRegister::a5];
+
+/// everything an `ecall` might read, its number first
+pub const PASSED: [ABIRegister; 7] = [ABIRegister::a7, ABIRegister::a0, ABIRegister::a1, ABIRegister::a2, ABIRegister::a3, ABIRegister::a4, ABIRegister::a5];
+
+/// what every system call that isn't known yet goes to, as it does in libc
+const FALLBACK: &str = "syscall";
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Syscall {
+    pub name: &'static str,
+    pub arguments: usize
+}
+
+impl Syscall {
+    /// # what it's declared as, when the c library doesn't declare it
+    /// every argument is a whole register, as is what comes back, apart from leaving, which doesn't come back at all
+    pub fn prototype(&self) -> Prototype {
+        let register = Type::Int { size: 8, signed: true };
+
+        Prototype {
+            name: self.name.to_string(),
+            parameters: Some(REGISTERS[..self.arguments].iter().map(|r| Declaration::new(register.clone(), r)).collect()),
+            returns: (!self.leaves()).then_some(register),
+            variadic: false
+        }
+    }
+
+    /// whether it's one that never comes back, as it leaves the thread or the whole program
+    pub fn leaves(&self) -> bool {
+        matches!(self.name, "exit" | "exit_group")
+    }
+
+    /// find a system call by its name
+    pub fn named(name: &str) -> Option<&'static Syscall> {
+        SYSCALLS.values().find(|syscall| syscall.name == name)
+    }
+}
+
+/// # the system calls on riscv64 linux
+/// from the generic `unistd.h`, which riscv uses with none of the older calls it leaves out, like `open` and `renameat`
+pub static SYSCALLS: phf::Map<u64, Syscall> = phf_map! {
+    0u64 => Syscall { name: "io_setup", arguments: 2 },
+    1u64 => Syscall { name: "io_destroy", arguments: 1 },
+    2u64 => Syscall { name: "io_submit", arguments: 3 },
+    3u64 => Syscall { name: "io_cancel", arguments: 3 },
+    4u64 => Syscall { name: "io_getevents", arguments: 5 },
+    5u64 => Syscall { name: "setxattr", arguments: 5 },
+    6u64 => Syscall { name: "lsetxattr", arguments: 5 },
+    7u64 => Syscall { name: "fsetxattr", arguments: 5 },
+    8u64 => Syscall { name: "getxattr", arguments: 4 },
+    9u64 => Syscall { name: "lgetxattr", arguments: 4 },
+    10u64 => Syscall { name: "fgetxattr", arguments: 4 },
+    11u64 => Syscall { name: "listxattr", arguments: 3 },
+    12u64 => Syscall { name: "llistxattr", arguments: 3 },
+    13u64 => Syscall { name: "flistxattr", arguments: 3 },
+    14u64 => Syscall { name: "removexattr", arguments: 2 },
+    15u64 => Syscall { name: "lremovexattr", arguments: 2 },
+    16u64 => Syscall { name: "fremovexattr", arguments: 2 },
+    17u64 => Syscall { name: "getcwd", arguments: 2 },
+    18u64 => Syscall { name: "lookup_dcookie", arguments: 3 },
+    19u64 => Syscall { name: "eventfd2", arguments: 2 },
+    20u64 => Syscall { name: "epoll_create1", arguments: 1 },
+    21u64 => Syscall { name: "epoll_ctl", arguments: 4 },
+    22u64 => Syscall { name: "epoll_pwait", arguments: 6 },
+    23u64 => Syscall { name: "dup", arguments: 1 },
+    24u64 => Syscall { name: "dup3", arguments: 3 },
+    25u64 => Syscall { name: "fcntl", arguments: 3 },
+    26u64 => Syscall { name: "inotify_init1", arguments: 1 },
+    27u64 => Syscall { name: "inotify_add_watch", arguments: 3 },
+    28u64 => Syscall { name: "inotify_rm_watch", arguments: 2 },
+    29u64 => Syscall { name: "ioctl", arguments: 3 },
+    30u64 => Syscall { name: "ioprio_set", arguments: 3 },
+    31u64 => Syscall { name: "ioprio_get", arguments: 2 },
+    32u64 => Syscall { name: "flock", arguments: 2 },
+    33u64 => Syscall { name: "mknodat", arguments: 4 },
+    34u64 => Syscall { name: "mkdirat", arguments: 3 },
+    35u64 => Syscall { name: "unlinkat", arguments: 3 },
+    36u64 => Syscall { name: "symlinkat", arguments: 3 },
+    37u64 => Syscall { name: "linkat", arguments: 5 },
+    39u64 => Syscall { name: "umount2", arguments: 2 },
+    40u64 => Syscall { name: "mount", arguments: 5 },
+    41u64 => Syscall { name: "pivot_root", arguments: 2 },
+    42u64 => Syscall { name: "nfsservctl", arguments: 3 },
+    43u64 => Syscall { name: "statfs", arguments: 2 },
+    44u64 => Syscall { name: "fstatfs", arguments: 2 },
+    45u64 => Syscall { name: "truncate", arguments: 2 },
+    46u64 => Syscall { name: "ftruncate", arguments: 2 },
+    47u64 => Syscall { name: "fallocate", arguments: 4 },
+    48u64 => Syscall { name: "faccessat", arguments: 3 },
+    49u64 => Syscall { name: "chdir", arguments: 1 },
+    50u64 => Syscall { name: "fchdir", arguments: 1 },
+    51u64 => Syscall { name: "chroot", arguments: 1 },
+    52u64 => Syscall { name: "fchmod", arguments: 2 },
+    53u64 => Syscall { name: "fchmodat", arguments: 3 },
+    54u64 => Syscall { name: "fchownat", arguments: 5 },
+    55u64 => Syscall { name: "fchown", arguments: 3 },
+    56u64 => Syscall { name: "openat", arguments: 4 },
+    57u64 => Syscall { name: "close", arguments: 1 },
+    58u64 => Syscall { name: "vhangup", arguments: 0 },
+    59u64 => Syscall { name: "pipe2", arguments: 2 },
+    60u64 => Syscall { name: "quotactl", arguments: 4 },
+    61u64 => Syscall { name: "getdents64", arguments: 3 },
+    62u64 => Syscall { name: "lseek", arguments: 3 },
+    63u64 => Syscall { name: "read", arguments: 3 },
+    64u64 => Syscall { name: "write", arguments: 3 },
+    65u64 => Syscall { name: "readv", arguments: 3 },
+    66u64 => Syscall { name: "writev", arguments: 3 },
+    67u64 => Syscall { name: "pread64", arguments: 4 },
+    68u64 => Syscall { name: "pwrite64", arguments: 4 },
+    69u64 => Syscall { name: "preadv", arguments: 5 },
+    70u64 => Syscall { name: "pwritev", arguments: 5 },
+    71u64 => Syscall { name: "sendfile", arguments: 4 },
+    72u64 => Syscall { name: "pselect6", arguments: 6 },
+    73u64 => Syscall { name: "ppoll", arguments: 5 },
+    74u64 => Syscall { name: "signalfd4", arguments: 4 },
+    75u64 => Syscall { name: "vmsplice", arguments: 4 },
+    76u64 => Syscall { name: "splice", arguments: 6 },
+    77u64 => Syscall { name: "tee", arguments: 4 },
+    78u64 => Syscall { name: "readlinkat", arguments: 4 },
+    79u64 => Syscall { name: "newfstatat", arguments: 4 },
+    80u64 => Syscall { name: "fstat", arguments: 2 },
+    81u64 => Syscall { name: "sync", arguments: 0 },
+    82u64 => Syscall { name: "fsync", arguments: 1 },
+    83u64 => Syscall { name: "fdatasync", arguments: 1 },
+    84u64 => Syscall { name: "sync_file_range", arguments: 4 },
+    85u64 => Syscall { name: "timerfd_create", arguments: 2 },
+    86u64 => Syscall { name: "timerfd_settime", arguments: 4 },
+    87u64 => Syscall { name: "timerfd_gettime", arguments: 2 },
+    88u64 => Syscall { name: "utimensat", arguments: 4 },
+    89u64 => Syscall { name: "acct", arguments: 1 },
+    90u64 => Syscall { name: "capget", arguments: 2 },
+    91u64 => Syscall { name: "capset", arguments: 2 },
+    92u64 => Syscall { name: "personality", arguments: 1 },
+    93u64 => Syscall { name: "exit", arguments: 1 },
+    94u64 => Syscall { name: "exit_group", arguments: 1 },
+    95u64 => Syscall { name: "waitid", arguments: 5 },
+    96u64 => Syscall { name: "set_tid_address", arguments: 1 },
+    97u64 => Syscall { name: "unshare", arguments: 1 },
+    98u64 => Syscall { name: "futex", arguments: 6 },
+    99u64 => Syscall { name: "set_robust_list", arguments: 2 },
+    100u64 => Syscall { name: "get_robust_list", arguments: 3 },
+    101u64 => Syscall { name: "nanosleep", arguments: 2 },
+    102u64 => Syscall { name: "getitimer", arguments: 2 },
+    103u64 => Syscall { name: "setitimer", arguments: 3 },
+    104u64 => Syscall { name: "kexec_load", arguments: 4 },
+    105u64 => Syscall { name: "init_module", arguments: 3 },
+    106u64 => Syscall { name: "delete_module", arguments: 2 },
+    107u64 => Syscall { name: "timer_create", arguments: 3 },
+    108u64 => Syscall { name: "timer_gettime", arguments: 2 },
+    109u64 => Syscall { name: "timer_getoverrun", arguments: 1 },
+    110u64 => Syscall { name: "timer_settime", arguments: 4 },
+    111u64 => Syscall { name: "timer_delete", arguments: 1 },
+    112u64 => Syscall { name: "clock_settime", arguments: 2 },
+    113u64 => Syscall { name: "clock_gettime", arguments: 2 },
+    114u64 => Syscall { name: "clock_getres", arguments: 2 },
+    115u64 => Syscall { name: "clock_nanosleep", arguments: 4 },
+    116u64 => Syscall { name: "syslog", arguments: 3 },
+    117u64 => Syscall { name: "ptrace", arguments: 4 },
+    118u64 => Syscall { name: "sched_setparam", arguments: 2 },
+    119u64 => Syscall { name: "sched_setscheduler", arguments: 3 },
+    120u64 => Syscall { name: "sched_getscheduler", arguments: 1 },
+    121u64 => Syscall { name: "sched_getparam", arguments: 2 },
+    122u64 => Syscall { name: "sched_setaffinity", arguments: 3 },
+    123u64 => Syscall { name: "sched_getaffinity", arguments: 3 },
+    124u64 => Syscall { name: "sched_yield", arguments: 0 },
+    125u64 => Syscall { name: "sched_get_priority_max", arguments: 1 },
+    126u64 => Syscall { name: "sched_get_priority_min", arguments: 1 },
+    127u64 => Syscall { name: "sched_rr_get_interval", arguments: 2 },
+    128u64 => Syscall { name: "restart_syscall", arguments: 0 },
+    129u64 => Syscall { name: "kill", arguments: 2 },
+    130u64 => Syscall { name: "tkill", arguments: 2 },
+    131u64 => Syscall { name: "tgkill", arguments: 3 },
+    132u64 => Syscall { name: "sigaltstack", arguments: 2 },
+    133u64 => Syscall { name: "rt_sigsuspend", arguments: 2 },
+    134u64 => Syscall { name: "rt_sigaction", arguments: 4 },
+    135u64 => Syscall { name: "rt_sigprocmask", arguments: 4 },
+    136u64 => Syscall { name: "rt_sigpending", arguments: 2 },
+    137u64 => Syscall { name: "rt_sigtimedwait", arguments: 4 },
+    138u64 => Syscall { name: "rt_sigqueueinfo", arguments: 3 },
+    139u64 => Syscall { name: "rt_sigreturn", arguments: 0 },
+    140u64 => Syscall { name: "setpriority", arguments: 3 },
+    141u64 => Syscall { name: "getpriority", arguments: 2 },
+    142u64 => Syscall { name: "reboot", arguments: 4 },
+    143u64 => Syscall { name: "setregid", arguments: 2 },
+    144u64 => Syscall { name: "setgid", arguments: 1 },
+    145u64 => Syscall { name: "setreuid", arguments: 2 },
+    146u64 => Syscall { name: "setuid", arguments: 1 },
+    147u64 => Syscall { name: "setresuid", arguments: 3 },
+    148u64 => Syscall { name: "getresuid", arguments: 3 },
+    149u64 => Syscall { name: "setresgid", arguments: 3 },
+    150u64 => Syscall { name: "getresgid", arguments: 3 },
+    151u64 => Syscall { name: "setfsuid", arguments: 1 },
+    152u64 => Syscall { name: "setfsgid", arguments: 1 },
+    153u64 => Syscall { name: "times", arguments: 1 },
+    154u64 => Syscall { name: "setpgid", arguments: 2 },
+    155u64 => Syscall { name: "getpgid", arguments: 1 },
+    156u64 => Syscall { name: "getsid", arguments: 1 },
+    157u64 => Syscall { name: "setsid", arguments: 0 },
+    158u64 => Syscall { name: "getgroups", arguments: 2 },
+    159u64 => Syscall { name: "setgroups", arguments: 2 },
+    160u64 => Syscall { name: "uname", arguments: 1 },
+    161u64 => Syscall { name: "sethostname", arguments: 2 },
+    162u64 => Syscall { name: "setdomainname", arguments: 2 },
+    163u64 => Syscall { name: "getrlimit", arguments: 2 },
+    164u64 => Syscall { name: "setrlimit", arguments: 2 },
+    165u64 => Syscall { name: "getrusage", arguments: 2 },
+    166u64 => Syscall { name: "umask", arguments: 1 },
+    167u64 => Syscall { name: "prctl", arguments: 5 },
+    168u64 => Syscall { name: "getcpu", arguments: 3 },
+    169u64 => Syscall { name: "gettimeofday", arguments: 2 },
+    170u64 => Syscall { name: "settimeofday", arguments: 2 },
+    171u64 => Syscall { name: "adjtimex", arguments: 1 },
+    172u64 => Syscall { name: "getpid", arguments: 0 },
+    173u64 => Syscall { name: "getppid", arguments: 0 },
+    174u64 => Syscall { name: "getuid", arguments: 0 },
+    175u64 => Syscall { name: "geteuid", arguments: 0 },
+    176u64 => Syscall { name: "getgid", arguments: 0 },
+    177u64 => Syscall { name: "getegid", arguments: 0 },
+    178u64 => Syscall { name: "gettid", arguments: 0 },
+    179u64 => Syscall { name: "sysinfo", arguments: 1 },
+    180u64 => Syscall { name: "mq_open", arguments: 4 },
+    181u64 => Syscall { name: "mq_unlink", arguments: 1 },
+    182u64 => Syscall { name: "mq_timedsend", arguments: 5 },
+    183u64 => Syscall { name: "mq_timedreceive", arguments: 5 },
+    184u64 => Syscall { name: "mq_notify", arguments: 2 },
+    185u64 => Syscall { name: "mq_getsetattr", arguments: 3 },
+    186u64 => Syscall { name: "msgget", arguments: 2 },
+    187u64 => Syscall { name: "msgctl", arguments: 3 },
+    188u64 => Syscall { name: "msgrcv", arguments: 5 },
+    189u64 => Syscall { name: "msgsnd", arguments: 4 },
+    190u64 => Syscall { name: "semget", arguments: 3 },
+    191u64 => Syscall { name: "semctl", arguments: 4 },
+    192u64 => Syscall { name: "semtimedop", arguments: 4 },
+    193u64 => Syscall { name: "semop", arguments: 3 },
+    194u64 => Syscall { name: "shmget", arguments: 3 },
+    195u64 => Syscall { name: "shmctl", arguments: 3 },
+    196u64 => Syscall { name: "shmat", arguments: 3 },
+    197u64 => Syscall { name: "shmdt", arguments: 1 },
+    198u64 => Syscall { name: "socket", arguments: 3 },
+    199u64 => Syscall { name: "socketpair", arguments: 4 },
+    200u64 => Syscall { name: "bind", arguments: 3 },
+    201u64 => Syscall { name: "listen", arguments: 2 },
+    202u64 => Syscall { name: "accept", arguments: 3 },
+    203u64 => Syscall { name: "connect", arguments: 3 },
+    204u64 => Syscall { name: "getsockname", arguments: 3 },
+    205u64 => Syscall { name: "getpeername", arguments: 3 },
+    206u64 => Syscall { name: "sendto", arguments: 6 },
+    207u64 => Syscall { name: "recvfrom", arguments: 6 },
+    208u64 => Syscall { name: "setsockopt", arguments: 5 },
+    209u64 => Syscall { name: "getsockopt", arguments: 5 },
+    210u64 => Syscall { name: "shutdown", arguments: 2 },
+    211u64 => Syscall { name: "sendmsg", arguments: 3 },
+    212u64 => Syscall { name: "recvmsg", arguments: 3 },
+    213u64 => Syscall { name: "readahead", arguments: 3 },
+    214u64 => Syscall { name: "brk", arguments: 1 },
+    215u64 => Syscall { name: "munmap", arguments: 2 },
+    216u64 => Syscall { name: "mremap", arguments: 5 },
+    217u64 => Syscall { name: "add_key", arguments: 5 },
+    218u64 => Syscall { name: "request_key", arguments: 4 },
+    219u64 => Syscall { name: "keyctl", arguments: 5 },
+    220u64 => Syscall { name: "clone", arguments: 5 },
+    221u64 => Syscall { name: "execve", arguments: 3 },
+    222u64 => Syscall { name: "mmap", arguments: 6 },
+    223u64 => Syscall { name: "fadvise64", arguments: 4 },
+    224u64 => Syscall { name: "swapon", arguments: 2 },
+    225u64 => Syscall { name: "swapoff", arguments: 1 },
+    226u64 => Syscall { name: "mprotect", arguments: 3 },
+    227u64 => Syscall { name: "msync", arguments: 3 },
+    228u64 => Syscall { name: "mlock", arguments: 2 },
+    229u64 => Syscall { name: "munlock", arguments: 2 },
+    230u64 => Syscall { name: "mlockall", arguments: 1 },
+    231u64 => Syscall { name: "munlockall", arguments: 0 },
+    232u64 => Syscall { name: "mincore", arguments: 3 },
+    233u64 => Syscall { name: "madvise", arguments: 3 },
+    234u64 => Syscall { name: "remap_file_pages", arguments: 5 },
+    235u64 => Syscall { name: "mbind", arguments: 6 },
+    236u64 => Syscall { name: "get_mempolicy", arguments: 5 },
+    237u64 => Syscall { name: "set_mempolicy", arguments: 3 },
+    238u64 => Syscall { name: "migrate_pages", arguments: 4 },
+    239u64 => Syscall { name: "move_pages", arguments: 6 },
+    240u64 => Syscall { name: "rt_tgsigqueueinfo", arguments: 4 },
+    241u64 => Syscall { name: "perf_event_open", arguments: 5 },
+    242u64 => Syscall { name: "accept4", arguments: 4 },
+    243u64 => Syscall { name: "recvmmsg", arguments: 5 },
+    258u64 => Syscall { name: "riscv_hwprobe", arguments: 5 },
+    259u64 => Syscall { name: "riscv_flush_icache", arguments: 3 },
+    260u64 => Syscall { name: "wait4", arguments: 4 },
+    261u64 => Syscall { name: "prlimit64", arguments: 4 },
+    262u64 => Syscall { name: "fanotify_init", arguments: 2 },
+    263u64 => Syscall { name: "fanotify_mark", arguments: 5 },
+    264u64 => Syscall { name: "name_to_handle_at", arguments: 5 },
+    265u64 => Syscall { name: "open_by_handle_at", arguments: 3 },
+    266u64 => Syscall { name: "clock_adjtime", arguments: 2 },
+    267u64 => Syscall { name: "syncfs", arguments: 1 },
+    268u64 => Syscall { name: "setns", arguments: 2 },
+    269u64 => Syscall { name: "sendmmsg", arguments: 4 },
+    270u64 => Syscall { name: "process_vm_readv", arguments: 6 },
+    271u64 => Syscall { name: "process_vm_writev", arguments: 6 },
+    272u64 => Syscall { name: "kcmp", arguments: 5 },
+    273u64 => Syscall { name: "finit_module", arguments: 3 },
+    274u64 => Syscall { name: "sched_setattr", arguments: 3 },
+    275u64 => Syscall { name: "sched_getattr", arguments: 4 },
+    276u64 => Syscall { name: "renameat2", arguments: 5 },
+    277u64 => Syscall { name: "seccomp", arguments: 3 },
+    278u64 => Syscall { name: "getrandom", arguments: 3 },
+    279u64 => Syscall { name: "memfd_create", arguments: 2 },
+    280u64 => Syscall { name: "bpf", arguments: 3 },
+    281u64 => Syscall { name: "execveat", arguments: 5 },
+    282u64 => Syscall { name: "userfaultfd", arguments: 1 },
+    283u64 => Syscall { name: "membarrier", arguments: 3 },
+    284u64 => Syscall { name: "mlock2", arguments: 3 },
+    285u64 => Syscall { name: "copy_file_range", arguments: 6 },
+    286u64 => Syscall { name: "preadv2", arguments: 6 },
+    287u64 => Syscall { name: "pwritev2", arguments: 6 },
+    288u64 => Syscall { name: "pkey_mprotect", arguments: 4 },
+    289u64 => Syscall { name: "pkey_alloc", arguments: 2 },
+    290u64 => Syscall { name: "pkey_free", arguments: 1 },
+    291u64 => Syscall { name: "statx", arguments: 5 },
+    292u64 => Syscall { name: "io_pgetevents", arguments: 6 },
+    293u64 => Syscall { name: "rseq", arguments: 4 },
+    294u64 => Syscall { name: "kexec_file_load", arguments: 5 },
+    424u64 => Syscall { name: "pidfd_send_signal", arguments: 4 },
+    425u64 => Syscall { name: "io_uring_setup", arguments: 2 },
+    426u64 => Syscall { name: "io_uring_enter", arguments: 6 },
+    427u64 => Syscall { name: "io_uring_register", arguments: 4 },
+    428u64 => Syscall { name: "open_tree", arguments: 3 },
+    429u64 => Syscall { name: "move_mount", arguments: 5 },
+    430u64 => Syscall { name: "fsopen", arguments: 2 },
+    431u64 => Syscall { name: "fsconfig", arguments: 5 },
+    432u64 => Syscall { name: "fsmount", arguments: 3 },
+    433u64 => Syscall { name: "fspick", arguments: 3 },
+    434u64 => Syscall { name: "pidfd_open", arguments: 2 },
+    435u64 => Syscall { name: "clone3", arguments: 2 },
+    436u64 => Syscall { name: "close_range", arguments: 3 },
+    437u64 => Syscall { name: "openat2", arguments: 4 },
+    438u64 => Syscall { name: "pidfd_getfd", arguments: 3 },
+    439u64 => Syscall { name: "faccessat2", arguments: 4 },
+    440u64 => Syscall { name: "process_madvise", arguments: 5 },
+    441u64 => Syscall { name: "epoll_pwait2", arguments: 6 },
+    442u64 => Syscall { name: "mount_setattr", arguments: 5 },
+    443u64 => Syscall { name: "quotactl_fd", arguments: 4 },
+    444u64 => Syscall { name: "landlock_create_ruleset", arguments: 3 },
+    445u64 => Syscall { name: "landlock_add_rule", arguments: 4 },
+    446u64 => Syscall { name: "landlock_restrict_self", arguments: 2 },
+    447u64 => Syscall { name: "memfd_secret", arguments: 1 },
+    448u64 => Syscall { name: "process_mrelease", arguments: 2 },
+    449u64 => Syscall { name: "futex_waitv", arguments: 5 },
+    450u64 => Syscall { name: "set_mempolicy_home_node", arguments: 4 },
+    451u64 => Syscall { name: "cachestat", arguments: 4 },
+    452u64 => Syscall { name: "fchmodat2", arguments: 4 },
+    453u64 => Syscall { name: "map_shadow_stack", arguments: 3 },
+    454u64 => Syscall { name: "futex_wake", arguments: 4 },
+    455u64 => Syscall { name: "futex_wait", arguments: 6 },
+    456u64 => Syscall { name: "futex_requeue", arguments: 4 },
+    457u64 => Syscall { name: "statmount", arguments: 4 },
+    458u64 => Syscall { name: "listmount", arguments: 4 },
+    459u64 => Syscall { name: "lsm_get_self_attr", arguments: 4 },
+    460u64 => Syscall { name: "lsm_set_self_attr", arguments: 4 },
+    461u64 => Syscall { name: "lsm_list_modules", arguments: 3 },
+    462u64 => Syscall { name: "mseal", arguments: 3 },
+};
+
+// ----------------------------------------
+// resolving
+// ----------------------------------------
+
+/// # pass system calls what they might read
+/// every `ecall` becomes a call to `syscall` with the number and all six arguments, giving back `a0`
+/// this goes before anything is propagated, so the number can be
+pub fn pass_syscall_arguments(sections: &mut SectionMap) {
+    let register = |r: ABIRegister| Expression::Variable(Variable::Register(r));
+
+    for section in sections.values_mut() {
+        let mut statements = section.get_statements().clone();
+
+        for statement in statements.values_mut().flatten() {
+            if !matches!(statement, Statement::Intrinsic { name: "ecall", .. }) {
+                continue;
+            }
+
+            let args = PASSED.into_iter().map(register).collect();
+            *statement = Statement::Call {
+                target: Expression::Symbol { name: FALLBACK.to_string(), offset: 0 },
+                args: Some(args),
+                dst: Some(Variable::Register(ABIRegister::a0))
+            };
+        }
+
+        section.set_statements(statements);
+    }
+}
+
+/// # name the system calls whose numbers are known
+/// once constants have been propagated, each call to `syscall` with a number in the table becomes a call to it by name,
+/// with only as many arguments as it takes
+/// gives whether any were named
+pub fn name_syscalls(sections: &mut SectionMap) -> bool {
+    let mut named = false;
+
+    for section in sections.values_mut() {
+        let mut statements = section.get_statements().clone();
+
+        for statement in statements.values_mut().flatten() {
+            let Statement::Call { target: Expression::Symbol { name, offset: 0 }, args: Some(args), dst } = statement else {
+                continue;
+            };
+            if name != FALLBACK {
+                continue;
+            }
+            let Some(Expression::Constant(number)) = args.first() else {
+                continue;
+            };
+            let Some(syscall) = SYSCALLS.get(&(*number as u64)) else {
+                continue;
+            };
+
+            *name = syscall.name.to_string();
+            *args = args[1..=syscall.arguments].to_vec();
+            named = true;
+
+            // nothing comes back from leaving
+            if syscall.leaves() {
+                *dst = None;
+            }
+        }
+
+        section.set_statements(statements);
+    }
+
+    named
+}
+
+/// # work out the system calls in a function on their own
+/// for anything that has to know what they read before the rest of the function is simplified, such as its signature,
+/// only constants are propagated, which is all it takes to find where a number's put straight into `a7`
+pub fn resolve_syscalls(sections: &mut SectionMap, image: &Image) {
+    pass_syscall_arguments(sections);
+    propagate_constants(sections, image);
+    name_syscalls(sections);
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixtures::{addi, ecall, sections};
+    use crate::instructions::InstructionType;
+    use crate::propagation::simplify;
+    use ABIRegister::*;
+
+    /// the calls left once the system calls have been worked out
+    fn calls(instructions: Vec<InstructionType>) -> Vec<String> {
+        let mut sections = sections(instructions);
+
+        pass_syscall_arguments(&mut sections);
+        simplify(&mut sections, &Image::new());
+        name_syscalls(&mut sections);
+
+        sections.values()
+            .flat_map(|section| section.get_statements().values().flatten())
+            .filter(|statement| matches!(statement, Statement::Call { .. }))
+            .map(|statement| statement.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_table() {
+        assert_eq!(SYSCALLS[&64], Syscall { name: "write", arguments: 3 });
+        assert_eq!(SYSCALLS[&94], Syscall { name: "exit_group", arguments: 1 });
+        assert_eq!(SYSCALLS[&222], Syscall { name: "mmap", arguments: 6 });
+        assert!(!SYSCALLS.contains_key(&1024));
+    }
+
+    #[test]
+    fn test_named() {
+        // a7 is set in one block and the call made in the next
+        let named = calls(vec![
+            addi(a7, zero, 64),
+            addi(a0, zero, 1),
+            addi(a2, zero, 13),
+            InstructionType::B { name: "beq", rs1: zero, rs2: zero, imm: 4 },
+            ecall(),
+            addi(a7, zero, 94),
+            addi(a0, zero, 0),
+            ecall(),
+            InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 }
+        ]);
+
+        assert_eq!(named, ["write(1, a1, 13)", "exit_group(0)"]);
+    }
+
+    #[test]
+    fn test_unknown() {
+        // a number that's not known, or not known until the function's called, is left to syscall
+        let unknown = calls(vec![
+            addi(a7, zero, 1000),
+            ecall(),
+            addi(a7, a0, 0),
+            ecall(),
+            InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 }
+        ]);
+
+        assert_eq!(unknown, ["a0 = syscall(1000, a0, a1, a2, a3, a4, a5)", "a0 = syscall(a0, a0, a1, a2, a3, a4, a5)"]);
+    }
+
+    #[test]
+    fn test_hello() {
+        let bytes = crate::read_compiled("executables/hello");
+        let image = crate::load_image(bytes.clone()).unwrap();
+        let graph = crate::generate_call_graph(bytes).unwrap();
+        let signatures = crate::signatures::infer_signatures(&graph, &crate::headers::PrototypeMap::new());
+
+        let start = graph.get_functions().values().find(|function| function.get_name() == "_start").unwrap();
+        let lines = crate::ast::Style::default().print(&crate::decompilation::decompile(start.cfg(), &image, &signatures, &Default::default()));
+
+        // the message after the code isn't any of it, and only what the calls read is left
+        assert_eq!(lines, [
+            "#include \"asha.h\"",
+            "",
+            "void exit(int32_t status);",
+            "int64_t write(int32_t fd, const void *buf, uint64_t count);",
+            "extern uint8_t hello[];",
+            "",
+            "void _start(void) {",
+            "\twrite(1, hello, 13);",
+            "\texit(13);",
+            "}"
+        ]);
+    }
+}
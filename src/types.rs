@@ -0,0 +1,946 @@
+//! # type inference
+//! gives every variable a c type from what the instructions using it say about it:
+//! how wide the loads and the `*w` instructions are, whether comparisons, division and shifts are signed,
+//! and what's used as an address, along with how wide the accesses through it are
+//!
+//! each of those is a constraint on a variable, and a copy has to have the same type as what it copies,
+//! so variables are grouped by copying and each group gets the one type that satisfies all of them
+//! once everything's typed, casts are only kept where the types don't already say the same thing,
+//! and pointer arithmetic goes in elements rather than bytes, the way it does in c
+//!
+//! a pointer only ever accessed at fixed offsets past it points at a struct with a field at each of them,
+//! and one indexed by a variable or stepped through points into an array,
+//! so those read as `p->field_8` and `arr[i]` rather than as arithmetic on addresses
+//!
+//! whatever's passed to or given back by a function with a declared prototype has the type it's declared with,
+//! as do the parameters of one being decompiled, which goes over anything the instructions say
+//!
+//! only the integer instructions are decoded, so nothing ends up as a float
+
+use std::collections::BTreeMap;
+
+use crate::ast::{Declaration, Prototype};
+use crate::decompilation::SectionMap;
+use crate::ir::{BinaryOp, Expression, Statement, Type, Variable};
+use crate::signatures::{Returns, Signature, SignatureMap};
+use crate::ssa::Groups;
+
+/// what a register holds when nothing says otherwise
+const REGISTER: Type = Type::Int { size: 8, signed: true };
+
+/// what a pointer points at when it's used for things of different sizes, so that it can go along in bytes
+const BYTE: Type = Type::Int { size: 1, signed: false };
+
+// ----------------------------------------
+// constraints
+// ----------------------------------------
+
+/// # everything seen of a group of variables
+/// the widest it's been used as, and it's only unsigned if nothing has used it as signed
+#[derive(Clone, Debug, Default)]
+struct Constraints {
+    size: Option<u8>,
+    signed: Option<bool>,
+    pointee: Option<Pointee>,
+    stepped: bool,              // moved along by a constant, as when going through an array
+    declared: Option<Type>      // the type a prototype gives it
+}
+
+/// what a pointer has been used to read and write, by how far from it
+#[derive(Clone, Debug, Default)]
+struct Pointee {
+    accesses: BTreeMap<i64, Access>,
+    indexed: bool               // accessed some variable distance along
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Access {
+    size: u8,
+    signed: Option<bool>,
+    mixed: bool                 // accessed with more than one width
+}
+
+/// unsigned only while nothing says signed
+fn join_signed(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    match (a, b) {
+        (Some(true), _) | (_, Some(true)) => Some(true),
+        (a, b) => a.or(b)
+    }
+}
+
+impl Access {
+    fn join(&mut self, other: &Access) {
+        self.mixed |= other.mixed || self.size != other.size;
+        self.size = self.size.max(other.size);
+        self.signed = join_signed(self.signed, other.signed);
+    }
+}
+
+impl Pointee {
+    /// # what it points at
+    /// something only ever accessed at fixed offsets past it is a struct, and anything else is an array of one type,
+    /// or of bytes when it's accessed with different widths
+    fn resolve(&self, structs: &mut BTreeMap<String, Struct>) -> Type {
+        let offsets = || self.accesses.keys();
+
+        if !self.indexed && offsets().any(|offset| *offset > 0) && offsets().all(|offset| *offset >= 0) {
+            let name = format!("struct_{}", structs.len());
+            structs.insert(name.clone(), Struct::from_accesses(&self.accesses));
+            return Type::Struct(name);
+        }
+
+        let mut accesses = self.accesses.values();
+        let Some(first) = accesses.next() else {
+            return BYTE;
+        };
+
+        if first.mixed || accesses.any(|access| access.mixed || access.size != first.size) {
+            BYTE
+        } else {
+            Type::Int { size: first.size, signed: first.signed.unwrap_or(true) }
+        }
+    }
+}
+
+impl Constraints {
+    fn width(&mut self, size: u8) {
+        self.size = Some(self.size.map_or(size, |s| s.max(size)));
+    }
+
+    fn sign(&mut self, signed: bool) {
+        self.signed = join_signed(self.signed, Some(signed));
+    }
+
+    fn access(&mut self, offset: i64, access: Access, indexed: bool) {
+        let pointee = self.pointee.get_or_insert_with(Pointee::default);
+        pointee.indexed |= indexed;
+        pointee.accesses.entry(offset)
+            .and_modify(|existing| existing.join(&access))
+            .or_insert(access);
+    }
+
+    fn join(&mut self, other: &Constraints) {
+        if let Some(size) = other.size {
+            self.width(size);
+        }
+        self.signed = join_signed(self.signed, other.signed);
+        self.stepped |= other.stepped;
+        if self.declared.is_none() {
+            self.declared = other.declared.clone();
+        }
+        if let Some(pointee) = &other.pointee {
+            for (offset, access) in pointee.accesses.iter() {
+                self.access(*offset, *access, pointee.indexed);
+            }
+        }
+    }
+
+    /// # the type that satisfies everything
+    /// anything used as an address is a pointer, whatever else it's been used as,
+    /// and anything stepped through is pointing into an array, unless it's been declared as something
+    fn resolve(&self, structs: &mut BTreeMap<String, Struct>) -> Type {
+        if let Some(declared) = &self.declared {
+            return declared.clone();
+        }
+
+        match &self.pointee {
+            Some(pointee) => {
+                let pointee = Pointee { indexed: pointee.indexed || self.stepped, ..pointee.clone() };
+                Type::Pointer(Box::new(pointee.resolve(structs)))
+            },
+            None => Type::Int { size: self.size.unwrap_or(8), signed: self.signed.unwrap_or(true) }
+        }
+    }
+}
+
+/// # the variable an address is based on
+/// along with how far past it the address is, and whether that's some variable distance further along,
+/// which is how arrays are indexed
+fn access_of(addr: &Expression) -> Option<(&Variable, i64, bool)> {
+    match addr {
+        Expression::Variable(variable) => Some((variable, 0, false)),
+        Expression::Binary(BinaryOp::Add, lhs, rhs) => match (&**lhs, &**rhs) {
+            (lhs, Expression::Constant(c)) => access_of(lhs).map(|(base, offset, indexed)| (base, offset.wrapping_add(*c), indexed)),
+            // an index that's been scaled is never the base
+            (Expression::Variable(base), _) => Some((base, 0, true)),
+            (_, Expression::Variable(base)) => Some((base, 0, true)),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// # the constraints of every variable in a function
+/// kept for each variable by itself, and only joined once every copy is known
+#[derive(Default)]
+struct Collector {
+    constraints: BTreeMap<Variable, Constraints>,
+    groups: Groups,
+    prototypes: BTreeMap<String, Prototype>     // of the functions called that have been declared
+}
+
+impl Collector {
+    fn of(&mut self, variable: &Variable) -> &mut Constraints {
+        self.constraints.entry(variable.clone()).or_default()
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Assign { dst, value } => {
+                self.of(dst);
+
+                match value {
+                    // a copy, or a step along from one
+                    Expression::Variable(src) => self.groups.union(dst, src),
+                    Expression::Binary(BinaryOp::Add | BinaryOp::Sub, lhs, rhs) if matches!(**rhs, Expression::Constant(_)) => {
+                        if let Expression::Variable(src) = &**lhs {
+                            self.groups.union(dst, src);
+                            self.of(dst).stepped = true;
+                        }
+                        self.of(dst).width(REGISTER.size());
+                    },
+                    // whatever else is worked out with the whole register, apart from a comparison's 0 or 1
+                    Expression::Binary(op, ..) if !matches!(op, BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Less | BinaryOp::LessUnsigned | BinaryOp::GreaterEqual | BinaryOp::GreaterEqualUnsigned) => self.of(dst).width(REGISTER.size()),
+                    Expression::Load { size, signed, .. } => {
+                        self.of(dst).width(*size);
+                        self.of(dst).sign(*signed);
+                    },
+                    Expression::Extend { bits, signed, .. } => {
+                        self.of(dst).width(bits / 8);
+                        self.of(dst).sign(*signed);
+                    },
+                    Expression::String { .. } => {
+                        let ty = Types::default().type_of(value);
+                        self.of(dst).declared = ty;
+                    },
+                    _ => {}
+                }
+            },
+            // storing only part of a variable says nothing about how wide the rest of it is, so only the address is looked at
+            Statement::Store { addr, size, .. } => {
+                if let Some((base, offset, indexed)) = access_of(addr) {
+                    self.of(base).access(offset, Access { size: *size, signed: None, mixed: false }, indexed);
+                }
+            },
+            Statement::Call { target: Expression::Symbol { name, offset: 0 }, args: Some(args), dst } if self.prototypes.contains_key(name) => {
+                let prototype = self.prototypes[name].clone();
+
+                for (arg, parameter) in args.iter().zip(prototype.parameters.iter().flatten()) {
+                    if let Expression::Variable(variable) = arg {
+                        self.of(variable).declared = Some(parameter.get_type().clone());
+                    }
+                }
+                if let (Some(dst), Some(returns)) = (dst, &prototype.returns) {
+                    self.of(dst).declared = Some(returns.clone());
+                }
+            },
+            _ => {}
+        }
+
+        if let Some(dst) = statement.get_def() {
+            self.of(dst);
+        }
+        for expr in statement.get_expressions() {
+            self.expression(expr);
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Variable(variable) => {
+                self.of(variable);
+            },
+            Expression::Binary(op, lhs, rhs) => self.binary(*op, lhs, rhs, REGISTER.size()),
+            Expression::Load { addr, size, signed } => {
+                if let Some((base, offset, indexed)) = access_of(addr) {
+                    self.of(base).access(offset, Access { size: *size, signed: Some(*signed), mixed: false }, indexed);
+                }
+                self.expression(addr);
+            },
+            Expression::Extend { value, bits, signed } => match &**value {
+                Expression::Variable(variable) => {
+                    self.of(variable).width(bits / 8);
+                    self.of(variable).sign(*signed);
+                },
+                // the `*w` instructions only use the low half of what they're given
+                Expression::Binary(op, lhs, rhs) => self.binary(*op, lhs, rhs, bits / 8),
+                value => self.expression(value)
+            },
+            Expression::Deref(addr) | Expression::Field { base: addr, .. } => self.expression(addr),
+            Expression::Cast { value, .. } => self.expression(value),
+            Expression::Constant(_) | Expression::Symbol { .. } | Expression::String { .. } => {}
+        }
+    }
+
+    /// # an operation done at a given width
+    /// whatever it works out with is that wide, as it uses all of it, and a comparison always uses the whole register
+    fn binary(&mut self, op: BinaryOp, lhs: &Expression, rhs: &Expression, size: u8) {
+        let signed = match op {
+            BinaryOp::Less | BinaryOp::GreaterEqual | BinaryOp::Div | BinaryOp::Rem => Some(true),
+            BinaryOp::LessUnsigned | BinaryOp::GreaterEqualUnsigned | BinaryOp::DivUnsigned | BinaryOp::RemUnsigned => Some(false),
+            _ => None
+        };
+        let shifted = match op {
+            BinaryOp::ShiftRightArithmetic => Some(true),
+            BinaryOp::ShiftRightLogical => Some(false),
+            _ => None
+        };
+
+        for (operand, signed) in [(lhs, signed.or(shifted)), (rhs, signed)] {
+            if let (Expression::Variable(variable), Some(signed)) = (operand, signed) {
+                self.of(variable).sign(signed);
+            }
+        }
+
+        // only the value is shifted, not what it's shifted by
+        let (operands, size) = match op {
+            BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Less | BinaryOp::LessUnsigned | BinaryOp::GreaterEqual | BinaryOp::GreaterEqualUnsigned => (vec![lhs, rhs], REGISTER.size()),
+            BinaryOp::ShiftLeft | BinaryOp::ShiftRightLogical | BinaryOp::ShiftRightArithmetic => (vec![lhs], size),
+            _ => (vec![lhs, rhs], size)
+        };
+        for operand in operands {
+            if let Expression::Variable(variable) = operand {
+                self.of(variable).width(size);
+            }
+        }
+
+        self.expression(lhs);
+        self.expression(rhs);
+    }
+
+    /// # join the constraints of each group
+    /// giving every variable its group's type, along with the structs any of them point at
+    fn solve(mut self) -> (BTreeMap<Variable, Type>, BTreeMap<String, Struct>) {
+        let mut joined: BTreeMap<Variable, Constraints> = BTreeMap::new();
+        let variables: Vec<Variable> = self.constraints.keys().cloned().collect();
+
+        for variable in variables.iter() {
+            let group = self.groups.find(variable);
+            joined.entry(group).or_default().join(&self.constraints[variable]);
+        }
+
+        let mut structs = BTreeMap::new();
+        let resolved: BTreeMap<Variable, Type> = joined.iter()
+            .map(|(group, constraints)| (group.clone(), constraints.resolve(&mut structs)))
+            .collect();
+
+        let types = variables.into_iter()
+            .map(|variable| {
+                let ty = resolved[&self.groups.find(&variable)].clone();
+                (variable, ty)
+            })
+            .collect();
+
+        (types, structs)
+    }
+}
+
+// ----------------------------------------
+// structs
+// ----------------------------------------
+
+/// new names for the fields of a function's structs, by the struct and the offset of the field
+pub type FieldNames = BTreeMap<(String, i64), String>;
+
+/// # a struct put together from the offsets it's accessed at
+/// fields are named after their offsets until they're renamed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Struct {
+    fields: BTreeMap<i64, Field>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    name: String,
+    ty: Type
+}
+
+impl Field {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_type(&self) -> &Type {
+        &self.ty
+    }
+}
+
+impl Struct {
+    /// a field for each offset, widest first, leaving out any that overlap the one before,
+    /// which can only be reached through a cast
+    fn from_accesses(accesses: &BTreeMap<i64, Access>) -> Self {
+        let mut fields = BTreeMap::new();
+        let mut end = 0;
+
+        for (offset, access) in accesses.iter() {
+            if *offset < end {
+                continue;
+            }
+
+            let ty = Type::Int { size: access.size, signed: access.signed.unwrap_or(true) };
+            fields.insert(*offset, Field { name: format!("field_{}", offset), ty });
+            end = offset + access.size as i64;
+        }
+
+        Struct { fields }
+    }
+
+    pub fn get_fields(&self) -> &BTreeMap<i64, Field> {
+        &self.fields
+    }
+
+    fn field_named(&self, name: &str) -> Option<&Field> {
+        self.fields.values().find(|field| field.name == name)
+    }
+
+    /// # the members of the struct
+    /// padded out so every field lands at the offset it was found at
+    pub fn members(&self) -> Vec<Declaration> {
+        let mut members = Vec::new();
+        let mut end = 0;
+
+        for (offset, field) in self.fields.iter() {
+            if *offset > end {
+                members.push(Declaration::array(BYTE, format!("pad_{}", end), (offset - end) as u64));
+            }
+            members.push(Declaration::new(field.ty.clone(), &field.name));
+            end = offset + field.ty.size() as i64;
+        }
+
+        members
+    }
+}
+
+// ----------------------------------------
+// types
+// ----------------------------------------
+
+/// # the types of a function's variables
+/// along with what it returns, when that's known, and the structs they point at
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Types {
+    variables: BTreeMap<Variable, Type>,
+    structs: BTreeMap<String, Struct>,
+    returned: Option<Type>
+}
+
+impl Types {
+    /// # infer the types of every variable in a function
+    /// from its statements once variables have been recovered, and the prototypes of it and anything it calls
+    pub fn infer(sections: &SectionMap, signature: &Signature, signatures: &SignatureMap) -> Self {
+        let mut collector = Collector {
+            prototypes: signatures.values()
+                .filter_map(|signature| signature.get_declared())
+                .map(|prototype| (prototype.name.clone(), prototype.clone()))
+                .collect(),
+            ..Default::default()
+        };
+
+        if let Some(declared) = signature.get_declared() {
+            for (register, parameter) in signature.get_arguments().iter().zip(declared.parameters.iter().flatten()) {
+                collector.of(&Variable::Register(register.clone())).declared = Some(parameter.get_type().clone());
+            }
+        }
+
+        for statement in sections.values().flat_map(|section| section.get_statements().values().flatten()) {
+            collector.statement(statement);
+        }
+
+        let (variables, structs) = collector.solve();
+        let mut types = Types { variables, structs, returned: None };
+
+        // whatever the returns give back, as wide as the widest of them
+        for statement in sections.values().flat_map(|section| section.get_statements().values().flatten()) {
+            if let Statement::Return { values: Some(values) } = statement {
+                if let Some(ty) = values.first().and_then(|value| types.type_of(value)) {
+                    types.returned = match types.returned.take() {
+                        Some(returned) if returned.size() >= ty.size() => Some(returned),
+                        _ => Some(ty)
+                    };
+                }
+            }
+        }
+
+        types
+    }
+
+    pub fn get_variables(&self) -> &BTreeMap<Variable, Type> {
+        &self.variables
+    }
+
+    /// what the function gives back, if it's known to be something other than a whole register
+    pub fn get_returned(&self) -> Option<&Type> {
+        self.returned.as_ref()
+    }
+
+    pub fn get_structs(&self) -> &BTreeMap<String, Struct> {
+        &self.structs
+    }
+
+    /// give fields the names they've been renamed to, leaving any that weren't as they are
+    pub fn rename_fields(&mut self, names: &FieldNames) {
+        for ((structure, offset), name) in names.iter() {
+            if let Some(field) = self.structs.get_mut(structure).and_then(|s| s.fields.get_mut(offset)) {
+                field.name = name.clone();
+            }
+        }
+    }
+
+    /// # the field read or written by an access
+    /// only one that's exactly where and as wide as the access is
+    fn field(&self, addr: &Expression, size: u8) -> Option<(Expression, &Field)> {
+        let (base, offset, false) = access_of(addr)? else {
+            return None;
+        };
+        let Type::Pointer(pointee) = self.get(base) else {
+            return None;
+        };
+        let Type::Struct(name) = *pointee else {
+            return None;
+        };
+
+        self.structs.get(&name)?.fields.get(&offset)
+            .filter(|field| field.ty.size() == size)
+            .map(|field| (Expression::Field { base: Box::new(Expression::Variable(base.clone())), name: field.name.clone() }, field))
+    }
+
+    /// the type of a variable, which is a whole register if nothing's known about it
+    pub fn get(&self, variable: &Variable) -> Type {
+        self.variables.get(variable).cloned().unwrap_or(REGISTER)
+    }
+
+    /// # the type of an expression
+    /// constants take on whatever they're used with, so they don't have one
+    pub fn type_of(&self, expr: &Expression) -> Option<Type> {
+        match expr {
+            Expression::Variable(variable) => Some(self.get(variable)),
+            Expression::Constant(_) => None,
+            // symbols are declared as arrays of bytes
+            Expression::Symbol { .. } => Some(Type::Pointer(Box::new(BYTE))),
+            Expression::String { wide: false, .. } => Some(Type::Pointer(Box::new(Type::Const(Box::new(Type::Char))))),
+            Expression::String { wide: true, .. } => Some(Type::Pointer(Box::new(Type::Const(Box::new(Type::Int { size: 2, signed: false }))))),
+            Expression::Load { size, signed, .. } => Some(Type::Int { size: *size, signed: *signed }),
+            Expression::Extend { bits, signed, .. } => Some(Type::Int { size: bits / 8, signed: *signed }),
+            Expression::Deref(addr) => match self.type_of(addr) {
+                Some(Type::Pointer(pointee)) => Some(*pointee),
+                _ => None
+            },
+            Expression::Cast { ty, .. } => Some(ty.clone()),
+            Expression::Field { base, name } => match self.type_of(base) {
+                Some(Type::Pointer(pointee)) => match *pointee {
+                    Type::Struct(structure) => Some(self.structs.get(&structure)?.field_named(name)?.ty.clone()),
+                    _ => None
+                },
+                _ => None
+            },
+            Expression::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.type_of(lhs), self.type_of(rhs));
+
+                match op {
+                    BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Less | BinaryOp::LessUnsigned |
+                    BinaryOp::GreaterEqual | BinaryOp::GreaterEqualUnsigned => Some(Type::Int { size: 4, signed: true }),
+                    BinaryOp::Sub if is_pointer(&lhs) && is_pointer(&rhs) => Some(REGISTER),
+                    BinaryOp::Add | BinaryOp::Sub if is_pointer(&lhs) => lhs,
+                    BinaryOp::Add if is_pointer(&rhs) => rhs,
+                    // the rest work on whole registers, so anything narrower is widened first
+                    // and as in c, one that's unsigned makes the result unsigned too
+                    _ => [lhs, rhs].into_iter().flatten()
+                        .filter(|t| !matches!(t, Type::Pointer(_)))
+                        .max_by_key(|t| (t.size(), matches!(t, Type::Int { signed: false, .. })))
+                        .map(|t| if t.size() == REGISTER.size() { t } else { REGISTER })
+                }
+            }
+        }
+    }
+
+    /// # the prototype of the function
+    /// with the parameters and return value typed, or declared, though the parameters still go by their registers
+    pub fn prototype(&self, signature: &Signature) -> Prototype {
+        let parameters = signature.get_arguments().iter()
+            .map(|register| Declaration::new(self.get(&Variable::Register(register.clone())), register))
+            .collect();
+        let returns = match (signature.get_declared(), signature.get_returns()) {
+            (Some(declared), _) => declared.returns.clone(),
+            (None, Returns::Single) => Some(self.returned.clone().unwrap_or(REGISTER)),
+            (None, returns) => returns.get_type()
+        };
+
+        Prototype { name: signature.get_name().to_string(), parameters: Some(parameters), returns, variadic: signature.is_variadic() }
+    }
+
+    /// the declarations of every variable that isn't a parameter
+    pub fn locals(&self, signature: &Signature) -> Vec<Declaration> {
+        self.variables.iter()
+            .filter(|(variable, _)| !variable.get_register().is_some_and(|r| signature.get_arguments().contains(r)))
+            .map(|(variable, ty)| Declaration::new(ty.clone(), variable))
+            .collect()
+    }
+
+    // ----------------------------------------
+
+    /// # write a function out with its types
+    /// every expression is rewritten so the casts it needs are explicit, and the ones it doesn't are gone
+    pub fn apply(&self, sections: &mut SectionMap) {
+        for section in sections.values_mut() {
+            let mut statements = section.get_statements().clone();
+
+            for statement in statements.values_mut().flatten() {
+                *statement = self.rewrite_statement(statement);
+            }
+
+            section.set_statements(statements);
+        }
+    }
+
+    fn rewrite_statement(&self, statement: &Statement) -> Statement {
+        match statement {
+            Statement::Assign { dst, value } => {
+                let ty = self.get(dst);
+
+                // assigning to something that narrow narrows it anyway
+                let value = match self.rewrite(value) {
+                    Expression::Extend { value, bits, .. } if matches!(ty, Type::Int { size, .. } if size * 8 == bits) => *value,
+                    value => value
+                };
+
+                Statement::Assign { dst: dst.clone(), value: self.convert(value, &ty) }
+            },
+            Statement::Store { addr, value, size } => {
+                let value = self.rewrite(value);
+                if let Some((field, _)) = self.field(addr, *size) {
+                    return Statement::Write { dst: field, value };
+                }
+
+                let addr = self.rewrite(addr);
+
+                match self.type_of(&addr) {
+                    Some(Type::Pointer(pointee)) if pointee.size() == *size => Statement::Write { dst: Expression::Deref(Box::new(addr)), value },
+                    _ => Statement::Store { addr, value, size: *size }
+                }
+            },
+            statement => {
+                let mut statement = statement.clone();
+                for expr in statement.get_expressions_mut() {
+                    *expr = self.rewrite(expr);
+                }
+                statement
+            }
+        }
+    }
+
+    /// a value going into a variable of another type, which c only does by itself between integers
+    fn convert(&self, value: Expression, ty: &Type) -> Expression {
+        match (self.type_of(&value), ty) {
+            (Some(from), to) if from == *to => value,
+            (Some(Type::Pointer(_)), Type::Pointer(_)) => match value {
+                Expression::Cast { value, .. } => Expression::Cast { value, ty: ty.clone() },
+                value => Expression::Cast { value: Box::new(value), ty: ty.clone() }
+            },
+            (Some(Type::Int { .. }), Type::Pointer(_)) => Expression::Cast { value: Box::new(value), ty: ty.clone() },
+            (Some(Type::Pointer(_)), Type::Int { size, signed }) => Expression::Extend { value: Box::new(value), bits: size * 8, signed: *signed },
+            _ => value
+        }
+    }
+
+    fn rewrite(&self, expr: &Expression) -> Expression {
+        match expr {
+            Expression::Binary(op, lhs, rhs) => self.rewrite_binary(*op, self.rewrite(lhs), self.rewrite(rhs), 64),
+            Expression::Load { addr, size, signed } => {
+                // a field that's read with a different sign is cast to it
+                if let Some((field, Field { ty, .. })) = self.field(addr, *size) {
+                    return match ty {
+                        Type::Int { signed: other, .. } if other != signed => Expression::extend(field, size * 8, *signed),
+                        _ => field
+                    };
+                }
+
+                let addr = self.rewrite(addr);
+                let ty = Type::Pointer(Box::new(Type::Int { size: *size, signed: *signed }));
+
+                match addr {
+                    addr if self.type_of(&addr) == Some(ty.clone()) => Expression::Deref(Box::new(addr)),
+                    // already cast to a pointer, just to the wrong one
+                    Expression::Cast { value, ty: Type::Pointer(_) } => Expression::Deref(Box::new(Expression::Cast { value, ty })),
+                    addr => Expression::load(addr, *size, *signed)
+                }
+            },
+            // already the type it's being cast to
+            Expression::Extend { value, bits, signed } => {
+                // a `*w` operation only keeps its low bits, so it can be done at that width
+                let value = match &**value {
+                    Expression::Binary(op, lhs, rhs) => self.rewrite_binary(*op, self.rewrite(lhs), self.rewrite(rhs), *bits),
+                    value => self.rewrite(value)
+                };
+
+                if self.type_of(&value) == Some(Type::Int { size: bits / 8, signed: *signed }) {
+                    value
+                } else {
+                    Expression::extend(value, *bits, *signed)
+                }
+            },
+            _ => expr.clone()
+        }
+    }
+
+    /// # an operation between typed values
+    /// the operator goes by the types of its operands in c, so the signedness of one is only cast in where they don't agree,
+    /// and anything narrower than the `bits` it's done at is widened, as c would do it as wide as the operands otherwise
+    fn rewrite_binary(&self, op: BinaryOp, lhs: Expression, rhs: Expression, bits: u8) -> Expression {
+        let (lhs_type, rhs_type) = (self.type_of(&lhs), self.type_of(&rhs));
+        let unsigned = |expr: &Expression, ty: &Option<Type>| {
+            matches!(expr, Expression::Constant(_)) || matches!(ty, Some(Type::Int { signed: false, .. }) | Some(Type::Pointer(_)))
+        };
+        let signed = |expr: Expression, ty: &Option<Type>| match ty {
+            Some(Type::Int { signed: false, .. } | Type::Pointer(_)) => Expression::extend(expr, 64, true),
+            Some(Type::Int { size, .. }) if size * 8 < bits => Expression::extend(expr, 64, true),
+            _ => expr
+        };
+        let integer = |expr: Expression, ty: &Option<Type>| match ty {
+            Some(Type::Pointer(_)) => Expression::extend(expr, 64, false),
+            Some(Type::Int { size, .. }) if size * 8 < bits => Expression::extend(expr, 64, true),
+            _ => expr
+        };
+        let typed = !matches!((&lhs, &rhs), (Expression::Constant(_), Expression::Constant(_)));
+
+        match op {
+            BinaryOp::Sub if is_pointer(&lhs_type) && is_pointer(&rhs_type) => {
+                Expression::Binary(op, Box::new(bytes(lhs, &lhs_type)), Box::new(bytes(rhs, &rhs_type)))
+            },
+            BinaryOp::Add | BinaryOp::Sub if is_pointer(&lhs_type) => offset(op, lhs, lhs_type.unwrap(), rhs),
+            BinaryOp::Add if is_pointer(&rhs_type) => offset(op, rhs, rhs_type.unwrap(), lhs),
+
+            // unsigned operands make the operator unsigned by themselves
+            BinaryOp::LessUnsigned | BinaryOp::GreaterEqualUnsigned | BinaryOp::DivUnsigned | BinaryOp::RemUnsigned
+                if typed && unsigned(&lhs, &lhs_type) && unsigned(&rhs, &rhs_type) => {
+                let op = match op {
+                    BinaryOp::LessUnsigned => BinaryOp::Less,
+                    BinaryOp::GreaterEqualUnsigned => BinaryOp::GreaterEqual,
+                    BinaryOp::DivUnsigned => BinaryOp::Div,
+                    _ => BinaryOp::Rem
+                };
+                Expression::Binary(op, Box::new(lhs), Box::new(rhs))
+            },
+            BinaryOp::ShiftRightLogical if typed && unsigned(&lhs, &lhs_type) => {
+                Expression::Binary(BinaryOp::ShiftRightArithmetic, Box::new(lhs), Box::new(rhs))
+            },
+
+            // and signed ones have to be cast to
+            BinaryOp::Less | BinaryOp::GreaterEqual | BinaryOp::Div | BinaryOp::Rem => {
+                Expression::Binary(op, Box::new(signed(lhs, &lhs_type)), Box::new(signed(rhs, &rhs_type)))
+            },
+            BinaryOp::ShiftRightArithmetic => Expression::Binary(op, Box::new(signed(lhs, &lhs_type)), Box::new(rhs)),
+
+            // pointers can only be compared, added to and taken from
+            BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::LessUnsigned | BinaryOp::GreaterEqualUnsigned => {
+                Expression::Binary(op, Box::new(lhs), Box::new(rhs))
+            },
+            op => Expression::Binary(op, Box::new(integer(lhs, &lhs_type)), Box::new(integer(rhs, &rhs_type)))
+        }
+    }
+}
+
+fn is_pointer(ty: &Option<Type>) -> bool {
+    matches!(ty, Some(Type::Pointer(_)))
+}
+
+/// a pointer as one that goes in bytes, unless it already does
+fn bytes(pointer: Expression, ty: &Option<Type>) -> Expression {
+    match ty {
+        Some(Type::Pointer(pointee)) if **pointee == BYTE => pointer,
+        _ => Expression::Cast { value: Box::new(pointer), ty: Type::Pointer(Box::new(BYTE)) }
+    }
+}
+
+/// # a pointer moved along by some number of bytes
+/// whole elements are counted in elements, including an index that's been scaled up to bytes,
+/// and anything else goes along in bytes and is cast back
+fn offset(op: BinaryOp, pointer: Expression, ty: Type, amount: Expression) -> Expression {
+    let Type::Pointer(pointee) = &ty else {
+        unreachable!();
+    };
+    let size = pointee.size() as i64;
+
+    match amount {
+        Expression::Constant(c) if size != 0 && c % size == 0 => Expression::binary(op, pointer, Expression::Constant(c / size)),
+        amount if size == 1 => Expression::Binary(op, Box::new(pointer), Box::new(amount)),
+        amount => match scaled(&amount, size).filter(|_| size != 0) {
+            Some(index) => Expression::Binary(op, Box::new(pointer), Box::new(index)),
+            None => {
+                let moved = Expression::Binary(op, Box::new(bytes(pointer, &Some(ty.clone()))), Box::new(amount));
+                Expression::Cast { value: Box::new(moved), ty }
+            }
+        }
+    }
+}
+
+/// the index a number of bytes counts, when it's that index times the size of an element
+fn scaled(amount: &Expression, size: i64) -> Option<Expression> {
+    match amount {
+        Expression::Binary(BinaryOp::ShiftLeft, index, shift) if matches!(**shift, Expression::Constant(k) if (0..63).contains(&k) && 1 << k == size) => Some(*index.clone()),
+        Expression::Binary(BinaryOp::Mul, index, c) | Expression::Binary(BinaryOp::Mul, c, index) if **c == Expression::Constant(size) => Some(*index.clone()),
+        _ => None
+    }
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::decompilation::{generate_sections, output_decompiled_code};
+    use crate::fixtures::{ld, program, r, register, ret, sections, statements};
+    use crate::image::Image;
+    use crate::instructions::{ABIRegister, InstructionType};
+    use crate::jumptable::JumpTableMap;
+    use crate::propagation::simplify;
+    use crate::ssa::recover_variables;
+    use ABIRegister::*;
+
+    /// simplify some instructions and recover their variables, ready to be typed
+    fn recovered(instructions: Vec<InstructionType>) -> SectionMap {
+        let mut sections = sections(instructions);
+        simplify(&mut sections, &Image::new());
+        recover_variables(&mut sections);
+        sections
+    }
+
+    #[test]
+    fn test_widths_and_signedness() {
+        let sections = recovered(vec![
+            InstructionType::I { name: "lhu", rd: s1, rs1: a1, imm: 0 },
+            InstructionType::R { name: "addw", rd: s2, rs1: a0, rs2: a2 },
+            InstructionType::R { name: "sltu", rd: s3, rs1: a3, rs2: a4 },
+            InstructionType::S { name: "sh", rs1: a5, rs2: s1, imm: 0 },
+            ret()
+        ]);
+        let types = Types::infer(&sections, &Signature::default(), &SignatureMap::new());
+
+        assert_eq!(types.get(&register(s1)), Type::Int { size: 2, signed: false });
+        assert_eq!(types.get(&register(s2)), Type::Int { size: 4, signed: true });
+        assert_eq!(types.get(&register(a0)), Type::Int { size: 4, signed: true });
+        assert_eq!(types.get(&register(a1)), Type::Pointer(Box::new(Type::Int { size: 2, signed: false })));
+        assert_eq!(types.get(&register(a3)), Type::Int { size: 8, signed: false });
+        assert_eq!(types.get(&register(a5)).declare("a5"), "int16_t *a5");
+    }
+
+    #[test]
+    fn test_casts_dropped() {
+        // the operands are already the types the instructions treat them as
+        let mut sections = recovered(vec![
+            InstructionType::R { name: "addw", rd: s1, rs1: a0, rs2: a1 },
+            InstructionType::R { name: "sltu", rd: s2, rs1: a2, rs2: a3 },
+            InstructionType::R { name: "slt", rd: s3, rs1: a4, rs2: a6 },
+            InstructionType::I { name: "lw", rd: s4, rs1: a5, imm: 8 },
+            InstructionType::S { name: "sw", rs1: a5, rs2: s4, imm: 4 },
+            ret()
+        ]);
+        let types = Types::infer(&sections, &Signature::default(), &SignatureMap::new());
+        types.apply(&mut sections);
+
+        assert_eq!(statements(&sections), [
+            "s1 = a0 + a1",
+            "s2 = a2 < a3",
+            "s3 = a4 < a6",
+            "s4 = a5->field_8",
+            "a5->field_4 = s4",
+            "return"
+        ]);
+    }
+
+    #[test]
+    fn test_pointer_arithmetic() {
+        // stepping through words four bytes at a time is one element at a time, anything else goes in bytes
+        let mut sections = recovered(vec![
+            InstructionType::I { name: "lw", rd: s1, rs1: a0, imm: 0 },
+            InstructionType::I { name: "addi", rd: a0, rs1: a0, imm: 4 },
+            InstructionType::I { name: "lw", rd: s2, rs1: a1, imm: 0 },
+            InstructionType::I { name: "addi", rd: a1, rs1: a1, imm: 2 },
+            ret()
+        ]);
+        let types = Types::infer(&sections, &Signature::default(), &SignatureMap::new());
+        types.apply(&mut sections);
+
+        assert_eq!(statements(&sections), [
+            "s1 = *a0",
+            "a0 = a0 + 1",
+            "s2 = *a1",
+            "a1 = (int32_t *)(((uint8_t *)a1) + 2)",
+            "return"
+        ]);
+    }
+
+    #[test]
+    fn test_arrays() {
+        // an index scaled up to the size of an element
+        let mut sections = recovered(vec![
+            InstructionType::I { name: "slli", rd: t0, rs1: a1, imm: 2 },
+            InstructionType::R { name: "add", rd: t0, rs1: a0, rs2: t0 },
+            InstructionType::I { name: "lw", rd: s1, rs1: t0, imm: 0 },
+            InstructionType::R { name: "add", rd: t1, rs1: a2, rs2: a3 },
+            InstructionType::S { name: "sb", rs1: t1, rs2: s1, imm: 0 },
+            ret()
+        ]);
+        let types = Types::infer(&sections, &Signature::default(), &SignatureMap::new());
+        types.apply(&mut sections);
+
+        assert_eq!(types.get(&register(a0)).declare("a0"), "int32_t *a0");
+        assert_eq!(statements(&sections), ["s1 = a0[a1]", "a2[a3] = s1", "return"]);
+    }
+
+    #[test]
+    fn test_structs() {
+        // fields at fixed offsets, with a gap before the second
+        let mut sections = recovered(vec![
+            InstructionType::I { name: "ld", rd: s1, rs1: a0, imm: 8 },
+            InstructionType::I { name: "lw", rd: s2, rs1: a0, imm: 16 },
+            InstructionType::S { name: "sh", rs1: a0, rs2: s2, imm: 0 },
+            ret()
+        ]);
+        let mut types = Types::infer(&sections, &Signature::default(), &SignatureMap::new());
+
+        assert_eq!(types.get(&register(a0)).declare("a0"), "struct struct_0 *a0");
+        let members = types.get_structs()["struct_0"].members().iter().map(|member| member.to_string()).collect::<Vec<_>>();
+        assert_eq!(members, ["int16_t field_0", "uint8_t pad_2[6]", "int64_t field_8", "int32_t field_16"]);
+
+        types.rename_fields(&FieldNames::from([(("struct_0".to_string(), 8), "next".to_string())]));
+        types.apply(&mut sections);
+
+        assert_eq!(statements(&sections), ["s1 = a0->next", "s2 = a0->field_16", "a0->field_0 = s2", "return"]);
+    }
+
+    #[test]
+    fn test_declaration() {
+        let mut sections = recovered(vec![
+            InstructionType::I { name: "lwu", rd: a0, rs1: a1, imm: 0 },
+            ret()
+        ]);
+        let signature = Signature::infer("f".to_string(), &sections, &BTreeMap::new());
+        signature.resolve_returns(&mut sections);
+        let types = Types::infer(&sections, &signature, &SignatureMap::new());
+
+        assert_eq!(types.prototype(&signature).to_string(), "uint32_t f(uint32_t a0, uint32_t *a1)");
+        assert!(types.locals(&signature).is_empty());
+    }
+
+    #[test]
+    fn test_result_apart_from_parameter() {
+        // a0 comes in as a pointer and goes back out as the sum, which are different types, so they're different variables
+        let instructions = program(vec![
+            ld(a5, a0, 8),
+            InstructionType::I { name: "lw", rd: a4, rs1: a0, imm: 16 },
+            r("add", a0, a5, a4),
+            ret()
+        ]);
+        let output = output_decompiled_code(generate_sections(instructions, &JumpTableMap::new()), &Image::new(), &SignatureMap::new(), &FieldNames::new());
+
+        assert_eq!(output[output.len() - 5..], [
+            "int64_t sub_100(struct struct_0 *a0) {",
+            "\tint64_t result;",
+            "\tresult = a0->field_8 + (int64_t)a0->field_16;",
+            "\treturn result;",
+            "}"
+        ]);
+    }
+}
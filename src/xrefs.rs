@@ -0,0 +1,226 @@
+//! # cross-references
+//! every address the program refers to, along with where from and how,
+//! so anything can be asked who calls it, jumps to it, reads it, writes it, or points at it
+//!
+//! references from the code are found once constants have been propagated through each function,
+//! so an address built up over a few instructions is put down to the instruction that finishes it
+//! references from the data are any aligned words that happen to be an address somewhere in the image
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::callgraph::CallGraph;
+use crate::image::Image;
+use crate::ir::{Expression, Statement};
+use crate::propagation::simplify;
+
+// ----------------------------------------
+// structures and methods
+// ----------------------------------------
+
+/// # how an address is referred to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Kind {
+    Call,
+    Branch,
+    Read,
+    Write,
+    /// used as a value by the code, which is how pointers to data are first made
+    Address,
+    /// kept in the data, as in tables of pointers to functions or text
+    Pointer
+}
+
+impl core::fmt::Display for Kind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut name = format!("{self:?}");
+        name.make_ascii_lowercase();
+        f.write_str(&name)
+    }
+}
+
+/// # somewhere an address is referred to from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Reference {
+    from: u64,
+    kind: Kind
+}
+
+impl Reference {
+    pub fn new(from: u64, kind: Kind) -> Self {
+        Reference { from, kind }
+    }
+
+    pub fn get_from(&self) -> u64 {
+        self.from
+    }
+
+    pub fn get_kind(&self) -> Kind {
+        self.kind
+    }
+
+    /// whether it's an instruction doing the referring, rather than some data
+    pub fn is_code(&self) -> bool {
+        self.kind != Kind::Pointer
+    }
+}
+
+/// # the references to every address
+/// only addresses that are somewhere in the image are kept, as anything else is just a number
+#[derive(Clone, Debug, Default)]
+pub struct Xrefs {
+    references: BTreeMap<u64, BTreeSet<Reference>>
+}
+
+impl Xrefs {
+    /// find the references made by every function in the call graph, and by every pointer in the data
+    pub fn new(graph: &CallGraph, image: &Image) -> Self {
+        let mut xrefs = Xrefs::default();
+
+        for function in graph.get_functions().values() {
+            let mut sections = function.cfg();
+            simplify(&mut sections, image);
+
+            for (address, statements) in sections.values().flat_map(|section| section.get_statements()) {
+                for statement in statements {
+                    xrefs.statement(*address, statement, image);
+                }
+            }
+        }
+
+        // pointers are kept aligned, so only whole words are looked at
+        for section in image.get_sections().iter().filter(|section| !section.is_executable()) {
+            let skip = section.get_address().wrapping_neg() % 8;
+            let data = section.get_data().get(skip as usize..).unwrap_or_default();
+
+            for (index, word) in data.chunks_exact(8).enumerate() {
+                let value = u64::from_le_bytes(word.try_into().unwrap());
+                let from = section.get_address() + skip + 8 * index as u64;
+                xrefs.add(value, Reference::new(from, Kind::Pointer), image);
+            }
+        }
+
+        xrefs
+    }
+
+    /// record a reference to an address, as long as it's one that's loaded
+    pub fn add(&mut self, address: u64, reference: Reference, image: &Image) {
+        if image.section_containing(address).is_some() {
+            self.references.entry(address).or_default().insert(reference);
+        }
+    }
+
+    /// everywhere an address is referred to from
+    pub fn get_references(&self, address: u64) -> BTreeSet<Reference> {
+        self.references.get(&address).cloned().unwrap_or_default()
+    }
+
+    /// the references to anywhere from `start` up to but not including `end`, by the address referred to
+    pub fn references_within(&self, start: u64, end: u64) -> impl Iterator<Item = (u64, &Reference)> {
+        self.references.range(start..end)
+            .flat_map(|(address, references)| references.iter().map(move |reference| (*address, reference)))
+    }
+
+    /// # every address referred to from somewhere
+    /// along with how
+    pub fn references_from(&self, from: u64) -> Vec<(u64, Kind)> {
+        self.references_within(0, u64::MAX)
+            .filter(|(_, reference)| reference.from == from)
+            .map(|(address, reference)| (address, reference.kind))
+            .collect()
+    }
+
+    /// what a statement refers to, which for calls, branches and stores depends on which part of it the address is in
+    fn statement(&mut self, from: u64, statement: &Statement, image: &Image) {
+        match statement {
+            Statement::Call { target, args, .. } => {
+                self.expression(from, target, Kind::Call, image);
+                for arg in args.iter().flatten() {
+                    self.expression(from, arg, Kind::Address, image);
+                }
+            },
+            Statement::Branch { condition, target } => {
+                if let Some(condition) = condition {
+                    self.expression(from, condition, Kind::Address, image);
+                }
+                self.expression(from, target, Kind::Branch, image);
+            },
+            Statement::Store { addr, value, .. } => {
+                self.expression(from, addr, Kind::Write, image);
+                self.expression(from, value, Kind::Address, image);
+            },
+            _ => {
+                for expr in statement.get_expressions() {
+                    self.expression(from, expr, Kind::Address, image);
+                }
+            }
+        }
+    }
+
+    /// the constants in an expression, which are referred to as `kind` if they're the whole of it
+    fn expression(&mut self, from: u64, expr: &Expression, kind: Kind, image: &Image) {
+        match expr {
+            Expression::Constant(c) => self.add(*c as u64, Reference::new(from, kind), image),
+            Expression::Load { addr, .. } => self.expression(from, addr, Kind::Read, image),
+            Expression::Binary(_, lhs, rhs) => {
+                self.expression(from, lhs, Kind::Address, image);
+                self.expression(from, rhs, Kind::Address, image);
+            },
+            Expression::Extend { value, .. } | Expression::Deref(value) | Expression::Cast { value, .. } |
+            Expression::Field { base: value, .. } => self.expression(from, value, Kind::Address, image),
+            Expression::Variable(_) | Expression::Symbol { .. } | Expression::String { .. } => {}
+        }
+    }
+}
+
+// ----------------------------------------
+// unit tests
+// ----------------------------------------
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::instructions::{ABIRegister::*, InstructionType};
+
+    #[test]
+    fn test_xrefs() {
+        let instructions = [
+            // f calls g, skips over a load through gp, and returns
+            (0x1000, InstructionType::J { name: "jal", rd: ra, imm: 0x10 }),
+            (0x1004, InstructionType::B { name: "beq", rs1: a0, rs2: zero, imm: 0x8 }),
+            (0x1008, InstructionType::I { name: "lw", rd: a1, rs1: gp, imm: 8 }),
+            (0x100c, InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 }),
+            // g stores to an address built by auipc, and gives back the start of the data
+            (0x1010, InstructionType::U { name: "auipc", rd: t0, imm: 0x1 }),
+            (0x1014, InstructionType::S { name: "sw", rs1: t0, rs2: a0, imm: 0 }),
+            (0x1018, InstructionType::U { name: "lui", rd: a0, imm: 0x2 }),
+            (0x101c, InstructionType::I { name: "jalr", rd: zero, rs1: ra, imm: 0 })
+        ].into_iter().collect();
+        let symbols = BTreeMap::from([(0x1000, "f".to_string()), (0x1010, "g".to_string())]);
+        let graph = CallGraph::new(&instructions, &symbols, Some(0x1000), &BTreeMap::new());
+
+        // with a pointer to g in the data
+        let mut data = vec![0; 0x1000];
+        data[0x18..0x20].copy_from_slice(&0x1010_u64.to_le_bytes());
+
+        let mut image = Image::new();
+        image.add_section(".text", 0x1000, vec![0; 0x20], true);
+        image.add_section(".data", 0x2000, data, false);
+        image.set_global_pointer(0x2800);
+
+        let xrefs = Xrefs::new(&graph, &image);
+        let all: Vec<(u64, u64, Kind)> = xrefs.references_within(0, u64::MAX)
+            .map(|(address, reference)| (address, reference.get_from(), reference.get_kind()))
+            .collect();
+
+        assert_eq!(all, [
+            (0x100c, 0x1004, Kind::Branch),
+            (0x1010, 0x1000, Kind::Call),
+            (0x1010, 0x2018, Kind::Pointer),
+            (0x2000, 0x1018, Kind::Address),
+            (0x2010, 0x1014, Kind::Write),
+            (0x2808, 0x1008, Kind::Read)
+        ]);
+        assert_eq!(xrefs.references_from(0x1000), [(0x1010, Kind::Call)]);
+        assert!(xrefs.get_references(0x1004).is_empty());
+    }
+}